@@ -0,0 +1,479 @@
+//! Orbit, pan, dolly and fly camera with inertia.
+//!
+//! The input methods ([`CameraController::rotate`], [`CameraController::pan`],
+//! [`CameraController::dolly`], [`CameraController::set_fly_key`]) don't depend on any windowing
+//! library; [`CameraController::handle_event`] drives them from `blinds` events, including
+//! pinch-zoom and two-finger pan when several pointers are down at once, as touches are on the web.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use blinds::event::{Event, ScrollDelta};
+use blinds::{Key, MouseButton, PointerId};
+use nalgebra_glm as glm;
+
+/// Keeps the eye off the poles, where the view would flip.
+const PITCH_LIMIT: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Rotate around the target and keep looking at it.
+    Orbit,
+    /// Rotate around the eye, and move with the fly keys.
+    Fly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FlyKey {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Radians per pixel dragged.
+    pub rotate_speed: f32,
+    /// Distance factor per scroll line or per pinch step.
+    pub zoom_step: f32,
+    /// Units per second in fly mode.
+    pub fly_speed: f32,
+    /// How quickly motion stops after the pointer is released, per second.
+    pub damping: f32,
+    /// Vertical field of view of the projection used with this camera, to make panning follow
+    /// the pointer.
+    pub fov_y: f32,
+    /// Bounds on the distance from the eye to the target, however far it is dollied.
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            rotate_speed: 0.01,
+            zoom_step: 1.1,
+            fly_speed: 1.0,
+            damping: 6.0,
+            fov_y: std::f32::consts::PI / 3.0,
+            min_distance: 1e-3,
+            max_distance: 1e6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Pointer {
+    /// Unknown until the pointer first moves.
+    location: Option<glm::Vec2>,
+    button: Option<MouseButton>,
+}
+
+/// Motion accumulated since the last update, or carried over from it as inertia.
+#[derive(Clone, Copy)]
+struct Motion {
+    rotate: glm::Vec2,
+    pan: glm::Vec2,
+    dolly: f32,
+}
+
+impl Motion {
+    fn zero() -> Motion {
+        Motion {
+            rotate: glm::zero(),
+            pan: glm::zero(),
+            dolly: 0.0,
+        }
+    }
+
+    fn scale(&self, s: f32) -> Motion {
+        Motion {
+            rotate: self.rotate * s,
+            pan: self.pan * s,
+            dolly: self.dolly * s,
+        }
+    }
+}
+
+pub struct CameraController {
+    pub settings: Settings,
+    target: glm::Vec3,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    mode: Mode,
+    viewport: glm::Vec2,
+    pointers: HashMap<PointerId, Pointer>,
+    shift: bool,
+    fly_keys: Vec<FlyKey>,
+    pending: Motion,
+    /// Per second.
+    velocity: Motion,
+}
+
+impl CameraController {
+    /// Looking at `target` from `distance` along +Z.
+    pub fn new(target: glm::Vec3, distance: f32) -> CameraController {
+        CameraController {
+            settings: Settings::default(),
+            target,
+            yaw: 0.0,
+            pitch: 0.0,
+            distance,
+            mode: Mode::Orbit,
+            viewport: glm::vec2(1.0, 1.0),
+            pointers: HashMap::new(),
+            shift: false,
+            fly_keys: Vec::new(),
+            pending: Motion::zero(),
+            velocity: Motion::zero(),
+        }
+    }
+
+    /// Look at `target` from `distance` along +Z again, stopping any motion.
+    pub fn reset(&mut self, target: glm::Vec3, distance: f32) {
+        self.target = target;
+        self.distance = distance;
+        self.yaw = 0.0;
+        self.pitch = 0.0;
+        self.pending = Motion::zero();
+        self.velocity = Motion::zero();
+    }
+
+    /// Orbit to `yaw` and `pitch` (radians) around the target, or turn to them in fly mode.
+    pub fn set_orientation(&mut self, yaw: f32, pitch: f32) {
+        let eye = self.eye();
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        if self.mode == Mode::Fly {
+            self.target = eye - self.backward() * self.distance;
+        }
+    }
+
+    /// Size of the viewport in the units pointer locations are given in.
+    pub fn set_viewport(&mut self, size: glm::Vec2) {
+        self.viewport = size;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.fly_keys.clear();
+    }
+
+    pub fn target(&self) -> glm::Vec3 {
+        self.target
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    /// Unit vector from the target to the eye.
+    fn backward(&self) -> glm::Vec3 {
+        glm::vec3(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.cos() * self.pitch.cos(),
+        )
+    }
+
+    pub fn eye(&self) -> glm::Vec3 {
+        self.target + self.backward() * self.distance
+    }
+
+    pub fn view_matrix(&self) -> glm::Mat4 {
+        glm::look_at(&self.eye(), &self.target, &glm::vec3(0.0, 1.0, 0.0))
+    }
+
+    /// Turn by a pointer movement in pixels.
+    pub fn rotate(&mut self, delta: glm::Vec2) {
+        self.pending.rotate += delta;
+    }
+
+    /// Move the target with the pointer, by a movement in pixels.
+    pub fn pan(&mut self, delta: glm::Vec2) {
+        self.pending.pan += delta;
+    }
+
+    /// Move towards the target by `steps` zoom steps; negative values move away.
+    pub fn dolly(&mut self, steps: f32) {
+        self.pending.dolly += steps;
+    }
+
+    pub fn set_fly_key(&mut self, key: FlyKey, down: bool) {
+        self.fly_keys.retain(|&k| k != key);
+        if down {
+            self.fly_keys.push(key);
+        }
+    }
+
+    /// Feed an input event. Returns whether the controller used it.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::PointerInput(e) => {
+                let pointer = self.pointers.entry(*e.pointer()).or_default();
+                pointer.button = if e.is_down() { Some(e.button()) } else { None };
+                true
+            }
+            Event::PointerMoved(e) => {
+                let location = glm::make_vec2(e.location().as_ref());
+                self.pointer_moved(*e.pointer(), location);
+                true
+            }
+            Event::PointerLeft(e) => {
+                self.pointers.remove(e.pointer());
+                true
+            }
+            Event::ScrollInput(delta) => {
+                let lines = match delta {
+                    ScrollDelta::Lines(delta) => delta.y,
+                    // Roughly one line per 40 pixels, like most browsers.
+                    ScrollDelta::Pixels(delta) => delta.y / 40.0,
+                };
+                self.dolly(lines);
+                true
+            }
+            Event::ModifiersChanged(modifiers) => {
+                self.shift = modifiers.shift();
+                false
+            }
+            Event::KeyboardInput(e) if self.mode == Mode::Fly => {
+                let key = match e.key() {
+                    Key::W => FlyKey::Forward,
+                    Key::S => FlyKey::Back,
+                    Key::A => FlyKey::Left,
+                    Key::D => FlyKey::Right,
+                    Key::E => FlyKey::Up,
+                    Key::Q => FlyKey::Down,
+                    _ => return false,
+                };
+                self.set_fly_key(key, e.is_down());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn pointer_moved(&mut self, id: PointerId, location: glm::Vec2) {
+        // Two pressed pointers make a pinch/pan gesture: the change in their distance zooms
+        // and the movement of their midpoint pans.
+        let pressed: Vec<(PointerId, Pointer)> = self
+            .pointers
+            .iter()
+            .filter(|(_, p)| p.button.is_some())
+            .map(|(&id, &p)| (id, p))
+            .take(2)
+            .collect();
+        if let [(a, pa), (b, pb)] = pressed[..] {
+            if let (true, Some(la), Some(lb)) = (id == a || id == b, pa.location, pb.location) {
+                let (na, nb) = if id == a {
+                    (location, lb)
+                } else {
+                    (la, location)
+                };
+                let before = glm::distance(&la, &lb);
+                let after = glm::distance(&na, &nb);
+                if before > 0.0 && after > 0.0 {
+                    self.dolly((after / before).ln() / self.settings.zoom_step.ln());
+                }
+                self.pan((na + nb - la - lb) * 0.5);
+            }
+        } else if let Some(&Pointer {
+            location: Some(previous),
+            button,
+        }) = self.pointers.get(&id)
+        {
+            let delta = location - previous;
+            match button {
+                Some(MouseButton::Left) if !self.shift => self.rotate(delta),
+                Some(MouseButton::Left) | Some(MouseButton::Right) | Some(MouseButton::Middle) => {
+                    self.pan(delta)
+                }
+                _ => {}
+            }
+        }
+
+        self.pointers.entry(id).or_default().location = Some(location);
+    }
+
+    /// Apply the input since the last update, or keep coasting if nothing is held.
+    pub fn update(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        let held = self.pointers.values().any(|p| p.button.is_some());
+        let motion = if held {
+            if dt > 0.0 {
+                self.velocity = self.pending.scale(1.0 / dt);
+            }
+            self.pending
+        } else {
+            let carried = self.velocity.scale(dt);
+            self.velocity = self.velocity.scale((-self.settings.damping * dt).exp());
+            Motion {
+                rotate: self.pending.rotate + carried.rotate,
+                pan: self.pending.pan + carried.pan,
+                dolly: self.pending.dolly + carried.dolly,
+            }
+        };
+        self.pending = Motion::zero();
+        self.apply(&motion);
+        self.fly(dt);
+    }
+
+    fn apply(&mut self, motion: &Motion) {
+        // In fly mode this turns the head rather than circle the target.
+        self.set_orientation(
+            self.yaw - motion.rotate.x * self.settings.rotate_speed,
+            self.pitch + motion.rotate.y * self.settings.rotate_speed,
+        );
+
+        // Scale so the point under the pointer at the target's depth follows it.
+        let world_per_pixel =
+            2.0 * self.distance * (self.settings.fov_y / 2.0).tan() / self.viewport.y.max(1.0);
+        let (right, up) = self.axes();
+        self.target += (up * motion.pan.y - right * motion.pan.x) * world_per_pixel;
+
+        self.distance = (self.distance * self.settings.zoom_step.powf(-motion.dolly))
+            .clamp(self.settings.min_distance, self.settings.max_distance);
+    }
+
+    fn fly(&mut self, dt: f32) {
+        if self.mode != Mode::Fly || self.fly_keys.is_empty() {
+            return;
+        }
+        let (right, up) = self.axes();
+        let forward = -self.backward();
+        let direction = self
+            .fly_keys
+            .iter()
+            .map(|key| match key {
+                FlyKey::Forward => forward,
+                FlyKey::Back => -forward,
+                FlyKey::Left => -right,
+                FlyKey::Right => right,
+                FlyKey::Up => up,
+                FlyKey::Down => -up,
+            })
+            .fold(glm::zero::<glm::Vec3>(), |a, b| a + b);
+        if glm::length(&direction) > 0.0 {
+            self.target += glm::normalize(&direction) * self.settings.fly_speed * dt;
+        }
+    }
+
+    /// Camera right and up vectors in world space.
+    fn axes(&self) -> (glm::Vec3, glm::Vec3) {
+        let backward = self.backward();
+        let right = glm::normalize(&glm::vec3(0.0, 1.0, 0.0).cross(&backward));
+        let up = backward.cross(&right);
+        (right, up)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    fn assert_near(a: glm::Vec3, b: glm::Vec3) {
+        assert!(glm::distance(&a, &b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn controller() -> CameraController {
+        CameraController::new(glm::vec3(1.0, 2.0, 3.0), 5.0)
+    }
+
+    #[test]
+    fn orbit_keeps_the_target_and_fly_keeps_the_eye() {
+        let mut controller = controller();
+        controller.rotate(glm::vec2(40.0, 20.0));
+        controller.update(FRAME);
+        assert_near(controller.target(), glm::vec3(1.0, 2.0, 3.0));
+        assert!((glm::distance(&controller.eye(), &controller.target()) - 5.0).abs() < 1e-4);
+
+        controller.set_mode(Mode::Fly);
+        let eye = controller.eye();
+        controller.rotate(glm::vec2(-60.0, 10.0));
+        controller.update(FRAME);
+        assert_near(controller.eye(), eye);
+        assert!(glm::distance(&controller.target(), &glm::vec3(1.0, 2.0, 3.0)) > 0.1);
+
+        controller.set_mode(Mode::Orbit);
+        let target = controller.target();
+        controller.rotate(glm::vec2(30.0, 0.0));
+        controller.update(FRAME);
+        assert_near(controller.target(), target);
+    }
+
+    #[test]
+    fn fly_keys_move_only_in_fly_mode_and_are_released_on_switching() {
+        let mut controller = controller();
+        controller.set_mode(Mode::Fly);
+        controller.set_fly_key(FlyKey::Forward, true);
+        controller.update(Duration::from_secs(1));
+        // Looking down -Z from +Z.
+        assert_near(controller.target(), glm::vec3(1.0, 2.0, 2.0));
+
+        controller.set_mode(Mode::Orbit);
+        controller.set_mode(Mode::Fly);
+        controller.update(Duration::from_secs(1));
+        assert_near(controller.target(), glm::vec3(1.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut controller = controller();
+        controller.settings.min_distance = 1.0;
+        controller.settings.max_distance = 10.0;
+        controller.dolly(100.0);
+        controller.update(FRAME);
+        assert_eq!(controller.distance(), 1.0);
+        controller.dolly(-100.0);
+        controller.update(FRAME);
+        assert_eq!(controller.distance(), 10.0);
+        controller.dolly(1.0);
+        controller.update(FRAME);
+        assert!((controller.distance() - 10.0 / 1.1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn pitch_is_clamped() {
+        let mut controller = controller();
+        controller.set_orientation(0.0, 10.0);
+        assert_eq!(controller.pitch(), PITCH_LIMIT);
+        controller.rotate(glm::vec2(0.0, -1e6));
+        controller.update(FRAME);
+        assert_eq!(controller.pitch(), -PITCH_LIMIT);
+    }
+
+    #[test]
+    fn reset_stops_motion() {
+        let mut controller = controller();
+        controller.rotate(glm::vec2(40.0, 20.0));
+        controller.pan(glm::vec2(5.0, 5.0));
+        controller.update(FRAME);
+        controller.velocity.rotate = glm::vec2(100.0, 0.0);
+        controller.dolly(3.0);
+
+        controller.reset(glm::vec3(0.0, 0.0, 0.0), 2.0);
+        controller.update(FRAME);
+        assert_eq!((controller.yaw(), controller.pitch()), (0.0, 0.0));
+        assert_eq!(controller.distance(), 2.0);
+        assert_near(controller.target(), glm::vec3(0.0, 0.0, 0.0));
+        assert_near(controller.eye(), glm::vec3(0.0, 0.0, 2.0));
+    }
+}
@@ -98,6 +98,10 @@ impl DigitRender {
                     Uniform::new("matrix", UniformType::Matrix(Dimension::D4)),
                     Uniform::new("num", UniformType::Scalar(NumberType::Float)),
                     Uniform::new("tex", UniformType::Sampler2D),
+                    Uniform::new(
+                        "color",
+                        UniformType::Vector(NumberType::Float, Dimension::D4),
+                    ),
                 ],
                 vertex_shader: r#" void main() {
                     gl_Position = matrix * vec4(vert_position, -1.0, 1.0);
@@ -108,9 +112,9 @@ impl DigitRender {
                     a.x /= 10.;
                     a.x += num / 10.;
                     a.y = 1. - a.y;
-                    vec4 color = texture(tex, a);
-                    if (color.a == 0.) discard;
-                    gl_FragColor = color;
+                    vec4 texel = texture(tex, a);
+                    if (texel.a == 0.) discard;
+                    gl_FragColor = texel * color;
                 }"#,
             },
         )
@@ -125,6 +129,15 @@ impl DigitRender {
     }
 
     pub fn draw(&mut self, num: usize, matrix: &glm::Mat4) -> Result<(), GolemError> {
+        self.draw_colored(num, matrix, [1.0; 4])
+    }
+
+    pub fn draw_colored(
+        &mut self,
+        num: usize,
+        matrix: &glm::Mat4,
+        color: [f32; 4],
+    ) -> Result<(), GolemError> {
         self.shader.bind();
         self.texture
             .set_active(unsafe { NonZeroU32::new_unchecked(1) });
@@ -136,6 +149,8 @@ impl DigitRender {
         self.shader
             .set_uniform("num", UniformValue::Float(num as f32))?;
         self.shader.set_uniform("tex", UniformValue::Int(1))?;
+        self.shader
+            .set_uniform("color", UniformValue::Vector4(color))?;
 
         unsafe {
             self.shader
@@ -0,0 +1,49 @@
+use camera_controller::CameraController;
+use nalgebra_glm as glm;
+
+use crate::golem_gltf::Aabb;
+
+pub const FOV_Y: f32 = std::f32::consts::PI / 3.0;
+
+/// Orbit view placed so the whole model fits on screen.
+pub struct Framing {
+    pub center: glm::Vec3,
+    pub radius: f32,
+}
+
+impl Framing {
+    pub fn new(bounds: Option<Aabb>) -> Framing {
+        match bounds {
+            Some(bounds) => Framing {
+                center: bounds.center(),
+                radius: bounds.radius().max(1e-3),
+            },
+            None => Framing {
+                center: glm::zero(),
+                radius: 1.0,
+            },
+        }
+    }
+
+    pub fn distance(&self, scroll_absolute: f32) -> f32 {
+        self.radius / (FOV_Y / 2.0).sin() * 1.1_f32.powf(scroll_absolute)
+    }
+
+    /// Put `controller` on the framed view, `zoom` scroll steps away, with its speed and zoom
+    /// limits set to the model's size.
+    pub fn reset(&self, controller: &mut CameraController, zoom: f32) {
+        controller.reset(self.center, self.distance(zoom));
+        controller.settings.fov_y = FOV_Y;
+        controller.settings.fly_speed = self.radius;
+        controller.settings.min_distance = self.radius * 1e-2;
+        controller.settings.max_distance = self.radius * 1e3;
+    }
+
+    pub fn p_matrix(&self, aspect: f32, distance: f32) -> glm::Mat4 {
+        // Keep the near plane as far out as possible for depth precision, without clipping the
+        // bounding sphere.
+        let near = (distance - self.radius).max(self.radius * 1e-3) * 0.9;
+        let far = (distance + self.radius) * 1.1;
+        glm::perspective(aspect, FOV_Y, near, far)
+    }
+}
@@ -1,95 +1,1501 @@
+use std::collections::HashMap;
 use std::convert::TryInto;
 
 use golem::*;
 use nalgebra_glm as glm;
 
+mod accessor;
+mod animation;
+mod bounds;
+mod camera;
+mod compression;
+mod debug;
+mod draco;
+mod environment;
+mod export;
+mod gpu_instancing;
+mod inspect;
+mod instancing;
+mod light;
+mod loader;
+mod material;
+mod meshopt;
+mod morph;
+mod optimize;
+mod picking;
+mod shadow;
+mod skin;
+mod texture;
+mod validation;
+
+pub use animation::{Animation, AnimationPlayer, NodePose};
+pub use bounds::{Aabb, Frustum};
+pub use camera::Camera;
+pub use debug::DebugView;
+pub use environment::{Environment, HdrImage, ToneMapping, ToneOperator};
+pub use export::export_file;
+pub use inspect::{MeshCounts, MeshInfo, TextureInfo};
+pub use instancing::Instancing;
+pub use light::{Light, LightKind};
+pub use loader::{LoadProgress, Loader};
+pub use material::{Material, TextureRef};
+use morph::MorphTargets;
+pub use optimize::{Optimization, OptimizationStats};
+pub use picking::{Hit, Ray};
+use shadow::Ground;
+pub use shadow::ShadowMap;
+use skin::Skin;
+pub use validation::{Issue, Severity, ValidationReport};
+
+use crate::time::Instant;
+
 pub struct Gltf {
     shader: ShaderProgram,
+    skinned_shader: ShaderProgram,
+    instanced_shader: ShaderProgram,
+    instancing: Option<Instancing>,
+    nodes: Vec<Node>,
+    node_order: Vec<usize>,
+    scenes: Vec<Scene>,
+    default_scene: Option<usize>,
+    scene: Option<usize>,
+    /// Whether each node is in `scene`.
+    in_scene: Vec<bool>,
+    skins: Vec<Skin>,
     primitives: Vec<Primitive>,
+    animations: Vec<Animation>,
+    rest_pose: Vec<NodePose>,
+    player: AnimationPlayer,
+    cameras: Vec<Camera>,
+    lights: Vec<Light>,
+    materials: Vec<Material>,
+    default_material: Material,
+    meshes: Vec<MeshInfo>,
+    /// `None` where the upload failed.
+    textures: Vec<Option<Texture>>,
+    texture_info: Vec<TextureInfo>,
+    /// Bound where a material has no texture.
+    white_texture: Texture,
+    validation: ValidationReport,
+    source: export::Source,
+    optimization: Option<OptimizationStats>,
+    culling: bool,
+    stats: DrawStats,
+    environment: Option<std::rc::Rc<Environment>>,
+    tone_mapping: ToneMapping,
+    shadow_map: Option<ShadowMap>,
+    ground: Ground,
+    show_ground: bool,
+    debug_view: DebugView,
+    debug_draw: Option<debug::DebugDraw>,
+    highlight: Option<usize>,
+}
+
+/// Primitive instances drawn and skipped by frustum culling in the last [`Gltf::draw`], and the
+/// draw calls it took.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DrawStats {
+    pub drawn: usize,
+    pub culled: usize,
+    pub draw_calls: usize,
 }
 
 impl Gltf {
     pub fn load(path: impl AsRef<std::path::Path>, ctx: &Context) -> Result<Gltf, gltf::Error> {
-        let (document, buffers, images) = gltf::import(path)?;
-        Self::load_impl(document, buffers, images, ctx)
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(gltf::Error::Io)?;
+        Ok(Self::load_parsed(
+            Parsed::from_slice(&data, Some(path))?,
+            ctx,
+        ))
+    }
+
+    /// Like [`Gltf::load`], optimizing the meshes first.
+    pub fn load_optimized(
+        path: impl AsRef<std::path::Path>,
+        optimization: &Optimization,
+        ctx: &Context,
+    ) -> Result<Gltf, gltf::Error> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(gltf::Error::Io)?;
+        Ok(Self::load_parsed(
+            Parsed::from_slice(&data, Some(path))?.optimize(optimization)?,
+            ctx,
+        ))
     }
 
     pub fn load_slice(slice: impl AsRef<[u8]>, ctx: &Context) -> Result<Gltf, gltf::Error> {
-        let (document, buffers, images) = gltf::import_slice(slice)?;
-        Self::load_impl(document, buffers, images, ctx)
+        Ok(Self::load_parsed(
+            Parsed::from_slice(slice.as_ref(), None)?,
+            ctx,
+        ))
     }
 
-    fn load_impl(
-        document: gltf::Document,
-        buffers: Vec<gltf::buffer::Data>,
-        images: Vec<gltf::image::Data>,
-        ctx: &Context,
-    ) -> Result<Gltf, gltf::Error> {
-        println!("#buffers: {}", buffers.len());
-        println!("#images: {}", images.len());
+    /// A model with nothing in it, to draw until one is loaded.
+    pub fn empty(ctx: &Context) -> Gltf {
+        Self::load_slice(br#"{"asset":{"version":"2.0"}}"#, ctx).unwrap()
+    }
+
+    fn load_parsed(parsed: Parsed, ctx: &Context) -> Gltf {
+        let (mut gltf, mut pending) = Self::begin_load(parsed, ctx);
+        while pending.upload_next(&mut gltf, ctx) {}
+        gltf
+    }
 
-        let mut primitives = Vec::new();
+    /// Everything but the meshes, which are left to [`PendingMeshes`] so they can be uploaded a
+    /// few at a time.
+    fn begin_load(parsed: Parsed, ctx: &Context) -> (Gltf, PendingMeshes) {
+        let Parsed {
+            document,
+            buffers,
+            images,
+            json,
+            optimization,
+        } = parsed;
+        let mut validation = ValidationReport::validate(&document, &buffers);
+
+        let mut nodes: Vec<Node> = document.nodes().map(|n| Node::from_gltf_node(&n)).collect();
         for node in document.nodes() {
-            if let Some(mesh) = node.mesh() {
-                println!("mesh: {:?}", mesh.name());
-                for primitive in mesh.primitives() {
-                    println!("  primitive:");
-                    let _material = primitive.material();
-                    primitives.push(Primitive::from_gltf_primitive(
-                        ctx,
-                        &primitive,
-                        &buffers[0].0,
-                    ));
-                }
+            for child in node.children() {
+                nodes[child.index()].parent = Some(node.index());
+                nodes[node.index()].children.push(child.index());
             }
         }
+        let node_order = Self::traversal_order(&document);
+        let scenes: Vec<Scene> = document
+            .scenes()
+            .map(|s| Scene {
+                name: s.name().map(str::to_owned),
+                nodes: s.nodes().map(|n| n.index()).collect(),
+            })
+            .collect();
+        // Files without a default scene leave it to the application; the first one is as good
+        // as any.
+        let default_scene = document
+            .default_scene()
+            .map(|s| s.index())
+            .or_else(|| Some(0).filter(|_| !scenes.is_empty()));
+
+        let skins: Vec<Skin> = document
+            .skins()
+            .map(|s| Skin::from_gltf_skin(&s, &buffers))
+            .collect();
+
+        let animations: Vec<Animation> = document
+            .animations()
+            .map(|a| Animation::from_gltf_animation(&a, &buffers, &validation))
+            .collect();
+        let rest_pose = nodes.iter().map(NodePose::from_node).collect();
+
+        let cameras: Vec<Camera> = document
+            .nodes()
+            .filter_map(|n| Camera::from_gltf_node(&n))
+            .collect();
 
+        let mut lights: Vec<Light> = document
+            .nodes()
+            .filter_map(|n| Light::from_gltf_node(&n))
+            .collect();
+        if lights.is_empty() {
+            lights = Light::default_rig();
+        }
+
+        let materials: Vec<Material> = document
+            .materials()
+            .map(|m| {
+                let json = match (&json, m.index()) {
+                    (Some(json), Some(index)) => &json["materials"][index],
+                    _ => &gltf::json::Value::Null,
+                };
+                Material::from_gltf_material(&m, json)
+            })
+            .collect();
+        let meshes = document
+            .meshes()
+            .map(|m| MeshInfo::from_gltf_mesh(&m))
+            .collect();
+        // Sampled as white until uploaded.
+        let textures: Vec<Option<Texture>> = document.textures().map(|_| None).collect();
+        let texture_info = document
+            .textures()
+            .map(|t| TextureInfo::from_gltf_texture(&t, &images))
+            .collect();
+
+        let gpu_instances = match &json {
+            Some(json)
+                if document
+                    .extensions_used()
+                    .any(|e| e == gpu_instancing::EXTENSION) =>
+            {
+                gpu_instancing::read_instances(json, &document, &buffers, &mut validation)
+            }
+            _ => Vec::new(),
+        };
+        let buffers = std::rc::Rc::new(buffers);
+
+        let fragment_shader = light::lighting_fragment_shader();
         let shader = ShaderProgram::new(
             ctx,
             ShaderDescription {
                 vertex_input: &[
                     Attribute::new("vert_position", AttributeType::Vector(Dimension::D3)),
                     Attribute::new("vert_normal", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("vert_uv0", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("vert_uv1", AttributeType::Vector(Dimension::D2)),
                 ],
-                fragment_input: &[Attribute::new(
-                    "frag_color",
-                    AttributeType::Vector(Dimension::D4),
-                )],
-                uniforms: &[Uniform::new(
-                    "mvp_matrix",
-                    UniformType::Matrix(Dimension::D4),
-                )],
+                fragment_input: &[
+                    Attribute::new("frag_position", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("frag_normal", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("frag_uv0", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("frag_uv1", AttributeType::Vector(Dimension::D2)),
+                ],
+                uniforms: &vec![
+                    Uniform::new("mvp_matrix", UniformType::Matrix(Dimension::D4)),
+                    Uniform::new("model_matrix", UniformType::Matrix(Dimension::D4)),
+                    Uniform::new("light_count", UniformType::Scalar(NumberType::Int)),
+                ]
+                .into_iter()
+                .chain(material::uniforms())
+                .chain(environment::uniforms())
+                .chain(shadow::uniforms())
+                .collect::<Vec<_>>(),
                 vertex_shader: r#" void main() {
-                    gl_Position = mvp_matrix * vec4(vert_position, 1.0);
-                    vec3 light = normalize(vec3(1.0, 1.0, 0.0));
-                    float diffuse = clamp(dot(vert_normal, light), 0.1, 1.0);
-                    frag_color = vec4(1.0, 1.0, 1.0, 1) * vec4(vec3(diffuse), 1.0);
+                    vec4 position = model_matrix * vec4(vert_position, 1.0);
+                    gl_Position = mvp_matrix * position;
+                    frag_position = position.xyz;
+                    frag_normal = (model_matrix * vec4(vert_normal, 0.0)).xyz;
+                    frag_uv0 = vert_uv0;
+                    frag_uv1 = vert_uv1;
                 }"#,
-                fragment_shader: r#" void main() {
-                    gl_FragColor = frag_color;
+                fragment_shader: &fragment_shader,
+            },
+        )
+        .unwrap();
+
+        // The joint matrix array is declared in the shader body, since golem writes array
+        // uniforms as `mat4[N] name` which GLSL ES 1.00 (WebGL 1) doesn't accept.
+        let skinned_vertex_shader = format!(
+            r#"
+                uniform mat4 joint_matrices[{}];
+                void main() {{
+                    mat4 skin_matrix =
+                        vert_weights.x * joint_matrices[int(vert_joints.x)] +
+                        vert_weights.y * joint_matrices[int(vert_joints.y)] +
+                        vert_weights.z * joint_matrices[int(vert_joints.z)] +
+                        vert_weights.w * joint_matrices[int(vert_joints.w)];
+                    vec4 position = skin_matrix * vec4(vert_position, 1.0);
+                    gl_Position = mvp_matrix * position;
+                    frag_position = position.xyz;
+                    frag_normal = (skin_matrix * vec4(vert_normal, 0.0)).xyz;
+                    frag_uv0 = vert_uv0;
+                    frag_uv1 = vert_uv1;
+                }}"#,
+            skin::MAX_JOINTS
+        );
+        let skinned_shader = ShaderProgram::new(
+            ctx,
+            ShaderDescription {
+                vertex_input: &[
+                    Attribute::new("vert_position", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("vert_normal", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("vert_uv0", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("vert_uv1", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("vert_joints", AttributeType::Vector(Dimension::D4)),
+                    Attribute::new("vert_weights", AttributeType::Vector(Dimension::D4)),
+                ],
+                fragment_input: &[
+                    Attribute::new("frag_position", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("frag_normal", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("frag_uv0", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("frag_uv1", AttributeType::Vector(Dimension::D2)),
+                ],
+                uniforms: &vec![
+                    Uniform::new("mvp_matrix", UniformType::Matrix(Dimension::D4)),
+                    Uniform::new("light_count", UniformType::Scalar(NumberType::Int)),
+                ]
+                .into_iter()
+                .chain(material::uniforms())
+                .chain(environment::uniforms())
+                .chain(shadow::uniforms())
+                .collect::<Vec<_>>(),
+                vertex_shader: &skinned_vertex_shader,
+                fragment_shader: &fragment_shader,
+            },
+        )
+        .unwrap();
+
+        // Same as `shader`, with the model matrix coming from per-instance attributes.
+        let instanced_shader = ShaderProgram::new(
+            ctx,
+            ShaderDescription {
+                vertex_input: &[
+                    Attribute::new("vert_position", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("vert_normal", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("vert_uv0", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("vert_uv1", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("vert_model_0", AttributeType::Vector(Dimension::D4)),
+                    Attribute::new("vert_model_1", AttributeType::Vector(Dimension::D4)),
+                    Attribute::new("vert_model_2", AttributeType::Vector(Dimension::D4)),
+                    Attribute::new("vert_model_3", AttributeType::Vector(Dimension::D4)),
+                ],
+                fragment_input: &[
+                    Attribute::new("frag_position", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("frag_normal", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("frag_uv0", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("frag_uv1", AttributeType::Vector(Dimension::D2)),
+                ],
+                uniforms: &vec![
+                    Uniform::new("mvp_matrix", UniformType::Matrix(Dimension::D4)),
+                    Uniform::new("light_count", UniformType::Scalar(NumberType::Int)),
+                ]
+                .into_iter()
+                .chain(material::uniforms())
+                .chain(environment::uniforms())
+                .chain(shadow::uniforms())
+                .collect::<Vec<_>>(),
+                vertex_shader: r#" void main() {
+                    mat4 model_matrix = mat4(vert_model_0, vert_model_1, vert_model_2, vert_model_3);
+                    vec4 position = model_matrix * vec4(vert_position, 1.0);
+                    gl_Position = mvp_matrix * position;
+                    frag_position = position.xyz;
+                    frag_normal = (model_matrix * vec4(vert_normal, 0.0)).xyz;
+                    frag_uv0 = vert_uv0;
+                    frag_uv1 = vert_uv1;
                 }"#,
+                fragment_shader: &fragment_shader,
             },
         )
         .unwrap();
 
-        Ok(Gltf { shader, primitives })
+        let mut gltf = Gltf {
+            shader,
+            skinned_shader,
+            instanced_shader,
+            instancing: None,
+            in_scene: vec![true; nodes.len()],
+            nodes,
+            node_order,
+            scenes,
+            default_scene,
+            scene: None,
+            skins,
+            primitives: Vec::new(),
+            animations,
+            rest_pose,
+            player: AnimationPlayer::new(),
+            cameras,
+            lights,
+            materials,
+            default_material: Material::default(),
+            meshes,
+            textures,
+            texture_info,
+            white_texture: texture::white(ctx).unwrap(),
+            validation,
+            source: export::Source {
+                json,
+                buffers: buffers.clone(),
+            },
+            optimization,
+            culling: true,
+            stats: DrawStats::default(),
+            environment: None,
+            tone_mapping: ToneMapping::default(),
+            shadow_map: None,
+            ground: Ground::new(ctx).unwrap(),
+            show_ground: false,
+            debug_view: DebugView::default(),
+            debug_draw: None,
+            highlight: None,
+        };
+        gltf.set_scene(default_scene);
+        gltf.update_transforms();
+        let pending = PendingMeshes {
+            mesh_nodes: document
+                .nodes()
+                .filter(|n| n.mesh().is_some())
+                .map(|n| n.index())
+                .collect(),
+            uploaded: 0,
+            images,
+            textures_uploaded: 0,
+            document,
+            buffers,
+            gpu_instances,
+            shared: HashMap::new(),
+        };
+        (gltf, pending)
+    }
+
+    /// Node indices ordered so that every parent comes before its children.
+    fn traversal_order(document: &gltf::Document) -> Vec<usize> {
+        fn visit(node: gltf::Node, order: &mut Vec<usize>) {
+            order.push(node.index());
+            for child in node.children() {
+                visit(child, order);
+            }
+        }
+        let mut is_child = vec![false; document.nodes().len()];
+        for node in document.nodes() {
+            for child in node.children() {
+                is_child[child.index()] = true;
+            }
+        }
+        let mut order = Vec::new();
+        for node in document.nodes().filter(|n| !is_child[n.index()]) {
+            visit(node, &mut order);
+        }
+        order
+    }
+
+    /// Recompute the global matrix of every node and the joint matrices of every skin from the
+    /// current local transforms.
+    pub fn update_transforms(&mut self) {
+        for &i in &self.node_order {
+            let local = self.nodes[i].local_matrix();
+            self.nodes[i].global_matrix = match self.nodes[i].parent {
+                Some(parent) => self.nodes[parent].global_matrix * local,
+                None => local,
+            };
+        }
+        for skin in &mut self.skins {
+            skin.update_joint_matrices(&self.nodes);
+        }
+        for light in &mut self.lights {
+            light.update(&self.nodes);
+        }
+    }
+
+    pub fn scenes(&self) -> &[Scene] {
+        &self.scenes
+    }
+
+    /// The scene the file says to show, or the first one if it doesn't say. `None` if the file
+    /// has no scenes.
+    pub fn default_scene(&self) -> Option<usize> {
+        self.default_scene
+    }
+
+    /// The scene drawn, or `None` if every node is.
+    pub fn scene(&self) -> Option<usize> {
+        self.scene
+    }
+
+    /// Draw the nodes of `scene` and their descendants, or every node with `None`. Bounds,
+    /// picking and lights follow, but the cameras of every scene are kept. A scene out of range
+    /// draws nothing.
+    pub fn set_scene(&mut self, scene: Option<usize>) {
+        self.scene = scene;
+        let roots = match scene {
+            Some(scene) => self.scenes.get(scene).map_or(&[][..], |s| &s.nodes[..]),
+            None => {
+                self.in_scene = vec![true; self.nodes.len()];
+                return;
+            }
+        };
+        self.in_scene = vec![false; self.nodes.len()];
+        let mut stack = roots.to_vec();
+        while let Some(node) = stack.pop() {
+            if let Some(false) = self.in_scene.get(node) {
+                self.in_scene[node] = true;
+                stack.extend_from_slice(&self.nodes[node].children);
+            }
+        }
+    }
+
+    pub fn is_in_scene(&self, node: usize) -> bool {
+        self.in_scene.get(node).copied().unwrap_or(false)
+    }
+
+    pub fn cameras(&self) -> &[Camera] {
+        &self.cameras
+    }
+
+    /// World-space bounds of everything drawn, using the current node transforms.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.primitives
+            .iter()
+            .flat_map(|p| {
+                self.model_matrices(p)
+                    .into_iter()
+                    .filter_map(move |m| self.world_bounds(p, &m))
+            })
+            .fold(None, |acc: Option<Aabb>, b| {
+                Some(acc.map_or(b, |acc| acc.union(&b)))
+            })
+    }
+
+    /// The instances of `primitive` on nodes in the current scene, with their index.
+    fn scene_instances<'a>(
+        &'a self,
+        primitive: &'a Primitive,
+    ) -> impl Iterator<Item = (usize, &'a Instance)> + 'a {
+        primitive
+            .instances
+            .iter()
+            .enumerate()
+            .filter(move |(_, i)| self.in_scene[i.node])
+    }
+
+    /// Model matrix of each instance of `primitive` in the current scene, using the current node
+    /// transforms.
+    fn model_matrices(&self, primitive: &Primitive) -> Vec<glm::Mat4> {
+        self.scene_instances(primitive)
+            .map(|(_, i)| self.nodes[i.node].global_matrix * i.transform)
+            .collect()
+    }
+
+    /// The lights on nodes in the current scene, or the default rig if there are none.
+    fn scene_lights(&self) -> Vec<Light> {
+        let lights: Vec<Light> = self
+            .lights
+            .iter()
+            .filter(|l| l.node().map_or(true, |n| self.in_scene[n]))
+            .cloned()
+            .collect();
+        if lights.is_empty() {
+            Light::default_rig()
+        } else {
+            lights
+        }
+    }
+
+    /// World-space bounds of `primitive` drawn with `model_matrix`, using the current node
+    /// transforms.
+    ///
+    /// Skinned primitives ignore the model matrix. A skinned vertex is a weighted average of its
+    /// position moved by each of its joints, so it stays within the union of the bounds moved by
+    /// every joint the primitive uses.
+    fn world_bounds(&self, primitive: &Primitive, model_matrix: &glm::Mat4) -> Option<Aabb> {
+        let bounds = primitive.bounds?;
+        match primitive.skin {
+            Some(skin) => {
+                let joint_matrices = self.skins[skin].joint_matrices();
+                primitive
+                    .joints
+                    .iter()
+                    .filter_map(|&j| joint_matrices.get(j))
+                    .map(|m| bounds.transform(m))
+                    .fold(None, |acc: Option<Aabb>, b| {
+                        Some(acc.map_or(b, |acc| acc.union(&b)))
+                    })
+            }
+            None => Some(bounds.transform(model_matrix)),
+        }
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn lights_mut(&mut self) -> &mut Vec<Light> {
+        &mut self.lights
+    }
+
+    pub fn animations(&self) -> &[Animation] {
+        &self.animations
+    }
+
+    pub fn player(&self) -> &AnimationPlayer {
+        &self.player
+    }
+
+    pub fn player_mut(&mut self) -> &mut AnimationPlayer {
+        &mut self.player
+    }
+
+    /// Advance the animation player to `now` and pose the nodes accordingly.
+    pub fn update_animation(&mut self, now: Instant) {
+        self.player.update(now, &self.animations);
+        if let Some(pose) = self.player.pose(&self.animations, &self.rest_pose) {
+            for (node_pose, node) in pose.iter().zip(&mut self.nodes) {
+                node_pose.apply_to(node);
+            }
+        }
+    }
+
+    /// Set the morph target weights of the mesh on `node`, overriding its default weights.
+    pub fn set_morph_weights(&mut self, node: usize, weights: &[f32]) {
+        self.nodes[node].weights = weights.to_vec();
+        self.rest_pose[node].weights = weights.to_vec();
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn nodes_mut(&mut self) -> &mut [Node] {
+        &mut self.nodes
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    pub fn materials_mut(&mut self) -> &mut [Material] {
+        &mut self.materials
+    }
+
+    pub fn meshes(&self) -> &[MeshInfo] {
+        &self.meshes
+    }
+
+    /// What has been uploaded of `mesh` so far, counting each primitive once however many nodes
+    /// draw it.
+    pub fn mesh_counts(&self, mesh: usize) -> MeshCounts {
+        let mut counted = Vec::new();
+        let mut counts = MeshCounts::default();
+        for primitive in self.primitives.iter().filter(|p| p.mesh == mesh) {
+            if !counted.contains(&primitive.mesh_primitive) {
+                counted.push(primitive.mesh_primitive);
+                counts.vertices += primitive.vertices.positions.len();
+                counts.triangles += primitive.triangles.len();
+            }
+        }
+        counts
+    }
+
+    pub fn textures(&self) -> &[TextureInfo] {
+        &self.texture_info
+    }
+
+    /// Whether texture `index` has been uploaded. Until it is, or if it fails to, it samples as
+    /// white.
+    pub fn is_texture_uploaded(&self, index: usize) -> bool {
+        matches!(self.textures.get(index), Some(Some(_)))
+    }
+
+    pub fn validation(&self) -> &ValidationReport {
+        &self.validation
+    }
+
+    /// What optimizing the meshes did, if they were.
+    pub fn optimization_stats(&self) -> Option<&OptimizationStats> {
+        self.optimization.as_ref()
+    }
+
+    pub fn culling(&self) -> bool {
+        self.culling
+    }
+
+    /// Skip primitives whose bounds are outside the view frustum. On by default.
+    pub fn set_culling(&mut self, culling: bool) {
+        self.culling = culling;
+    }
+
+    /// Light with `environment` as well as the lights, which also enables the metallic-roughness
+    /// parameters of materials.
+    pub fn set_environment(&mut self, environment: Option<std::rc::Rc<Environment>>) {
+        self.environment = environment;
+    }
+
+    pub fn tone_mapping(&self) -> &ToneMapping {
+        &self.tone_mapping
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    /// Cast shadows from the first directional light through `map`, drawn by
+    /// [`Gltf::draw_shadows`].
+    pub fn set_shadow_map(&mut self, map: Option<ShadowMap>) {
+        self.shadow_map = map;
+    }
+
+    pub fn shadow_map(&self) -> Option<&ShadowMap> {
+        self.shadow_map.as_ref()
+    }
+
+    /// Whether a floor is drawn under the model, where it can receive its shadows.
+    pub fn ground(&self) -> bool {
+        self.show_ground
+    }
+
+    pub fn set_ground(&mut self, ground: bool) {
+        self.show_ground = ground;
+    }
+
+    pub fn draw_stats(&self) -> DrawStats {
+        self.stats
+    }
+
+    /// The closest triangle hit by `ray`, in world space, with the current node transforms, skin
+    /// poses and morph weights.
+    pub fn pick(&mut self, ray: &Ray) -> Option<Hit> {
+        self.update_transforms();
+
+        // Static geometry gets a BVH the first time. Skinned and morphed geometry changes too
+        // often, so every triangle is tested instead.
+        for primitive in &mut self.primitives {
+            if primitive.bvh.is_none() && primitive.skin.is_none() && primitive.morph.is_none() {
+                primitive.bvh = Some(picking::Bvh::new(
+                    &primitive.vertices.positions,
+                    &primitive.triangles,
+                ));
+            }
+        }
+
+        // (primitive, instance, hit, model matrix)
+        let mut closest: Option<(usize, usize, picking::TriangleHit, glm::Mat4)> = None;
+        for (i, primitive) in self.primitives.iter().enumerate() {
+            let candidates: Vec<(usize, picking::TriangleHit, glm::Mat4)> = match primitive.skin {
+                Some(_) if self.scene_instances(primitive).next().is_none() => Vec::new(),
+                Some(skin) => {
+                    // Skinned vertices end up in world space.
+                    let (positions, _) =
+                        primitive.skinned_vertices(self.skins[skin].joint_matrices());
+                    picking::intersect_all(ray, &positions, &primitive.triangles)
+                        .map(|hit| (0, hit, glm::identity()))
+                        .into_iter()
+                        .collect()
+                }
+                None => {
+                    let positions = &primitive.vertices().positions;
+                    self.scene_instances(primitive)
+                        .map(|(instance, i)| {
+                            (instance, self.nodes[i.node].global_matrix * i.transform)
+                        })
+                        .filter_map(|(instance, m)| {
+                            let local = ray.transform_inverse(&m);
+                            let hit = match &primitive.bvh {
+                                Some(bvh) => bvh.intersect(&local, positions, &primitive.triangles),
+                                None => {
+                                    picking::intersect_all(&local, positions, &primitive.triangles)
+                                }
+                            };
+                            hit.map(|hit| (instance, hit, m))
+                        })
+                        .collect()
+                }
+            };
+            for (instance, hit, m) in candidates {
+                match closest {
+                    Some((_, _, c, _)) if c.1 <= hit.1 => {}
+                    _ => closest = Some((i, instance, hit, m)),
+                }
+            }
+        }
+
+        let (i, instance, (triangle, distance, u, v), model_matrix) = closest?;
+        let primitive = &self.primitives[i];
+        let corners = primitive.triangles[triangle];
+        let (positions, normals) = match primitive.skin {
+            Some(skin) => primitive.skinned_vertices(self.skins[skin].joint_matrices()),
+            None => {
+                let vertices = primitive.vertices();
+                (vertices.positions.clone(), vertices.normals.clone())
+            }
+        };
+        let corner = |data: &[[f32; 3]], k: usize| glm::make_vec3(&data[corners[k] as usize]);
+        let interpolated =
+            corner(&normals, 0) * (1.0 - u - v) + corner(&normals, 1) * u + corner(&normals, 2) * v;
+        let face = (corner(&positions, 1) - corner(&positions, 0))
+            .cross(&(corner(&positions, 2) - corner(&positions, 0)));
+        let local_normal = if glm::length(&interpolated) > 1e-6 {
+            interpolated
+        } else {
+            face
+        };
+        let normal_matrix = glm::transpose(&glm::inverse(&glm::mat4_to_mat3(&model_matrix)));
+
+        let node = primitive.instances[instance].node;
+        Some(Hit {
+            node,
+            instance: primitive.instances[..instance]
+                .iter()
+                .filter(|i| i.node == node)
+                .count(),
+            mesh: primitive.mesh,
+            primitive: primitive.mesh_primitive,
+            triangle,
+            distance,
+            point: ray.at(distance),
+            normal: glm::normalize(&(normal_matrix * local_normal)),
+        })
+    }
+
+    /// Draw primitives used by several nodes or instances with one instanced draw call. Without
+    /// it, each instance is drawn separately.
+    pub fn set_instancing(&mut self, instancing: Option<Instancing>) {
+        self.instancing = instancing;
     }
 
-    pub fn draw(&mut self, mvp_matrix: &glm::Mat4) -> Result<(), GolemError> {
-        self.shader.bind();
+    fn material(&self, primitive: &Primitive) -> &Material {
+        primitive
+            .material
+            .map_or(&self.default_material, |m| &self.materials[m])
+    }
 
-        self.shader.set_uniform(
-            "mvp_matrix",
-            UniformValue::Matrix4(glm::value_ptr(mvp_matrix).try_into().unwrap()),
-        )?;
+    /// Draw opaque and alpha-masked primitives first, then blended ones from back to front.
+    /// Instances outside the frustum of `mvp_matrix` are skipped if culling is enabled.
+    pub fn draw(&mut self, ctx: &Context, mvp_matrix: &glm::Mat4) -> Result<(), GolemError> {
+        self.prepare();
+        let has_lines = self.debug_view.has_lines() || self.highlight.is_some();
+        if (self.debug_view != DebugView::default() || has_lines) && self.debug_draw.is_none() {
+            self.debug_draw = Some(debug::DebugDraw::new(ctx)?);
+        }
 
+        let lights = self.scene_lights();
+        for shader in &mut [
+            &mut self.shader,
+            &mut self.skinned_shader,
+            &mut self.instanced_shader,
+        ] {
+            shader.bind();
+            light::set_light_uniforms(shader, &lights)?;
+            Environment::set_uniforms(
+                self.environment.as_deref(),
+                shader,
+                mvp_matrix,
+                &self.white_texture,
+            )?;
+            self.tone_mapping.set_uniforms(shader)?;
+            shader.set_uniform("shadow_pass", UniformValue::Float(0.0))?;
+            ShadowMap::set_uniforms(self.shadow_map.as_ref(), shader, &self.white_texture)?;
+        }
+
+        let frustum = Frustum::from_matrix(mvp_matrix);
+        let mut stats = DrawStats::default();
+        // Model matrices of the instances left after culling, per primitive.
+        let mut visible: Vec<Vec<glm::Mat4>> = Vec::with_capacity(self.primitives.len());
         for primitive in &self.primitives {
-            unsafe {
-                primitive.draw(&self.shader)?;
+            let (inside, outside): (Vec<glm::Mat4>, Vec<glm::Mat4>) = self
+                .model_matrices(primitive)
+                .into_iter()
+                .partition(|m| match self.world_bounds(primitive, m) {
+                    Some(b) if self.culling => frustum.intersects(&b),
+                    _ => true,
+                });
+            stats.drawn += inside.len();
+            stats.culled += outside.len();
+            visible.push(inside);
+        }
+
+        let mut blended: Vec<(f32, usize, glm::Mat4)> = Vec::new();
+        for (i, matrices) in visible.iter().enumerate() {
+            if matrices.is_empty() {
+                continue;
+            }
+            let primitive = &self.primitives[i];
+            if !self.material(primitive).is_blended() {
+                stats.draw_calls += self.draw_primitive(i, mvp_matrix, matrices)?;
+                continue;
+            }
+            // Blended instances are sorted one by one, so they can't be batched.
+            for m in matrices {
+                let center = self
+                    .world_bounds(primitive, m)
+                    .map_or(glm::zero(), |b| b.center());
+                let depth = (mvp_matrix * glm::vec4(center.x, center.y, center.z, 1.0)).z;
+                blended.push((depth, i, *m));
             }
         }
+        if let (true, Some(bounds)) = (self.show_ground, self.bounding_box()) {
+            let shader = &mut self.shader;
+            shader.bind();
+            shader.set_uniform(
+                "mvp_matrix",
+                UniformValue::Matrix4(glm::value_ptr(mvp_matrix).try_into().unwrap()),
+            )?;
+            self.ground
+                .draw(shader, &Ground::model_matrix(&bounds), &self.white_texture)?;
+            stats.draw_calls += 1;
+        }
+        blended.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        if !blended.is_empty() {
+            ctx.set_blend_mode(Some(blended_mode()));
+            ctx.set_depth_test_mode(Some(depth::DepthTestMode {
+                depth_mask: false,
+                ..Default::default()
+            }));
+            for (_, i, m) in &blended {
+                stats.draw_calls += self.draw_primitive(*i, mvp_matrix, std::slice::from_ref(m))?;
+            }
+            ctx.set_blend_mode(None);
+            ctx.set_depth_test_mode(Some(depth::DepthTestMode::default()));
+        }
+
+        if has_lines {
+            let (surface, overlay) = self.debug_lines();
+            let debug = self.debug_draw.as_mut().unwrap();
+            // Pulled slightly towards the eye, to win over the surfaces they lie on.
+            ctx.set_depth_test_mode(Some(depth::DepthTestMode {
+                function: depth::DepthTestFunction::LessOrEqual,
+                range_far: 0.9995,
+                ..Default::default()
+            }));
+            debug.draw(&surface, mvp_matrix)?;
+            ctx.set_depth_test_mode(None);
+            debug.draw(&overlay, mvp_matrix)?;
+            ctx.set_depth_test_mode(Some(depth::DepthTestMode::default()));
+            stats.draw_calls += 2;
+        }
+        self.stats = stats;
         Ok(())
     }
+
+    /// Lines for the debug views: those drawn against the surfaces, and those drawn through them.
+    fn debug_lines(&self) -> (debug::Lines, debug::Lines) {
+        let view = self.debug_view;
+        let mut surface = debug::Lines::default();
+        let mut overlay = debug::Lines::default();
+        let size = self.bounding_box().map_or(1.0, |b| b.radius());
+        let mut node_bounds: Vec<Option<Aabb>> = vec![None; self.nodes.len()];
+
+        for primitive in &self.primitives {
+            let model_matrices = self.model_matrices(primitive);
+            for ((_, instance), m) in self.scene_instances(primitive).zip(&model_matrices) {
+                if let Some(b) = self.world_bounds(primitive, m) {
+                    let bounds = &mut node_bounds[instance.node];
+                    *bounds = Some(bounds.map_or(b, |bounds| bounds.union(&b)));
+                }
+            }
+            if (!view.wireframe && !view.normals) || model_matrices.is_empty() {
+                continue;
+            }
+
+            // World-space positions, normals and tangents of each instance.
+            let to_world = |m: &glm::Mat4, v: &[[f32; 3]], w: f32| -> Vec<glm::Vec3> {
+                v.iter()
+                    .map(|p| (m * glm::vec4(p[0], p[1], p[2], w)).xyz())
+                    .collect()
+            };
+            let instances = match primitive.skin {
+                Some(skin) => {
+                    let (positions, normals) =
+                        primitive.skinned_vertices(self.skins[skin].joint_matrices());
+                    let identity = glm::identity();
+                    vec![(
+                        to_world(&identity, &positions, 1.0),
+                        to_world(&identity, &normals, 0.0),
+                        Vec::new(),
+                    )]
+                }
+                None => {
+                    let vertices = primitive.vertices();
+                    model_matrices
+                        .iter()
+                        .map(|m| {
+                            (
+                                to_world(m, &vertices.positions, 1.0),
+                                to_world(m, &vertices.normals, 0.0),
+                                to_world(m, &vertices.tangents, 0.0),
+                            )
+                        })
+                        .collect()
+                }
+            };
+            for (positions, normals, tangents) in &instances {
+                if view.wireframe {
+                    for triangle in &primitive.triangles {
+                        let corners: Option<Vec<&glm::Vec3>> = triangle
+                            .iter()
+                            .map(|&i| positions.get(i as usize))
+                            .collect();
+                        if let Some(c) = corners {
+                            surface.line(c[0], c[1], debug::WIREFRAME_COLOR);
+                            surface.line(c[1], c[2], debug::WIREFRAME_COLOR);
+                            surface.line(c[2], c[0], debug::WIREFRAME_COLOR);
+                        }
+                    }
+                }
+                if view.normals {
+                    for (directions, color) in &[
+                        (normals, debug::NORMAL_COLOR),
+                        (tangents, debug::TANGENT_COLOR),
+                    ] {
+                        for (p, d) in positions.iter().zip(directions.iter()) {
+                            if d.norm() > 0.0 {
+                                surface.line(p, &(p + d.normalize() * size * 0.03), *color);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if view.bounds {
+            for bounds in node_bounds.iter().flatten() {
+                surface.aabb(bounds, debug::BOUNDS_COLOR);
+            }
+        }
+        if view.skeleton {
+            for skin in &self.skins {
+                let joints = skin.joints();
+                let position = |node: usize| {
+                    (self.nodes[node].global_matrix * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz()
+                };
+                for &joint in joints {
+                    let p = position(joint);
+                    overlay.point(&p, size * 0.01, debug::SKELETON_COLOR);
+                    if let Some(parent) = self.nodes[joint].parent().filter(|p| joints.contains(p))
+                    {
+                        overlay.line(&p, &position(parent), debug::SKELETON_COLOR);
+                    }
+                }
+            }
+        }
+        if view.axes {
+            for node in &self.nodes {
+                overlay.axes(&node.global_matrix, size * 0.1);
+            }
+        }
+        if let Some(highlight) = self.highlight.filter(|&h| h < self.nodes.len()) {
+            // The bounds of everything below the node, so groups are outlined too.
+            let below = node_bounds.iter().enumerate().filter_map(|(i, b)| {
+                let mut node = Some(i);
+                while let Some(n) = node.filter(|&n| n != highlight) {
+                    node = self.nodes[n].parent;
+                }
+                b.filter(|_| node.is_some())
+            });
+            if let Some(bounds) = below.fold(None, |acc: Option<Aabb>, b| {
+                Some(acc.map_or(b, |acc| acc.union(&b)))
+            }) {
+                overlay.aabb(&bounds, debug::HIGHLIGHT_COLOR);
+            }
+            overlay.axes(&self.nodes[highlight].global_matrix, size * 0.2);
+        }
+        (surface, overlay)
+    }
+
+    pub fn debug_view(&self) -> DebugView {
+        self.debug_view
+    }
+
+    /// Turn debug views on and off.
+    pub fn set_debug_view(&mut self, view: DebugView) {
+        self.debug_view = view;
+    }
+
+    pub fn highlight(&self) -> Option<usize> {
+        self.highlight
+    }
+
+    /// Outline a node and everything below it, through the surfaces, with its axes.
+    pub fn set_highlight(&mut self, node: Option<usize>) {
+        self.highlight = node;
+    }
+
+    /// Pose the nodes and morph the meshes for drawing.
+    fn prepare(&mut self) {
+        self.update_transforms();
+        for primitive in &mut self.primitives {
+            // Primitives with morph targets aren't shared, so they belong to a single node.
+            if let Some(instance) = primitive.instances.first() {
+                primitive.update_morph(&self.nodes[instance.node].weights);
+            }
+        }
+    }
+
+    /// Draw the depth of opaque and alpha-masked primitives, as seen from the first directional
+    /// light, into the shadow map, which the next [`Gltf::draw`] uses. Nothing is drawn without a
+    /// shadow map. This leaves the screen bound and the viewport changed, so it goes before
+    /// setting up the frame.
+    pub fn draw_shadows(&mut self, ctx: &Context) -> Result<(), GolemError> {
+        let mut map = match self.shadow_map.take() {
+            Some(map) => map,
+            None => return Ok(()),
+        };
+        self.prepare();
+        let lights = self.scene_lights();
+        let result = match (ShadowMap::caster(&lights), self.bounding_box()) {
+            (Some(light), Some(bounds)) => {
+                let light_matrix = ShadowMap::light_matrix(&lights[light].direction(), &bounds);
+                map.begin(ctx, light, &light_matrix);
+                let result = self.draw_depth(ctx, &light_matrix);
+                map.end();
+                result
+            }
+            _ => {
+                map.clear();
+                Ok(())
+            }
+        };
+        self.shadow_map = Some(map);
+        result
+    }
+
+    fn draw_depth(&mut self, ctx: &Context, light_matrix: &glm::Mat4) -> Result<(), GolemError> {
+        for shader in &mut [
+            &mut self.shader,
+            &mut self.skinned_shader,
+            &mut self.instanced_shader,
+        ] {
+            shader.bind();
+            shader.set_uniform("shadow_pass", UniformValue::Float(1.0))?;
+        }
+        ctx.set_depth_test_mode(Some(depth::DepthTestMode::default()));
+        for i in 0..self.primitives.len() {
+            let primitive = &self.primitives[i];
+            let matrices = self.model_matrices(primitive);
+            if !matrices.is_empty() && !self.material(primitive).is_blended() {
+                self.draw_primitive(i, light_matrix, &matrices)?;
+            }
+        }
+        ctx.set_depth_test_mode(None);
+        Ok(())
+    }
+
+    /// Draw a primitive once for each of `model_matrices`, with a single instanced draw if
+    /// possible. Returns the number of draw calls.
+    fn draw_primitive(
+        &mut self,
+        index: usize,
+        mvp_matrix: &glm::Mat4,
+        model_matrices: &[glm::Mat4],
+    ) -> Result<usize, GolemError> {
+        let primitive = &self.primitives[index];
+        let material = match primitive.material {
+            Some(m) => &self.materials[m],
+            None => &self.default_material,
+        };
+        let mvp_matrix = UniformValue::Matrix4(glm::value_ptr(mvp_matrix).try_into().unwrap());
+
+        if let (Some(instancing), None, true) =
+            (&self.instancing, primitive.skin, model_matrices.len() > 1)
+        {
+            let shader = &mut self.instanced_shader;
+            shader.bind();
+            shader.set_uniform("mvp_matrix", mvp_matrix)?;
+            material.set_uniforms(shader, &self.textures, &self.white_texture)?;
+            if let (true, Some(debug)) = (self.debug_view.uv_checker, &self.debug_draw) {
+                material::set_checker_uniforms(shader, debug.checker())?;
+            }
+            unsafe { primitive.draw_instanced(instancing, shader, model_matrices)? };
+            return Ok(1);
+        }
+
+        let shader = match primitive.skin {
+            Some(_) => &mut self.skinned_shader,
+            None => &mut self.shader,
+        };
+        shader.bind();
+        shader.set_uniform("mvp_matrix", mvp_matrix)?;
+        material.set_uniforms(shader, &self.textures, &self.white_texture)?;
+        if let (true, Some(debug)) = (self.debug_view.uv_checker, &self.debug_draw) {
+            material::set_checker_uniforms(shader, debug.checker())?;
+        }
+        match primitive.skin {
+            // The joints place skinned primitives, so there is only ever one instance.
+            Some(skin) => {
+                self.skins[skin].set_uniforms(shader)?;
+                unsafe { primitive.draw(shader)? };
+                Ok(1)
+            }
+            None => {
+                for model_matrix in model_matrices {
+                    shader.set_uniform(
+                        "model_matrix",
+                        UniformValue::Matrix4(glm::value_ptr(model_matrix).try_into().unwrap()),
+                    )?;
+                    unsafe { primitive.draw(shader)? };
+                }
+                Ok(model_matrices.len())
+            }
+        }
+    }
+}
+
+/// Source-over blending, with destination alpha accumulating coverage so offscreen images come
+/// out opaque where the background was.
+fn blended_mode() -> blend::BlendMode {
+    use blend::*;
+    let source_alpha = BlendFactor::Color {
+        input: BlendInput::Source,
+        channel: BlendChannel::Alpha,
+        is_inverse: false,
+    };
+    let inverse_source_alpha = BlendFactor::Color {
+        input: BlendInput::Source,
+        channel: BlendChannel::Alpha,
+        is_inverse: true,
+    };
+    // golem 0.1 passes these to glBlendFuncSeparate as (source_color, source_alpha,
+    // destination_alpha, destination_color), while GL expects (srcRGB, dstRGB, srcAlpha,
+    // dstAlpha), so the fields are filled in that order.
+    BlendMode {
+        function: BlendFunction::Separate {
+            source_color: source_alpha,
+            source_alpha: inverse_source_alpha,
+            destination_alpha: BlendFactor::One,
+            destination_color: inverse_source_alpha,
+        },
+        ..BlendMode::default()
+    }
+}
+
+pub struct Scene {
+    pub name: Option<String>,
+    /// The root nodes.
+    pub nodes: Vec<usize>,
+}
+
+pub struct Node {
+    pub name: Option<String>,
+    pub translation: glm::Vec3,
+    pub rotation: glm::Quat,
+    pub scale: glm::Vec3,
+    pub weights: Vec<f32>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    global_matrix: glm::Mat4,
+}
+
+impl Node {
+    fn from_gltf_node(node: &gltf::Node) -> Node {
+        let (t, r, s) = node.transform().decomposed();
+        Node {
+            name: node.name().map(str::to_owned),
+            translation: glm::make_vec3(&t),
+            rotation: glm::quat(r[0], r[1], r[2], r[3]),
+            scale: glm::make_vec3(&s),
+            weights: node
+                .weights()
+                .or_else(|| node.mesh().and_then(|m| m.weights()))
+                .map(<[f32]>::to_vec)
+                .unwrap_or_default(),
+            parent: None,
+            children: Vec::new(),
+            mesh: node.mesh().map(|m| m.index()),
+            global_matrix: glm::identity(),
+        }
+    }
+
+    pub fn local_matrix(&self) -> glm::Mat4 {
+        glm::translation(&self.translation)
+            * glm::quat_to_mat4(&self.rotation)
+            * glm::scaling(&self.scale)
+    }
+
+    pub fn global_matrix(&self) -> &glm::Mat4 {
+        &self.global_matrix
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[usize] {
+        &self.children
+    }
+
+    pub fn mesh(&self) -> Option<usize> {
+        self.mesh
+    }
+}
+
+/// CPU-side copy of a primitive's vertex attributes.
+#[derive(Clone, Default)]
+pub struct Vertices {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tangents: Vec<[f32; 3]>,
+    /// `TEXCOORD_0` and `TEXCOORD_1`.
+    pub tex_coords: [Vec<[f32; 2]>; 2],
+    pub joints: Vec<[f32; 4]>,
+    pub weights: Vec<[f32; 4]>,
+}
+
+impl Vertices {
+    /// Floats per attribute in the output of [`Vertices::interleave`].
+    fn layout(&self) -> &'static [i32] {
+        if self.joints.is_empty() {
+            &[3, 3, 2, 2]
+        } else {
+            &[3, 3, 2, 2, 4, 4]
+        }
+    }
+
+    /// Interleave the attributes in the layout the shaders expect.
+    fn interleave(&self) -> Vec<f32> {
+        let mut data = Vec::new();
+        for (i, (p, n)) in self.positions.iter().zip(&self.normals).enumerate() {
+            data.extend_from_slice(p);
+            data.extend_from_slice(n);
+            for tex_coords in &self.tex_coords {
+                data.extend_from_slice(tex_coords.get(i).unwrap_or(&[0.0; 2]));
+            }
+            if !self.joints.is_empty() {
+                data.extend_from_slice(&self.joints[i]);
+                data.extend_from_slice(&self.weights[i]);
+            }
+        }
+        data
+    }
+}
+
+/// A file read into memory, before anything is uploaded.
+struct Parsed {
+    document: gltf::Document,
+    buffers: Vec<gltf::buffer::Data>,
+    images: Vec<gltf::image::Data>,
+    /// The file's own JSON, with what gltf doesn't parse.
+    json: Option<gltf::json::Value>,
+    optimization: Option<OptimizationStats>,
+}
+
+impl Parsed {
+    /// `path` is where `data` was read from, for resolving the files it refers to.
+    fn from_slice(data: &[u8], path: Option<&std::path::Path>) -> Result<Parsed, gltf::Error> {
+        // Compressed geometry is decoded up front into a self-contained binary file.
+        let decompressed = compression::decompress(data, path)?;
+        let (data, path) = match &decompressed {
+            Some(decompressed) => (decompressed.as_slice(), None),
+            None => (data, path),
+        };
+        let (document, buffers, images) = match (gltf::import_slice(data), path) {
+            (Err(gltf::Error::ExternalReferenceInSliceImport), Some(path)) => gltf::import(path),
+            (imported, _) => imported,
+        }?;
+        // Rather than draw the file wrong.
+        let unsupported = validation::unsupported_required_extensions(&document);
+        if !unsupported.is_empty() {
+            return Err(gltf::Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported required extensions: {}",
+                    unsupported.join(", ")
+                ),
+            )));
+        }
+        Ok(Parsed {
+            document,
+            buffers,
+            images,
+            json: gpu_instancing::json_of(data),
+            optimization: None,
+        })
+    }
+
+    fn optimize(mut self, options: &Optimization) -> Result<Parsed, gltf::Error> {
+        let json = match &mut self.json {
+            Some(json) => json,
+            None => return Ok(self),
+        };
+        let stats = optimize::optimize(json, &self.document, &mut self.buffers, options);
+        let root =
+            gltf::json::deserialize::from_value(json.clone()).map_err(gltf::Error::Deserialize)?;
+        self.document = gltf::Document::from_json(root)?;
+        self.optimization = Some(stats);
+        Ok(self)
+    }
+}
+
+/// The meshes of a model still to be uploaded, one node at a time, then its textures.
+struct PendingMeshes {
+    document: gltf::Document,
+    buffers: std::rc::Rc<Vec<gltf::buffer::Data>>,
+    gpu_instances: Vec<Option<Vec<glm::Mat4>>>,
+    /// Nodes with a mesh, in file order.
+    mesh_nodes: Vec<usize>,
+    uploaded: usize,
+    /// Index in `Gltf::primitives` of each static (mesh, primitive) uploaded so far.
+    shared: HashMap<(usize, usize), usize>,
+    /// Dropped once the textures are uploaded.
+    images: Vec<gltf::image::Data>,
+    textures_uploaded: usize,
+}
+
+impl PendingMeshes {
+    fn len(&self) -> usize {
+        self.mesh_nodes.len()
+    }
+
+    fn textures_len(&self) -> usize {
+        self.document.textures().len()
+    }
+
+    /// Upload the mesh of the next node into `gltf`, or once there are none left its next
+    /// texture, returning `false` once everything is uploaded.
+    fn upload_next(&mut self, gltf: &mut Gltf, ctx: &Context) -> bool {
+        let node = match self.mesh_nodes.get(self.uploaded) {
+            Some(&index) => self.document.nodes().nth(index).unwrap(),
+            None => return self.upload_next_texture(gltf, ctx),
+        };
+        self.uploaded += 1;
+        let mesh = node.mesh().unwrap();
+        // Instancing is ignored on skinned meshes, which the joints place.
+        let transforms = match self.gpu_instances.get(node.index()).cloned().flatten() {
+            Some(transforms) if node.skin().is_none() => transforms,
+            _ => vec![glm::identity()],
+        };
+        let instances: Vec<Instance> = transforms
+            .into_iter()
+            .map(|transform| Instance {
+                node: node.index(),
+                transform,
+            })
+            .collect();
+        // Static meshes are uploaded once and drawn for every node using them. Skinned and
+        // morphed ones get their own copy per node, since their vertices depend on the node.
+        let shareable = node.skin().is_none()
+            && mesh
+                .primitives()
+                .all(|p| p.morph_targets().next().is_none());
+
+        for primitive in mesh.primitives() {
+            if !gltf
+                .validation
+                .is_renderable(mesh.index(), primitive.index())
+            {
+                continue;
+            }
+            let key = (mesh.index(), primitive.index());
+            if let Some(&i) = self.shared.get(&key).filter(|_| shareable) {
+                gltf.primitives[i]
+                    .instances
+                    .extend(instances.iter().cloned());
+                continue;
+            }
+            let mut loaded = Primitive::from_gltf_primitive(
+                ctx,
+                &primitive,
+                &self.buffers,
+                &node,
+                &gltf.validation,
+            );
+            loaded.instances = instances.clone();
+            if shareable {
+                self.shared.insert(key, gltf.primitives.len());
+            }
+            gltf.primitives.push(loaded);
+        }
+        true
+    }
+
+    fn upload_next_texture(&mut self, gltf: &mut Gltf, ctx: &Context) -> bool {
+        let texture = match self.document.textures().nth(self.textures_uploaded) {
+            Some(texture) => texture,
+            None => {
+                self.images = Vec::new();
+                return false;
+            }
+        };
+        self.textures_uploaded += 1;
+        gltf.textures[texture.index()] = match texture::upload(ctx, &texture, &self.images) {
+            Ok(uploaded) => Some(uploaded),
+            Err(e) => {
+                gltf.validation.push(Issue::TextureNotUploaded {
+                    texture: texture.index(),
+                    error: e.to_string(),
+                });
+                None
+            }
+        };
+        true
+    }
+}
+
+/// A node drawing a primitive, once for each `EXT_mesh_gpu_instancing` transform if it has any.
+#[derive(Clone)]
+struct Instance {
+    node: usize,
+    /// Relative to the node.
+    transform: glm::Mat4,
 }
 
 pub struct Primitive {
@@ -97,28 +1503,88 @@ pub struct Primitive {
     eb: ElementBuffer,
     indices_len: usize,
     mode: GeometryMode,
+    mesh: usize,
+    /// Index of the primitive in the mesh.
+    mesh_primitive: usize,
+    triangles: Vec<[u32; 3]>,
+    /// Built on the first pick.
+    bvh: Option<picking::Bvh>,
+    instances: Vec<Instance>,
+    skin: Option<usize>,
+    /// Indices into the skin's joints of those with any weight on this primitive.
+    joints: Vec<usize>,
+    vertices: Vertices,
+    morph: Option<(MorphTargets, Vertices)>,
+    bounds: Option<Aabb>,
+    material: Option<usize>,
 }
 impl Primitive {
     pub fn from_gltf_primitive(
         ctx: &Context,
         primitive: &gltf::Primitive,
-        buffer: &[u8],
+        buffers: &[gltf::buffer::Data],
+        node: &gltf::Node,
+        validation: &ValidationReport,
     ) -> Primitive {
-        let reader = primitive.reader(|_| Some(buffer));
-
-        let mut vertices = Vec::new();
-        if let Some(pos) = reader.read_positions() {
-            if let Some(normal) = reader.read_normals() {
-                for (p, n) in pos.into_iter().zip(normal.into_iter()) {
-                    vertices.extend_from_slice(&p);
-                    vertices.extend_from_slice(&n);
-                }
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()].0[..]));
+
+        let mut vertices = Vertices::default();
+        // Through `accessor` rather than `reader`, which doesn't dequantize.
+        let read_vec3 = |semantic| {
+            primitive
+                .get(&semantic)
+                .and_then(|a| accessor::read_vec3(&a, buffers))
+        };
+        if let Some(positions) = read_vec3(gltf::Semantic::Positions) {
+            vertices.positions = positions;
+        }
+        vertices.normals = read_vec3(gltf::Semantic::Normals)
+            .unwrap_or_else(|| vec![[0.0; 3]; vertices.positions.len()]);
+        if let Some(tangents) = read_vec3(gltf::Semantic::Tangents) {
+            vertices.tangents = tangents;
+        }
+        for (set, tex_coords) in vertices.tex_coords.iter_mut().enumerate() {
+            let floats = primitive
+                .get(&gltf::Semantic::TexCoords(set as u32))
+                .and_then(|a| accessor::read_floats(&a, buffers));
+            if let Some(floats) = floats {
+                *tex_coords = floats.chunks_exact(2).map(|c| [c[0], c[1]]).collect();
             }
         }
-        let indices: Vec<u32> = reader.read_indices().unwrap().into_u32().collect();
+        let skin = node.skin().filter(|s| validation.is_skin_usable(s.index()));
+        let skin = match (skin, reader.read_joints(0), reader.read_weights(0)) {
+            (Some(skin), Some(joints), Some(weights)) => {
+                vertices.joints = joints
+                    .into_u16()
+                    .map(|j| [j[0] as f32, j[1] as f32, j[2] as f32, j[3] as f32])
+                    .collect();
+                vertices.weights = weights.into_f32().collect();
+                Some(skin.index())
+            }
+            _ => None,
+        };
+        let mut joints: Vec<usize> = vertices
+            .joints
+            .iter()
+            .zip(&vertices.weights)
+            .flat_map(|(j, w)| {
+                (0..4)
+                    .filter(move |&k| w[k] != 0.0)
+                    .map(move |k| j[k] as usize)
+            })
+            .collect();
+        joints.sort_unstable();
+        joints.dedup();
+
+        let morph = MorphTargets::from_reader(&reader).map(|targets| (targets, vertices.clone()));
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.positions.len() as u32).collect(),
+        };
         let mut vb = VertexBuffer::new(ctx).unwrap();
         let mut eb = ElementBuffer::new(ctx).unwrap();
-        vb.set_data(&vertices);
+        vb.set_data(&vertices.interleave());
         eb.set_data(&indices);
 
         let mode = match primitive.mode() {
@@ -136,10 +1602,97 @@ impl Primitive {
             eb,
             indices_len: indices.len(),
             mode,
+            mesh: node.mesh().map_or(0, |m| m.index()),
+            mesh_primitive: primitive.index(),
+            triangles: picking::triangles(&indices, primitive.mode()),
+            bvh: None,
+            instances: vec![Instance {
+                node: node.index(),
+                transform: glm::identity(),
+            }],
+            skin,
+            joints,
+            bounds: primitive
+                .get(&gltf::Semantic::Positions)
+                .and_then(|a| Aabb::from_accessor(&a))
+                .or_else(|| Aabb::from_points(&vertices.positions)),
+            vertices,
+            morph,
+            material: primitive.material().index(),
+        }
+    }
+
+    /// Bounds of the vertex positions in the space of the nodes (or instances) drawing the
+    /// primitive, or of the skin's bind pose if it is skinned. Follows the morph weights last
+    /// applied.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    /// Vertex attributes as currently drawn, including any applied morph weights.
+    pub fn vertices(&self) -> &Vertices {
+        match &self.morph {
+            Some((_, morphed)) => morphed,
+            None => &self.vertices,
         }
     }
 
+    /// Positions and normals moved by the skin's `joint_matrices`, as the skinned shader does.
+    fn skinned_vertices(&self, joint_matrices: &[glm::Mat4]) -> (Vec<[f32; 3]>, Vec<[f32; 3]>) {
+        let vertices = self.vertices();
+        let identity = glm::identity();
+        let mut positions = Vec::with_capacity(vertices.positions.len());
+        let mut normals = Vec::with_capacity(vertices.normals.len());
+        for (i, (p, n)) in vertices.positions.iter().zip(&vertices.normals).enumerate() {
+            let (joints, weights) = (vertices.joints[i], vertices.weights[i]);
+            let skin_matrix: glm::Mat4 = (0..4)
+                .map(|k| joint_matrices.get(joints[k] as usize).unwrap_or(&identity) * weights[k])
+                .sum();
+            let p = skin_matrix * glm::vec4(p[0], p[1], p[2], 1.0);
+            let n = skin_matrix * glm::vec4(n[0], n[1], n[2], 0.0);
+            positions.push([p.x, p.y, p.z]);
+            normals.push([n.x, n.y, n.z]);
+        }
+        (positions, normals)
+    }
+
+    /// Displace the vertices by the morph `weights` and re-upload them if they changed.
+    pub fn update_morph(&mut self, weights: &[f32]) {
+        if let Some((targets, morphed)) = &mut self.morph {
+            if targets.needs_update(weights) {
+                targets.apply(weights, &self.vertices, morphed);
+                self.vb.set_data(&morphed.interleave());
+                self.bounds = Aabb::from_points(&morphed.positions).or(self.bounds);
+            }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Same as [`ShaderProgram::draw`]: `shader` must be bound, and its attributes must match
+    /// the layout written by [`Vertices::interleave`].
     pub unsafe fn draw(&self, shader: &ShaderProgram) -> Result<(), GolemError> {
         shader.draw(&self.vb, &self.eb, 0..self.indices_len, self.mode)
     }
+
+    /// # Safety
+    ///
+    /// Same as [`Instancing::draw`], with `shader` taking the attributes written by
+    /// [`Vertices::interleave`] followed by the model matrix columns.
+    pub unsafe fn draw_instanced(
+        &self,
+        instancing: &Instancing,
+        shader: &ShaderProgram,
+        model_matrices: &[glm::Mat4],
+    ) -> Result<(), GolemError> {
+        instancing.draw(
+            shader,
+            &self.vb,
+            &self.eb,
+            0..self.indices_len,
+            self.mode,
+            self.vertices.layout(),
+            model_matrices,
+        )
+    }
 }
@@ -0,0 +1,115 @@
+//! Reading accessors whatever their component type, which gltf's reader leaves to the caller: it
+//! reads positions, normals and tangents as floats even when `KHR_mesh_quantization` stores them
+//! as integers.
+
+use std::convert::TryInto;
+
+use gltf::accessor::{sparse::IndexType, DataType};
+
+/// The accessor's elements, `accessor.size()` bytes each without padding, with sparse values
+/// applied. `None` if any of it lies outside the buffers.
+pub fn read_elements(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Option<Vec<u8>> {
+    let size = accessor.size();
+    let count = accessor.count();
+    let mut data = match accessor.view() {
+        Some(view) => {
+            let stride = view.stride().unwrap_or(size);
+            let bytes = view_bytes(&view, buffers)?;
+            let start = accessor.offset();
+            let mut data = Vec::with_capacity(size * count);
+            for i in 0..count {
+                data.extend_from_slice(bytes.get(start + i * stride..start + i * stride + size)?);
+            }
+            data
+        }
+        None => vec![0; size * count],
+    };
+
+    if let Some(sparse) = accessor.sparse() {
+        let indices = sparse.indices();
+        let index_bytes = view_bytes(&indices.view(), buffers)?;
+        let values = sparse.values();
+        let value_bytes = view_bytes(&values.view(), buffers)?;
+        for i in 0..sparse.count() as usize {
+            let index = match indices.index_type() {
+                IndexType::U8 => *index_bytes.get(indices.offset() as usize + i)? as usize,
+                IndexType::U16 => {
+                    let at = indices.offset() as usize + i * 2;
+                    u16::from_le_bytes(index_bytes.get(at..at + 2)?.try_into().ok()?) as usize
+                }
+                IndexType::U32 => {
+                    let at = indices.offset() as usize + i * 4;
+                    u32::from_le_bytes(index_bytes.get(at..at + 4)?.try_into().ok()?) as usize
+                }
+            };
+            let at = values.offset() as usize + i * size;
+            data.get_mut(index * size..(index + 1) * size)?
+                .copy_from_slice(value_bytes.get(at..at + size)?);
+        }
+    }
+    Some(data)
+}
+
+fn view_bytes<'a>(
+    view: &gltf::buffer::View,
+    buffers: &'a [gltf::buffer::Data],
+) -> Option<&'a [u8]> {
+    let buffer = buffers.get(view.buffer().index())?;
+    buffer.0.get(view.offset()..view.offset() + view.length())
+}
+
+/// The components of every element as floats, mapping normalized integers to 0..1 or -1..1 and
+/// converting the others as they are.
+pub fn read_floats(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Option<Vec<f32>> {
+    let data = read_elements(accessor, buffers)?;
+    let normalized = accessor.normalized();
+    let floats = match accessor.data_type() {
+        DataType::F32 => data
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect(),
+        DataType::I8 => data
+            .iter()
+            .map(|&c| c as i8 as f32)
+            .map(|c| if normalized { (c / 127.0).max(-1.0) } else { c })
+            .collect(),
+        DataType::U8 => data
+            .iter()
+            .map(|&c| c as f32)
+            .map(|c| if normalized { c / 255.0 } else { c })
+            .collect(),
+        DataType::I16 => data
+            .chunks_exact(2)
+            .map(|c| i16::from_le_bytes([c[0], c[1]]) as f32)
+            .map(|c| {
+                if normalized {
+                    (c / 32767.0).max(-1.0)
+                } else {
+                    c
+                }
+            })
+            .collect(),
+        DataType::U16 => data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]) as f32)
+            .map(|c| if normalized { c / 65535.0 } else { c })
+            .collect(),
+        DataType::U32 => data
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f32)
+            .collect(),
+    };
+    Some(floats)
+}
+
+pub fn read_vec3(
+    accessor: &gltf::Accessor,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Vec<[f32; 3]>> {
+    let floats = read_floats(accessor, buffers)?;
+    let n = accessor.dimensions().multiplicity();
+    if n < 3 {
+        return None;
+    }
+    Some(floats.chunks_exact(n).map(|c| [c[0], c[1], c[2]]).collect())
+}
@@ -0,0 +1,527 @@
+use gltf::animation::{Interpolation, Property};
+use nalgebra_glm as glm;
+
+use super::{Node, ValidationReport};
+use crate::time::*;
+
+pub struct Animation {
+    name: Option<String>,
+    channels: Vec<Channel>,
+    duration: f32,
+}
+
+impl Animation {
+    /// Channels whose sampler reads outside the buffers, per `validation`, are dropped.
+    pub fn from_gltf_animation(
+        animation: &gltf::Animation,
+        buffers: &[gltf::buffer::Data],
+        validation: &ValidationReport,
+    ) -> Animation {
+        let channels: Vec<Channel> = animation
+            .channels()
+            .filter(|c| {
+                let sampler = c.sampler();
+                validation.is_accessor_readable(sampler.input().index())
+                    && validation.is_accessor_readable(sampler.output().index())
+            })
+            .filter_map(|c| Channel::from_gltf_channel(&c, buffers))
+            .collect();
+        let duration = channels
+            .iter()
+            .filter_map(|c| c.times.last().copied())
+            .fold(0.0, f32::max);
+        Animation {
+            name: animation.name().map(str::to_owned),
+            channels,
+            duration,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Length of the clip in seconds.
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    /// Write the values of every channel at `time` into the matching nodes of `pose`.
+    fn sample(&self, time: f32, pose: &mut [NodePose]) {
+        for channel in &self.channels {
+            let value = channel.sample(time);
+            let target = &mut pose[channel.node];
+            match channel.property {
+                Property::Translation => target.translation = glm::make_vec3(&value),
+                Property::Rotation => target.rotation = glm::make_quat(&value),
+                Property::Scale => target.scale = glm::make_vec3(&value),
+                Property::MorphTargetWeights => target.weights = value,
+            }
+        }
+    }
+}
+
+struct Channel {
+    node: usize,
+    property: Property,
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    // Flattened output values, `stride` floats per element. Cubic-spline samplers store three
+    // elements (in-tangent, value, out-tangent) per keyframe.
+    values: Vec<f32>,
+    stride: usize,
+}
+
+impl Channel {
+    fn from_gltf_channel(
+        channel: &gltf::animation::Channel,
+        buffers: &[gltf::buffer::Data],
+    ) -> Option<Channel> {
+        use gltf::animation::util::ReadOutputs;
+
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()].0[..]));
+        let times: Vec<f32> = reader.read_inputs()?.collect();
+        if times.is_empty() {
+            return None;
+        }
+        let values: Vec<f32> = match reader.read_outputs()? {
+            ReadOutputs::Translations(t) => t.flatten().collect(),
+            ReadOutputs::Rotations(r) => r.into_f32().flatten().collect(),
+            ReadOutputs::Scales(s) => s.flatten().collect(),
+            ReadOutputs::MorphTargetWeights(w) => w.into_f32().collect(),
+        };
+        let interpolation = channel.sampler().interpolation();
+        let elements_per_key = match interpolation {
+            Interpolation::CubicSpline => 3,
+            _ => 1,
+        };
+        let stride = values.len() / (times.len() * elements_per_key);
+        if stride == 0 {
+            return None;
+        }
+
+        Some(Channel {
+            node: channel.target().node().index(),
+            property: channel.target().property(),
+            interpolation,
+            times,
+            values,
+            stride,
+        })
+    }
+
+    fn element(&self, index: usize) -> &[f32] {
+        &self.values[index * self.stride..(index + 1) * self.stride]
+    }
+
+    /// The keyframe value itself, skipping the cubic-spline tangents.
+    fn key_value(&self, key: usize) -> &[f32] {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.element(key * 3 + 1),
+            _ => self.element(key),
+        }
+    }
+
+    fn sample(&self, time: f32) -> Vec<f32> {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return self.key_value(0).to_vec();
+        }
+        if time >= self.times[last] {
+            return self.key_value(last).to_vec();
+        }
+
+        let next = self.times.partition_point(|&t| t <= time);
+        let prev = next - 1;
+        let delta = self.times[next] - self.times[prev];
+        let t = (time - self.times[prev]) / delta;
+
+        match self.interpolation {
+            Interpolation::Step => self.key_value(prev).to_vec(),
+            Interpolation::Linear => {
+                let a = self.key_value(prev);
+                let b = self.key_value(next);
+                if let Property::Rotation = self.property {
+                    let q = slerp(&glm::make_quat(a), &glm::make_quat(b), t);
+                    vec![q.coords.x, q.coords.y, q.coords.z, q.coords.w]
+                } else {
+                    a.iter().zip(b).map(|(a, b)| a + (b - a) * t).collect()
+                }
+            }
+            Interpolation::CubicSpline => {
+                let v0 = self.element(prev * 3 + 1);
+                let b0 = self.element(prev * 3 + 2);
+                let a1 = self.element(next * 3);
+                let v1 = self.element(next * 3 + 1);
+                let t2 = t * t;
+                let t3 = t2 * t;
+                let mut value: Vec<f32> = (0..self.stride)
+                    .map(|i| {
+                        (2.0 * t3 - 3.0 * t2 + 1.0) * v0[i]
+                            + (t3 - 2.0 * t2 + t) * delta * b0[i]
+                            + (-2.0 * t3 + 3.0 * t2) * v1[i]
+                            + (t3 - t2) * delta * a1[i]
+                    })
+                    .collect();
+                if let Property::Rotation = self.property {
+                    let len = value.iter().map(|v| v * v).sum::<f32>().sqrt();
+                    value.iter_mut().for_each(|v| *v /= len);
+                }
+                value
+            }
+        }
+    }
+}
+
+fn slerp(a: &glm::Quat, b: &glm::Quat, t: f32) -> glm::Quat {
+    // Take the shortest path between the two rotations.
+    let b = if glm::quat_dot(a, b) < 0.0 { -*b } else { *b };
+    glm::quat_normalize(&glm::quat_slerp(a, &b, t))
+}
+
+/// Local transform and morph weights of a single node.
+#[derive(Clone)]
+pub struct NodePose {
+    pub translation: glm::Vec3,
+    pub rotation: glm::Quat,
+    pub scale: glm::Vec3,
+    pub weights: Vec<f32>,
+}
+
+impl NodePose {
+    pub fn from_node(node: &Node) -> NodePose {
+        NodePose {
+            translation: node.translation,
+            rotation: node.rotation,
+            scale: node.scale,
+            weights: node.weights.clone(),
+        }
+    }
+
+    pub fn apply_to(&self, node: &mut Node) {
+        node.translation = self.translation;
+        node.rotation = self.rotation;
+        node.scale = self.scale;
+        node.weights.clone_from(&self.weights);
+    }
+
+    fn blend(&mut self, other: &NodePose, weight: f32) {
+        self.translation += (other.translation - self.translation) * weight;
+        self.rotation = slerp(&self.rotation, &other.rotation, weight);
+        self.scale += (other.scale - self.scale) * weight;
+        for (a, b) in self.weights.iter_mut().zip(&other.weights) {
+            *a += (b - *a) * weight;
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ClipState {
+    clip: usize,
+    time: f32,
+}
+
+struct CrossFade {
+    elapsed: f32,
+    duration: f32,
+}
+
+/// Plays back up to two animation clips at a time, blending between them.
+pub struct AnimationPlayer {
+    primary: Option<ClipState>,
+    secondary: Option<ClipState>,
+    blend_weight: f32,
+    cross_fade: Option<CrossFade>,
+    speed: f32,
+    looping: bool,
+    playing: bool,
+    last_update: Option<Instant>,
+}
+
+impl AnimationPlayer {
+    pub fn new() -> AnimationPlayer {
+        AnimationPlayer {
+            primary: None,
+            secondary: None,
+            blend_weight: 0.0,
+            cross_fade: None,
+            speed: 1.0,
+            looping: true,
+            playing: false,
+            last_update: None,
+        }
+    }
+
+    /// Start playing `clip` from the beginning, dropping any blend in progress. Clips that
+    /// don't exist play as the rest pose.
+    pub fn play(&mut self, clip: usize) {
+        self.primary = Some(ClipState { clip, time: 0.0 });
+        self.secondary = None;
+        self.blend_weight = 0.0;
+        self.cross_fade = None;
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn resume(&mut self) {
+        self.playing = self.primary.is_some();
+        self.last_update = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.primary = None;
+        self.secondary = None;
+        self.cross_fade = None;
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Jump to `time` seconds in the playing clips.
+    pub fn seek(&mut self, time: f32) {
+        for state in self.primary.iter_mut().chain(self.secondary.iter_mut()) {
+            state.time = time;
+        }
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Playback rate, where 1.0 is normal speed and negative values play backwards.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn current_clip(&self) -> Option<usize> {
+        self.primary.map(|s| s.clip)
+    }
+
+    pub fn current_time(&self) -> Option<f32> {
+        self.primary.map(|s| s.time)
+    }
+
+    /// Play `clip` on top of the current clip with a fixed blend weight in `0.0..=1.0`.
+    pub fn blend_with(&mut self, clip: usize, weight: f32) {
+        let time = match self.secondary {
+            Some(state) if state.clip == clip => state.time,
+            _ => self.primary.map_or(0.0, |s| s.time),
+        };
+        self.secondary = Some(ClipState { clip, time });
+        self.blend_weight = weight.clamp(0.0, 1.0);
+        self.cross_fade = None;
+    }
+
+    /// Fade from the current clip to `clip` over `duration`.
+    pub fn cross_fade(&mut self, clip: usize, duration: Duration) {
+        if self.primary.is_none() {
+            self.play(clip);
+            return;
+        }
+        self.secondary = Some(ClipState { clip, time: 0.0 });
+        self.blend_weight = 0.0;
+        self.cross_fade = Some(CrossFade {
+            elapsed: 0.0,
+            duration: duration.as_secs_f32(),
+        });
+        self.playing = true;
+    }
+
+    /// Advance the playing clips to `now`.
+    pub fn update(&mut self, now: Instant, animations: &[Animation]) {
+        let delta = match self.last_update {
+            Some(last) if self.playing => (now - last).as_secs_f32(),
+            _ => 0.0,
+        };
+        self.last_update = Some(now);
+        if delta == 0.0 {
+            return;
+        }
+
+        let mut finished = true;
+        for state in self.primary.iter_mut().chain(self.secondary.iter_mut()) {
+            let duration = animations.get(state.clip).map_or(0.0, |a| a.duration);
+            state.time += delta * self.speed;
+            if self.looping && duration > 0.0 {
+                state.time = state.time.rem_euclid(duration);
+                finished = false;
+            } else {
+                state.time = state.time.max(0.0).min(duration);
+                finished &= state.time == 0.0 || state.time == duration;
+            }
+        }
+
+        if let Some(fade) = &mut self.cross_fade {
+            fade.elapsed += delta;
+            if fade.elapsed >= fade.duration {
+                self.primary = self.secondary.take();
+                self.blend_weight = 0.0;
+                self.cross_fade = None;
+            } else {
+                self.blend_weight = fade.elapsed / fade.duration;
+                finished = false;
+            }
+        }
+
+        if finished {
+            self.playing = false;
+        }
+    }
+
+    /// Compute the blended pose of the playing clips, starting from `rest_pose`.
+    pub fn pose(&self, animations: &[Animation], rest_pose: &[NodePose]) -> Option<Vec<NodePose>> {
+        let primary = self.primary?;
+        let mut pose = rest_pose.to_vec();
+        if let Some(animation) = animations.get(primary.clip) {
+            animation.sample(primary.time, &mut pose);
+        }
+        if let Some(secondary) = self.secondary {
+            if self.blend_weight > 0.0 {
+                let mut other = rest_pose.to_vec();
+                if let Some(animation) = animations.get(secondary.clip) {
+                    animation.sample(secondary.time, &mut other);
+                }
+                for (a, b) in pose.iter_mut().zip(&other) {
+                    a.blend(b, self.blend_weight);
+                }
+            }
+        }
+        Some(pose)
+    }
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> AnimationPlayer {
+        AnimationPlayer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(property: Property, interpolation: Interpolation, values: &[f32]) -> Channel {
+        let keys = match interpolation {
+            Interpolation::CubicSpline => 6,
+            _ => 2,
+        };
+        Channel {
+            node: 0,
+            property,
+            interpolation,
+            times: vec![1.0, 3.0],
+            values: values.to_vec(),
+            stride: values.len() / keys,
+        }
+    }
+
+    fn assert_near(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn clamps_outside_the_keyframes() {
+        let c = channel(
+            Property::Translation,
+            Interpolation::Linear,
+            &[0.0, 0.0, 0.0, 2.0, 4.0, 6.0],
+        );
+        assert_near(&c.sample(0.0), &[0.0, 0.0, 0.0]);
+        assert_near(&c.sample(5.0), &[2.0, 4.0, 6.0]);
+    }
+
+    #[test]
+    fn step_holds_the_previous_key() {
+        let c = channel(
+            Property::Scale,
+            Interpolation::Step,
+            &[1.0, 1.0, 1.0, 2.0, 2.0, 2.0],
+        );
+        assert_near(&c.sample(2.9), &[1.0, 1.0, 1.0]);
+        assert_near(&c.sample(3.0), &[2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn linear_interpolates_components() {
+        let c = channel(
+            Property::Translation,
+            Interpolation::Linear,
+            &[0.0, 0.0, 0.0, 2.0, 4.0, 6.0],
+        );
+        assert_near(&c.sample(1.5), &[0.5, 1.0, 1.5]);
+    }
+
+    #[test]
+    fn linear_rotations_take_the_short_way() {
+        // The same quarter turn about Y, once negated: halfway is an eighth of a turn.
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let c = channel(
+            Property::Rotation,
+            Interpolation::Linear,
+            &[0.0, 0.0, 0.0, 1.0, 0.0, -half, 0.0, -half],
+        );
+        let angle = std::f32::consts::PI / 8.0;
+        assert_near(&c.sample(2.0), &[0.0, angle.sin(), 0.0, angle.cos()]);
+    }
+
+    #[test]
+    fn cubic_spline_follows_the_tangents() {
+        // In-tangent, value, out-tangent per key. With flat tangents the curve eases in and out.
+        let c = channel(
+            Property::Translation,
+            Interpolation::CubicSpline,
+            &[
+                0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, //
+                0.0, 0.0, 0.0, 4.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+            ],
+        );
+        assert_near(&c.sample(2.0), &[2.0, 0.0, 0.0]);
+        assert_near(&c.sample(1.5), &[4.0 * 0.15625, 0.0, 0.0]);
+        assert_near(&c.sample(3.0), &[4.0, 0.0, 0.0]);
+
+        // A constant slope of 2 units a second.
+        let c = channel(
+            Property::Translation,
+            Interpolation::CubicSpline,
+            &[
+                2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, //
+                2.0, 0.0, 0.0, 4.0, 0.0, 0.0, 2.0, 0.0, 0.0,
+            ],
+        );
+        assert_near(&c.sample(1.5), &[1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn missing_clips_play_the_rest_pose() {
+        let rest = vec![NodePose {
+            translation: glm::vec3(1.0, 2.0, 3.0),
+            rotation: glm::quat_identity(),
+            scale: glm::vec3(1.0, 1.0, 1.0),
+            weights: Vec::new(),
+        }];
+        let mut player = AnimationPlayer::new();
+        player.play(3);
+        player.blend_with(7, 0.5);
+        let start = Instant::now();
+        player.update(start, &[]);
+        player.update(start + Duration::from_millis(100), &[]);
+        let pose = player.pose(&[], &rest).unwrap();
+        assert_eq!(pose[0].translation, rest[0].translation);
+    }
+}
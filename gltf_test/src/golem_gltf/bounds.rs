@@ -0,0 +1,126 @@
+use nalgebra_glm as glm;
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl Aabb {
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a [f32; 3]>) -> Option<Aabb> {
+        let mut points = points.into_iter().map(|p| glm::make_vec3(p));
+        let first = points.next()?;
+        Some(points.fold(
+            Aabb {
+                min: first,
+                max: first,
+            },
+            |aabb, p| Aabb {
+                min: glm::min2(&aabb.min, &p),
+                max: glm::max2(&aabb.max, &p),
+            },
+        ))
+    }
+
+    /// The `min` and `max` of a `VEC3` accessor, which the spec requires for positions.
+    pub fn from_accessor(accessor: &gltf::Accessor) -> Option<Aabb> {
+        let read = |value: Option<gltf::json::Value>| {
+            gltf::json::deserialize::from_value::<[f32; 3]>(value?).ok()
+        };
+        Some(Aabb {
+            min: glm::make_vec3(&read(accessor.min())?),
+            max: glm::make_vec3(&read(accessor.max())?),
+        })
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> glm::Vec3 {
+        self.max - self.min
+    }
+
+    /// Radius of the sphere around the box.
+    pub fn radius(&self) -> f32 {
+        glm::length(&self.size()) * 0.5
+    }
+
+    pub fn corners(&self) -> [glm::Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            glm::vec3(a.x, a.y, a.z),
+            glm::vec3(b.x, a.y, a.z),
+            glm::vec3(a.x, b.y, a.z),
+            glm::vec3(b.x, b.y, a.z),
+            glm::vec3(a.x, a.y, b.z),
+            glm::vec3(b.x, a.y, b.z),
+            glm::vec3(a.x, b.y, b.z),
+            glm::vec3(b.x, b.y, b.z),
+        ]
+    }
+
+    /// The box enclosing this one after transforming it by `matrix`.
+    pub fn transform(&self, matrix: &glm::Mat4) -> Aabb {
+        let corners: Vec<[f32; 3]> = self
+            .corners()
+            .iter()
+            .map(|c| {
+                let p = matrix * glm::vec4(c.x, c.y, c.z, 1.0);
+                [p.x, p.y, p.z]
+            })
+            .collect();
+        Aabb::from_points(&corners).unwrap()
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: glm::min2(&self.min, &other.min),
+            max: glm::max2(&self.max, &other.max),
+        }
+    }
+}
+
+/// The six clip planes of a view-projection matrix, pointing inwards.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    planes: [glm::Vec4; 6],
+}
+
+impl Frustum {
+    /// Planes of the region `matrix` maps into the clip volume (Gribb & Hartmann).
+    pub fn from_matrix(matrix: &glm::Mat4) -> Frustum {
+        let row = |i| glm::row(matrix, i);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [w + x, w - x, w + y, w - y, w + z, w - z],
+        }
+    }
+
+    /// Whether any part of `aabb` may be inside. Boxes near the corners can be reported as
+    /// visible when they aren't, but never the other way round.
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal.
+            let corner = glm::vec3(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            plane.xyz().dot(&corner) + plane.w >= 0.0
+        })
+    }
+}
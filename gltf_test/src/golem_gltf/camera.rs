@@ -0,0 +1,88 @@
+use nalgebra_glm as glm;
+
+use super::Node;
+
+pub enum Projection {
+    Perspective {
+        yfov: f32,
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+/// A camera defined in the glTF file, placed by the node it is attached to.
+pub struct Camera {
+    name: Option<String>,
+    node: usize,
+    projection: Projection,
+}
+
+impl Camera {
+    pub fn from_gltf_node(node: &gltf::Node) -> Option<Camera> {
+        let camera = node.camera()?;
+        let projection = match camera.projection() {
+            gltf::camera::Projection::Perspective(p) => Projection::Perspective {
+                yfov: p.yfov(),
+                aspect_ratio: p.aspect_ratio(),
+                znear: p.znear(),
+                zfar: p.zfar(),
+            },
+            gltf::camera::Projection::Orthographic(o) => Projection::Orthographic {
+                xmag: o.xmag(),
+                ymag: o.ymag(),
+                znear: o.znear(),
+                zfar: o.zfar(),
+            },
+        };
+        Some(Camera {
+            name: camera.name().or_else(|| node.name()).map(str::to_owned),
+            node: node.index(),
+            projection,
+        })
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    pub fn projection(&self) -> &Projection {
+        &self.projection
+    }
+
+    pub fn view_matrix(&self, nodes: &[Node]) -> glm::Mat4 {
+        glm::inverse(nodes[self.node].global_matrix())
+    }
+
+    /// Projection matrix for a viewport with the given aspect ratio. The aspect ratio stored in
+    /// the file is ignored so the image isn't stretched to fit the window.
+    pub fn projection_matrix(&self, viewport_aspect: f32) -> glm::Mat4 {
+        match self.projection {
+            Projection::Perspective {
+                yfov, znear, zfar, ..
+            } => glm::perspective(
+                viewport_aspect,
+                yfov,
+                znear,
+                // An infinite projection isn't available, so push the far plane out instead.
+                zfar.unwrap_or(znear * 100_000.0),
+            ),
+            Projection::Orthographic {
+                ymag, znear, zfar, ..
+            } => {
+                let xmag = ymag * viewport_aspect;
+                glm::ortho(-xmag, xmag, -ymag, ymag, znear, zfar)
+            }
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Files with geometry compressed by `EXT_meshopt_compression` or `KHR_draco_mesh_compression`,
+//! which gltf can't read, are decoded before it imports them.
+//!
+//! The decoded data takes the place of the compressed data in the JSON, the extensions are
+//! dropped, and everything (images in files of their own included) is packed into a `.glb` held
+//! in memory, so the rest of the loader, and exporting, see an ordinary file.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use gltf::json::Value;
+
+use super::{draco, export, meshopt};
+
+/// The data to import instead of `data`, or `None` if it doesn't use either extension. `path`
+/// is where `data` was read from, for resolving the files it refers to.
+pub fn decompress(data: &[u8], path: Option<&Path>) -> Result<Option<Vec<u8>>, gltf::Error> {
+    let (mut json, blob): (Value, _) = if data.starts_with(b"glTF") {
+        let glb = gltf::Glb::from_slice(data)?;
+        let json =
+            gltf::json::deserialize::from_slice(&glb.json).map_err(gltf::Error::Deserialize)?;
+        (json, glb.bin.map(Cow::into_owned))
+    } else {
+        let json = gltf::json::deserialize::from_slice(data).map_err(gltf::Error::Deserialize)?;
+        (json, None)
+    };
+    let used = |name: &str| {
+        json["extensionsUsed"]
+            .as_array()
+            .is_some_and(|names| names.iter().any(|n| n == name))
+    };
+    if !used(meshopt::EXTENSION) && !used(draco::EXTENSION) {
+        return Ok(None);
+    }
+
+    let base = path.map(|p| p.parent().unwrap_or_else(|| Path::new("./")));
+    let mut buffers = read_buffers(&json, blob, base)?;
+    embed_images(&mut json, &mut buffers, base)?;
+
+    let views = json["bufferViews"].as_array().map_or(0, Vec::len);
+    for index in 0..views {
+        let extension = match json["bufferViews"][index]["extensions"].get(meshopt::EXTENSION) {
+            Some(extension) => extension,
+            None => continue,
+        };
+        let data = meshopt::decode_buffer_view(extension, &buffers).map_err(|e| {
+            invalid(format!(
+                "can't decode {} buffer view {}: {}",
+                meshopt::EXTENSION,
+                index,
+                e
+            ))
+        })?;
+        let length = data.len();
+        let buffer = add_buffer(&mut json, &mut buffers, data);
+        let view = &mut json["bufferViews"][index];
+        view["buffer"] = Value::from(buffer);
+        view["byteLength"] = Value::from(length);
+        remove(view, "byteOffset");
+        remove_extension(view, meshopt::EXTENSION);
+    }
+
+    let meshes = json["meshes"].as_array().map_or(0, Vec::len);
+    for mesh in 0..meshes {
+        let primitives = json["meshes"][mesh]["primitives"]
+            .as_array()
+            .map_or(0, Vec::len);
+        for primitive in 0..primitives {
+            decode_draco_primitive(&mut json, &mut buffers, mesh, primitive).map_err(|e| {
+                invalid(format!(
+                    "can't decode {} mesh {} primitive {}: {}",
+                    draco::EXTENSION,
+                    mesh,
+                    primitive,
+                    e
+                ))
+            })?;
+        }
+    }
+
+    for key in &["extensionsUsed", "extensionsRequired"] {
+        if let Some(names) = json.get_mut(*key).and_then(Value::as_array_mut) {
+            names.retain(|n| n != meshopt::EXTENSION && n != draco::EXTENSION);
+            if names.is_empty() {
+                remove(&mut json, key);
+            }
+        }
+    }
+    // The fallback buffers meshopt leaves without data are among those dropped here.
+    let bin = export::pack_buffers(&mut json, &buffers, None);
+    let json = gltf::json::serialize::to_vec(&json).map_err(|e| gltf::Error::Io(e.into()))?;
+    let glb = gltf::Glb {
+        header: gltf::binary::Header {
+            magic: *b"glTF",
+            version: 2,
+            length: 0,
+        },
+        json: Cow::Owned(json),
+        bin: bin.map(Cow::Owned),
+    };
+    glb.to_vec().map(Some)
+}
+
+/// The data of every buffer, as gltf imports it. The fallback buffers of meshopt, which have
+/// none, are left empty.
+fn read_buffers(
+    json: &Value,
+    mut blob: Option<Vec<u8>>,
+    base: Option<&Path>,
+) -> Result<Vec<gltf::buffer::Data>, gltf::Error> {
+    let mut buffers = Vec::new();
+    for (index, buffer) in json["buffers"].as_array().into_iter().flatten().enumerate() {
+        let fallback = buffer["extensions"][meshopt::EXTENSION]["fallback"] == true;
+        // Only the first buffer can be the binary chunk of a .glb.
+        let bin = if index == 0 { blob.take() } else { None };
+        let data = match (buffer["uri"].as_str(), bin) {
+            (Some(uri), _) => read_uri(uri, base)?.1,
+            (None, Some(bin)) => bin,
+            (None, None) if fallback => Vec::new(),
+            (None, None) => return Err(gltf::Error::MissingBlob),
+        };
+        buffers.push(gltf::buffer::Data(data));
+    }
+    Ok(buffers)
+}
+
+/// Put the images stored in files of their own or in data URIs into buffers, since a `.glb`
+/// read from memory can't refer to anything else.
+fn embed_images(
+    json: &mut Value,
+    buffers: &mut Vec<gltf::buffer::Data>,
+    base: Option<&Path>,
+) -> Result<(), gltf::Error> {
+    let images = json["images"].as_array().map_or(0, Vec::len);
+    for index in 0..images {
+        let uri = match json["images"][index]["uri"].as_str() {
+            Some(uri) => uri.to_owned(),
+            None => continue,
+        };
+        let (media_type, data) = read_uri(&uri, base)?;
+        let extension = uri.rsplit('.').next().unwrap_or_default();
+        let mime_type = match media_type {
+            Some(media_type) if media_type == "image/png" || media_type == "image/jpeg" => {
+                media_type
+            }
+            _ if extension.eq_ignore_ascii_case("png") => "image/png".to_owned(),
+            _ if extension.eq_ignore_ascii_case("jpg")
+                || extension.eq_ignore_ascii_case("jpeg") =>
+            {
+                "image/jpeg".to_owned()
+            }
+            _ => match json["images"][index]["mimeType"].as_str() {
+                Some(mime_type) => mime_type.to_owned(),
+                None => return Err(gltf::Error::UnsupportedImageEncoding),
+            },
+        };
+        let length = data.len();
+        let buffer = add_buffer(json, buffers, data);
+        let mut view = Value::Null;
+        view["buffer"] = Value::from(buffer);
+        view["byteLength"] = Value::from(length);
+        let view = push(json, "bufferViews", view);
+        let image = &mut json["images"][index];
+        remove(image, "uri");
+        image["bufferView"] = Value::from(view);
+        image["mimeType"] = Value::from(mime_type);
+    }
+    Ok(())
+}
+
+/// The media type of a data URI, and the data `uri` refers to.
+fn read_uri(uri: &str, base: Option<&Path>) -> Result<(Option<String>, Vec<u8>), gltf::Error> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (media_type, data) = data
+            .split_once(";base64,")
+            .ok_or(gltf::Error::UnsupportedScheme)?;
+        let data = base64::decode(data).map_err(gltf::Error::Base64)?;
+        return Ok((Some(media_type.to_owned()), data));
+    }
+    let base = base.ok_or(gltf::Error::ExternalReferenceInSliceImport)?;
+    let data = std::fs::read(base.join(uri)).map_err(gltf::Error::Io)?;
+    Ok((None, data))
+}
+
+/// Replace a Draco compressed primitive's accessors with the data it decodes to.
+fn decode_draco_primitive(
+    json: &mut Value,
+    buffers: &mut Vec<gltf::buffer::Data>,
+    mesh: usize,
+    primitive: usize,
+) -> Result<(), String> {
+    let extension = &json["meshes"][mesh]["primitives"][primitive]["extensions"][draco::EXTENSION];
+    if extension.is_null() {
+        return Ok(());
+    }
+    let view = &json["bufferViews"][extension["bufferView"].as_u64().unwrap_or(u64::MAX) as usize];
+    let (offset, length) = (
+        view["byteOffset"].as_u64().unwrap_or(0) as usize,
+        view["byteLength"].as_u64().unwrap_or(0) as usize,
+    );
+    let data = buffers
+        .get(view["buffer"].as_u64().unwrap_or(0) as usize)
+        .and_then(|b| b.0.get(offset..offset + length))
+        .ok_or("its buffer view is out of range")?;
+    let decoded = draco::decode(data)?;
+
+    // The accessors keep the type the file declares them with, which the decoded values are
+    // converted to.
+    let attributes: Vec<(String, u64)> = extension["attributes"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, id)| Some((name.clone(), id.as_u64()?)))
+        .collect();
+    for (name, id) in attributes {
+        let accessor = json["meshes"][mesh]["primitives"][primitive]["attributes"][&name]
+            .as_u64()
+            .ok_or_else(|| format!("{} isn't one of its attributes", name))?
+            as usize;
+        let attribute = decoded
+            .attributes
+            .iter()
+            .find(|a| a.unique_id as u64 == id)
+            .ok_or_else(|| format!("it has no attribute {} for {}", id, name))?;
+        let json_accessor = &json["accessors"][accessor];
+        let component_type = json_accessor["componentType"].as_u64().unwrap_or(0) as u32;
+        let normalized = json_accessor["normalized"] == true;
+        let data = attribute.to_bytes(component_type, normalized)?;
+        set_accessor(json, buffers, accessor, data, decoded.points, ARRAY_BUFFER);
+    }
+
+    let points = decoded.points;
+    let indices = match json["meshes"][mesh]["primitives"][primitive]["indices"].as_u64() {
+        Some(indices) => indices as usize,
+        None => {
+            let accessor = push(json, "accessors", Value::Null);
+            json["meshes"][mesh]["primitives"][primitive]["indices"] = Value::from(accessor);
+            accessor
+        }
+    };
+    let (component_type, data) = if points <= u16::MAX as usize + 1 {
+        let data = decoded
+            .indices
+            .iter()
+            .flat_map(|&i| (i as u16).to_le_bytes());
+        (UNSIGNED_SHORT, data.collect())
+    } else {
+        let data = decoded.indices.iter().flat_map(|&i| i.to_le_bytes());
+        (UNSIGNED_INT, data.collect())
+    };
+    json["accessors"][indices]["componentType"] = Value::from(component_type);
+    json["accessors"][indices]["type"] = Value::from("SCALAR");
+    let count = decoded.indices.len();
+    set_accessor(json, buffers, indices, data, count, ELEMENT_ARRAY_BUFFER);
+
+    remove_extension(
+        &mut json["meshes"][mesh]["primitives"][primitive],
+        draco::EXTENSION,
+    );
+    Ok(())
+}
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+
+/// Point `accessor` at `data`, tightly packed in a buffer view of its own.
+fn set_accessor(
+    json: &mut Value,
+    buffers: &mut Vec<gltf::buffer::Data>,
+    accessor: usize,
+    data: Vec<u8>,
+    count: usize,
+    target: u32,
+) {
+    let length = data.len();
+    let buffer = add_buffer(json, buffers, data);
+    let mut view = Value::Null;
+    view["buffer"] = Value::from(buffer);
+    view["byteLength"] = Value::from(length);
+    view["target"] = Value::from(target);
+    let view = push(json, "bufferViews", view);
+    let accessor = &mut json["accessors"][accessor];
+    accessor["bufferView"] = Value::from(view);
+    accessor["count"] = Value::from(count);
+    remove(accessor, "byteOffset");
+    remove(accessor, "sparse");
+}
+
+fn add_buffer(json: &mut Value, buffers: &mut Vec<gltf::buffer::Data>, data: Vec<u8>) -> usize {
+    let mut buffer = Value::Null;
+    buffer["byteLength"] = Value::from(data.len());
+    buffers.push(gltf::buffer::Data(data));
+    push(json, "buffers", buffer)
+}
+
+fn invalid(message: String) -> gltf::Error {
+    gltf::Error::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        message,
+    ))
+}
+
+fn push(json: &mut Value, key: &str, value: Value) -> usize {
+    if !json[key].is_array() {
+        json[key] = Value::from(Vec::<Value>::new());
+    }
+    let array = json[key].as_array_mut().unwrap();
+    array.push(value);
+    array.len() - 1
+}
+
+fn remove(object: &mut Value, key: &str) {
+    if let Some(object) = object.as_object_mut() {
+        object.remove(key);
+    }
+}
+
+fn remove_extension(object: &mut Value, name: &str) {
+    if let Some(extensions) = object.get_mut("extensions") {
+        remove(extensions, name);
+        if extensions.as_object().is_some_and(|e| e.is_empty()) {
+            remove(object, "extensions");
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Debug views drawn over a model, to inspect assets that look wrong: lines in world space,
+//! rebuilt every frame so they follow animation, and a checker texture in place of base colors
+//! to show the texture coordinates.
+
+use std::convert::TryInto;
+
+use golem::*;
+use nalgebra_glm as glm;
+
+use super::Aabb;
+
+pub const WIREFRAME_COLOR: [f32; 3] = [1.0, 0.75, 0.1];
+pub const NORMAL_COLOR: [f32; 3] = [0.2, 0.4, 1.0];
+pub const TANGENT_COLOR: [f32; 3] = [1.0, 0.2, 0.2];
+pub const BOUNDS_COLOR: [f32; 3] = [0.0, 1.0, 1.0];
+pub const SKELETON_COLOR: [f32; 3] = [1.0, 0.2, 1.0];
+pub const HIGHLIGHT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// Which debug views [`super::Gltf::draw`] adds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DebugView {
+    /// Triangle edges over the shaded surfaces.
+    pub wireframe: bool,
+    /// Vertex normals, and tangents where the file has them. Skinned tangents aren't shown.
+    pub normals: bool,
+    /// A checker texture through `TEXCOORD_0` in place of every base color.
+    pub uv_checker: bool,
+    /// World-space bounds of each node with a mesh.
+    pub bounds: bool,
+    /// Each joint of the skins, linked to its parent joint, drawn through the surfaces.
+    pub skeleton: bool,
+    /// The X, Y and Z axes of each node in red, green and blue, drawn through the surfaces.
+    pub axes: bool,
+}
+
+impl DebugView {
+    /// The names [`DebugView::parse`] takes, in the order of the viewer's keys.
+    pub const NAMES: [&'static str; 6] =
+        ["wireframe", "normals", "uv", "bounds", "skeleton", "axes"];
+
+    /// From a comma-separated list of [`DebugView::NAMES`].
+    pub fn parse(list: &str) -> Option<DebugView> {
+        let mut view = DebugView::default();
+        for name in list.split(',') {
+            *view.get_mut(Self::NAMES.iter().position(|&n| n == name.trim())?) = true;
+        }
+        Some(view)
+    }
+
+    /// The view named `NAMES[index]`.
+    pub fn get_mut(&mut self, index: usize) -> &mut bool {
+        match index {
+            0 => &mut self.wireframe,
+            1 => &mut self.normals,
+            2 => &mut self.uv_checker,
+            3 => &mut self.bounds,
+            4 => &mut self.skeleton,
+            _ => &mut self.axes,
+        }
+    }
+
+    /// Whether any view draws lines.
+    pub fn has_lines(&self) -> bool {
+        self.wireframe || self.normals || self.bounds || self.skeleton || self.axes
+    }
+}
+
+/// Colored line segments, as pairs of interleaved positions and colors.
+#[derive(Default)]
+pub struct Lines {
+    vertices: Vec<f32>,
+}
+
+impl Lines {
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    pub fn line(&mut self, a: &glm::Vec3, b: &glm::Vec3, color: [f32; 3]) {
+        for p in &[a, b] {
+            self.vertices.extend_from_slice(&[p.x, p.y, p.z]);
+            self.vertices.extend_from_slice(&color);
+        }
+    }
+
+    /// The twelve edges of `bounds`.
+    pub fn aabb(&mut self, bounds: &Aabb, color: [f32; 3]) {
+        let corners = bounds.corners();
+        // Corners differing in exactly one coordinate, which is one bit of their index.
+        for i in 0..8 {
+            for bit in &[1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(&corners[i], &corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    /// A small cross marking `point`.
+    pub fn point(&mut self, point: &glm::Vec3, size: f32, color: [f32; 3]) {
+        for axis in 0..3 {
+            let mut offset = glm::vec3(0.0, 0.0, 0.0);
+            offset[axis] = size;
+            self.line(&(point - offset), &(point + offset), color);
+        }
+    }
+
+    /// The axes of `matrix` from its origin, `length` long.
+    pub fn axes(&mut self, matrix: &glm::Mat4, length: f32) {
+        let origin = (matrix * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz();
+        for axis in 0..3 {
+            let mut direction = glm::vec4(0.0, 0.0, 0.0, 0.0);
+            direction[axis] = 1.0;
+            let direction = (matrix * direction).xyz();
+            if direction.norm() > 0.0 {
+                let mut color = [0.0; 3];
+                color[axis] = 1.0;
+                self.line(&origin, &(origin + direction.normalize() * length), color);
+            }
+        }
+    }
+}
+
+/// What the debug views draw with. It's only created once a view is turned on.
+pub struct DebugDraw {
+    shader: ShaderProgram,
+    vb: VertexBuffer,
+    eb: ElementBuffer,
+    checker: Texture,
+}
+
+impl DebugDraw {
+    pub fn new(ctx: &Context) -> Result<DebugDraw, GolemError> {
+        let shader = ShaderProgram::new(
+            ctx,
+            ShaderDescription {
+                vertex_input: &[
+                    Attribute::new("vert_position", AttributeType::Vector(Dimension::D3)),
+                    Attribute::new("vert_color", AttributeType::Vector(Dimension::D3)),
+                ],
+                fragment_input: &[Attribute::new(
+                    "frag_color",
+                    AttributeType::Vector(Dimension::D3),
+                )],
+                uniforms: &[Uniform::new(
+                    "mvp_matrix",
+                    UniformType::Matrix(Dimension::D4),
+                )],
+                vertex_shader: r#" void main() {
+                    gl_Position = mvp_matrix * vec4(vert_position, 1.0);
+                    frag_color = vert_color;
+                }"#,
+                fragment_shader: r#" void main() {
+                    gl_FragColor = vec4(frag_color, 1.0);
+                }"#,
+            },
+        )?;
+        Ok(DebugDraw {
+            shader,
+            vb: VertexBuffer::new(ctx)?,
+            eb: ElementBuffer::new(ctx)?,
+            checker: checker(ctx)?,
+        })
+    }
+
+    /// The texture shown by [`DebugView::uv_checker`].
+    pub fn checker(&self) -> &Texture {
+        &self.checker
+    }
+
+    /// Draw `lines` with the current depth test mode.
+    pub fn draw(&mut self, lines: &Lines, mvp_matrix: &glm::Mat4) -> Result<(), GolemError> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let count = lines.vertices.len() / 6;
+        self.vb.set_data(&lines.vertices);
+        self.eb.set_data(&(0..count as u32).collect::<Vec<_>>());
+        self.shader.bind();
+        self.shader.set_uniform(
+            "mvp_matrix",
+            UniformValue::Matrix4(glm::value_ptr(mvp_matrix).try_into().unwrap()),
+        )?;
+        unsafe {
+            self.shader
+                .draw(&self.vb, &self.eb, 0..count, GeometryMode::Lines)
+        }
+    }
+}
+
+/// 8 by 8 squares, alternating between white and a color that gets redder along U and greener
+/// along V, so both the scale and the direction of the coordinates show.
+fn checker(ctx: &Context) -> Result<Texture, GolemError> {
+    const SIZE: u32 = 64;
+    const SQUARE: u32 = SIZE / 8;
+    let mut pixels = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let (i, j) = (x / SQUARE, y / SQUARE);
+            if (i + j) % 2 == 0 {
+                pixels.extend_from_slice(&[255, 255, 255, 255]);
+            } else {
+                pixels.extend_from_slice(&[(i * 32) as u8, (j * 32) as u8, 96, 255]);
+            }
+        }
+    }
+    let mut texture = Texture::new(ctx)?;
+    texture.set_image(Some(&pixels), SIZE, SIZE, ColorFormat::RGBA);
+    texture.set_minification(TextureFilter::Nearest)?;
+    texture.set_magnification(TextureFilter::Nearest)?;
+    texture.set_wrap_h(TextureWrap::Repeat)?;
+    texture.set_wrap_v(TextureWrap::Repeat)?;
+    Ok(texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lists_of_views() {
+        let view = DebugView::parse("wireframe, axes").unwrap();
+        assert!(view.wireframe && view.axes && !view.normals);
+        assert!(view.has_lines());
+        assert!(!DebugView::parse("uv").unwrap().has_lines());
+        assert_eq!(DebugView::parse("wireframe,shadows"), None);
+    }
+
+    #[test]
+    fn boxes_have_twelve_edges() {
+        let mut lines = Lines::default();
+        lines.aabb(
+            &Aabb {
+                min: glm::vec3(0.0, 0.0, 0.0),
+                max: glm::vec3(1.0, 1.0, 1.0),
+            },
+            BOUNDS_COLOR,
+        );
+        assert_eq!(lines.vertices.len(), 12 * 2 * 6);
+    }
+}
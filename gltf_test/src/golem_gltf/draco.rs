@@ -0,0 +1,2295 @@
+//! `KHR_draco_mesh_compression`: primitives compressed with Draco, decoded the way its reference
+//! decoder does, for version 2.2 of the bitstream (what encoders have written since 2018).
+//!
+//! - Connectivity is either a list of faces, or an Edgebreaker traversal of the mesh that is
+//!   replayed backwards to rebuild a corner table.
+//! - Attributes are decoded in the order that table is traversed in, as the corrections to a
+//!   prediction from the values already decoded, and then dequantized.
+//!
+//! Everything Draco's encoder can write for a triangle mesh is read, except for the
+//! deprecated prediction schemes and the Edgebreaker valence coding.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+pub const EXTENSION: &str = "KHR_draco_mesh_compression";
+
+/// A decoded mesh: `attributes` have a value for each of `points`, which `indices` refer to.
+pub struct Mesh {
+    pub points: usize,
+    pub indices: Vec<u32>,
+    pub attributes: Vec<Attribute>,
+}
+
+pub struct Attribute {
+    /// What the extension's `attributes` refer to the attribute by.
+    pub unique_id: u32,
+    /// Position, normal, color, texture coordinates or something else.
+    kind: u8,
+    data_type: u8,
+    components: usize,
+    /// The value of each component of each entry.
+    values: Vec<f64>,
+    /// The integers that `values` are decoded from, which predictions work with.
+    portable: Vec<i32>,
+    /// The entry each point uses.
+    point_to_entry: Vec<u32>,
+}
+
+impl Attribute {
+    /// The attribute's values for every point, as components of type `component_type`.
+    pub fn to_bytes(&self, component_type: u32, normalized: bool) -> Result<Vec<u8>, String> {
+        let float = self.data_type == DT_FLOAT32 || self.data_type == DT_FLOAT64;
+        let mut out = Vec::new();
+        for &entry in &self.point_to_entry {
+            let entry = entry as usize * self.components;
+            for &value in &self.values[entry..entry + self.components] {
+                // Floats only end up in integer accessors that are normalized.
+                let scaled = |max: f64| {
+                    if float && normalized {
+                        (value * max).round()
+                    } else {
+                        value
+                    }
+                };
+                match component_type {
+                    5120 => out.push(scaled(127.0) as i8 as u8),
+                    5121 => out.push(scaled(255.0) as u8),
+                    5122 => out.extend_from_slice(&(scaled(32767.0) as i16).to_le_bytes()),
+                    5123 => out.extend_from_slice(&(scaled(65535.0) as u16).to_le_bytes()),
+                    5125 => out.extend_from_slice(&(value as u32).to_le_bytes()),
+                    5126 => out.extend_from_slice(&(value as f32).to_le_bytes()),
+                    _ => return Err(format!("unknown component type {}", component_type)),
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// The mesh `data`, a Draco bitstream, decodes to.
+pub fn decode(data: &[u8]) -> Result<Mesh, String> {
+    if data.len() < 11 || &data[..5] != b"DRACO" {
+        return Err("its data isn't Draco's".into());
+    }
+    let (major, minor) = (data[5], data[6]);
+    if (major, minor) != (2, 2) {
+        return Err(format!("Draco version {}.{} isn't supported", major, minor));
+    }
+    if data[7] != TRIANGULAR_MESH {
+        return Err("its Draco data isn't a mesh".into());
+    }
+    let method = data[8];
+    let flags = u16::from_le_bytes([data[9], data[10]]);
+    let mut reader = Reader::new(&data[11..]);
+    let mesh = (|| {
+        if flags & METADATA_FLAG != 0 {
+            skip_metadata(&mut reader)?;
+        }
+        match method {
+            SEQUENTIAL => decode_sequential(&mut reader),
+            EDGEBREAKER => decode_edgebreaker(&mut reader),
+            _ => None,
+        }
+    })();
+    mesh.ok_or_else(|| "its Draco data is corrupt or uses a feature that isn't supported".into())
+}
+
+const TRIANGULAR_MESH: u8 = 1;
+const SEQUENTIAL: u8 = 0;
+const EDGEBREAKER: u8 = 1;
+const METADATA_FLAG: u16 = 0x8000;
+
+const DT_INT8: u8 = 1;
+const DT_UINT8: u8 = 2;
+const DT_INT16: u8 = 3;
+const DT_UINT16: u8 = 4;
+const DT_INT32: u8 = 5;
+const DT_UINT32: u8 = 6;
+const DT_FLOAT32: u8 = 9;
+const DT_FLOAT64: u8 = 10;
+
+const INVALID: u32 = u32::MAX;
+
+/// Little-endian values, and varints, from the front of the data.
+#[derive(Clone)]
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.data.len() < n {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.bytes(4)
+            .map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.bytes(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn varint_usize(&mut self) -> Option<usize> {
+        self.varint()?.try_into().ok()
+    }
+
+    /// Bits from the front of the data, of which there are `size` bytes if given. The reader
+    /// skips them once the caller has read them all.
+    fn bits(&self, size: Option<usize>) -> Option<Bits<'a>> {
+        let data = match size {
+            Some(size) => self.data.get(..size)?,
+            None => self.data,
+        };
+        Some(Bits { data, position: 0 })
+    }
+
+    fn skip_bits(&mut self, bits: &Bits) {
+        let bytes = bits.position.div_ceil(8);
+        self.data = &self.data[bytes.min(self.data.len())..];
+    }
+}
+
+/// Bits from the least significant one of each byte up, reading zeros past the end.
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for bit in 0..count {
+            let byte = self.data.get(self.position / 8).copied().unwrap_or(0);
+            value |= (((byte >> (self.position % 8)) & 1) as u32) << bit;
+            self.position += 1;
+        }
+        value
+    }
+}
+
+/// Metadata only names things, and nothing here needs it.
+fn skip_metadata(reader: &mut Reader) -> Option<()> {
+    fn skip_entries(reader: &mut Reader) -> Option<()> {
+        for _ in 0..reader.varint()? {
+            let key = reader.u8()? as usize;
+            reader.bytes(key)?;
+            let value = reader.varint_usize()?;
+            reader.bytes(value)?;
+        }
+        for _ in 0..reader.varint()? {
+            let key = reader.u8()? as usize;
+            reader.bytes(key)?;
+            skip_entries(reader)?;
+        }
+        Some(())
+    }
+    for _ in 0..reader.varint()? {
+        reader.varint()?;
+        skip_entries(reader)?;
+    }
+    skip_entries(reader)
+}
+
+/// Bits coded with a binary asymmetric numeral system, as Draco's `RAnsBitDecoder`.
+struct BitDecoder<'a> {
+    data: &'a [u8],
+    state: u32,
+    probability_zero: u32,
+}
+
+impl<'a> BitDecoder<'a> {
+    const L_BASE: u32 = 4096;
+    const IO_BASE: u32 = 256;
+
+    fn start(reader: &mut Reader<'a>) -> Option<Self> {
+        let probability_zero = reader.u8()? as u32;
+        let size = reader.varint_usize()?;
+        let data = reader.bytes(size)?;
+        let (&last, _) = data.split_last()?;
+        let (length, state) = match last >> 6 {
+            0 => (size - 1, (last & 0x3f) as u32),
+            1 if size >= 2 => (size - 2, read_le(&data[size - 2..]) & 0x3fff),
+            2 if size >= 3 => (size - 3, read_le(&data[size - 3..]) & 0x3f_ffff),
+            _ => return None,
+        };
+        let state = state + Self::L_BASE;
+        if state >= Self::L_BASE * Self::IO_BASE {
+            return None;
+        }
+        Some(BitDecoder {
+            data: &data[..length],
+            state,
+            probability_zero,
+        })
+    }
+
+    fn read(&mut self) -> bool {
+        if self.state < Self::L_BASE {
+            if let Some((&byte, rest)) = self.data.split_last() {
+                self.state = self.state * Self::IO_BASE + byte as u32;
+                self.data = rest;
+            }
+        }
+        let p = 256 - self.probability_zero;
+        let (quotient, remainder) = (self.state / 256, self.state % 256);
+        let one = remainder < p;
+        self.state = if one {
+            quotient * p + remainder
+        } else {
+            self.state - quotient * p - p
+        };
+        one
+    }
+}
+
+/// A little-endian integer of up to four bytes.
+fn read_le(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .take(4)
+        .rev()
+        .fold(0, |value, &b| value << 8 | b as u32)
+}
+
+/// Symbols coded with a range asymmetric numeral system, as Draco's `RAnsSymbolDecoder`.
+struct SymbolDecoder<'a> {
+    data: &'a [u8],
+    state: u32,
+    precision: u32,
+    /// The symbol, its probability and the sum of the probabilities before it, for each slot.
+    table: Vec<(u32, u32, u32)>,
+}
+
+impl<'a> SymbolDecoder<'a> {
+    /// Read the probabilities and the coded data, for symbols of up to `bit_length` bits.
+    fn start(reader: &mut Reader<'a>, bit_length: u32) -> Option<Self> {
+        let precision = 1 << (3 * bit_length / 2).clamp(12, 20);
+        let symbols = reader.varint_usize()?;
+        let mut probabilities = Vec::with_capacity(symbols.min(1 << 20));
+        while probabilities.len() < symbols {
+            let byte = reader.u8()?;
+            if byte & 3 == 3 {
+                // A run of symbols that don't occur.
+                let run = (byte >> 2) as usize + 1;
+                if probabilities.len() + run > symbols {
+                    return None;
+                }
+                probabilities.resize(probabilities.len() + run, 0);
+            } else {
+                let mut probability = (byte >> 2) as u32;
+                for i in 0..(byte & 3) as u32 {
+                    probability |= (reader.u8()? as u32) << (8 * (i + 1) - 2);
+                }
+                probabilities.push(probability);
+            }
+        }
+
+        let mut table = Vec::with_capacity(precision as usize);
+        let mut sum = 0;
+        for (symbol, &probability) in probabilities.iter().enumerate() {
+            if sum + probability > precision {
+                return None;
+            }
+            for _ in 0..probability {
+                table.push((symbol as u32, probability, sum));
+            }
+            sum += probability;
+        }
+        if symbols > 0 && sum != precision {
+            return None;
+        }
+
+        let mut decoder = SymbolDecoder {
+            data: &[],
+            state: 0,
+            precision,
+            table,
+        };
+        if symbols == 0 {
+            return Some(decoder);
+        }
+        let size = reader.varint_usize()?;
+        let data = reader.bytes(size)?;
+        let (&last, _) = data.split_last()?;
+        let width = (last >> 6) as usize + 1;
+        if size < width {
+            return None;
+        }
+        let state = read_le(&data[size - width..]) & ((1 << (8 * width - 2)) - 1);
+        decoder.data = &data[..size - width];
+        decoder.state = state + decoder.precision * 4;
+        if decoder.state >= decoder.precision * 4 * 256 {
+            return None;
+        }
+        Some(decoder)
+    }
+
+    fn read(&mut self) -> Option<u32> {
+        let base = self.precision * 4;
+        while self.state < base {
+            match self.data.split_last() {
+                Some((&byte, rest)) => {
+                    self.state = self.state * 256 + byte as u32;
+                    self.data = rest;
+                }
+                None => break,
+            }
+        }
+        let (quotient, remainder) = (self.state / self.precision, self.state % self.precision);
+        let &(symbol, probability, sum) = self.table.get(remainder as usize)?;
+        self.state = quotient * probability + remainder - sum;
+        Some(symbol)
+    }
+}
+
+/// `count` integers, coded in groups of `components` as Draco's `DecodeSymbols` does.
+fn decode_symbols(reader: &mut Reader, count: usize, components: usize) -> Option<Vec<u32>> {
+    let mut out = Vec::with_capacity(count.min(1 << 24));
+    if count == 0 {
+        return Some(out);
+    }
+    match reader.u8()? {
+        // Each group is prefixed with a symbol saying how many bits its integers take.
+        0 => {
+            let mut tags = SymbolDecoder::start(reader, 5)?;
+            let mut bits = reader.bits(None)?;
+            while out.len() < count {
+                let length = tags.read()?;
+                if length > 32 {
+                    return None;
+                }
+                for _ in 0..components.min(count - out.len()) {
+                    out.push(bits.read(length));
+                }
+            }
+            reader.skip_bits(&bits);
+        }
+        // The integers are the symbols.
+        1 => {
+            let bit_length = reader.u8()? as u32;
+            if bit_length == 0 || bit_length > 18 {
+                return None;
+            }
+            let mut symbols = SymbolDecoder::start(reader, bit_length)?;
+            for _ in 0..count {
+                out.push(symbols.read()?);
+            }
+        }
+        _ => return None,
+    }
+    Some(out)
+}
+
+fn to_signed(v: u32) -> i32 {
+    ((v >> 1) ^ (v & 1).wrapping_neg()) as i32
+}
+
+/// A list of faces, each point a corner of one. Points that share the same vertex (the same
+/// position) are told apart by the other attributes.
+fn decode_sequential(reader: &mut Reader) -> Option<Mesh> {
+    let faces = reader.varint_usize()?;
+    let points = reader.varint_usize()?;
+    let count = faces.checked_mul(3)?;
+    let indices = match reader.u8()? {
+        0 => {
+            // Differences from the previous index.
+            let mut last = 0i32;
+            let mut indices = decode_symbols(reader, count, 1)?;
+            for index in &mut indices {
+                last = last.wrapping_add(to_signed(*index));
+                *index = last as u32;
+            }
+            indices
+        }
+        1 => {
+            let size = match points {
+                p if p < 1 << 8 => 1,
+                p if p < 1 << 16 => 2,
+                p if p < 1 << 21 => 0,
+                _ => 4,
+            };
+            (0..count)
+                .map(|_| match size {
+                    0 => reader.varint().and_then(|i| i.try_into().ok()),
+                    _ => reader.bytes(size).map(read_le),
+                })
+                .collect::<Option<_>>()?
+        }
+        _ => return None,
+    };
+    if indices.iter().any(|&i| i as usize >= points) {
+        return None;
+    }
+
+    let mut mesh = Mesh {
+        points,
+        indices,
+        attributes: Vec::new(),
+    };
+    let decoders = reader.u8()?;
+    let decoders = (0..decoders)
+        .map(|_| read_attributes(reader, &mut mesh))
+        .collect::<Option<Vec<_>>>()?;
+    // Every point has an entry of its own, in order.
+    let sequence: Vec<u32> = (0..points as u32).collect();
+    for attributes in decoders {
+        decode_values(reader, &mut mesh, &attributes, &sequence, &sequence, None)?;
+    }
+    Some(mesh)
+}
+
+/// Connectivity as corners, three to a face in order, each at a vertex and opposite the corner
+/// across the edge it faces, if there is one. `INVALID` stands for no corner or vertex.
+trait Corners {
+    fn vertex(&self, corner: u32) -> u32;
+    fn opposite(&self, corner: u32) -> u32;
+    /// The first corner of a vertex going clockwise, which is on a boundary if the vertex is.
+    fn left_most_corner(&self, vertex: u32) -> u32;
+    fn vertex_count(&self) -> usize;
+    fn face_count(&self) -> usize;
+
+    fn next(&self, corner: u32) -> u32 {
+        match corner {
+            INVALID => INVALID,
+            c if c % 3 == 2 => c - 2,
+            c => c + 1,
+        }
+    }
+
+    fn previous(&self, corner: u32) -> u32 {
+        match corner {
+            INVALID => INVALID,
+            c if c % 3 == 0 => c + 2,
+            c => c - 1,
+        }
+    }
+
+    fn swing_left(&self, corner: u32) -> u32 {
+        self.next(self.opposite(self.next(corner)))
+    }
+
+    fn swing_right(&self, corner: u32) -> u32 {
+        self.previous(self.opposite(self.previous(corner)))
+    }
+
+    /// The corner at the same vertex on the face to the left.
+    fn left_corner(&self, corner: u32) -> u32 {
+        self.opposite(self.previous(corner))
+    }
+
+    fn right_corner(&self, corner: u32) -> u32 {
+        self.opposite(self.next(corner))
+    }
+
+    fn is_on_boundary(&self, vertex: u32) -> bool {
+        self.swing_left(self.left_most_corner(vertex)) == INVALID
+    }
+
+    /// The corners around the vertex at `corner`, going counterclockwise and then, if that
+    /// reaches a boundary, clockwise from `corner`.
+    fn corners_around(&self, corner: u32) -> Vec<u32> {
+        let mut corners = vec![corner];
+        let mut c = self.swing_left(corner);
+        while c != INVALID && c != corner {
+            corners.push(c);
+            c = self.swing_left(c);
+        }
+        if c == INVALID {
+            c = self.swing_right(corner);
+            while c != INVALID && c != corner {
+                corners.push(c);
+                c = self.swing_right(c);
+            }
+        }
+        corners
+    }
+}
+
+fn get(v: &[u32], i: u32) -> u32 {
+    v.get(i as usize).copied().unwrap_or(INVALID)
+}
+
+#[derive(Default)]
+struct CornerTable {
+    corner_to_vertex: Vec<u32>,
+    opposite: Vec<u32>,
+    left_most_corner: Vec<u32>,
+}
+
+impl Corners for CornerTable {
+    fn vertex(&self, corner: u32) -> u32 {
+        get(&self.corner_to_vertex, corner)
+    }
+
+    fn opposite(&self, corner: u32) -> u32 {
+        get(&self.opposite, corner)
+    }
+
+    fn left_most_corner(&self, vertex: u32) -> u32 {
+        get(&self.left_most_corner, vertex)
+    }
+
+    fn vertex_count(&self) -> usize {
+        self.left_most_corner.len()
+    }
+
+    fn face_count(&self) -> usize {
+        self.corner_to_vertex.len() / 3
+    }
+}
+
+impl CornerTable {
+    fn set_opposite(&mut self, a: u32, b: u32) {
+        self.opposite[a as usize] = b;
+        self.opposite[b as usize] = a;
+    }
+
+    fn add_vertex(&mut self) -> u32 {
+        self.left_most_corner.push(INVALID);
+        self.left_most_corner.len() as u32 - 1
+    }
+}
+
+/// The connectivity of an attribute whose values change across some edges, its seams: every
+/// run of faces around a vertex between two seams has a vertex of its own.
+struct AttributeCorners<'a> {
+    table: &'a CornerTable,
+    seam: Vec<bool>,
+    vertex_on_seam: Vec<bool>,
+    corner_to_vertex: Vec<u32>,
+    left_most_corner: Vec<u32>,
+}
+
+impl Corners for AttributeCorners<'_> {
+    fn vertex(&self, corner: u32) -> u32 {
+        get(&self.corner_to_vertex, corner)
+    }
+
+    fn opposite(&self, corner: u32) -> u32 {
+        match self.seam.get(corner as usize) {
+            Some(false) => self.table.opposite(corner),
+            _ => INVALID,
+        }
+    }
+
+    fn left_most_corner(&self, vertex: u32) -> u32 {
+        get(&self.left_most_corner, vertex)
+    }
+
+    fn vertex_count(&self) -> usize {
+        self.left_most_corner.len()
+    }
+
+    fn face_count(&self) -> usize {
+        self.table.face_count()
+    }
+}
+
+impl<'a> AttributeCorners<'a> {
+    /// `seams` are the corners facing seam edges, on either side.
+    fn new(table: &'a CornerTable, seams: &[u32]) -> Option<Self> {
+        let corners = table.corner_to_vertex.len();
+        let mut seam = vec![false; corners];
+        let mut vertex_on_seam = vec![false; table.vertex_count()];
+        for &corner in seams {
+            for c in [corner, table.opposite(corner)]
+                .iter()
+                .filter(|&&c| c != INVALID)
+            {
+                *seam.get_mut(*c as usize)? = true;
+                for v in [
+                    table.vertex(table.next(*c)),
+                    table.vertex(table.previous(*c)),
+                ]
+                .iter()
+                {
+                    *vertex_on_seam.get_mut(*v as usize)? = true;
+                }
+            }
+        }
+        let mut attribute = AttributeCorners {
+            table,
+            seam,
+            vertex_on_seam,
+            corner_to_vertex: vec![INVALID; corners],
+            left_most_corner: Vec::new(),
+        };
+
+        for v in 0..table.vertex_count() {
+            let c = table.left_most_corner(v as u32);
+            if c == INVALID {
+                continue;
+            }
+            // Start from a seam, so the corners before it around the vertex aren't split from
+            // the ones after.
+            let mut first = c;
+            if attribute.vertex_on_seam[v] {
+                let mut act = attribute.swing_left(first);
+                while act != INVALID {
+                    first = act;
+                    act = attribute.swing_left(act);
+                    if act == c {
+                        return None;
+                    }
+                }
+            }
+            let mut vertex = attribute.left_most_corner.len() as u32;
+            attribute.corner_to_vertex[first as usize] = vertex;
+            attribute.left_most_corner.push(first);
+            let mut act = table.swing_right(first);
+            while act != INVALID && act != first {
+                if attribute.seam[table.next(act) as usize] {
+                    vertex = attribute.left_most_corner.len() as u32;
+                    attribute.left_most_corner.push(act);
+                }
+                attribute.corner_to_vertex[act as usize] = vertex;
+                act = table.swing_right(act);
+            }
+        }
+        Some(attribute)
+    }
+
+    /// Whether the vertex at `corner` has a seam through it.
+    fn is_on_seam(&self, corner: u32) -> bool {
+        let vertex = self.table.vertex(corner) as usize;
+        self.vertex_on_seam.get(vertex).copied().unwrap_or(false)
+    }
+}
+
+const STANDARD_EDGEBREAKER: u8 = 0;
+const VALENCE_EDGEBREAKER: u8 = 2;
+
+const TOPOLOGY_C: u32 = 0;
+const TOPOLOGY_S: u32 = 1;
+const TOPOLOGY_L: u32 = 3;
+const TOPOLOGY_R: u32 = 5;
+const TOPOLOGY_E: u32 = 7;
+
+/// Where the encoder's traversal split the mesh in two, to be joined again by an `S` symbol.
+struct TopologySplit {
+    source_symbol: usize,
+    split_symbol: usize,
+    right_edge: bool,
+}
+
+/// The symbols of the traversal, which are either coded directly, or coded with a context of
+/// the valence of the vertex the traversal reached.
+enum Symbols<'a> {
+    Standard(Bits<'a>),
+    Valence {
+        contexts: Vec<Vec<u32>>,
+        context: Option<usize>,
+        valences: Vec<u32>,
+        last: u32,
+    },
+}
+
+impl Symbols<'_> {
+    fn read(&mut self) -> Option<u32> {
+        match self {
+            Symbols::Standard(bits) => {
+                let symbol = bits.read(1);
+                if symbol == TOPOLOGY_C {
+                    return Some(symbol);
+                }
+                Some(symbol | bits.read(2) << 1)
+            }
+            Symbols::Valence {
+                contexts,
+                context,
+                last,
+                ..
+            } => {
+                *last = match *context {
+                    // The symbols of each context are read from the back.
+                    Some(context) => {
+                        let symbol = contexts[context].pop()?;
+                        *[TOPOLOGY_C, TOPOLOGY_S, TOPOLOGY_L, TOPOLOGY_R, TOPOLOGY_E]
+                            .get(symbol as usize)?
+                    }
+                    None => TOPOLOGY_E,
+                };
+                Some(*last)
+            }
+        }
+    }
+
+    fn reached(&mut self, table: &CornerTable, corner: u32) -> Option<()> {
+        if let Symbols::Valence {
+            context,
+            valences,
+            last,
+            ..
+        } = self
+        {
+            let (next, previous) = (table.next(corner), table.previous(corner));
+            let added = match *last {
+                TOPOLOGY_C | TOPOLOGY_S => [0, 1, 1],
+                TOPOLOGY_R => [1, 1, 2],
+                TOPOLOGY_L => [1, 2, 1],
+                TOPOLOGY_E => [2, 2, 2],
+                _ => [0, 0, 0],
+            };
+            for (&c, &n) in [corner, next, previous].iter().zip(added.iter()) {
+                *valences.get_mut(table.vertex(c) as usize)? += n;
+            }
+            let valence = *valences.get(table.vertex(next) as usize)?;
+            *context = Some(valence.clamp(2, 7) as usize - 2);
+        }
+        Some(())
+    }
+
+    fn merge_vertices(&mut self, destination: u32, source: u32) -> Option<()> {
+        if let Symbols::Valence { valences, .. } = self {
+            let source = *valences.get(source as usize)?;
+            *valences.get_mut(destination as usize)? += source;
+        }
+        Some(())
+    }
+}
+
+/// An Edgebreaker traversal of the mesh, replayed backwards to rebuild its corner table, and
+/// the seams of its attributes.
+fn decode_edgebreaker(reader: &mut Reader) -> Option<Mesh> {
+    let coding = reader.u8()?;
+    if coding != STANDARD_EDGEBREAKER && coding != VALENCE_EDGEBREAKER {
+        return None;
+    }
+    let vertices = reader.varint_usize()?;
+    let faces = reader.varint_usize()?;
+    let attribute_data = reader.u8()? as usize;
+    let symbol_count = reader.varint_usize()?;
+    let split_symbols = reader.varint_usize()?;
+    // Only a new component started from an interior face adds a face without a symbol.
+    if faces < symbol_count || faces > symbol_count + symbol_count / 3 {
+        return None;
+    }
+    if split_symbols > symbol_count {
+        return None;
+    }
+
+    let split_count = reader.varint_usize()?;
+    if split_count > faces {
+        return None;
+    }
+    let mut splits = Vec::with_capacity(split_count);
+    let mut last_source = 0;
+    for _ in 0..split_count {
+        let source_symbol = last_source + reader.varint_usize()?;
+        let split_symbol = source_symbol.checked_sub(reader.varint_usize()?)?;
+        last_source = source_symbol;
+        splits.push(TopologySplit {
+            source_symbol,
+            split_symbol,
+            right_edge: false,
+        });
+    }
+    let mut bits = reader.bits(None)?;
+    for split in &mut splits {
+        split.right_edge = bits.read(1) == 1;
+    }
+    reader.skip_bits(&bits);
+
+    let max_vertices = vertices + split_symbols;
+    let mut symbols = match coding {
+        STANDARD_EDGEBREAKER => {
+            let size = reader.varint_usize()?;
+            let bits = reader.bits(Some(size))?;
+            reader.bytes(size)?;
+            Symbols::Standard(bits)
+        }
+        _ => Symbols::Valence {
+            contexts: Vec::new(),
+            context: None,
+            valences: vec![0; max_vertices],
+            last: TOPOLOGY_E,
+        },
+    };
+    let mut start_faces = BitDecoder::start(reader)?;
+    let mut seam_decoders = (0..attribute_data)
+        .map(|_| BitDecoder::start(reader))
+        .collect::<Option<Vec<_>>>()?;
+    if let Symbols::Valence { contexts, .. } = &mut symbols {
+        if reader.varint_usize()? >= max_vertices {
+            return None;
+        }
+        // Valences from 2 to 7, the only mode there is.
+        if reader.u8()? != 0 {
+            return None;
+        }
+        for _ in 2..=7 {
+            let count = reader.varint_usize()?;
+            if count > faces {
+                return None;
+            }
+            contexts.push(decode_symbols(reader, count, 1)?);
+        }
+    }
+
+    let mut table = CornerTable {
+        corner_to_vertex: vec![INVALID; faces * 3],
+        opposite: vec![INVALID; faces * 3],
+        left_most_corner: Vec::with_capacity(max_vertices),
+    };
+    let mut is_hole = vec![true; max_vertices];
+    let remove_isolated = attribute_data == 0;
+    let vertex_count = decode_traversal(
+        &mut table,
+        &mut symbols,
+        &mut start_faces,
+        &mut splits,
+        symbol_count,
+        &mut is_hole,
+        remove_isolated,
+    )?;
+
+    // Whether the attributes have a seam along each edge, once for each pair of faces.
+    let mut seams = vec![Vec::new(); attribute_data];
+    for corner in 0..faces as u32 * 3 {
+        let opposite = table.opposite(corner);
+        if opposite == INVALID {
+            seams.iter_mut().for_each(|s| s.push(corner));
+        } else if opposite / 3 >= corner / 3 {
+            for (seams, decoder) in seams.iter_mut().zip(&mut seam_decoders) {
+                if decoder.read() {
+                    seams.push(corner);
+                }
+            }
+        }
+    }
+    let attribute_tables = seams
+        .iter()
+        .map(|seams| AttributeCorners::new(&table, seams))
+        .collect::<Option<Vec<_>>>()?;
+
+    let corner_to_point = assign_points(&table, &attribute_tables, &is_hole, vertex_count)?;
+    let points = corner_to_point
+        .iter()
+        .map(|&p| p as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let mut mesh = Mesh {
+        points: if remove_isolated {
+            vertex_count
+        } else {
+            points
+        },
+        indices: corner_to_point,
+        attributes: Vec::new(),
+    };
+    decode_edgebreaker_attributes(reader, &mut mesh, &table, &attribute_tables)?;
+    Some(mesh)
+}
+
+/// Replay the traversal's symbols, returning how many vertices the mesh has.
+fn decode_traversal(
+    table: &mut CornerTable,
+    symbols: &mut Symbols,
+    start_faces: &mut BitDecoder,
+    splits: &mut Vec<TopologySplit>,
+    symbol_count: usize,
+    is_hole: &mut [bool],
+    remove_isolated: bool,
+) -> Option<usize> {
+    let max_vertices = is_hole.len();
+    // The open edges new faces are added to, by the corner opposite each.
+    let mut active: Vec<u32> = Vec::new();
+    let mut split_active = HashMap::new();
+    let mut isolated = Vec::new();
+
+    for symbol_id in 0..symbol_count {
+        let corner = symbol_id as u32 * 3;
+        let symbol = symbols.read()?;
+        let mut check_split = false;
+        match symbol {
+            TOPOLOGY_C => {
+                // A face between the active edge and the edge next to it around vertex x.
+                let a = *active.last()?;
+                let x = table.vertex(table.next(a));
+                let b = table.next(table.left_most_corner(x));
+                if a == b || table.opposite(a) != INVALID || table.opposite(b) != INVALID {
+                    return None;
+                }
+                table.set_opposite(a, corner + 1);
+                table.set_opposite(b, corner + 2);
+                let a_previous = table.vertex(table.previous(a));
+                let b_next = table.vertex(table.next(b));
+                if x == a_previous || x == b_next {
+                    return None;
+                }
+                table.corner_to_vertex[corner as usize..corner as usize + 3]
+                    .copy_from_slice(&[x, b_next, a_previous]);
+                *table.left_most_corner.get_mut(a_previous as usize)? = corner + 2;
+                *is_hole.get_mut(x as usize)? = false;
+                *active.last_mut()? = corner;
+            }
+            TOPOLOGY_R | TOPOLOGY_L => {
+                // A face on the active edge, with a new vertex.
+                let a = *active.last()?;
+                if table.opposite(a) != INVALID {
+                    return None;
+                }
+                let (opposite, left, right) = if symbol == TOPOLOGY_R {
+                    (corner + 2, corner + 1, corner)
+                } else {
+                    (corner + 1, corner, corner + 2)
+                };
+                table.set_opposite(opposite, a);
+                let vertex = table.add_vertex();
+                if table.vertex_count() > max_vertices {
+                    return None;
+                }
+                table.corner_to_vertex[opposite as usize] = vertex;
+                table.left_most_corner[vertex as usize] = opposite;
+                let right_vertex = table.vertex(table.previous(a));
+                table.corner_to_vertex[right as usize] = right_vertex;
+                *table.left_most_corner.get_mut(right_vertex as usize)? = right;
+                table.corner_to_vertex[left as usize] = table.vertex(table.next(a));
+                *active.last_mut()? = corner;
+                check_split = true;
+            }
+            TOPOLOGY_S => {
+                // A face joining the last two active edges, whose vertices p and n merge.
+                let b = active.pop()?;
+                if let Some(&a) = split_active.get(&symbol_id) {
+                    active.push(a);
+                }
+                let a = *active.last()?;
+                if a == b || table.opposite(a) != INVALID || table.opposite(b) != INVALID {
+                    return None;
+                }
+                table.set_opposite(a, corner + 2);
+                table.set_opposite(b, corner + 1);
+                let p = table.vertex(table.previous(a));
+                let a_next = table.vertex(table.next(a));
+                let b_previous = table.vertex(table.previous(b));
+                table.corner_to_vertex[corner as usize..corner as usize + 3]
+                    .copy_from_slice(&[p, a_next, b_previous]);
+                *table.left_most_corner.get_mut(b_previous as usize)? = corner + 2;
+                let mut n_corner = table.next(b);
+                let n = table.vertex(n_corner);
+                symbols.merge_vertices(p, n)?;
+                let n_left_most = table.left_most_corner(n);
+                *table.left_most_corner.get_mut(p as usize)? = n_left_most;
+                let first = n_corner;
+                while n_corner != INVALID {
+                    table.corner_to_vertex[n_corner as usize] = p;
+                    n_corner = table.swing_left(n_corner);
+                    if n_corner == first {
+                        return None;
+                    }
+                }
+                *table.left_most_corner.get_mut(n as usize)? = INVALID;
+                if remove_isolated {
+                    isolated.push(n);
+                }
+                *active.last_mut()? = corner;
+            }
+            TOPOLOGY_E => {
+                // A face on its own, with three new vertices.
+                for i in 0..3 {
+                    let vertex = table.add_vertex();
+                    table.corner_to_vertex[(corner + i) as usize] = vertex;
+                    table.left_most_corner[vertex as usize] = corner + i;
+                }
+                if table.vertex_count() > max_vertices {
+                    return None;
+                }
+                active.push(corner);
+                check_split = true;
+            }
+            _ => return None,
+        }
+        symbols.reached(table, *active.last()?)?;
+
+        if check_split {
+            // The encoder numbered symbols the other way round.
+            let encoder_symbol = symbol_count - symbol_id - 1;
+            while let Some(split) = splits.last() {
+                if split.source_symbol > encoder_symbol {
+                    return None;
+                }
+                if split.source_symbol != encoder_symbol {
+                    break;
+                }
+                let top = *active.last()?;
+                let new_active = if split.right_edge {
+                    table.next(top)
+                } else {
+                    table.previous(top)
+                };
+                let split_symbol = symbol_count.checked_sub(split.split_symbol + 1)?;
+                split_active.insert(split_symbol, new_active);
+                splits.pop();
+            }
+        }
+    }
+    if table.vertex_count() > max_vertices {
+        return None;
+    }
+
+    // Each component starts with a face, which is either inside the mesh, joining the three
+    // edges around it, or on a boundary.
+    let mut faces = symbol_count;
+    while let Some(a) = active.pop() {
+        if !start_faces.read() {
+            continue;
+        }
+        if faces >= table.face_count() {
+            return None;
+        }
+        let n = table.vertex(table.next(a));
+        let b = table.next(table.left_most_corner(n));
+        let x = table.vertex(table.next(b));
+        let c = table.next(table.left_most_corner(x));
+        if a == b || a == c || b == c {
+            return None;
+        }
+        if [a, b, c].iter().any(|&c| table.opposite(c) != INVALID) {
+            return None;
+        }
+        let p = table.vertex(table.next(c));
+        let corner = faces as u32 * 3;
+        faces += 1;
+        table.set_opposite(corner, a);
+        table.set_opposite(corner + 1, b);
+        table.set_opposite(corner + 2, c);
+        table.corner_to_vertex[corner as usize..corner as usize + 3].copy_from_slice(&[x, p, n]);
+        for &v in &[x, p, n] {
+            *is_hole.get_mut(v as usize)? = false;
+        }
+    }
+    if faces != table.face_count() {
+        return None;
+    }
+
+    // Move the last vertices into the places of those that merged into others.
+    let mut vertex_count = table.vertex_count();
+    for vertex in isolated {
+        let mut source = vertex_count.checked_sub(1)? as u32;
+        while table.left_most_corner(source) == INVALID {
+            vertex_count = vertex_count.checked_sub(1)?;
+            source = vertex_count.checked_sub(1)? as u32;
+        }
+        if source < vertex {
+            continue;
+        }
+        for c in table.corners_around(table.left_most_corner(source)) {
+            if table.vertex(c) != source {
+                return None;
+            }
+            table.corner_to_vertex[c as usize] = vertex;
+        }
+        table.left_most_corner[vertex as usize] = table.left_most_corner(source);
+        table.left_most_corner[source as usize] = INVALID;
+        is_hole[vertex as usize] = is_hole[source as usize];
+        is_hole[source as usize] = false;
+        vertex_count -= 1;
+    }
+    Some(vertex_count)
+}
+
+/// The point at each corner. Without attribute seams that's its vertex, and otherwise each run
+/// of corners around a vertex that the attributes don't change across is a point.
+fn assign_points(
+    table: &CornerTable,
+    attributes: &[AttributeCorners],
+    is_hole: &[bool],
+    vertex_count: usize,
+) -> Option<Vec<u32>> {
+    if attributes.is_empty() {
+        return Some(table.corner_to_vertex.clone());
+    }
+    let mut corner_to_point = vec![INVALID; table.corner_to_vertex.len()];
+    let mut points = 0;
+    for v in 0..vertex_count.max(table.vertex_count()) as u32 {
+        let c = table.left_most_corner(v);
+        if c == INVALID {
+            continue;
+        }
+        // Start from a seam, where the corners before it get a point of their own anyway.
+        let mut first = c;
+        if !is_hole[v as usize] {
+            for attribute in attributes {
+                if !attribute.is_on_seam(c) {
+                    continue;
+                }
+                let vertex = attribute.vertex(c);
+                let mut act = table.swing_right(c);
+                let mut found = false;
+                while act != c {
+                    if act == INVALID {
+                        return None;
+                    }
+                    if attribute.vertex(act) != vertex {
+                        first = act;
+                        found = true;
+                        break;
+                    }
+                    act = table.swing_right(act);
+                }
+                if found {
+                    break;
+                }
+            }
+        }
+
+        corner_to_point[first as usize] = points;
+        points += 1;
+        let mut previous = first;
+        let mut act = table.swing_right(first);
+        while act != INVALID && act != first {
+            let seam = attributes
+                .iter()
+                .any(|a| a.vertex(act) != a.vertex(previous));
+            corner_to_point[act as usize] = if seam {
+                points += 1;
+                points - 1
+            } else {
+                corner_to_point[previous as usize]
+            };
+            previous = act;
+            act = table.swing_right(act);
+        }
+    }
+    if corner_to_point.contains(&INVALID) {
+        return None;
+    }
+    Some(corner_to_point)
+}
+
+const VERTEX_ATTRIBUTE: u8 = 0;
+const CORNER_ATTRIBUTE: u8 = 1;
+const DEPTH_FIRST: u8 = 0;
+const PREDICTION_DEGREE: u8 = 1;
+
+/// The attributes of an Edgebreaker mesh, each decoder's in the order a traversal of the
+/// connectivity it uses reaches their entries.
+fn decode_edgebreaker_attributes(
+    reader: &mut Reader,
+    mesh: &mut Mesh,
+    table: &CornerTable,
+    attribute_tables: &[AttributeCorners],
+) -> Option<()> {
+    let count = reader.u8()?;
+    let mut decoders = Vec::new();
+    for _ in 0..count {
+        let data = reader.u8()? as i8;
+        let kind = reader.u8()?;
+        let traversal = reader.u8()?;
+        let corners: &dyn Corners = match (kind, data) {
+            (VERTEX_ATTRIBUTE, -1) => table,
+            (VERTEX_ATTRIBUTE, d) if d >= 0 && (d as usize) < attribute_tables.len() => table,
+            (CORNER_ATTRIBUTE, d) if d >= 0 && traversal == DEPTH_FIRST => {
+                attribute_tables.get(d as usize)?
+            }
+            _ => return None,
+        };
+        if traversal != DEPTH_FIRST && traversal != PREDICTION_DEGREE {
+            return None;
+        }
+        decoders.push((corners, traversal));
+    }
+    let attributes = (0..count)
+        .map(|_| read_attributes(reader, mesh))
+        .collect::<Option<Vec<_>>>()?;
+
+    for ((corners, traversal), attributes) in decoders.into_iter().zip(attributes) {
+        let traversal = Traverser::traverse(corners, &mesh.indices, traversal)?;
+        let mut point_to_entry = vec![0; mesh.points];
+        for (corner, &point) in mesh.indices.iter().enumerate() {
+            let vertex = corners.vertex(corner as u32);
+            point_to_entry[point as usize] = get(&traversal.vertex_to_entry, vertex);
+        }
+        let data = MeshData {
+            corners,
+            vertex_to_entry: &traversal.vertex_to_entry,
+            entry_to_corner: &traversal.entry_to_corner,
+        };
+        decode_values(
+            reader,
+            mesh,
+            &attributes,
+            &traversal.sequence,
+            &point_to_entry,
+            Some(&data),
+        )?;
+    }
+    Some(())
+}
+
+/// The order a traversal reached each vertex of a mesh in.
+struct Traversal {
+    /// The point at each entry.
+    sequence: Vec<u32>,
+    /// The corner each entry was reached through.
+    entry_to_corner: Vec<u32>,
+    vertex_to_entry: Vec<u32>,
+}
+
+struct Traverser<'a> {
+    corners: &'a dyn Corners,
+    corner_to_point: &'a [u32],
+    face_visited: Vec<bool>,
+    vertex_visited: Vec<bool>,
+    out: Traversal,
+}
+
+impl<'a> Traverser<'a> {
+    fn traverse(
+        corners: &'a dyn Corners,
+        corner_to_point: &'a [u32],
+        method: u8,
+    ) -> Option<Traversal> {
+        let mut traverser = Traverser {
+            corners,
+            corner_to_point,
+            face_visited: vec![false; corners.face_count()],
+            vertex_visited: vec![false; corners.vertex_count()],
+            out: Traversal {
+                sequence: Vec::new(),
+                entry_to_corner: Vec::new(),
+                vertex_to_entry: vec![INVALID; corners.vertex_count()],
+            },
+        };
+        let mut degrees = vec![0; corners.vertex_count()];
+        let mut stacks = [Vec::new(), Vec::new(), Vec::new()];
+        for face in 0..corners.face_count() as u32 {
+            match method {
+                DEPTH_FIRST => traverser.depth_first(face * 3)?,
+                _ => traverser.by_degree(face * 3, &mut degrees, &mut stacks)?,
+            }
+        }
+        Some(traverser.out)
+    }
+
+    fn is_face_visited(&self, corner: u32) -> bool {
+        corner == INVALID || self.face_visited[corner as usize / 3]
+    }
+
+    fn is_vertex_visited(&self, corner: u32) -> Option<bool> {
+        let vertex = self.corners.vertex(corner);
+        self.vertex_visited.get(vertex as usize).copied()
+    }
+
+    /// Give the vertex at `corner` the next entry, if it doesn't have one.
+    fn visit_vertex(&mut self, corner: u32) -> Option<()> {
+        let vertex = self.corners.vertex(corner) as usize;
+        if !*self.vertex_visited.get(vertex)? {
+            self.vertex_visited[vertex] = true;
+            self.out.vertex_to_entry[vertex] = self.out.sequence.len() as u32;
+            self.out
+                .sequence
+                .push(*self.corner_to_point.get(corner as usize)?);
+            self.out.entry_to_corner.push(corner);
+        }
+        Some(())
+    }
+
+    /// Draco's `DepthFirstTraverser`.
+    fn depth_first(&mut self, corner: u32) -> Option<()> {
+        if self.is_face_visited(corner) {
+            return Some(());
+        }
+        let corners = self.corners;
+        self.visit_vertex(corners.next(corner))?;
+        self.visit_vertex(corners.previous(corner))?;
+
+        let mut stack = vec![corner];
+        while let Some(&top) = stack.last() {
+            let mut corner = top;
+            if self.is_face_visited(corner) {
+                stack.pop();
+                continue;
+            }
+            loop {
+                self.face_visited[corner as usize / 3] = true;
+                if !self.is_vertex_visited(corner)? {
+                    let on_boundary = corners.is_on_boundary(corners.vertex(corner));
+                    self.visit_vertex(corner)?;
+                    if !on_boundary {
+                        corner = corners.right_corner(corner);
+                        if corner == INVALID {
+                            return None;
+                        }
+                        continue;
+                    }
+                }
+                let right = corners.right_corner(corner);
+                let left = corners.left_corner(corner);
+                match (self.is_face_visited(right), self.is_face_visited(left)) {
+                    (true, true) => {
+                        stack.pop();
+                        break;
+                    }
+                    (true, false) => corner = left,
+                    (false, true) => corner = right,
+                    (false, false) => {
+                        // The right face first, then the left one.
+                        *stack.last_mut()? = left;
+                        stack.push(right);
+                        break;
+                    }
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Draco's `MaxPredictionDegreeTraverser`, which goes to the faces whose new vertex the
+    /// most decoded neighbours predict first.
+    fn by_degree(
+        &mut self,
+        corner: u32,
+        degrees: &mut [u32],
+        stacks: &mut [Vec<u32>; 3],
+    ) -> Option<()> {
+        let corners = self.corners;
+        stacks[0].push(corner);
+        let mut best = 0;
+        self.visit_vertex(corners.next(corner))?;
+        self.visit_vertex(corners.previous(corner))?;
+        self.visit_vertex(corner)?;
+
+        while let Some(i) = (best..3).find(|&i| !stacks[i].is_empty()) {
+            best = i;
+            let mut corner = stacks[i].pop()?;
+            if self.is_face_visited(corner) {
+                continue;
+            }
+            loop {
+                self.face_visited[corner as usize / 3] = true;
+                self.visit_vertex(corner)?;
+                let right = corners.right_corner(corner);
+                let left = corners.left_corner(corner);
+                let right_visited = self.is_face_visited(right);
+                let left_visited = self.is_face_visited(left);
+                let mut priority = |corner: u32, visited: &[bool]| -> Option<usize> {
+                    let vertex = corners.vertex(corner) as usize;
+                    if *visited.get(vertex)? {
+                        return Some(0);
+                    }
+                    let degree = degrees.get_mut(vertex)?;
+                    *degree += 1;
+                    Some(if *degree > 1 { 1 } else { 2 })
+                };
+                if !left_visited {
+                    let p = priority(left, &self.vertex_visited)?;
+                    if right_visited && p <= best {
+                        corner = left;
+                        continue;
+                    }
+                    stacks[p].push(left);
+                    best = best.min(p);
+                }
+                if !right_visited {
+                    let p = priority(right, &self.vertex_visited)?;
+                    if p <= best {
+                        corner = right;
+                        continue;
+                    }
+                    stacks[p].push(right);
+                    best = best.min(p);
+                }
+                break;
+            }
+        }
+        Some(())
+    }
+}
+
+const POSITION: u8 = 0;
+
+const GENERIC: u8 = 0;
+const INTEGER: u8 = 1;
+const QUANTIZATION: u8 = 2;
+const NORMALS: u8 = 3;
+
+/// Add the attributes one decoder has to the mesh, returning them and how each is coded.
+fn read_attributes(reader: &mut Reader, mesh: &mut Mesh) -> Option<Vec<(usize, u8)>> {
+    let count = reader.varint_usize()?;
+    if count == 0 || count > 256 {
+        return None;
+    }
+    let mut attributes = Vec::with_capacity(count);
+    for _ in 0..count {
+        let kind = reader.u8()?;
+        let data_type = reader.u8()?;
+        let components = reader.u8()? as usize;
+        let _normalized = reader.u8()?;
+        let unique_id = reader.varint()?.try_into().ok()?;
+        if kind > 4 || data_type == 0 || data_type > DT_FLOAT64 || components == 0 {
+            return None;
+        }
+        attributes.push(mesh.attributes.len());
+        mesh.attributes.push(Attribute {
+            unique_id,
+            kind,
+            data_type,
+            components,
+            values: Vec::new(),
+            portable: Vec::new(),
+            point_to_entry: Vec::new(),
+        });
+    }
+    attributes
+        .into_iter()
+        .map(|index| {
+            let coding = reader.u8()?;
+            if coding > NORMALS {
+                return None;
+            }
+            Some((index, coding))
+        })
+        .collect()
+}
+
+/// Decode the values of one decoder's `attributes`, which have an entry for each point of
+/// `sequence`. `data` is the connectivity their predictions use, if the mesh has one.
+fn decode_values(
+    reader: &mut Reader,
+    mesh: &mut Mesh,
+    attributes: &[(usize, u8)],
+    sequence: &[u32],
+    point_to_entry: &[u32],
+    data: Option<&MeshData>,
+) -> Option<()> {
+    let entries = sequence.len();
+    for &(index, _) in attributes {
+        mesh.attributes[index].point_to_entry = point_to_entry.to_vec();
+    }
+    for &(index, coding) in attributes {
+        let attribute = &mesh.attributes[index];
+        if coding == GENERIC {
+            let size = data_type_size(attribute.data_type)?;
+            let count = entries.checked_mul(attribute.components)?;
+            let bytes = reader.bytes(count.checked_mul(size)?)?;
+            let values = bytes
+                .chunks_exact(size)
+                .map(|b| read_value(b, attribute.data_type))
+                .collect();
+            mesh.attributes[index].values = values;
+        } else {
+            let components = if coding == NORMALS {
+                2
+            } else {
+                attribute.components
+            };
+            let portable = decode_integers(reader, mesh, coding, components, sequence, data)?;
+            mesh.attributes[index].portable = portable;
+        }
+    }
+
+    // What turns the integers back into values comes after all of them.
+    for &(index, coding) in attributes {
+        let attribute = &mut mesh.attributes[index];
+        match coding {
+            INTEGER => {
+                let data_type = attribute.data_type;
+                attribute.values = attribute
+                    .portable
+                    .iter()
+                    .map(|&v| read_value(&v.to_le_bytes(), data_type))
+                    .collect();
+            }
+            QUANTIZATION => {
+                let minimum = (0..attribute.components)
+                    .map(|_| reader.f32())
+                    .collect::<Option<Vec<_>>>()?;
+                let range = reader.f32()?;
+                let bits = reader.u8()? as u32;
+                if bits == 0 || bits > 30 {
+                    return None;
+                }
+                let delta = range / ((1u32 << bits) - 1) as f32;
+                attribute.values = attribute
+                    .portable
+                    .iter()
+                    .zip(minimum.iter().cycle())
+                    .map(|(&v, &minimum)| (v as f32 * delta + minimum) as f64)
+                    .collect();
+            }
+            NORMALS => {
+                if attribute.components != 3 {
+                    return None;
+                }
+                let octahedron = Octahedron::new(reader.u8()? as u32)?;
+                attribute.values = attribute
+                    .portable
+                    .chunks_exact(2)
+                    .flat_map(|st| octahedron.to_unit_vector(st[0], st[1]).to_vec())
+                    .map(f64::from)
+                    .collect();
+            }
+            _ => {}
+        }
+        if attribute.values.len() != entries * attribute.components {
+            return None;
+        }
+    }
+    Some(())
+}
+
+fn data_type_size(data_type: u8) -> Option<usize> {
+    match data_type {
+        DT_INT8 | DT_UINT8 => Some(1),
+        DT_INT16 | DT_UINT16 => Some(2),
+        DT_INT32 | DT_UINT32 | DT_FLOAT32 => Some(4),
+        DT_FLOAT64 => Some(8),
+        _ => None,
+    }
+}
+
+/// A value of `data_type` from the start of `bytes`, which are long enough for it.
+fn read_value(bytes: &[u8], data_type: u8) -> f64 {
+    match data_type {
+        DT_INT8 => bytes[0] as i8 as f64,
+        DT_UINT8 => bytes[0] as f64,
+        DT_INT16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        DT_UINT16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        DT_INT32 => i32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+        DT_UINT32 => u32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+        DT_FLOAT32 => f32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+        _ => f64::from_le_bytes(bytes[..8].try_into().unwrap()),
+    }
+}
+
+const PREDICTION_NONE: i8 = -2;
+const PREDICTION_DIFFERENCE: i8 = 0;
+const PARALLELOGRAM: i8 = 1;
+const MULTI_PARALLELOGRAM: i8 = 2;
+const CONSTRAINED_MULTI_PARALLELOGRAM: i8 = 4;
+const TEX_COORDS_PORTABLE: i8 = 5;
+const GEOMETRIC_NORMAL: i8 = 6;
+
+const TRANSFORM_WRAP: i8 = 1;
+const TRANSFORM_OCTAHEDRON: i8 = 2;
+const TRANSFORM_OCTAHEDRON_CANONICALIZED: i8 = 3;
+
+/// The connectivity an attribute decoder's predictions use.
+struct MeshData<'a> {
+    corners: &'a dyn Corners,
+    vertex_to_entry: &'a [u32],
+    entry_to_corner: &'a [u32],
+}
+
+impl MeshData<'_> {
+    fn entry(&self, corner: u32) -> usize {
+        get(self.vertex_to_entry, self.corners.vertex(corner)) as usize
+    }
+}
+
+/// The integers of an attribute coded with `coding`, with `components` of them for each entry
+/// of `sequence`.
+fn decode_integers(
+    reader: &mut Reader,
+    mesh: &Mesh,
+    coding: u8,
+    components: usize,
+    sequence: &[u32],
+    data: Option<&MeshData>,
+) -> Option<Vec<i32>> {
+    let mut method = reader.u8()? as i8;
+    let mut transform = None;
+    if method != PREDICTION_NONE {
+        let transform_type = reader.u8()? as i8;
+        if !(-1..=GEOMETRIC_NORMAL).contains(&method)
+            || !(-1..=TRANSFORM_OCTAHEDRON_CANONICALIZED).contains(&transform_type)
+        {
+            return None;
+        }
+        // Schemes that need connectivity fall back to differences without it.
+        if data.is_none() {
+            method = PREDICTION_DIFFERENCE;
+        }
+        // Normals are only predicted in octahedral coordinates, and other values only wrapped
+        // around their range; anything else isn't predicted at all.
+        let octahedral = transform_type == TRANSFORM_OCTAHEDRON
+            || transform_type == TRANSFORM_OCTAHEDRON_CANONICALIZED;
+        if octahedral && coding == NORMALS || transform_type == TRANSFORM_WRAP && coding != NORMALS
+        {
+            transform = Some(transform_type);
+        }
+    }
+
+    let count = sequence.len().checked_mul(components)?;
+    let values = if reader.u8()? > 0 {
+        decode_symbols(reader, count, components)?
+    } else {
+        let size = reader.u8()? as usize;
+        if size == 0 || size > 4 {
+            return None;
+        }
+        let bytes = reader.bytes(count.checked_mul(size)?)?;
+        bytes.chunks_exact(size).map(read_le).collect()
+    };
+    let transform_type = match transform {
+        Some(transform_type) => transform_type,
+        None => return Some(values.into_iter().map(to_signed).collect()),
+    };
+    // Octahedral corrections are never negative.
+    let corrections: Vec<i32> = if transform_type == TRANSFORM_WRAP {
+        values.into_iter().map(to_signed).collect()
+    } else {
+        values.into_iter().map(|v| v as i32).collect()
+    };
+
+    let parent = mesh.attributes.iter().find(|a| a.kind == POSITION);
+    let mut prediction = Prediction {
+        data,
+        sequence,
+        components,
+        parent: parent.map(|p| (p.portable.as_slice(), p.point_to_entry.as_slice())),
+        transform: Transform::Wrap { min: 0, max: 0 },
+        crease_edges: Default::default(),
+        orientations: Vec::new(),
+        flips: None,
+    };
+    match method {
+        CONSTRAINED_MULTI_PARALLELOGRAM => {
+            for crease_edges in &mut prediction.crease_edges {
+                let count = reader.varint_usize()?;
+                if count > data?.corners.face_count() * 3 {
+                    return None;
+                }
+                if count > 0 {
+                    let mut bits = BitDecoder::start(reader)?;
+                    *crease_edges = (0..count).map(|_| bits.read()).collect();
+                }
+            }
+        }
+        TEX_COORDS_PORTABLE => {
+            let count: usize = reader.i32()?.try_into().ok()?;
+            let mut bits = BitDecoder::start(reader)?;
+            let mut last = true;
+            for _ in 0..count {
+                if !bits.read() {
+                    last = !last;
+                }
+                prediction.orientations.push(last);
+            }
+        }
+        _ => {}
+    }
+    prediction.transform = Transform::read(reader, transform_type)?;
+    if method == GEOMETRIC_NORMAL {
+        prediction.flips = Some(BitDecoder::start(reader)?);
+    }
+    prediction.compute(method, &corrections)
+}
+
+/// What a predicted value and its correction make: either their sum wrapped around the range
+/// of the values, or their sum in octahedral coordinates, wrapped around the octahedron.
+enum Transform {
+    Wrap {
+        min: i32,
+        max: i32,
+    },
+    Octahedron {
+        octahedron: Octahedron,
+        canonicalized: bool,
+    },
+}
+
+impl Transform {
+    fn read(reader: &mut Reader, transform_type: i8) -> Option<Self> {
+        if transform_type == TRANSFORM_WRAP {
+            let (min, max) = (reader.i32()?, reader.i32()?);
+            if min > max || max as i64 - min as i64 >= i32::MAX as i64 {
+                return None;
+            }
+            return Some(Transform::Wrap { min, max });
+        }
+        let max_quantized = reader.i32()?;
+        if max_quantized <= 0 || max_quantized % 2 == 0 {
+            return None;
+        }
+        Some(Transform::Octahedron {
+            octahedron: Octahedron::new(32 - max_quantized.leading_zeros())?,
+            canonicalized: transform_type == TRANSFORM_OCTAHEDRON_CANONICALIZED,
+        })
+    }
+
+    fn apply(&self, predicted: &[i32], corrections: &[i32], out: &mut [i32]) {
+        match *self {
+            Transform::Wrap { min, max } => {
+                let range = max - min + 1;
+                for ((out, &p), &c) in out.iter_mut().zip(predicted).zip(corrections) {
+                    let v = p.clamp(min, max).wrapping_add(c);
+                    *out = if v > max {
+                        v - range
+                    } else if v < min {
+                        v + range
+                    } else {
+                        v
+                    };
+                }
+            }
+            Transform::Octahedron {
+                octahedron: o,
+                canonicalized,
+            } => {
+                let center = o.center;
+                let (mut s, mut t) = (predicted[0] - center, predicted[1] - center);
+                let in_diamond = o.is_in_diamond(s, t);
+                if !in_diamond {
+                    let (is, it) = o.invert_diamond(s, t);
+                    s = is;
+                    t = it;
+                }
+                // Canonicalized predictions are turned into the bottom left quadrant first.
+                let bottom_left = !canonicalized || s == 0 && t == 0 || s < 0 && t <= 0;
+                let rotation = match (s.signum(), t.signum()) {
+                    (0, 0) => 0,
+                    (0, 1) => 3,
+                    (0, _) => 1,
+                    (1, -1) => 1,
+                    (1, _) => 2,
+                    (_, 1) => 3,
+                    _ => 0,
+                };
+                if !bottom_left {
+                    let (rs, rt) = rotate(s, t, rotation);
+                    s = rs;
+                    t = rt;
+                }
+                let (mut s, mut t) = (
+                    o.mod_max(s.wrapping_add(corrections[0])),
+                    o.mod_max(t.wrapping_add(corrections[1])),
+                );
+                if !bottom_left {
+                    let (rs, rt) = rotate(s, t, (4 - rotation) % 4);
+                    s = rs;
+                    t = rt;
+                }
+                if !in_diamond {
+                    let (is, it) = o.invert_diamond(s, t);
+                    s = is;
+                    t = it;
+                }
+                out[0] = s + center;
+                out[1] = t + center;
+            }
+        }
+    }
+}
+
+fn rotate(s: i32, t: i32, rotation: u32) -> (i32, i32) {
+    match rotation {
+        1 => (t, -s),
+        2 => (-s, -t),
+        3 => (-t, s),
+        _ => (s, t),
+    }
+}
+
+/// Draco's `OctahedronToolBox`, for unit vectors quantized to octahedral coordinates of `bits`
+/// each.
+#[derive(Clone, Copy)]
+struct Octahedron {
+    max_quantized: i32,
+    max: i32,
+    center: i32,
+}
+
+impl Octahedron {
+    fn new(bits: u32) -> Option<Self> {
+        if !(2..=30).contains(&bits) {
+            return None;
+        }
+        let max_quantized = (1 << bits) - 1;
+        Some(Octahedron {
+            max_quantized,
+            max: max_quantized - 1,
+            center: (max_quantized - 1) / 2,
+        })
+    }
+
+    fn is_in_diamond(&self, s: i32, t: i32) -> bool {
+        s.abs() + t.abs() <= self.center
+    }
+
+    fn invert_diamond(&self, s: i32, t: i32) -> (i32, i32) {
+        let (sign_s, sign_t) = if s >= 0 && t >= 0 {
+            (1, 1)
+        } else if s <= 0 && t <= 0 {
+            (-1, -1)
+        } else {
+            (if s > 0 { 1 } else { -1 }, if t > 0 { 1 } else { -1 })
+        };
+        let (corner_s, corner_t) = (sign_s * self.center, sign_t * self.center);
+        let (s, t) = (2 * s - corner_s, 2 * t - corner_t);
+        let (s, t) = if sign_s * sign_t >= 0 {
+            (-t, -s)
+        } else {
+            (t, s)
+        };
+        ((s + corner_s) / 2, (t + corner_t) / 2)
+    }
+
+    fn mod_max(&self, x: i32) -> i32 {
+        if x > self.center {
+            x - self.max_quantized
+        } else if x < -self.center {
+            x + self.max_quantized
+        } else {
+            x
+        }
+    }
+
+    /// Scale a vector to lie on the octahedron.
+    fn canonicalize(&self, v: [i64; 3]) -> [i32; 3] {
+        let sum = v[0].abs() + v[1].abs() + v[2].abs();
+        if sum == 0 {
+            return [self.center, 0, 0];
+        }
+        let center = self.center as i64;
+        let (x, y) = ((v[0] * center / sum) as i32, (v[1] * center / sum) as i32);
+        let z = self.center - x.abs() - y.abs();
+        [x, y, if v[2] >= 0 { z } else { -z }]
+    }
+
+    /// The octahedral coordinates of a vector on the octahedron.
+    fn to_coords(self, v: [i32; 3]) -> (i32, i32) {
+        let (s, t) = if v[0] >= 0 {
+            (v[1] + self.center, v[2] + self.center)
+        } else {
+            (
+                if v[1] < 0 {
+                    v[2].abs()
+                } else {
+                    self.max - v[2].abs()
+                },
+                if v[2] < 0 {
+                    v[1].abs()
+                } else {
+                    self.max - v[1].abs()
+                },
+            )
+        };
+        let (max, center) = (self.max, self.center);
+        if s == 0 && (t == 0 || t == max) || s == max && t == 0 {
+            (max, max)
+        } else if s == 0 && t > center {
+            (s, center - (t - center))
+        } else if s == max && t < center {
+            (s, center + (center - t))
+        } else if t == max && s < center {
+            (center + (center - s), t)
+        } else if t == 0 && s > center {
+            (center - (s - center), t)
+        } else {
+            (s, t)
+        }
+    }
+
+    fn to_unit_vector(self, s: i32, t: i32) -> [f32; 3] {
+        let scale = 1.0 / self.max as f32;
+        let y = s as f32 * scale * 2.0 - 1.0;
+        let z = t as f32 * scale * 2.0 - 1.0;
+        let x = 1.0 - y.abs() - z.abs();
+        let offset = (-x).max(0.0);
+        let y = if y < 0.0 { y + offset } else { y - offset };
+        let z = if z < 0.0 { z + offset } else { z - offset };
+        let norm = x * x + y * y + z * z;
+        if norm < 1e-6 {
+            return [0.0; 3];
+        }
+        let d = 1.0 / norm.sqrt();
+        [x * d, y * d, z * d]
+    }
+}
+
+/// How the values of an attribute were predicted from those decoded before them, and what's
+/// needed to make the same predictions.
+struct Prediction<'a> {
+    data: Option<&'a MeshData<'a>>,
+    sequence: &'a [u32],
+    components: usize,
+    /// The portable values of the positions, and the entry each point uses.
+    parent: Option<(&'a [i32], &'a [u32])>,
+    transform: Transform,
+    /// Which parallelograms aren't used, by how many of them there were.
+    crease_edges: [Vec<bool>; 4],
+    /// Which side of the edge opposite it each texture coordinate is on, from the last one.
+    orientations: Vec<bool>,
+    /// Whether each normal points the other way from the predicted one.
+    flips: Option<BitDecoder<'a>>,
+}
+
+impl Prediction<'_> {
+    fn compute(&mut self, method: i8, corrections: &[i32]) -> Option<Vec<i32>> {
+        let k = self.components;
+        let entries = corrections.len() / k;
+        let mesh_method = [
+            PARALLELOGRAM,
+            MULTI_PARALLELOGRAM,
+            CONSTRAINED_MULTI_PARALLELOGRAM,
+            TEX_COORDS_PORTABLE,
+            GEOMETRIC_NORMAL,
+        ]
+        .contains(&method);
+        let data = match self.data {
+            Some(data) if mesh_method => {
+                if data.entry_to_corner.len() != entries {
+                    return None;
+                }
+                Some(data)
+            }
+            None if mesh_method => return None,
+            _ => None,
+        };
+
+        let mut out = vec![0; corrections.len()];
+        let mut predicted = vec![0; k];
+        let mut crease_positions = [0; 4];
+        for p in 0..entries {
+            let found = match data {
+                Some(data) => {
+                    let corner = data.entry_to_corner[p];
+                    match method {
+                        PARALLELOGRAM => {
+                            p > 0 && parallelogram(data, p, corner, &out, k, &mut predicted)
+                        }
+                        MULTI_PARALLELOGRAM => {
+                            p > 0 && multi_parallelogram(data, p, corner, &out, k, &mut predicted)
+                        }
+                        CONSTRAINED_MULTI_PARALLELOGRAM => {
+                            p > 0
+                                && self.constrained_multi_parallelogram(
+                                    data,
+                                    p,
+                                    corner,
+                                    &out,
+                                    &mut crease_positions,
+                                    &mut predicted,
+                                )?
+                        }
+                        TEX_COORDS_PORTABLE => {
+                            self.tex_coords(data, p, corner, &out, &mut predicted)?;
+                            true
+                        }
+                        _ => {
+                            self.normal(data, corner, &mut predicted)?;
+                            true
+                        }
+                    }
+                }
+                None => false,
+            };
+            if !found {
+                // The difference from the previous value.
+                match p {
+                    0 => predicted.iter_mut().for_each(|v| *v = 0),
+                    _ => predicted.copy_from_slice(&out[(p - 1) * k..p * k]),
+                }
+            }
+            let range = p * k..(p + 1) * k;
+            self.transform
+                .apply(&predicted, &corrections[range.clone()], &mut out[range]);
+        }
+        Some(out)
+    }
+
+    /// The portable position of the point at `entry` of the predicted attribute.
+    fn position(&self, entry: usize) -> Option<[i64; 3]> {
+        let (positions, point_to_entry) = self.parent?;
+        let point = *self.sequence.get(entry)?;
+        let entry = *point_to_entry.get(point as usize)? as usize * 3;
+        let p = positions.get(entry..entry + 3)?;
+        Some([p[0] as i64, p[1] as i64, p[2] as i64])
+    }
+
+    /// Draco's `MeshPredictionSchemeConstrainedMultiParallelogram`: the average of the
+    /// parallelograms around the vertex that aren't across a crease.
+    fn constrained_multi_parallelogram(
+        &self,
+        data: &MeshData,
+        p: usize,
+        start: u32,
+        out: &[i32],
+        positions: &mut [usize; 4],
+        predicted: &mut [i32],
+    ) -> Option<bool> {
+        let k = self.components;
+        let corners = data.corners;
+        let mut parallelograms = Vec::new();
+        let mut prediction = vec![0; k];
+        let mut corner = start;
+        let mut first_pass = true;
+        while corner != INVALID {
+            if parallelogram(data, p, corner, out, k, &mut prediction) {
+                parallelograms.push(prediction.clone());
+                if parallelograms.len() == 4 {
+                    break;
+                }
+            }
+            // Around to the left, and then to the right if that reaches a boundary.
+            corner = if first_pass {
+                corners.swing_left(corner)
+            } else {
+                corners.swing_right(corner)
+            };
+            if corner == start {
+                break;
+            }
+            if corner == INVALID && first_pass {
+                first_pass = false;
+                corner = corners.swing_right(start);
+            }
+        }
+
+        let mut used = 0;
+        predicted.iter_mut().for_each(|v| *v = 0);
+        if !parallelograms.is_empty() {
+            let context = parallelograms.len() - 1;
+            for parallelogram in &parallelograms {
+                let crease = *self.crease_edges[context].get(positions[context])?;
+                positions[context] += 1;
+                if !crease {
+                    used += 1;
+                    for (v, &c) in predicted.iter_mut().zip(parallelogram) {
+                        *v = v.wrapping_add(c);
+                    }
+                }
+            }
+        }
+        if used == 0 {
+            return Some(false);
+        }
+        predicted.iter_mut().for_each(|v| *v /= used);
+        Some(true)
+    }
+
+    /// Draco's `MeshPredictionSchemeTexCoordsPortable`: where the triangle the positions make
+    /// puts the texture coordinates of its tip, given those of its other corners.
+    fn tex_coords(
+        &mut self,
+        data: &MeshData,
+        p: usize,
+        corner: u32,
+        out: &[i32],
+        predicted: &mut [i32],
+    ) -> Option<()> {
+        if self.components != 2 {
+            return None;
+        }
+        let corners = data.corners;
+        let next = data.entry(corners.next(corner));
+        let previous = data.entry(corners.previous(corner));
+        let uv = |entry: usize| [out[entry * 2] as i64, out[entry * 2 + 1] as i64];
+        if next < p && previous < p {
+            let (n_uv, p_uv) = (uv(next), uv(previous));
+            if p_uv == n_uv {
+                predicted[0] = p_uv[0] as i32;
+                predicted[1] = p_uv[1] as i32;
+                return Some(());
+            }
+            let tip = self.position(p)?;
+            let next_position = self.position(next)?;
+            let previous_position = self.position(previous)?;
+            let pn = sub(previous_position, next_position);
+            let pn_norm2 = dot(pn, pn);
+            if pn_norm2 != 0 {
+                let cn = sub(tip, next_position);
+                let cn_dot_pn = dot(pn, cn);
+                let pn_uv = [p_uv[0] - n_uv[0], p_uv[1] - n_uv[1]];
+                let n_uv_max = n_uv[0].abs().max(n_uv[1].abs());
+                let pn_uv_max = pn_uv[0].abs().max(pn_uv[1].abs());
+                let pn_max = pn[0].abs().max(pn[1].abs()).max(pn[2].abs());
+                if n_uv_max > i64::MAX / pn_norm2
+                    || cn_dot_pn > i64::MAX / pn_uv_max
+                    || cn_dot_pn > i64::MAX / pn_max
+                {
+                    return None;
+                }
+                let x_uv = [
+                    n_uv[0].wrapping_mul(pn_norm2) + cn_dot_pn.wrapping_mul(pn_uv[0]),
+                    n_uv[1].wrapping_mul(pn_norm2) + cn_dot_pn.wrapping_mul(pn_uv[1]),
+                ];
+                let mut x_position = next_position;
+                for (x, &d) in x_position.iter_mut().zip(&pn) {
+                    *x = x.wrapping_add(cn_dot_pn.wrapping_mul(d) / pn_norm2);
+                }
+                let cx = sub(tip, x_position);
+                let cx_norm2 = dot(cx, cx) as u64;
+                let norm = int_sqrt(cx_norm2.wrapping_mul(pn_norm2 as u64)) as i64;
+                let cx_uv = [pn_uv[1].wrapping_mul(norm), (-pn_uv[0]).wrapping_mul(norm)];
+                let sign = if self.orientations.pop()? { 1 } else { -1 };
+                for i in 0..2 {
+                    let v = x_uv[i].wrapping_add(sign * cx_uv[i]) / pn_norm2;
+                    predicted[i] = v as i32;
+                }
+                return Some(());
+            }
+        }
+        // The value of a neighbour, or the previous value.
+        let entry = if next < p {
+            next
+        } else if p > 0 {
+            p - 1
+        } else {
+            predicted.iter_mut().for_each(|v| *v = 0);
+            return Some(());
+        };
+        predicted.copy_from_slice(&out[entry * 2..entry * 2 + 2]);
+        Some(())
+    }
+
+    /// Draco's `MeshPredictionSchemeGeometricNormal`: the normal of the faces around the
+    /// vertex, weighted by their area, flipped if the encoder says so.
+    fn normal(&mut self, data: &MeshData, corner: u32, predicted: &mut [i32]) -> Option<()> {
+        let octahedron = match self.transform {
+            Transform::Octahedron { octahedron, .. } => octahedron,
+            Transform::Wrap { .. } => return None,
+        };
+        let corners = data.corners;
+        let center = self.position(data.entry(corner))?;
+        let mut normal = [0i64; 3];
+        for c in corners.corners_around(corner) {
+            let next = sub(self.position(data.entry(corners.next(c)))?, center);
+            let previous = sub(self.position(data.entry(corners.previous(c)))?, center);
+            let cross = [
+                next[1].wrapping_mul(previous[2]) - next[2].wrapping_mul(previous[1]),
+                next[2].wrapping_mul(previous[0]) - next[0].wrapping_mul(previous[2]),
+                next[0].wrapping_mul(previous[1]) - next[1].wrapping_mul(previous[0]),
+            ];
+            for (n, c) in normal.iter_mut().zip(&cross) {
+                *n = n.wrapping_add(*c);
+            }
+        }
+        let upper_bound = 1 << 29;
+        let sum = normal.iter().fold(0i64, |s, n| s.wrapping_add(n.abs()));
+        if sum > upper_bound {
+            let quotient = sum / upper_bound;
+            normal.iter_mut().for_each(|n| *n /= quotient);
+        }
+        let normal = normal.map(|n| n as i32 as i64);
+        let mut v = octahedron.canonicalize(normal);
+        if self.flips.as_mut()?.read() {
+            v = v.map(|c| -c);
+        }
+        let (s, t) = octahedron.to_coords(v);
+        predicted[0] = s;
+        predicted[1] = t;
+        Some(())
+    }
+}
+
+/// Draco's `MeshPredictionSchemeParallelogram`: the vertex opposite `corner` across the edge
+/// it faces, mirrored to complete a parallelogram, if all three of them are decoded.
+fn parallelogram(
+    data: &MeshData,
+    p: usize,
+    corner: u32,
+    out: &[i32],
+    k: usize,
+    predicted: &mut [i32],
+) -> bool {
+    let corners = data.corners;
+    let opposite = corners.opposite(corner);
+    if opposite == INVALID {
+        return false;
+    }
+    let o = data.entry(opposite);
+    let n = data.entry(corners.next(opposite));
+    let v = data.entry(corners.previous(opposite));
+    if o >= p || n >= p || v >= p {
+        return false;
+    }
+    for (c, predicted) in predicted.iter_mut().enumerate() {
+        let value = out[n * k + c] as i64 + out[v * k + c] as i64 - out[o * k + c] as i64;
+        *predicted = value as i32;
+    }
+    true
+}
+
+/// The average of every parallelogram around the vertex.
+fn multi_parallelogram(
+    data: &MeshData,
+    p: usize,
+    start: u32,
+    out: &[i32],
+    k: usize,
+    predicted: &mut [i32],
+) -> bool {
+    let mut prediction = vec![0; k];
+    let mut count = 0;
+    predicted.iter_mut().for_each(|v| *v = 0);
+    let mut corner = start;
+    while corner != INVALID {
+        if parallelogram(data, p, corner, out, k, &mut prediction) {
+            for (v, &c) in predicted.iter_mut().zip(&prediction) {
+                *v = v.wrapping_add(c);
+            }
+            count += 1;
+        }
+        corner = data.corners.swing_right(corner);
+        if corner == start {
+            break;
+        }
+    }
+    if count == 0 {
+        return false;
+    }
+    predicted.iter_mut().for_each(|v| *v /= count);
+    true
+}
+
+fn sub(a: [i64; 3], b: [i64; 3]) -> [i64; 3] {
+    [
+        a[0].wrapping_sub(b[0]),
+        a[1].wrapping_sub(b[1]),
+        a[2].wrapping_sub(b[2]),
+    ]
+}
+
+fn dot(a: [i64; 3], b: [i64; 3]) -> i64 {
+    (0..3).fold(0i64, |s, i| s.wrapping_add(a[i].wrapping_mul(b[i])))
+}
+
+fn int_sqrt(number: u64) -> u64 {
+    if number == 0 {
+        return 0;
+    }
+    let mut act = number;
+    let mut root = 1u64;
+    while act >= 2 {
+        root *= 2;
+        act /= 4;
+    }
+    loop {
+        root = (root + number / root) / 2;
+        if root.wrapping_mul(root) <= number {
+            return root;
+        }
+    }
+}
@@ -0,0 +1,804 @@
+//! Image-based lighting from an equirectangular HDR environment, which is also drawn as the sky.
+//!
+//! The environment is prefiltered on the CPU when loaded: into an irradiance map for diffuse
+//! lighting, and into levels of increasing roughness for specular reflections, stacked in one
+//! texture. golem only has 8-bit RGBA textures, so the radiance is stored as RGBM (see
+//! [`encode_rgbm`]), which clamps it at [`RGBM_RANGE`]. The split-sum BRDF term comes from an
+//! analytic fit rather than a lookup texture.
+
+use std::convert::TryInto;
+use std::num::NonZeroU32;
+
+use golem::*;
+use nalgebra_glm as glm;
+
+/// Largest radiance the environment textures hold.
+pub const RGBM_RANGE: f32 = 16.0;
+/// Roughness 0, 0.25, 0.5, 0.75 and 1.
+const SPECULAR_LEVELS: u32 = 5;
+/// Size of the sharpest specular level. Every level is this wide and half as tall as the one
+/// before, so they can all wrap around horizontally in one texture.
+const SPECULAR_WIDTH: u32 = 128;
+const IRRADIANCE_WIDTH: u32 = 32;
+const MAX_SKY_WIDTH: u32 = 1024;
+
+const IRRADIANCE_BIND_POINT: u32 = 4;
+const SPECULAR_BIND_POINT: u32 = 5;
+const SKY_BIND_POINT: u32 = 1;
+
+/// How the shaded colors are turned into what is written to the framebuffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapping {
+    /// Multiplies the color before the operator.
+    pub exposure: f32,
+    pub operator: ToneOperator,
+    /// Colors in textures are taken to be encoded with this gamma, and the output is encoded
+    /// with it. 1 leaves colors as they are.
+    pub gamma: f32,
+}
+
+impl Default for ToneMapping {
+    /// Colors written as they are shaded.
+    fn default() -> ToneMapping {
+        ToneMapping {
+            exposure: 1.0,
+            operator: ToneOperator::Clamp,
+            gamma: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneOperator {
+    /// Anything brighter than 1 is clipped.
+    Clamp,
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// Colors are written encoded for a [`post_process::PostProcess`] target, which tone maps
+    /// them later. `gamma` still decodes textures.
+    Hdr,
+}
+
+impl ToneMapping {
+    /// The tone mapping a viewer would pick for HDR lighting.
+    pub fn filmic() -> ToneMapping {
+        ToneMapping {
+            exposure: 1.0,
+            operator: ToneOperator::Aces,
+            gamma: 2.2,
+        }
+    }
+
+    /// What to draw into a post-process chain with, when the chain applies this tone mapping
+    /// (see [`ToneMapping::post_settings`]).
+    pub fn hdr_target(&self) -> ToneMapping {
+        ToneMapping {
+            exposure: 1.0,
+            operator: ToneOperator::Hdr,
+            gamma: self.gamma,
+        }
+    }
+
+    /// `settings` with this exposure, operator and gamma.
+    pub fn post_settings(&self, settings: post_process::Settings) -> post_process::Settings {
+        let operator = match self.operator {
+            ToneOperator::Clamp | ToneOperator::Hdr => post_process::Operator::Clamp,
+            ToneOperator::Reinhard => post_process::Operator::Reinhard,
+            ToneOperator::Aces => post_process::Operator::Aces,
+        };
+        post_process::Settings {
+            exposure: self.exposure,
+            operator,
+            gamma: self.gamma,
+            ..settings
+        }
+    }
+
+    pub fn set_uniforms(&self, shader: &ShaderProgram) -> Result<(), GolemError> {
+        let operator = match self.operator {
+            ToneOperator::Clamp => 0.0,
+            ToneOperator::Reinhard => 1.0,
+            ToneOperator::Aces => 2.0,
+            ToneOperator::Hdr => 3.0,
+        };
+        shader.set_uniform("exposure", UniformValue::Float(self.exposure))?;
+        shader.set_uniform("tone_operator", UniformValue::Float(operator))?;
+        shader.set_uniform("gamma", UniformValue::Float(self.gamma))
+    }
+}
+
+/// GLSL for `tone_map`, which applies a [`ToneMapping`], and `decode_rgbm`. It needs
+/// [`post_process::ENCODE_GLSL`] before it.
+pub const TONE_MAPPING_GLSL: &str = r#"
+        vec3 tone_map(vec3 color) {
+            color *= exposure;
+            if (tone_operator == 3.0) {
+                return encode_hdr(color);
+            } else if (tone_operator == 1.0) {
+                color = color / (1.0 + color);
+            } else if (tone_operator == 2.0) {
+                color = (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
+            }
+            return pow(clamp(color, 0.0, 1.0), vec3(1.0 / gamma));
+        }
+        vec3 decode_rgbm(vec4 rgbm) {
+            vec3 s = rgbm.rgb * rgbm.a;
+            return s * s * 16.0;
+        }
+        vec2 equirect(vec3 d) {
+            return vec2(
+                0.5 + atan(d.x, -d.z) / 6.2831853,
+                acos(clamp(d.y, -1.0, 1.0)) / 3.1415927
+            );
+        }
+"#;
+
+/// The uniforms `TONE_MAPPING_GLSL` uses.
+pub fn tone_mapping_uniforms() -> Vec<Uniform> {
+    let float = || UniformType::Scalar(NumberType::Float);
+    vec![
+        Uniform::new("exposure", float()),
+        Uniform::new("tone_operator", float()),
+        Uniform::new("gamma", float()),
+    ]
+}
+
+/// GLSL sampling the environment maps, for the lighting shader.
+pub const LIGHTING_GLSL: &str = r#"
+        vec3 specular_level(vec2 uv, float level) {
+            float height = 64.0 / exp2(level);
+            float y = clamp(uv.y * height, 0.5, height - 0.5) + 128.0 - 2.0 * height;
+            return decode_rgbm(texture(specular_map, vec2(uv.x, y / 128.0)));
+        }
+        vec3 prefiltered(vec3 d, float roughness) {
+            vec2 uv = equirect(d);
+            float level = roughness * 4.0;
+            float l0 = floor(level);
+            float l1 = min(l0 + 1.0, 4.0);
+            return mix(specular_level(uv, l0), specular_level(uv, l1), level - l0);
+        }
+        vec3 irradiance(vec3 n) {
+            return decode_rgbm(texture(irradiance_map, equirect(n)));
+        }
+        // Karis' fit of the split-sum scale and bias to F0.
+        vec2 environment_brdf(float n_dot_v, float roughness) {
+            vec4 r = roughness * vec4(-1.0, -0.0275, -0.572, 0.022)
+                + vec4(1.0, 0.0425, 1.04, -0.04);
+            float a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+            return vec2(-1.04, 1.04) * a004 + r.zw;
+        }
+"#;
+
+/// The uniforms [`Environment::set_uniforms`] sets, for every shader drawing materials.
+pub fn uniforms() -> Vec<Uniform> {
+    let mut uniforms = vec![
+        Uniform::new("environment", UniformType::Scalar(NumberType::Float)),
+        Uniform::new("eye", UniformType::Vector(NumberType::Float, Dimension::D4)),
+        Uniform::new("irradiance_map", UniformType::Sampler2D),
+        Uniform::new("specular_map", UniformType::Sampler2D),
+    ];
+    uniforms.extend(tone_mapping_uniforms());
+    uniforms
+}
+
+/// The eye position of the view-projection `mvp_matrix`, with w = 1, or for an orthographic
+/// projection the direction towards the eye, with w = 0.
+pub fn eye(mvp_matrix: &glm::Mat4) -> glm::Vec4 {
+    // The eye is the point the projection sends to infinity.
+    let eye = glm::inverse(mvp_matrix) * glm::vec4(0.0, 0.0, 1.0, 0.0);
+    if eye.w.abs() > 1e-6 {
+        glm::vec4(eye.x / eye.w, eye.y / eye.w, eye.z / eye.w, 1.0)
+    } else {
+        let towards = -glm::normalize(&eye.xyz());
+        glm::vec4(towards.x, towards.y, towards.z, 0.0)
+    }
+}
+
+/// Radiance in every direction, as a linear RGB equirectangular image: +Y up, with -Z in the
+/// middle column.
+#[derive(Clone, Debug)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<glm::Vec3>,
+}
+
+impl HdrImage {
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<HdrImage> {
+        HdrImage::from_slice(&std::fs::read(path)?)
+    }
+
+    /// Read a Radiance `.hdr` file, flat or run-length encoded, in the usual `-Y height +X width`
+    /// orientation.
+    pub fn from_slice(data: &[u8]) -> std::io::Result<HdrImage> {
+        let invalid = |message: &str| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_owned())
+        };
+        let mut lines = data.split(|&b| b == b'\n');
+        let mut read = 0;
+        let mut line = || {
+            let line = lines.next()?;
+            read += line.len() + 1;
+            Some(String::from_utf8_lossy(line).into_owned())
+        };
+
+        let magic = line().unwrap_or_default();
+        if !magic.starts_with("#?") {
+            return Err(invalid("not a Radiance HDR file"));
+        }
+        loop {
+            let header = line().ok_or_else(|| invalid("the header doesn't end"))?;
+            if header.is_empty() {
+                break;
+            }
+            if let Some(format) = header.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(invalid("only the RGBE format is supported"));
+                }
+            }
+        }
+        let resolution = line().ok_or_else(|| invalid("no resolution"))?;
+        let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => (height.parse().ok(), width.parse().ok()),
+            _ => return Err(invalid("only the -Y +X orientation is supported")),
+        };
+        let (width, height): (usize, usize) = match (width, height) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+            _ => return Err(invalid("bad resolution")),
+        };
+
+        let mut data = data.get(read..).unwrap_or(&[]);
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut scanline = vec![[0u8; 4]; width];
+        for _ in 0..height {
+            data = read_scanline(data, &mut scanline).ok_or_else(|| invalid("truncated"))?;
+            pixels.extend(scanline.iter().map(|&rgbe| from_rgbe(rgbe)));
+        }
+        Ok(HdrImage {
+            width: width as u32,
+            height: height as u32,
+            pixels,
+        })
+    }
+
+    /// A clear sky over a grey ground, with the sun where the default key light comes from.
+    pub fn sky() -> HdrImage {
+        let (width, height) = (256, 128);
+        let sun = glm::normalize(&glm::vec3(1.0, 1.0, 0.0));
+        let horizon = glm::vec3(1.0, 0.95, 0.9) * 1.5;
+        let zenith = glm::vec3(0.25, 0.45, 0.9);
+        let ground = glm::vec3(0.3, 0.27, 0.25) * 0.6;
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let d = direction(x, y, width, height);
+                let sky = if d.y >= 0.0 {
+                    lerp(&horizon, &zenith, d.y.sqrt())
+                } else {
+                    lerp(&horizon, &ground, (-d.y * 8.0).min(1.0))
+                };
+                let glow = d.dot(&sun).max(0.0).powf(256.0);
+                sky + glm::vec3(1.0, 0.9, 0.7) * (glow * 12.0)
+            })
+            .collect();
+        HdrImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Bilinear, wrapping around horizontally.
+    fn sample(&self, u: f32, v: f32) -> glm::Vec3 {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let x = u * width as f32 - 0.5;
+        let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let at = |x: i64, y: i64| {
+            self.pixels[(y.min(height - 1) * width + x.rem_euclid(width)) as usize]
+        };
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = lerp(&at(x0, y0), &at(x0 + 1, y0), fx);
+        let bottom = lerp(&at(x0, y0 + 1), &at(x0 + 1, y0 + 1), fx);
+        lerp(&top, &bottom, fy)
+    }
+
+    /// Resampled to `width` by `height`, averaging enough samples per pixel to cover the
+    /// pixels of `self` it spans.
+    pub fn resize(&self, width: u32, height: u32) -> HdrImage {
+        let samples_x = self.width.div_ceil(width).max(1);
+        let samples_y = self.height.div_ceil(height).max(1);
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = glm::Vec3::zeros();
+                for sy in 0..samples_y {
+                    for sx in 0..samples_x {
+                        let u = (x as f32 + (sx as f32 + 0.5) / samples_x as f32) / width as f32;
+                        let v = (y as f32 + (sy as f32 + 0.5) / samples_y as f32) / height as f32;
+                        sum += self.sample(u, v);
+                    }
+                }
+                pixels.push(sum / (samples_x * samples_y) as f32);
+            }
+        }
+        HdrImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Cosine-weighted average radiance around each direction, through the first nine
+    /// spherical harmonics (Ramamoorthi & Hanrahan), so that a Lambertian surface reflects
+    /// its albedo times this.
+    pub fn irradiance(&self, width: u32, height: u32) -> HdrImage {
+        let source = self.resize(64, 32);
+        let mut coefficients = [glm::Vec3::zeros(); 9];
+        for (i, radiance) in source.pixels.iter().enumerate() {
+            let (x, y) = (i as u32 % source.width, i as u32 / source.width);
+            let d = direction(x, y, source.width, source.height);
+            let weight = solid_angle(y, source.width, source.height);
+            for (c, basis) in coefficients.iter_mut().zip(&sh_basis(&d)) {
+                *c += radiance * (basis * weight);
+            }
+        }
+        // Convolution with the clamped cosine, divided by pi.
+        let bands = [
+            1.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+        ];
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let d = direction(x, y, width, height);
+                let basis = sh_basis(&d);
+                (0..9).fold(glm::Vec3::zeros(), |sum, i| {
+                    sum + coefficients[i] * (bands[i] * basis[i])
+                })
+            })
+            .map(|e| glm::max(&e, 0.0))
+            .collect();
+        HdrImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Average radiance around each direction weighted by the GGX distribution of
+    /// `roughness`, taking the normal and the view to be the reflected direction.
+    pub fn prefilter(&self, roughness: f32, width: u32, height: u32) -> HdrImage {
+        if roughness == 0.0 {
+            return self.resize(width, height);
+        }
+        let source = self.resize(width, height);
+        let texels: Vec<(glm::Vec3, f32)> = (0..source.height)
+            .flat_map(|y| (0..source.width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                (
+                    direction(x, y, source.width, source.height),
+                    solid_angle(y, source.width, source.height),
+                )
+            })
+            .collect();
+        let a2 = roughness.powi(4);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let n = direction(x, y, width, height);
+                let mut sum = glm::Vec3::zeros();
+                let mut total = 0.0;
+                for ((l, angle), radiance) in texels.iter().zip(&source.pixels) {
+                    let n_dot_l = n.dot(l);
+                    if n_dot_l <= 0.0 {
+                        continue;
+                    }
+                    // With n = v, the half vector's angle to n is half that of l.
+                    let n_dot_h = ((1.0 + n_dot_l) * 0.5).sqrt();
+                    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+                    let weight = a2 / (d * d) * n_dot_l * angle;
+                    sum += radiance * weight;
+                    total += weight;
+                }
+                if total > 0.0 {
+                    sum / total
+                } else {
+                    glm::Vec3::zeros()
+                }
+            })
+            .collect();
+        HdrImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// RGBA8 pixels, encoded with [`encode_rgbm`].
+    fn rgbm(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|&c| encode_rgbm(c)).collect()
+    }
+}
+
+/// The next scanline of `data` into `scanline`, returning what follows it.
+fn read_scanline<'a>(data: &'a [u8], scanline: &mut [[u8; 4]]) -> Option<&'a [u8]> {
+    let width = scanline.len();
+    let rle = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && ((data[2] as usize) << 8 | data[3] as usize) == width;
+    if !rle {
+        let (flat, rest) = (data.get(..width * 4)?, data.get(width * 4..)?);
+        for (pixel, rgbe) in scanline.iter_mut().zip(flat.chunks_exact(4)) {
+            pixel.copy_from_slice(rgbe);
+        }
+        return Some(rest);
+    }
+
+    // Each channel of the scanline in turn, as runs and literals.
+    let mut data = &data[4..];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, rest) = data.split_first()?;
+            if count > 128 {
+                let count = (count - 128) as usize;
+                let (&value, rest) = rest.split_first()?;
+                for pixel in scanline.get_mut(x..x + count)? {
+                    pixel[channel] = value;
+                }
+                x += count;
+                data = rest;
+            } else {
+                let count = count as usize;
+                if count == 0 {
+                    return None;
+                }
+                for (pixel, &value) in scanline
+                    .get_mut(x..x + count)?
+                    .iter_mut()
+                    .zip(rest.get(..count)?)
+                {
+                    pixel[channel] = value;
+                }
+                x += count;
+                data = &rest[count..];
+            }
+        }
+    }
+    Some(data)
+}
+
+fn from_rgbe([r, g, b, e]: [u8; 4]) -> glm::Vec3 {
+    if e == 0 {
+        return glm::Vec3::zeros();
+    }
+    let scale = 2f32.powi(e as i32 - 136);
+    glm::vec3(r as f32, g as f32, b as f32) * scale
+}
+
+/// `color` as RGB times a shared multiplier in alpha, of the square root of the color over
+/// [`RGBM_RANGE`], to keep precision in the dark.
+pub fn encode_rgbm(color: glm::Vec3) -> [u8; 4] {
+    let s = glm::min(&glm::max(&color, 0.0), RGBM_RANGE).map(|c| (c / RGBM_RANGE).sqrt());
+    let m = (glm::comp_max(&s) * 255.0).ceil().max(1.0);
+    let rgb = s * (255.0 * 255.0 / m);
+    [
+        rgb.x.round().min(255.0) as u8,
+        rgb.y.round().min(255.0) as u8,
+        rgb.z.round().min(255.0) as u8,
+        m as u8,
+    ]
+}
+
+pub fn decode_rgbm([r, g, b, m]: [u8; 4]) -> glm::Vec3 {
+    let s = glm::vec3(r as f32, g as f32, b as f32) * (m as f32 / (255.0 * 255.0));
+    s.component_mul(&s) * RGBM_RANGE
+}
+
+fn lerp(a: &glm::Vec3, b: &glm::Vec3, t: f32) -> glm::Vec3 {
+    a * (1.0 - t) + b * t
+}
+
+/// Unit vector through the center of pixel (`x`, `y`) of an equirectangular image.
+fn direction(x: u32, y: u32, width: u32, height: u32) -> glm::Vec3 {
+    let phi = ((x as f32 + 0.5) / width as f32 - 0.5) * std::f32::consts::TAU;
+    let theta = (y as f32 + 0.5) / height as f32 * std::f32::consts::PI;
+    glm::vec3(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+/// Solid angle covered by a pixel in row `y` of an equirectangular image.
+fn solid_angle(y: u32, width: u32, height: u32) -> f32 {
+    let theta = (y as f32 + 0.5) / height as f32 * std::f32::consts::PI;
+    let d_theta = std::f32::consts::PI / height as f32;
+    let d_phi = std::f32::consts::TAU / width as f32;
+    theta.sin() * d_theta * d_phi
+}
+
+fn sh_basis(d: &glm::Vec3) -> [f32; 9] {
+    [
+        0.282_095,
+        0.488_603 * d.y,
+        0.488_603 * d.z,
+        0.488_603 * d.x,
+        1.092_548 * d.x * d.y,
+        1.092_548 * d.y * d.z,
+        0.315_392 * (3.0 * d.z * d.z - 1.0),
+        1.092_548 * d.x * d.z,
+        0.546_274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+/// An environment uploaded for lighting and drawing the sky. One can be shared between models.
+pub struct Environment {
+    sky: Texture,
+    irradiance: Texture,
+    specular: Texture,
+    /// Binding takes `&mut`, but the environment is shared.
+    sky_shader: std::cell::RefCell<ShaderProgram>,
+    quad: (VertexBuffer, ElementBuffer),
+}
+
+impl Environment {
+    /// Prefilter `image` and upload it.
+    pub fn new(ctx: &Context, image: &HdrImage) -> Result<Environment, GolemError> {
+        let sky_width = image.width.next_power_of_two().min(MAX_SKY_WIDTH);
+        let sky = upload(ctx, &image.resize(sky_width, sky_width / 2))?;
+        let irradiance = upload(
+            ctx,
+            &image.irradiance(IRRADIANCE_WIDTH, IRRADIANCE_WIDTH / 2),
+        )?;
+
+        // The levels top to bottom, each half as tall as the one above. Rougher levels are
+        // filtered at a lower resolution, like mipmaps, and stretched to the full width.
+        let mut atlas = Vec::with_capacity((SPECULAR_WIDTH * SPECULAR_WIDTH * 4) as usize);
+        for level in 0..SPECULAR_LEVELS {
+            let roughness = level as f32 / (SPECULAR_LEVELS - 1) as f32;
+            let height = (SPECULAR_WIDTH / 2) >> level;
+            let filtered = image.prefilter(roughness, SPECULAR_WIDTH >> level, height);
+            atlas.extend(filtered.resize(SPECULAR_WIDTH, height).rgbm());
+        }
+        atlas.resize((SPECULAR_WIDTH * SPECULAR_WIDTH * 4) as usize, 0);
+        let mut specular = Texture::new(ctx)?;
+        specular.set_image(
+            Some(&atlas),
+            SPECULAR_WIDTH,
+            SPECULAR_WIDTH,
+            ColorFormat::RGBA,
+        );
+        set_sampling(&specular)?;
+
+        let sky_shader = ShaderProgram::new(
+            ctx,
+            ShaderDescription {
+                vertex_input: &[Attribute::new(
+                    "vert_position",
+                    AttributeType::Vector(Dimension::D2),
+                )],
+                fragment_input: &[Attribute::new(
+                    "frag_ndc",
+                    AttributeType::Vector(Dimension::D2),
+                )],
+                uniforms: &vec![
+                    Uniform::new("inverse_mvp", UniformType::Matrix(Dimension::D4)),
+                    Uniform::new("eye", UniformType::Vector(NumberType::Float, Dimension::D4)),
+                    Uniform::new("sky_map", UniformType::Sampler2D),
+                ]
+                .into_iter()
+                .chain(tone_mapping_uniforms())
+                .collect::<Vec<_>>(),
+                vertex_shader: r#" void main() {
+                    gl_Position = vec4(vert_position, 1.0, 1.0);
+                    frag_ndc = vert_position;
+                }"#,
+                fragment_shader: &format!(
+                    r#"{}{}
+                    void main() {{
+                        vec4 far = inverse_mvp * vec4(frag_ndc, 1.0, 1.0);
+                        vec3 d = eye.w == 0.0 ? -eye.xyz : far.xyz / far.w - eye.xyz;
+                        vec3 color = decode_rgbm(texture(sky_map, equirect(normalize(d))));
+                        gl_FragColor = vec4(tone_map(color), 1.0);
+                    }}"#,
+                    post_process::ENCODE_GLSL,
+                    TONE_MAPPING_GLSL
+                ),
+            },
+        )?;
+        let mut vb = VertexBuffer::new(ctx)?;
+        vb.set_data(&[-1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, 1.0]);
+        let mut eb = ElementBuffer::new(ctx)?;
+        eb.set_data(&[0, 1, 2, 2, 3, 0]);
+
+        Ok(Environment {
+            sky,
+            irradiance,
+            specular,
+            sky_shader: std::cell::RefCell::new(sky_shader),
+            quad: (vb, eb),
+        })
+    }
+
+    /// Fill the background with the sky as seen through `mvp_matrix`, without depth testing.
+    pub fn draw_sky(
+        &self,
+        mvp_matrix: &glm::Mat4,
+        tone_mapping: &ToneMapping,
+    ) -> Result<(), GolemError> {
+        let shader = &mut *self.sky_shader.borrow_mut();
+        shader.bind();
+        self.sky
+            .set_active(NonZeroU32::new(SKY_BIND_POINT).unwrap());
+        shader.set_uniform("sky_map", UniformValue::Int(SKY_BIND_POINT as i32))?;
+        let inverse = glm::inverse(mvp_matrix);
+        shader.set_uniform(
+            "inverse_mvp",
+            UniformValue::Matrix4(glm::value_ptr(&inverse).try_into().unwrap()),
+        )?;
+        let eye = eye(mvp_matrix);
+        shader.set_uniform(
+            "eye",
+            UniformValue::Vector4(glm::value_ptr(&eye).try_into().unwrap()),
+        )?;
+        tone_mapping.set_uniforms(shader)?;
+        let (vb, eb) = &self.quad;
+        unsafe { shader.draw(vb, eb, 0..6, GeometryMode::Triangles) }
+    }
+
+    /// Bind the maps for the lighting shader. Without an environment, `white` is bound in their
+    /// place and the shader lights with the lights alone.
+    pub fn set_uniforms(
+        environment: Option<&Environment>,
+        shader: &ShaderProgram,
+        mvp_matrix: &glm::Mat4,
+        white: &Texture,
+    ) -> Result<(), GolemError> {
+        let (irradiance, specular) = match environment {
+            Some(environment) => (&environment.irradiance, &environment.specular),
+            None => (white, white),
+        };
+        irradiance.set_active(NonZeroU32::new(IRRADIANCE_BIND_POINT).unwrap());
+        specular.set_active(NonZeroU32::new(SPECULAR_BIND_POINT).unwrap());
+        shader.set_uniform(
+            "irradiance_map",
+            UniformValue::Int(IRRADIANCE_BIND_POINT as i32),
+        )?;
+        shader.set_uniform(
+            "specular_map",
+            UniformValue::Int(SPECULAR_BIND_POINT as i32),
+        )?;
+        shader.set_uniform(
+            "environment",
+            UniformValue::Float(if environment.is_some() { 1.0 } else { 0.0 }),
+        )?;
+        let eye = eye(mvp_matrix);
+        shader.set_uniform(
+            "eye",
+            UniformValue::Vector4(glm::value_ptr(&eye).try_into().unwrap()),
+        )
+    }
+}
+
+/// Linear filtering, wrapping around horizontally.
+fn set_sampling(texture: &Texture) -> Result<(), GolemError> {
+    texture.set_minification(TextureFilter::Linear)?;
+    texture.set_magnification(TextureFilter::Linear)?;
+    texture.set_wrap_h(TextureWrap::Repeat)?;
+    texture.set_wrap_v(TextureWrap::ClampToEdge)
+}
+
+fn upload(ctx: &Context, image: &HdrImage) -> Result<Texture, GolemError> {
+    let mut texture = Texture::new(ctx)?;
+    texture.set_image(
+        Some(&image.rgbm()),
+        image.width,
+        image.height,
+        ColorFormat::RGBA,
+    );
+    set_sampling(&texture)?;
+    Ok(texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(color: glm::Vec3) -> HdrImage {
+        HdrImage {
+            width: 16,
+            height: 8,
+            pixels: vec![color; 128],
+        }
+    }
+
+    #[test]
+    fn reads_flat_and_run_length_encoded_files() {
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n".to_vec();
+        // 1.0 is 128 with exponent 129, and 0.5 the same with 128.
+        let mut file = header.clone();
+        for _ in 0..8 {
+            file.extend_from_slice(&[128, 128, 128, 129]);
+        }
+        // A run of eight per channel, then literals.
+        file.extend_from_slice(&[2, 2, 0, 8]);
+        for &value in &[128, 0, 128] {
+            file.extend_from_slice(&[128 + 8, value]);
+        }
+        file.extend_from_slice(&[8, 128, 128, 128, 128, 128, 128, 128, 128]);
+
+        let image = HdrImage::from_slice(&file).unwrap();
+        assert_eq!((image.width, image.height), (8, 2));
+        assert_eq!(image.pixels[0], glm::vec3(1.0, 1.0, 1.0));
+        assert_eq!(image.pixels[15], glm::vec3(0.5, 0.0, 0.5));
+
+        assert!(HdrImage::from_slice(&file[..file.len() - 3]).is_err());
+        assert!(HdrImage::from_slice(b"P6\n8 2\n255\n").is_err());
+    }
+
+    #[test]
+    fn rgbm_round_trips() {
+        for &c in &[0.0, 0.001, 0.02, 0.3, 1.0, 4.0, 15.0] {
+            let color = glm::vec3(c, c * 0.5, c * 0.25);
+            let decoded = decode_rgbm(encode_rgbm(color));
+            assert!(
+                glm::distance(&decoded, &color) <= 0.01 + c * 0.02,
+                "{:?} became {:?}",
+                color,
+                decoded
+            );
+        }
+        assert_eq!(
+            decode_rgbm(encode_rgbm(glm::vec3(100.0, 0.0, 0.0))).x,
+            RGBM_RANGE
+        );
+    }
+
+    #[test]
+    fn a_uniform_environment_stays_uniform_when_filtered() {
+        let color = glm::vec3(0.5, 1.0, 2.0);
+        let image = constant(color);
+        for filtered in &[image.irradiance(8, 4), image.prefilter(0.5, 8, 4)] {
+            for pixel in &filtered.pixels {
+                assert!(glm::distance(pixel, &color) < 0.02, "{:?}", pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn irradiance_faces_the_light() {
+        // Bright above the horizon and dark below.
+        let mut image = constant(glm::Vec3::zeros());
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            if i / 16 < 4 {
+                *pixel = glm::vec3(1.0, 1.0, 1.0);
+            }
+        }
+        let irradiance = image.irradiance(8, 4);
+        let (up, down) = (irradiance.pixels[0].x, irradiance.pixels[31].x);
+        assert!(up > 0.8 && down < 0.2, "{} {}", up, down);
+    }
+
+    #[test]
+    fn eye_of_a_perspective_view() {
+        let eye = glm::vec3(1.0, 2.0, 3.0);
+        let view = glm::look_at(&eye, &glm::Vec3::zeros(), &glm::vec3(0.0, 1.0, 0.0));
+        let mvp = glm::perspective(1.0, 1.0, 0.1, 100.0) * view;
+        let found = super::eye(&mvp);
+        assert!(glm::distance(&found.xyz(), &eye) < 1e-3, "{:?}", found);
+        assert_eq!(found.w, 1.0);
+    }
+}
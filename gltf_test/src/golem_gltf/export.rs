@@ -0,0 +1,468 @@
+//! Writing the scene back out, as `.glb` or as `.gltf` with its buffer in a `.bin` next to it.
+//!
+//! The JSON the scene was loaded from is the starting point, so extensions and extras this crate
+//! doesn't read are kept. Node names, transforms and morph weights, materials and punctual lights
+//! are written from the loaded scene, in their current state (mid-animation, that is the current
+//! pose), and the buffer data still in use is packed into one buffer. Images stored in files of
+//! their own keep their URIs, which are relative to the file the scene was loaded from.
+//!
+//! [`export_file`] converts a file without loading it, and so without a GL context: the scene is
+//! written as the file has it.
+
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+use gltf::json::Value;
+
+use super::light::LightKind;
+use super::material::{AlphaMode, TextureRef};
+use super::{Gltf, Optimization, OptimizationStats, Parsed};
+
+/// What a loaded scene was read from.
+pub struct Source {
+    pub json: Option<Value>,
+    pub buffers: Rc<Vec<gltf::buffer::Data>>,
+}
+
+impl Gltf {
+    /// Write the scene to `path`: a `.gltf` file and a `.bin` file with the same stem if `path`
+    /// ends in `.gltf`, and a `.glb` file otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), gltf::Error> {
+        save(path.as_ref(), |bin_uri| self.export(bin_uri))
+    }
+
+    pub fn write_glb(&self, writer: impl Write) -> Result<(), gltf::Error> {
+        write_glb(self.export(None)?, writer)
+    }
+
+    /// The JSON and the single buffer it refers to, by `bin_uri` or as the GLB binary chunk.
+    fn export(&self, bin_uri: Option<&str>) -> Result<(Value, Option<Vec<u8>>), gltf::Error> {
+        let mut json = self.source.json.clone().ok_or_else(no_json)?;
+        let bin = pack_buffers(&mut json, &self.source.buffers, bin_uri);
+
+        if let Some(nodes) = json.get_mut("nodes").and_then(Value::as_array_mut) {
+            for (node, out) in self.nodes.iter().zip(nodes) {
+                set(out, "name", node.name.clone().map(Value::from));
+                remove(out, "matrix");
+                let t = node.translation;
+                let r = node.rotation.coords;
+                let s = node.scale;
+                set(out, "translation", non_default(&[t.x, t.y, t.z], &[0.0; 3]));
+                set(
+                    out,
+                    "rotation",
+                    non_default(&[r.x, r.y, r.z, r.w], &[0.0, 0.0, 0.0, 1.0]),
+                );
+                set(out, "scale", non_default(&[s.x, s.y, s.z], &[1.0; 3]));
+                if !node.weights.is_empty() {
+                    out["weights"] = numbers(&node.weights);
+                }
+            }
+        }
+
+        if let Some(materials) = json.get_mut("materials").and_then(Value::as_array_mut) {
+            for (material, out) in self.materials.iter().zip(materials) {
+                set(out, "name", material.name.clone().map(Value::from));
+                let c = material.base_color_factor;
+                let factors = vec![
+                    (
+                        "baseColorFactor",
+                        non_default(&[c.x, c.y, c.z, c.w], &[1.0; 4]),
+                    ),
+                    (
+                        "metallicFactor",
+                        non_default(&[material.metallic_factor], &[1.0]),
+                    ),
+                    (
+                        "roughnessFactor",
+                        non_default(&[material.roughness_factor], &[1.0]),
+                    ),
+                ];
+                for (key, factor) in factors {
+                    match factor {
+                        Some(factor) => out["pbrMetallicRoughness"][key] = factor,
+                        None => {
+                            if let Some(pbr) = out.get_mut("pbrMetallicRoughness") {
+                                remove(pbr, key);
+                            }
+                        }
+                    }
+                }
+                let alpha_mode = match material.alpha_mode {
+                    AlphaMode::Opaque => None,
+                    AlphaMode::Mask => Some("MASK"),
+                    AlphaMode::Blend => Some("BLEND"),
+                };
+                set(out, "alphaMode", alpha_mode.map(Value::from));
+                set(
+                    out,
+                    "alphaCutoff",
+                    Some(number(material.alpha_cutoff))
+                        .filter(|_| material.alpha_mode == AlphaMode::Mask),
+                );
+                set(
+                    out,
+                    "doubleSided",
+                    Some(Value::from(true)).filter(|_| material.double_sided),
+                );
+                let e = material.emissive_factor;
+                set(
+                    out,
+                    "emissiveFactor",
+                    non_default(&[e.x, e.y, e.z], &[0.0; 3]),
+                );
+                set_texture(
+                    out,
+                    &["pbrMetallicRoughness", "baseColorTexture"],
+                    material.base_color_texture.as_ref(),
+                );
+                set_texture(
+                    out,
+                    &["pbrMetallicRoughness", "metallicRoughnessTexture"],
+                    material.metallic_roughness_texture.as_ref(),
+                );
+                set_texture(
+                    out,
+                    &["emissiveTexture"],
+                    material.emissive_texture.as_ref(),
+                );
+                set_extension(
+                    out,
+                    "KHR_materials_emissive_strength",
+                    non_default(&[material.emissive_strength], &[1.0]).map(|strength| {
+                        let mut extension = Value::Null;
+                        extension["emissiveStrength"] = strength;
+                        extension
+                    }),
+                );
+                set_extension(
+                    out,
+                    "KHR_materials_unlit",
+                    Some(Value::Object(Default::default())).filter(|_| material.unlit),
+                );
+            }
+            let materials = json["materials"].as_array().cloned().unwrap_or_default();
+            for name in &[
+                "KHR_materials_emissive_strength",
+                "KHR_materials_unlit",
+                "KHR_texture_transform",
+            ] {
+                if materials.iter().any(|m| uses_extension(m, name)) {
+                    list_extension(&mut json, name);
+                }
+            }
+        }
+
+        for light in &self.lights {
+            let index = match light.node().and_then(|n| {
+                json["nodes"][n]["extensions"]["KHR_lights_punctual"]["light"].as_u64()
+            }) {
+                Some(index) => index,
+                None => continue,
+            };
+            let pointer = format!("/extensions/KHR_lights_punctual/lights/{}", index);
+            let out = match json.pointer_mut(&pointer) {
+                Some(out) if out.is_object() => out,
+                _ => continue,
+            };
+            let c = light.color;
+            set(out, "color", non_default(&[c.x, c.y, c.z], &[1.0; 3]));
+            set(out, "intensity", non_default(&[light.intensity], &[1.0]));
+            set(out, "range", light.range.map(number));
+            remove(out, "spot");
+            let kind = match light.kind {
+                LightKind::Directional => "directional",
+                LightKind::Point => "point",
+                LightKind::Spot {
+                    inner_cone_angle,
+                    outer_cone_angle,
+                } => {
+                    out["spot"]["innerConeAngle"] = number(inner_cone_angle);
+                    out["spot"]["outerConeAngle"] = number(outer_cone_angle);
+                    "spot"
+                }
+            };
+            out["type"] = Value::from(kind);
+        }
+
+        Ok((json, bin))
+    }
+}
+
+/// Read the file at `path`, optimize it if asked to, and write it to `output` as [`Gltf::save`]
+/// would, without a GL context. Returns what optimizing did.
+pub fn export_file(
+    path: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    optimization: Option<&Optimization>,
+) -> Result<Option<OptimizationStats>, gltf::Error> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(gltf::Error::Io)?;
+    let mut parsed = Parsed::from_slice(&data, Some(path))?;
+    if let Some(optimization) = optimization {
+        parsed = parsed.optimize(optimization)?;
+    }
+    save(output.as_ref(), |bin_uri| {
+        let mut json = parsed.json.clone().ok_or_else(no_json)?;
+        let bin = pack_buffers(&mut json, &parsed.buffers, bin_uri);
+        Ok((json, bin))
+    })?;
+    Ok(parsed.optimization)
+}
+
+/// Write what `export` gives to `path`: a `.gltf` file and a `.bin` file with the same stem if
+/// `path` ends in `.gltf`, and a `.glb` file otherwise.
+fn save(
+    path: &Path,
+    export: impl FnOnce(Option<&str>) -> Result<(Value, Option<Vec<u8>>), gltf::Error>,
+) -> Result<(), gltf::Error> {
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gltf"))
+    {
+        let bin_path = path.with_extension("bin");
+        let uri = bin_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let (json, bin) = export(uri.as_deref())?;
+        if let Some(bin) = bin {
+            std::fs::write(&bin_path, bin).map_err(gltf::Error::Io)?;
+        }
+        let json =
+            gltf::json::serialize::to_vec_pretty(&json).map_err(|e| gltf::Error::Io(e.into()))?;
+        std::fs::write(path, json).map_err(gltf::Error::Io)
+    } else {
+        let file = std::fs::File::create(path).map_err(gltf::Error::Io)?;
+        write_glb(export(None)?, std::io::BufWriter::new(file))
+    }
+}
+
+fn write_glb((json, bin): (Value, Option<Vec<u8>>), writer: impl Write) -> Result<(), gltf::Error> {
+    let json = gltf::json::serialize::to_vec(&json).map_err(|e| gltf::Error::Io(e.into()))?;
+    gltf::Glb {
+        // The length is worked out while writing.
+        header: gltf::binary::Header {
+            magic: *b"glTF",
+            version: 2,
+            length: 0,
+        },
+        json: Cow::Owned(json),
+        bin: bin.map(Cow::Owned),
+    }
+    .to_writer(writer)
+}
+
+fn no_json() -> gltf::Error {
+    gltf::Error::Io(std::io::Error::other("the scene's JSON wasn't kept"))
+}
+
+/// Put the buffer views still referred to into one buffer, 4-byte aligned, dropping the others
+/// (such as those optimizing left behind) and renumbering the references to the rest.
+pub fn pack_buffers(
+    json: &mut Value,
+    buffers: &[gltf::buffer::Data],
+    uri: Option<&str>,
+) -> Option<Vec<u8>> {
+    let mut views = match json.get_mut("bufferViews").map(Value::take) {
+        Some(Value::Array(views)) => views,
+        _ => Vec::new(),
+    };
+    let mut used = vec![false; views.len()];
+    visit_buffer_views(json, &mut |view| {
+        if let Some(used) = view.as_u64().and_then(|v| used.get_mut(v as usize)) {
+            *used = true;
+        }
+    });
+
+    let mut bin = Vec::new();
+    let mut renumbered = vec![None; views.len()];
+    let mut packed = Vec::new();
+    for (i, mut view) in views.drain(..).enumerate() {
+        if !used[i] {
+            continue;
+        }
+        let buffer = view["buffer"].as_u64().unwrap_or(0) as usize;
+        let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let length = view["byteLength"].as_u64().unwrap_or(0) as usize;
+        let data = buffers
+            .get(buffer)
+            .and_then(|b| b.0.get(offset..offset + length))
+            .unwrap_or(&[]);
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+        view["buffer"] = Value::from(0);
+        set(
+            &mut view,
+            "byteOffset",
+            Some(Value::from(bin.len())).filter(|_| !bin.is_empty()),
+        );
+        bin.extend_from_slice(data);
+        renumbered[i] = Some(packed.len());
+        packed.push(view);
+    }
+    visit_buffer_views(json, &mut |view| {
+        if let Some(&Some(v)) = view.as_u64().and_then(|v| renumbered.get(v as usize)) {
+            *view = Value::from(v);
+        }
+    });
+
+    if packed.is_empty() {
+        remove(json, "bufferViews");
+        remove(json, "buffers");
+        return None;
+    }
+    json["bufferViews"] = Value::from(packed);
+    let mut buffer = Value::Null;
+    buffer["byteLength"] = Value::from(bin.len());
+    if let Some(uri) = uri {
+        buffer["uri"] = Value::from(uri);
+    }
+    json["buffers"] = Value::from(vec![buffer]);
+    Some(bin)
+}
+
+/// Every `bufferView` property in `value`, which is how accessors, images and extensions refer
+/// to buffer views.
+fn visit_buffer_views(value: &mut Value, f: &mut impl FnMut(&mut Value)) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if key == "bufferView" {
+                    f(value);
+                } else {
+                    visit_buffer_views(value, f);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|v| visit_buffer_views(v, f)),
+        _ => {}
+    }
+}
+
+/// Write `texture` to the texture info at `path` in `material`, keeping what else it has, or
+/// remove the info if there is no texture.
+fn set_texture(material: &mut Value, path: &[&str], texture: Option<&TextureRef>) {
+    let (key, parents) = path.split_last().unwrap();
+    let texture = match texture {
+        Some(texture) => texture,
+        None => {
+            let parent = parents
+                .iter()
+                .try_fold(&mut *material, |v, k| v.get_mut(*k));
+            if let Some(parent) = parent {
+                remove(parent, key);
+            }
+            return;
+        }
+    };
+    let info = path.iter().fold(material, |v, k| &mut v[*k]);
+    info["index"] = Value::from(texture.texture);
+    if !texture.has_transform() {
+        set(
+            info,
+            "texCoord",
+            Some(Value::from(texture.tex_coord)).filter(|_| texture.tex_coord != 0),
+        );
+        set_extension(info, "KHR_texture_transform", None);
+        return;
+    }
+    // The extension's texCoord overrides the info's, which readers without the extension use.
+    let info_tex_coord = info["texCoord"].as_u64().unwrap_or(0) as u32;
+    let mut transform = info["extensions"]["KHR_texture_transform"].take();
+    if !transform.is_object() {
+        transform = Value::Object(Default::default());
+    }
+    let (o, s) = (texture.offset, texture.scale);
+    set(
+        &mut transform,
+        "offset",
+        non_default(&[o.x, o.y], &[0.0; 2]),
+    );
+    set(
+        &mut transform,
+        "rotation",
+        non_default(&[texture.rotation], &[0.0]),
+    );
+    set(&mut transform, "scale", non_default(&[s.x, s.y], &[1.0; 2]));
+    set(
+        &mut transform,
+        "texCoord",
+        Some(Value::from(texture.tex_coord)).filter(|_| texture.tex_coord != info_tex_coord),
+    );
+    set_extension(info, "KHR_texture_transform", Some(transform));
+}
+
+/// Set or remove the extension `name` of `object`, dropping `extensions` once it is empty.
+fn set_extension(object: &mut Value, name: &str, extension: Option<Value>) {
+    match extension {
+        Some(extension) => object["extensions"][name] = extension,
+        None => {
+            if let Some(extensions) = object.get_mut("extensions") {
+                remove(extensions, name);
+                if extensions.as_object().is_some_and(|e| e.is_empty()) {
+                    remove(object, "extensions");
+                }
+            }
+        }
+    }
+}
+
+/// Whether `value` or anything in it has the extension `name`.
+fn uses_extension(value: &Value, name: &str) -> bool {
+    match value {
+        Value::Object(object) => object.iter().any(|(key, value)| {
+            (key == "extensions" && value.get(name).is_some()) || uses_extension(value, name)
+        }),
+        Value::Array(values) => values.iter().any(|v| uses_extension(v, name)),
+        _ => false,
+    }
+}
+
+fn list_extension(json: &mut Value, name: &str) {
+    let listed = json["extensionsUsed"]
+        .as_array()
+        .is_some_and(|names| names.iter().any(|n| n == name));
+    if !listed {
+        match json["extensionsUsed"].as_array_mut() {
+            Some(names) => names.push(Value::from(name)),
+            None => json["extensionsUsed"] = Value::from(vec![name]),
+        }
+    }
+}
+
+fn set(object: &mut Value, key: &str, value: Option<Value>) {
+    match value {
+        Some(value) => object[key] = value,
+        None => remove(object, key),
+    }
+}
+
+fn remove(object: &mut Value, key: &str) {
+    if let Some(object) = object.as_object_mut() {
+        object.remove(key);
+    }
+}
+
+/// `values`, unless they are `default`, which the spec says to leave out.
+fn non_default(values: &[f32], default: &[f32]) -> Option<Value> {
+    if values == default {
+        None
+    } else if values.len() == 1 {
+        Some(number(values[0]))
+    } else {
+        Some(numbers(values))
+    }
+}
+
+fn numbers(values: &[f32]) -> Value {
+    Value::from(values.iter().copied().map(number).collect::<Vec<_>>())
+}
+
+/// Through the shortest decimal that reads back as the same `f32`, so that 0.1 isn't written as
+/// 0.10000000149011612.
+fn number(value: f32) -> Value {
+    Value::from(value.to_string().parse::<f64>().unwrap_or(f64::NAN))
+}
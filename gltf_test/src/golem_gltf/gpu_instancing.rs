@@ -0,0 +1,112 @@
+//! `EXT_mesh_gpu_instancing`, which gltf 0.15 drops while parsing, so it is read from the raw
+//! JSON.
+
+use gltf::accessor::{DataType, Dimensions, Iter};
+use gltf::animation::util::Rotations;
+use gltf::json::Value;
+use nalgebra_glm as glm;
+
+use super::{Issue, ValidationReport};
+
+pub const EXTENSION: &str = "EXT_mesh_gpu_instancing";
+
+/// The JSON of a `.gltf` file or the JSON chunk of a `.glb` one.
+pub fn json_of(data: &[u8]) -> Option<Value> {
+    if data.starts_with(b"glTF") {
+        let glb = gltf::Glb::from_slice(data).ok()?;
+        gltf::json::deserialize::from_slice(&glb.json).ok()
+    } else {
+        gltf::json::deserialize::from_slice(data).ok()
+    }
+}
+
+/// Instance transforms of each node, relative to the node, or `None` for nodes without the
+/// extension. Nodes whose instances can't be read are reported and drawn once.
+pub fn read_instances(
+    json: &Value,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    validation: &mut ValidationReport,
+) -> Vec<Option<Vec<glm::Mat4>>> {
+    document
+        .nodes()
+        .map(|node| {
+            let attributes = &json["nodes"][node.index()]["extensions"][EXTENSION]["attributes"];
+            if attributes.is_null() {
+                return None;
+            }
+            let instances = read_node(attributes, document, buffers);
+            if instances.is_none() {
+                validation.push(Issue::UnreadableInstances { node: node.index() });
+            }
+            instances
+        })
+        .collect()
+}
+
+fn read_node(
+    attributes: &Value,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Vec<glm::Mat4>> {
+    let get_buffer_data = |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|d| &d.0[..]);
+    let accessor = |semantic: &str| match &attributes[semantic] {
+        Value::Null => Ok(None),
+        index => index
+            .as_u64()
+            .and_then(|i| document.accessors().nth(i as usize))
+            .map(Some)
+            .ok_or(()),
+    };
+    let vec3s = |accessor: Option<gltf::Accessor>| -> Option<Option<Vec<glm::Vec3>>> {
+        match accessor {
+            Some(a) if (a.data_type(), a.dimensions()) == (DataType::F32, Dimensions::Vec3) => {
+                let iter = Iter::<[f32; 3]>::new(a, get_buffer_data)?;
+                Some(Some(iter.map(|v| glm::make_vec3(&v)).collect()))
+            }
+            Some(_) => None,
+            None => Some(None),
+        }
+    };
+
+    let translations = vec3s(accessor("TRANSLATION").ok()?)?;
+    let scales = vec3s(accessor("SCALE").ok()?)?;
+    let rotations = match accessor("ROTATION").ok()? {
+        Some(a) if a.dimensions() == Dimensions::Vec4 => {
+            let rotations = match a.data_type() {
+                DataType::F32 => Rotations::F32(Iter::new(a, get_buffer_data)?),
+                DataType::I8 => Rotations::I8(Iter::new(a, get_buffer_data)?),
+                DataType::I16 => Rotations::I16(Iter::new(a, get_buffer_data)?),
+                _ => return None,
+            };
+            Some(
+                rotations
+                    .into_f32()
+                    .map(|r| glm::quat(r[0], r[1], r[2], r[3]))
+                    .collect::<Vec<_>>(),
+            )
+        }
+        Some(_) => return None,
+        None => None,
+    };
+
+    let count = [
+        translations.as_ref().map(Vec::len),
+        rotations.as_ref().map(Vec::len),
+        scales.as_ref().map(Vec::len),
+    ]
+    .iter()
+    .flatten()
+    .copied()
+    .min()?;
+    Some(
+        (0..count)
+            .map(|i| {
+                let t = translations.as_ref().map_or(glm::zero(), |t| t[i]);
+                let r = rotations.as_ref().map_or(glm::quat_identity(), |r| r[i]);
+                let s = scales.as_ref().map_or(glm::vec3(1.0, 1.0, 1.0), |s| s[i]);
+                glm::translation(&t) * glm::quat_to_mat4(&r) * glm::scaling(&s)
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,56 @@
+//! Names and sizes kept from the document for inspecting a model, beyond what drawing it needs.
+
+pub struct MeshInfo {
+    pub name: Option<String>,
+    /// The material of each primitive in the file, including any that can't be drawn.
+    pub materials: Vec<Option<usize>>,
+}
+
+impl MeshInfo {
+    pub fn from_gltf_mesh(mesh: &gltf::Mesh) -> MeshInfo {
+        MeshInfo {
+            name: mesh.name().map(str::to_owned),
+            materials: mesh.primitives().map(|p| p.material().index()).collect(),
+        }
+    }
+}
+
+/// Vertices and triangles uploaded for a mesh.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshCounts {
+    pub vertices: usize,
+    pub triangles: usize,
+}
+
+pub struct TextureInfo {
+    pub name: Option<String>,
+    /// The name of the image, or its URI.
+    pub image: Option<String>,
+    /// Of the image in the file, before any resizing for upload.
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TextureInfo {
+    pub fn from_gltf_texture(texture: &gltf::Texture, images: &[gltf::image::Data]) -> TextureInfo {
+        let source = texture.source();
+        let image = source
+            .name()
+            .map(str::to_owned)
+            .or_else(|| match source.source() {
+                gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                    Some(uri.to_owned())
+                }
+                _ => None,
+            });
+        let (width, height) = images
+            .get(source.index())
+            .map_or((0, 0), |i| (i.width, i.height));
+        TextureInfo {
+            name: texture.name().map(str::to_owned),
+            image,
+            width,
+            height,
+        }
+    }
+}
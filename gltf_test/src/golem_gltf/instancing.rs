@@ -0,0 +1,132 @@
+//! Instanced draws, which golem 0.1 doesn't expose. They go through a second `glow` context on
+//! the same GL context, after golem has bound the shader and buffers.
+
+use std::ops::Range;
+
+use glow::HasContext;
+use golem::*;
+use nalgebra_glm as glm;
+
+pub struct Instancing {
+    gl: glow::Context,
+    buffer: <glow::Context as HasContext>::Buffer,
+}
+
+impl Instancing {
+    /// `None` before GL 3.3, where vertex attribute divisors became core.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(gl: glow::Context) -> Option<Instancing> {
+        let version = unsafe {
+            (
+                gl.get_parameter_i32(glow::MAJOR_VERSION),
+                gl.get_parameter_i32(glow::MINOR_VERSION),
+            )
+        };
+        if version >= (3, 3) {
+            Self::with_context(gl)
+        } else {
+            None
+        }
+    }
+
+    /// `None` if the context lacks `ANGLE_instanced_arrays`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_webgl1_context(context: web_sys::WebGlRenderingContext) -> Option<Instancing> {
+        context.get_extension("ANGLE_instanced_arrays").ok()??;
+        Self::with_context(glow::Context::from_webgl1_context(context))
+    }
+
+    fn with_context(gl: glow::Context) -> Option<Instancing> {
+        let buffer = unsafe { gl.create_buffer() }.ok()?;
+        Some(Instancing { gl, buffer })
+    }
+
+    /// Draw the elements in `range` once for each of `model_matrices`.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`ShaderProgram::draw`]. In addition, the shader's vertex inputs must be the
+    /// attributes of `vertex_layout`, in floats, followed by the four columns of the model matrix
+    /// as `vec4`s.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn draw(
+        &self,
+        shader: &ShaderProgram,
+        vb: &VertexBuffer,
+        eb: &ElementBuffer,
+        range: Range<usize>,
+        mode: GeometryMode,
+        vertex_layout: &[i32],
+        model_matrices: &[glm::Mat4],
+    ) -> Result<(), GolemError> {
+        assert!(range.end <= eb.size(), "range exceeds the element buffer");
+        // Binds both buffers and enables every input, but golem takes the matrix columns to be
+        // part of each vertex, so all the pointers are set up again here.
+        shader.prepare_draw(vb, eb)?;
+
+        let gl = &self.gl;
+        let float_size = std::mem::size_of::<f32>() as i32;
+        let stride = vertex_layout.iter().sum::<i32>() * float_size;
+        let mut offset = 0;
+        for (index, &size) in vertex_layout.iter().enumerate() {
+            gl.vertex_attrib_pointer_f32(index as u32, size, glow::FLOAT, false, stride, offset);
+            offset += size * float_size;
+        }
+
+        let data: Vec<f32> = model_matrices
+            .iter()
+            .flat_map(|m| m.as_slice().iter().copied())
+            .collect();
+        let bytes = std::slice::from_raw_parts(
+            data.as_ptr() as *const u8,
+            data.len() * std::mem::size_of::<f32>(),
+        );
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.buffer));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, bytes, glow::STREAM_DRAW);
+        let first_column = vertex_layout.len() as u32;
+        for column in 0..4 {
+            let index = first_column + column;
+            gl.vertex_attrib_pointer_f32(
+                index,
+                4,
+                glow::FLOAT,
+                false,
+                16 * float_size,
+                column as i32 * 4 * float_size,
+            );
+            gl.vertex_attrib_divisor(index, 1);
+        }
+
+        gl.draw_elements_instanced(
+            geometry_mode(mode),
+            (range.end - range.start) as i32,
+            glow::UNSIGNED_INT,
+            (range.start * std::mem::size_of::<u32>()) as i32,
+            model_matrices.len() as i32,
+        );
+
+        // golem doesn't know about divisors, and reuses these attributes for per-vertex data.
+        for column in 0..4 {
+            gl.vertex_attrib_divisor(first_column + column, 0);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Instancing {
+    fn drop(&mut self) {
+        unsafe { self.gl.delete_buffer(self.buffer) };
+    }
+}
+
+fn geometry_mode(mode: GeometryMode) -> u32 {
+    match mode {
+        GeometryMode::Points => glow::POINTS,
+        GeometryMode::Lines => glow::LINES,
+        GeometryMode::LineStrip => glow::LINE_STRIP,
+        GeometryMode::LineLoop => glow::LINE_LOOP,
+        GeometryMode::TriangleStrip => glow::TRIANGLE_STRIP,
+        GeometryMode::TriangleFan => glow::TRIANGLE_FAN,
+        GeometryMode::Triangles => glow::TRIANGLES,
+    }
+}
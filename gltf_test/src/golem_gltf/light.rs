@@ -0,0 +1,251 @@
+use std::convert::TryInto;
+
+use golem::*;
+use nalgebra_glm as glm;
+
+use super::{environment, shadow, Node};
+
+/// Size of the light uniform arrays in the fragment shader.
+pub const MAX_LIGHTS: usize = 8;
+
+#[derive(Clone, Copy)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+#[derive(Clone)]
+pub struct Light {
+    pub name: Option<String>,
+    pub kind: LightKind,
+    pub color: glm::Vec3,
+    pub intensity: f32,
+    pub range: Option<f32>,
+    node: Option<usize>,
+    position: glm::Vec3,
+    direction: glm::Vec3,
+}
+
+impl Light {
+    pub fn from_gltf_node(node: &gltf::Node) -> Option<Light> {
+        use gltf::khr_lights_punctual::Kind;
+
+        let light = node.light()?;
+        let kind = match light.kind() {
+            Kind::Directional => LightKind::Directional,
+            Kind::Point => LightKind::Point,
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+        };
+        Some(Light {
+            name: light.name().or_else(|| node.name()).map(str::to_owned),
+            kind,
+            color: glm::make_vec3(&light.color()),
+            intensity: light.intensity(),
+            range: light.range(),
+            node: Some(node.index()),
+            position: glm::zero(),
+            direction: glm::vec3(0.0, 0.0, -1.0),
+        })
+    }
+
+    fn directional(direction: glm::Vec3, intensity: f32) -> Light {
+        Light {
+            name: None,
+            kind: LightKind::Directional,
+            color: glm::vec3(1.0, 1.0, 1.0),
+            intensity,
+            range: None,
+            node: None,
+            position: glm::zero(),
+            direction: glm::normalize(&direction),
+        }
+    }
+
+    /// Key and fill lights used when the file doesn't define any.
+    pub fn default_rig() -> Vec<Light> {
+        vec![
+            Light::directional(glm::vec3(-1.0, -1.0, 0.0), 1.0),
+            Light::directional(glm::vec3(0.5, -0.25, -1.0), 0.3),
+        ]
+    }
+
+    pub fn node(&self) -> Option<usize> {
+        self.node
+    }
+
+    /// World-space position, meaningless for directional lights.
+    pub fn position(&self) -> glm::Vec3 {
+        self.position
+    }
+
+    /// World-space direction the light is pointing in.
+    pub fn direction(&self) -> glm::Vec3 {
+        self.direction
+    }
+
+    /// Follow the node the light is attached to. Lights point down their local -Z axis.
+    pub fn update(&mut self, nodes: &[Node]) {
+        if let Some(node) = self.node {
+            let matrix = nodes[node].global_matrix();
+            self.position = (matrix * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz();
+            self.direction = glm::normalize(&(matrix * glm::vec4(0.0, 0.0, -1.0, 0.0)).xyz());
+        }
+    }
+}
+
+/// Fragment shader body shading a material (see [`super::material::uniforms`]) with up to
+/// [`MAX_LIGHTS`] lights, shadowed by a shadow map if there is one (see [`shadow::uniforms`]), and
+/// the environment maps if there are some (see [`environment::uniforms`]). The light arrays are declared here, since golem writes
+/// array uniforms in a form GLSL ES 1.00 doesn't accept.
+pub fn lighting_fragment_shader() -> String {
+    format!(
+        r#"
+        uniform vec4 light_position[{max}];
+        uniform vec4 light_direction[{max}];
+        uniform vec3 light_color[{max}];
+        uniform vec2 light_cone[{max}];
+        {encode}
+        {tone_mapping}
+        {environment}
+        {shadow}
+        vec4 sample_map(sampler2D map, mat3 transform, float tex_coord) {{
+            vec2 uv = tex_coord == 0.0 ? frag_uv0 : frag_uv1;
+            return texture(map, (transform * vec3(uv, 1.0)).xy);
+        }}
+        vec4 linear(vec4 color) {{
+            return vec4(pow(color.rgb, vec3(gamma)), color.a);
+        }}
+        // Cook-Torrance with GGX, times pi so that lights are as bright as without an
+        // environment.
+        vec3 brdf(vec3 n, vec3 v, vec3 l, vec3 diffuse, vec3 f0, float roughness) {{
+            vec3 h = normalize(l + v);
+            float n_dot_l = max(dot(n, l), 0.0);
+            float n_dot_v = max(dot(n, v), 0.0001);
+            float n_dot_h = max(dot(n, h), 0.0);
+            float a = roughness * roughness;
+            float a2 = a * a;
+            float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+            float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+            float g = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+            vec3 f = f0 + (1.0 - f0) * pow(1.0 - max(dot(v, h), 0.0), 5.0);
+            vec3 specular = a2 / (d * d) * g * f / (4.0 * n_dot_l * n_dot_v + 0.0001);
+            return (1.0 - f) * diffuse + specular;
+        }}
+        void main() {{
+            vec4 base = base_color
+                * linear(sample_map(base_color_map, base_color_uv, base_color_tex_coord));
+            if (base.a < alpha_cutoff) discard;
+            if (!gl_FrontFacing && double_sided == 0.0) discard;
+            if (shadow_pass == 1.0) {{
+                gl_FragColor = pack_depth(gl_FragCoord.z);
+                return;
+            }}
+            float alpha = mix(1.0, base.a, alpha_blend);
+            if (unlit == 1.0) {{
+                vec3 shown = tone_operator == 3.0
+                    ? encode_hdr(base.rgb) : pow(base.rgb, vec3(1.0 / gamma));
+                gl_FragColor = vec4(shown, alpha);
+                return;
+            }}
+            vec3 emitted = emissive
+                * linear(sample_map(emissive_map, emissive_uv, emissive_tex_coord)).rgb;
+            vec3 normal = normalize(frag_normal);
+            if (!gl_FrontFacing) normal = -normal;
+
+            vec4 metallic_roughness_sample = sample_map(
+                metallic_roughness_map, metallic_roughness_uv, metallic_roughness_tex_coord);
+            float rough = clamp(roughness * metallic_roughness_sample.g, 0.04, 1.0);
+            float metal = clamp(metallic * metallic_roughness_sample.b, 0.0, 1.0);
+            vec3 diffuse = base.rgb * (1.0 - metal);
+            vec3 f0 = mix(vec3(0.04), base.rgb, metal);
+            vec3 v = eye.w == 0.0 ? eye.xyz : normalize(eye.xyz - frag_position);
+
+            // Without an environment, a constant ambient term and Lambertian lights.
+            vec3 color = environment == 0.0 ? base.rgb * 0.1 : vec3(0.0);
+            for (int i = 0; i < {max}; i++) {{
+                if (i >= light_count) break;
+                vec3 to_light;
+                float attenuation = 1.0;
+                if (light_position[i].w == 0.0) {{
+                    to_light = -light_direction[i].xyz;
+                }} else {{
+                    vec3 d = light_position[i].xyz - frag_position;
+                    float dist2 = max(dot(d, d), 0.0001);
+                    to_light = d * inversesqrt(dist2);
+                    attenuation = 1.0 / dist2;
+                    float range = light_direction[i].w;
+                    if (range > 0.0) {{
+                        float r = dist2 / (range * range);
+                        attenuation *= clamp(1.0 - r * r, 0.0, 1.0);
+                    }}
+                    if (light_position[i].w == 2.0) {{
+                        float cd = dot(light_direction[i].xyz, -to_light);
+                        attenuation *= smoothstep(light_cone[i].x, light_cone[i].y, cd);
+                    }}
+                }}
+                float n_dot_l = max(dot(normal, to_light), 0.0);
+                vec3 incoming = light_color[i] * n_dot_l * attenuation;
+                if (float(i) == shadow_light) incoming *= shadow(frag_position, n_dot_l);
+                if (environment == 0.0) {{
+                    color += base.rgb * incoming;
+                }} else {{
+                    color += brdf(normal, v, to_light, diffuse, f0, rough) * incoming;
+                }}
+            }}
+            if (environment == 1.0) {{
+                float n_dot_v = max(dot(normal, v), 0.0001);
+                vec2 scale_bias = environment_brdf(n_dot_v, rough);
+                color += diffuse * irradiance(normal)
+                    + (f0 * scale_bias.x + scale_bias.y) * prefiltered(reflect(-v, normal), rough);
+            }}
+            gl_FragColor = vec4(tone_map(color + emitted), alpha);
+        }}"#,
+        max = MAX_LIGHTS,
+        encode = post_process::ENCODE_GLSL,
+        tone_mapping = environment::TONE_MAPPING_GLSL,
+        environment = environment::LIGHTING_GLSL,
+        shadow = shadow::shadow_glsl(),
+    )
+}
+
+pub fn set_light_uniforms(shader: &ShaderProgram, lights: &[Light]) -> Result<(), GolemError> {
+    let count = lights.len().min(MAX_LIGHTS);
+    shader.set_uniform("light_count", UniformValue::Int(count as i32))?;
+    for (i, light) in lights.iter().take(count).enumerate() {
+        let (kind, cone) = match light.kind {
+            LightKind::Directional => (0.0, [0.0, 0.0]),
+            LightKind::Point => (1.0, [0.0, 0.0]),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (2.0, [outer_cone_angle.cos(), inner_cone_angle.cos()]),
+        };
+        let p = light.position;
+        let d = light.direction;
+        let c = light.color * light.intensity;
+        shader.set_uniform(
+            &format!("light_position[{}]", i),
+            UniformValue::Vector4([p.x, p.y, p.z, kind]),
+        )?;
+        shader.set_uniform(
+            &format!("light_direction[{}]", i),
+            UniformValue::Vector4([d.x, d.y, d.z, light.range.unwrap_or(0.0)]),
+        )?;
+        shader.set_uniform(
+            &format!("light_color[{}]", i),
+            UniformValue::Vector3(glm::value_ptr(&c).try_into().unwrap()),
+        )?;
+        shader.set_uniform(&format!("light_cone[{}]", i), UniformValue::Vector2(cone))?;
+    }
+    Ok(())
+}
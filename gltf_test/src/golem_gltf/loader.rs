@@ -0,0 +1,352 @@
+//! Loading a model without blocking the frame loop.
+//!
+//! The file is read (natively, on a thread, which also parses it) or fetched (on the web) in the
+//! background. Meshes, then textures, are uploaded a few at a time from [`Loader::poll`], since GL
+//! calls have to stay on the thread drawing the frames, and the partly loaded model can be drawn
+//! in the meantime. Meshes to be optimized are optimized along with parsing.
+//!
+//! The web has no threads to spare, so there the file is parsed and optimized within the
+//! [`Loader::poll`] call that receives its last chunk, which holds up that frame for however long
+//! it takes, whatever the budget.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+
+use golem::Context;
+
+use super::{Gltf, Optimization, Parsed, PendingMeshes};
+use crate::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub bytes_fetched: usize,
+    /// `None` until the size is known, and on the web when the server doesn't send one.
+    pub bytes_total: Option<usize>,
+    /// Nodes whose mesh has been uploaded.
+    pub meshes_loaded: usize,
+    /// Known once the file is parsed.
+    pub meshes_total: usize,
+    /// Decoded along with the buffers.
+    pub images_decoded: usize,
+    /// Uploaded after the meshes, and sampled as white until then.
+    pub textures_loaded: usize,
+    /// Known once the file is parsed.
+    pub textures_total: usize,
+}
+
+impl LoadProgress {
+    /// From 0 to 1, counting fetching as the first half and uploading meshes and textures as
+    /// the second.
+    pub fn fraction(&self) -> f32 {
+        let total = self.meshes_total + self.textures_total;
+        let fetched = match self.bytes_total {
+            Some(total) if total > 0 => (self.bytes_fetched as f32 / total as f32).min(1.0),
+            _ if total > 0 || self.meshes_loaded > 0 => 1.0,
+            _ => 0.0,
+        };
+        let uploaded = if total > 0 {
+            (self.meshes_loaded + self.textures_loaded) as f32 / total as f32
+        } else {
+            0.0
+        };
+        (fetched + uploaded) / 2.0
+    }
+}
+
+enum Message {
+    Fetched {
+        bytes: usize,
+        total: Option<usize>,
+    },
+    /// The whole file, still to be parsed.
+    Data(Vec<u8>),
+    Parsed(Box<Result<Parsed, gltf::Error>>),
+}
+
+enum Stage {
+    Fetching(Receiver<Message>),
+    Uploading(Box<Gltf>, Box<PendingMeshes>),
+    Done(Box<Gltf>),
+    Failed,
+}
+
+/// A model being loaded. Dropping it cancels whatever is still in flight.
+pub struct Loader {
+    stage: Stage,
+    optimization: Option<Optimization>,
+    progress: LoadProgress,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Loader {
+    fn new(
+        receiver: Receiver<Message>,
+        optimization: Option<Optimization>,
+        cancelled: Arc<AtomicBool>,
+    ) -> Loader {
+        Loader {
+            stage: Stage::Fetching(receiver),
+            optimization,
+            progress: LoadProgress::default(),
+            cancelled,
+        }
+    }
+
+    /// Read and parse the file at `path` on a thread.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: impl Into<std::path::PathBuf>, optimization: Option<Optimization>) -> Loader {
+        let path = path.into();
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = cancelled.clone();
+        std::thread::spawn(move || {
+            let message = match read(&path, &sender, &thread_cancelled) {
+                Ok(Some(data)) => {
+                    Message::Parsed(Box::new(parse(&data, Some(&path), optimization.as_ref())))
+                }
+                Ok(None) => return,
+                Err(e) => Message::Parsed(Box::new(Err(e))),
+            };
+            let _ = sender.send(message);
+        });
+        Loader::new(receiver, None, cancelled)
+    }
+
+    /// Fetch `url`, reporting the bytes as they arrive.
+    #[cfg(target_arch = "wasm32")]
+    pub fn fetch(url: impl Into<String>, optimization: Option<Optimization>) -> Loader {
+        let url = url.into();
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let task_cancelled = cancelled.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let message = match fetch(&url, &sender, &task_cancelled).await {
+                Ok(Some(data)) => Message::Data(data),
+                Ok(None) => return,
+                Err(e) => Message::Parsed(Box::new(Err(e))),
+            };
+            let _ = sender.send(message);
+        });
+        Loader::new(receiver, optimization, cancelled)
+    }
+
+    /// A file that is already in memory, such as one dropped on the page.
+    pub fn from_bytes(data: Vec<u8>, optimization: Option<Optimization>) -> Loader {
+        let (sender, receiver) = mpsc::channel();
+        let _ = sender.send(Message::Fetched {
+            bytes: data.len(),
+            total: Some(data.len()),
+        });
+        let _ = sender.send(Message::Data(data));
+        Loader::new(receiver, optimization, Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Take in what the background work has done, then upload meshes and textures until `budget`
+    /// is spent. At least one is uploaded per call, so large ones still make progress. On the
+    /// web, the call that gets the last of the file also parses it, past the budget.
+    ///
+    /// Returns `Ok(true)` once the model is complete, and then [`Loader::into_model`] gives it
+    /// back. After an error, the loader stays empty.
+    pub fn poll(&mut self, ctx: &Context, budget: Duration) -> Result<bool, gltf::Error> {
+        let start = Instant::now();
+        if let Stage::Fetching(receiver) = &self.stage {
+            let parsed = loop {
+                match receiver.try_recv() {
+                    Ok(Message::Fetched { bytes, total }) => {
+                        self.progress.bytes_fetched = bytes;
+                        self.progress.bytes_total = total;
+                    }
+                    Ok(Message::Data(data)) => {
+                        break Some(parse(&data, None, self.optimization.as_ref()))
+                    }
+                    Ok(Message::Parsed(parsed)) => break Some(*parsed),
+                    Err(TryRecvError::Empty) => break None,
+                    Err(TryRecvError::Disconnected) => {
+                        break Some(Err(gltf::Error::Io(std::io::Error::other(
+                            "loading stopped before the file was read",
+                        ))))
+                    }
+                }
+            };
+            match parsed {
+                Some(Ok(parsed)) => {
+                    self.progress.images_decoded = parsed.images.len();
+                    let (gltf, pending) = Gltf::begin_load(parsed, ctx);
+                    self.progress.meshes_total = pending.len();
+                    self.progress.textures_total = pending.textures_len();
+                    self.stage = Stage::Uploading(Box::new(gltf), Box::new(pending));
+                }
+                Some(Err(e)) => {
+                    self.stage = Stage::Failed;
+                    return Err(e);
+                }
+                None => return Ok(false),
+            }
+        }
+
+        if let Stage::Uploading(gltf, pending) = &mut self.stage {
+            while pending.upload_next(gltf, ctx) {
+                self.progress.meshes_loaded = pending.uploaded;
+                self.progress.textures_loaded = pending.textures_uploaded;
+                if start.elapsed() >= budget {
+                    return Ok(false);
+                }
+            }
+            self.stage = match std::mem::replace(&mut self.stage, Stage::Failed) {
+                Stage::Uploading(gltf, _) => Stage::Done(gltf),
+                _ => unreachable!(),
+            };
+        }
+        Ok(matches!(self.stage, Stage::Done(_)))
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+
+    /// The model as far as it is loaded, once the file is parsed.
+    pub fn model(&self) -> Option<&Gltf> {
+        match &self.stage {
+            Stage::Uploading(gltf, _) | Stage::Done(gltf) => Some(gltf),
+            _ => None,
+        }
+    }
+
+    pub fn model_mut(&mut self) -> Option<&mut Gltf> {
+        match &mut self.stage {
+            Stage::Uploading(gltf, _) | Stage::Done(gltf) => Some(gltf),
+            _ => None,
+        }
+    }
+
+    /// The model, if [`Loader::poll`] has finished it.
+    pub fn into_model(mut self) -> Option<Gltf> {
+        match std::mem::replace(&mut self.stage, Stage::Failed) {
+            Stage::Done(gltf) => Some(*gltf),
+            _ => None,
+        }
+    }
+}
+
+fn parse(
+    data: &[u8],
+    path: Option<&std::path::Path>,
+    optimization: Option<&Optimization>,
+) -> Result<Parsed, gltf::Error> {
+    let parsed = Parsed::from_slice(data, path)?;
+    match optimization {
+        Some(optimization) => parsed.optimize(optimization),
+        None => Ok(parsed),
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The contents of the file at `path`, or `None` if cancelled first.
+#[cfg(not(target_arch = "wasm32"))]
+fn read(
+    path: &std::path::Path,
+    sender: &Sender<Message>,
+    cancelled: &AtomicBool,
+) -> Result<Option<Vec<u8>>, gltf::Error> {
+    use std::io::Read;
+
+    const CHUNK_SIZE: usize = 1 << 20;
+
+    let mut file = std::fs::File::open(path).map_err(gltf::Error::Io)?;
+    let total = file.metadata().ok().map(|m| m.len() as usize);
+    let mut data = Vec::with_capacity(total.unwrap_or(0));
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let read = match file.read(&mut chunk) {
+            Ok(0) => return Ok(Some(data)),
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(gltf::Error::Io(e)),
+        };
+        data.extend_from_slice(&chunk[..read]);
+        if sender
+            .send(Message::Fetched {
+                bytes: data.len(),
+                total,
+            })
+            .is_err()
+        {
+            return Ok(None);
+        }
+    }
+}
+
+/// The body of `url`, or `None` if cancelled first. The stream reader is reached through
+/// `Reflect`, since web-sys only exposes it as an unstable API.
+#[cfg(target_arch = "wasm32")]
+async fn fetch(
+    url: &str,
+    sender: &Sender<Message>,
+    cancelled: &AtomicBool,
+) -> Result<Option<Vec<u8>>, gltf::Error> {
+    use js_sys::{Function, Promise, Reflect, Uint8Array};
+    use wasm_bindgen::{JsCast, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::Response;
+
+    let js_error = |e: JsValue| gltf::Error::Io(std::io::Error::other(format!("{:?}", e)));
+    let call = |target: &JsValue, method: &str| -> Result<JsValue, gltf::Error> {
+        Reflect::get(target, &method.into())
+            .and_then(|f| f.dyn_into::<Function>().map_err(JsValue::from))
+            .and_then(|f| f.call0(target))
+            .map_err(js_error)
+    };
+
+    let window = web_sys::window().unwrap();
+    let response: Response = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .and_then(|r| r.dyn_into().map_err(JsValue::from))
+        .map_err(js_error)?;
+    if !response.ok() {
+        return Err(gltf::Error::Io(std::io::Error::other(format!(
+            "{} answered {}",
+            url,
+            response.status()
+        ))));
+    }
+    // The length of the body as sent, which is less than what is read when it is compressed.
+    let total = response
+        .headers()
+        .get("Content-Length")
+        .ok()
+        .flatten()
+        .and_then(|length| length.parse().ok());
+
+    let body = Reflect::get(&response, &"body".into()).map_err(js_error)?;
+    let reader = call(&body, "getReader")?;
+    let mut data = Vec::with_capacity(total.unwrap_or(0));
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            let _ = call(&reader, "cancel");
+            return Ok(None);
+        }
+        let read: Promise = call(&reader, "read")?.unchecked_into();
+        let chunk = JsFuture::from(read).await.map_err(js_error)?;
+        if Reflect::get(&chunk, &"done".into())
+            .map_err(js_error)?
+            .is_truthy()
+        {
+            return Ok(Some(data));
+        }
+        let value = Reflect::get(&chunk, &"value".into()).map_err(js_error)?;
+        data.extend(Uint8Array::new(&value).to_vec());
+        let _ = sender.send(Message::Fetched {
+            bytes: data.len(),
+            total: total.map(|t: usize| t.max(data.len())),
+        });
+    }
+}
@@ -0,0 +1,227 @@
+use std::convert::TryInto;
+use std::num::NonZeroU32;
+
+use gltf::json::Value;
+use golem::*;
+use nalgebra_glm as glm;
+
+pub use super::texture::TextureRef;
+pub use gltf::material::AlphaMode;
+
+const BASE_COLOR_BIND_POINT: u32 = 1;
+const EMISSIVE_BIND_POINT: u32 = 2;
+const METALLIC_ROUGHNESS_BIND_POINT: u32 = 3;
+
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color_factor: glm::Vec4,
+    pub base_color_texture: Option<TextureRef>,
+    /// Only used when lighting with an [`super::Environment`]; the lights alone shade every
+    /// material as a rough dielectric.
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Roughness in green and metalness in blue.
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub emissive_factor: glm::Vec3,
+    /// From `KHR_materials_emissive_strength`.
+    pub emissive_strength: f32,
+    pub emissive_texture: Option<TextureRef>,
+    /// From `KHR_materials_unlit`: the base color is drawn as it is, without lighting.
+    pub unlit: bool,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl Material {
+    /// `json` is the material in the file's JSON, for the extensions gltf doesn't parse.
+    pub fn from_gltf_material(material: &gltf::Material, json: &Value) -> Material {
+        let pbr = material.pbr_metallic_roughness();
+        Material {
+            name: material.name().map(str::to_owned),
+            base_color_factor: glm::make_vec4(&pbr.base_color_factor()),
+            base_color_texture: pbr.base_color_texture().map(|info| {
+                TextureRef::from_info(&info, &json["pbrMetallicRoughness"]["baseColorTexture"])
+            }),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            metallic_roughness_texture: pbr.metallic_roughness_texture().map(|info| {
+                TextureRef::from_info(
+                    &info,
+                    &json["pbrMetallicRoughness"]["metallicRoughnessTexture"],
+                )
+            }),
+            emissive_factor: glm::make_vec3(&material.emissive_factor()),
+            emissive_strength: json["extensions"]["KHR_materials_emissive_strength"]
+                ["emissiveStrength"]
+                .as_f64()
+                .map_or(1.0, |s| s as f32),
+            emissive_texture: material
+                .emissive_texture()
+                .map(|info| TextureRef::from_info(&info, &json["emissiveTexture"])),
+            unlit: material.unlit(),
+            alpha_mode: material.alpha_mode(),
+            alpha_cutoff: material.alpha_cutoff(),
+            double_sided: material.double_sided(),
+        }
+    }
+
+    pub fn is_blended(&self) -> bool {
+        self.alpha_mode == AlphaMode::Blend
+    }
+
+    /// `textures` are the file's textures, and `white` stands in for missing ones and those that
+    /// failed to upload.
+    pub fn set_uniforms(
+        &self,
+        shader: &ShaderProgram,
+        textures: &[Option<Texture>],
+        white: &Texture,
+    ) -> Result<(), GolemError> {
+        let (alpha_cutoff, alpha_blend) = match self.alpha_mode {
+            AlphaMode::Opaque => (-1.0, 0.0),
+            AlphaMode::Mask => (self.alpha_cutoff, 0.0),
+            AlphaMode::Blend => (-1.0, 1.0),
+        };
+        shader.set_uniform(
+            "base_color",
+            UniformValue::Vector4(glm::value_ptr(&self.base_color_factor).try_into().unwrap()),
+        )?;
+        shader.set_uniform("alpha_cutoff", UniformValue::Float(alpha_cutoff))?;
+        shader.set_uniform("alpha_blend", UniformValue::Float(alpha_blend))?;
+        shader.set_uniform(
+            "double_sided",
+            UniformValue::Float(if self.double_sided { 1.0 } else { 0.0 }),
+        )?;
+        shader.set_uniform("metallic", UniformValue::Float(self.metallic_factor))?;
+        shader.set_uniform("roughness", UniformValue::Float(self.roughness_factor))?;
+        let emissive = self.emissive_factor * self.emissive_strength;
+        shader.set_uniform(
+            "emissive",
+            UniformValue::Vector3(glm::value_ptr(&emissive).try_into().unwrap()),
+        )?;
+        shader.set_uniform(
+            "unlit",
+            UniformValue::Float(if self.unlit { 1.0 } else { 0.0 }),
+        )?;
+        set_texture_uniforms(
+            shader,
+            "base_color",
+            BASE_COLOR_BIND_POINT,
+            self.base_color_texture.as_ref(),
+            textures,
+            white,
+        )?;
+        set_texture_uniforms(
+            shader,
+            "metallic_roughness",
+            METALLIC_ROUGHNESS_BIND_POINT,
+            self.metallic_roughness_texture.as_ref(),
+            textures,
+            white,
+        )?;
+        set_texture_uniforms(
+            shader,
+            "emissive",
+            EMISSIVE_BIND_POINT,
+            self.emissive_texture.as_ref(),
+            textures,
+            white,
+        )?;
+        Ok(())
+    }
+}
+
+/// Binds the texture (or `white`) and sets `<name>_map`, `<name>_uv` and `<name>_tex_coord`.
+/// Shader names can't contain "texture", which golem replaces with "texture2D" on the web.
+fn set_texture_uniforms(
+    shader: &ShaderProgram,
+    name: &str,
+    bind_point: u32,
+    texture: Option<&TextureRef>,
+    textures: &[Option<Texture>],
+    white: &Texture,
+) -> Result<(), GolemError> {
+    let (bound, transform, tex_coord) = match texture {
+        Some(t) => (
+            textures
+                .get(t.texture)
+                .and_then(Option::as_ref)
+                .unwrap_or(white),
+            t.transform(),
+            t.tex_coord,
+        ),
+        None => (white, glm::identity(), 0),
+    };
+    bound.set_active(NonZeroU32::new(bind_point).unwrap());
+    shader.set_uniform(
+        &format!("{}_map", name),
+        UniformValue::Int(bind_point as i32),
+    )?;
+    shader.set_uniform(
+        &format!("{}_uv", name),
+        UniformValue::Matrix3(glm::value_ptr(&transform).try_into().unwrap()),
+    )?;
+    shader.set_uniform(
+        &format!("{}_tex_coord", name),
+        UniformValue::Float(tex_coord as f32),
+    )
+}
+
+/// Show `checker` through `TEXCOORD_0` in place of the base color, after
+/// [`Material::set_uniforms`], for [`super::DebugView::uv_checker`].
+pub fn set_checker_uniforms(shader: &ShaderProgram, checker: &Texture) -> Result<(), GolemError> {
+    checker.set_active(NonZeroU32::new(BASE_COLOR_BIND_POINT).unwrap());
+    shader.set_uniform("base_color", UniformValue::Vector4([1.0; 4]))?;
+    shader.set_uniform(
+        "base_color_uv",
+        UniformValue::Matrix3(glm::value_ptr(&glm::Mat3::identity()).try_into().unwrap()),
+    )?;
+    shader.set_uniform("base_color_tex_coord", UniformValue::Float(0.0))
+}
+
+/// The uniforms [`Material::set_uniforms`] sets, for every shader drawing materials.
+pub fn uniforms() -> Vec<Uniform> {
+    let float = || UniformType::Scalar(NumberType::Float);
+    let vector = |d| UniformType::Vector(NumberType::Float, d);
+    vec![
+        Uniform::new("base_color", vector(Dimension::D4)),
+        Uniform::new("alpha_cutoff", float()),
+        Uniform::new("alpha_blend", float()),
+        Uniform::new("double_sided", float()),
+        Uniform::new("metallic", float()),
+        Uniform::new("roughness", float()),
+        Uniform::new("emissive", vector(Dimension::D3)),
+        Uniform::new("unlit", float()),
+        Uniform::new("base_color_map", UniformType::Sampler2D),
+        Uniform::new("base_color_uv", UniformType::Matrix(Dimension::D3)),
+        Uniform::new("base_color_tex_coord", float()),
+        Uniform::new("metallic_roughness_map", UniformType::Sampler2D),
+        Uniform::new("metallic_roughness_uv", UniformType::Matrix(Dimension::D3)),
+        Uniform::new("metallic_roughness_tex_coord", float()),
+        Uniform::new("emissive_map", UniformType::Sampler2D),
+        Uniform::new("emissive_uv", UniformType::Matrix(Dimension::D3)),
+        Uniform::new("emissive_tex_coord", float()),
+    ]
+}
+
+impl Default for Material {
+    /// The material used by primitives that don't reference one.
+    fn default() -> Material {
+        Material {
+            name: None,
+            base_color_factor: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            emissive_factor: glm::vec3(0.0, 0.0, 0.0),
+            emissive_strength: 1.0,
+            emissive_texture: None,
+            unlit: false,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
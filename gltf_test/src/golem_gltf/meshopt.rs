@@ -0,0 +1,402 @@
+//! `EXT_meshopt_compression`: buffer views compressed with meshoptimizer's vertex and index
+//! codecs, decoded the way its reference decoder does.
+//!
+//! - `ATTRIBUTES` data is split in blocks of vertices, and each byte of a vertex is stored as its
+//!   difference from the same byte of the previous vertex, in groups of 16 bytes packed to 0, 2,
+//!   4 or 8 bits each.
+//! - `TRIANGLES` data refers back to recent edges and vertices through two small FIFOs.
+//! - `INDICES` data is a sequence of differences from one of the last two indices.
+//!
+//! Filters then turn the decoded attributes into what the accessors expect: octahedral normals,
+//! quaternions from three components, or floats from a shared exponent.
+
+use std::convert::TryInto;
+
+use gltf::json::Value;
+
+pub const EXTENSION: &str = "EXT_meshopt_compression";
+
+const VERTEX_HEADER: u8 = 0xa0;
+const INDEX_HEADER: u8 = 0xe0;
+const SEQUENCE_HEADER: u8 = 0xd0;
+
+const VERTEX_BLOCK_SIZE_BYTES: usize = 8192;
+const VERTEX_BLOCK_MAX_SIZE: usize = 256;
+const BYTE_GROUP_SIZE: usize = 16;
+const TAIL_MAX_SIZE: usize = 32;
+
+/// The data `extension`, the buffer view's `EXT_meshopt_compression` object, decodes to.
+pub fn decode_buffer_view(
+    extension: &Value,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Vec<u8>, String> {
+    let number = |key: &str| extension[key].as_u64().map(|n| n as usize);
+    let field = |key: &str| number(key).ok_or_else(|| format!("no {}", key));
+    let (buffer, offset, length) = (
+        field("buffer")?,
+        number("byteOffset").unwrap_or(0),
+        field("byteLength")?,
+    );
+    let (stride, count) = (field("byteStride")?, field("count")?);
+    let source = buffers
+        .get(buffer)
+        .and_then(|b| b.0.get(offset..offset + length))
+        .ok_or_else(|| format!("buffer {} is too short", buffer))?;
+
+    let mode = extension["mode"].as_str().unwrap_or_default();
+    let mut data = match mode {
+        "ATTRIBUTES" => decode_vertex_buffer(source, count, stride),
+        "TRIANGLES" => decode_index_buffer(source, count, stride),
+        "INDICES" => decode_index_sequence(source, count, stride),
+        _ => return Err(format!("unknown mode {:?}", mode)),
+    }
+    .ok_or_else(|| format!("malformed {} data", mode))?;
+
+    let filter = extension["filter"].as_str().unwrap_or("NONE");
+    match (filter, stride) {
+        ("NONE", _) => {}
+        ("OCTAHEDRAL", 4) => data.chunks_exact_mut(4).for_each(octahedral_8),
+        ("OCTAHEDRAL", 8) => data.chunks_exact_mut(8).for_each(octahedral_16),
+        ("QUATERNION", 8) => data.chunks_exact_mut(8).for_each(quaternion),
+        ("EXPONENTIAL", _) if stride % 4 == 0 => data.chunks_exact_mut(4).for_each(exponential),
+        _ => return Err(format!("filter {} with a stride of {}", filter, stride)),
+    }
+    Ok(data)
+}
+
+fn unzigzag(v: u8) -> u8 {
+    (v >> 1) ^ (v & 1).wrapping_neg()
+}
+
+fn decode_vertex_buffer(data: &[u8], count: usize, stride: usize) -> Option<Vec<u8>> {
+    if stride == 0 || stride > 256 || stride % 4 != 0 {
+        return None;
+    }
+    if data.len() < 1 + stride || data[0] != VERTEX_HEADER {
+        return None;
+    }
+    // The first vertex is stored relative to the tail, which the encoder fills with it.
+    let mut last = data[data.len() - stride..].to_vec();
+    let block_size =
+        ((VERTEX_BLOCK_SIZE_BYTES / stride) & !(BYTE_GROUP_SIZE - 1)).min(VERTEX_BLOCK_MAX_SIZE);
+
+    let mut out = vec![0; count * stride];
+    let mut position = 1;
+    let mut deltas = [0; VERTEX_BLOCK_MAX_SIZE];
+    for start in (0..count).step_by(block_size) {
+        let block = block_size.min(count - start);
+        let aligned = block.div_ceil(BYTE_GROUP_SIZE) * BYTE_GROUP_SIZE;
+        for k in 0..stride {
+            position = decode_bytes(data, position, &mut deltas[..aligned])?;
+            let mut previous = last[k];
+            for (i, &delta) in deltas[..block].iter().enumerate() {
+                previous = unzigzag(delta).wrapping_add(previous);
+                out[(start + i) * stride + k] = previous;
+            }
+            last[k] = previous;
+        }
+    }
+
+    if data.len() - position != stride.max(TAIL_MAX_SIZE) {
+        return None;
+    }
+    Some(out)
+}
+
+/// Decode one byte of a block of vertices, returning where its data ends.
+fn decode_bytes(data: &[u8], mut position: usize, out: &mut [u8]) -> Option<usize> {
+    // Two bits per group, saying how many bits its values take.
+    let header_size = (out.len() / BYTE_GROUP_SIZE).div_ceil(4);
+    if data.len() - position < header_size {
+        return None;
+    }
+    let header = position;
+    position += header_size;
+
+    for (group, out) in out.chunks_exact_mut(BYTE_GROUP_SIZE).enumerate() {
+        // Enough for any group, so the reads below can't run past the data.
+        if data.len() - position < TAIL_MAX_SIZE {
+            return None;
+        }
+        let bits_log2 = (data[header + group / 4] >> ((group % 4) * 2)) & 3;
+        position = match bits_log2 {
+            0 => {
+                out.iter_mut().for_each(|v| *v = 0);
+                position
+            }
+            3 => {
+                out.copy_from_slice(&data[position..position + BYTE_GROUP_SIZE]);
+                position + BYTE_GROUP_SIZE
+            }
+            _ => {
+                // Values that don't fit are all ones, and follow in full after the packed ones.
+                let bits = 1 << bits_log2;
+                let sentinel = (1 << bits) - 1;
+                let mut extra = position + BYTE_GROUP_SIZE * bits / 8;
+                for (i, out) in out.iter_mut().enumerate() {
+                    let byte = data[position + i * bits / 8];
+                    let value = (byte >> (8 - bits - i * bits % 8)) & sentinel;
+                    *out = if value == sentinel {
+                        extra += 1;
+                        data[extra - 1]
+                    } else {
+                        value
+                    };
+                }
+                extra
+            }
+        };
+    }
+    Some(position)
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> u32 {
+    let lead = data[*position];
+    *position += 1;
+    if lead < 128 {
+        return lead as u32;
+    }
+    // Up to four more groups of seven bits, stopping there even in malformed data.
+    let mut result = (lead & 127) as u32;
+    for i in 0..4 {
+        let group = data[*position];
+        *position += 1;
+        result |= ((group & 127) as u32) << (7 * (i + 1));
+        if group < 128 {
+            break;
+        }
+    }
+    result
+}
+
+fn decode_index(data: &[u8], position: &mut usize, last: u32) -> u32 {
+    let v = read_varint(data, position);
+    last.wrapping_add((v >> 1) ^ (v & 1).wrapping_neg())
+}
+
+fn write_index(out: &mut Vec<u8>, index: u32, size: usize) {
+    match size {
+        2 => out.extend_from_slice(&(index as u16).to_le_bytes()),
+        _ => out.extend_from_slice(&index.to_le_bytes()),
+    }
+}
+
+fn decode_index_buffer(data: &[u8], count: usize, size: usize) -> Option<Vec<u8>> {
+    if count % 3 != 0 || (size != 2 && size != 4) {
+        return None;
+    }
+    // At least a byte per triangle, and the 16 byte table of the codes below at the end.
+    if data.len() < 1 + count / 3 + 16 || data[0] & 0xf0 != INDEX_HEADER {
+        return None;
+    }
+    let version = data[0] & 0x0f;
+    if version > 1 {
+        return None;
+    }
+
+    let mut edges = Fifo::new([u32::MAX; 2]);
+    let mut vertices = Fifo::new(u32::MAX);
+    let (mut next, mut last) = (0u32, 0u32);
+    let mut position = 1 + count / 3;
+    let data_end = data.len() - 16;
+    let aux_table = &data[data_end..];
+    // Version 1 spends two of the codes on the free vertex being one after or before the last.
+    let fifo_codes = if version >= 1 { 13 } else { 15 };
+
+    let mut out = Vec::with_capacity(count * size);
+    for &code_triangle in &data[1..1 + count / 3] {
+        // No triangle reads more than 16 bytes, which the table ensures are there.
+        if position > data_end {
+            return None;
+        }
+
+        let triangle = if code_triangle < 0xf0 {
+            // An edge from the FIFO, and a vertex from the FIFO, a new one or an encoded one.
+            let [a, b] = edges.get(1 + (code_triangle >> 4) as usize);
+            let fec = (code_triangle & 15) as u32;
+            let c = if fec == 0 {
+                next += 1;
+                next - 1
+            } else if fec < fifo_codes {
+                vertices.get(1 + fec as usize)
+            } else {
+                last = if fec == 15 {
+                    decode_index(data, &mut position, last)
+                } else {
+                    // 13 and 14 are one before and one after.
+                    last.wrapping_add(fec.wrapping_sub(fec ^ 3))
+                };
+                last
+            };
+            vertices.push(c, fec == 0 || fec >= fifo_codes);
+            edges.push([c, b], true);
+            edges.push([a, c], true);
+            [a, b, c]
+        } else {
+            // A triangle with a new first vertex. The codes below 0xfe look up how the other
+            // two are stored in the table, the others in the next byte.
+            let code_aux = if code_triangle < 0xfe {
+                aux_table[(code_triangle & 15) as usize]
+            } else {
+                position += 1;
+                data[position - 1]
+            };
+            let fea = if code_triangle == 0xff { 15 } else { 0 };
+            let (feb, fec) = ((code_aux >> 4) as usize, (code_aux & 15) as usize);
+
+            let vertex = |fe: usize, next: &mut u32| match fe {
+                0 => {
+                    *next += 1;
+                    *next - 1
+                }
+                15 => 0,
+                _ => vertices.get(fe),
+            };
+            let mut a = if fea == 0 {
+                next += 1;
+                next - 1
+            } else {
+                0
+            };
+            let mut b = vertex(feb, &mut next);
+            let mut c = vertex(fec, &mut next);
+            if fea == 15 {
+                last = decode_index(data, &mut position, last);
+                a = last;
+            }
+            if feb == 15 {
+                last = decode_index(data, &mut position, last);
+                b = last;
+            }
+            if fec == 15 {
+                last = decode_index(data, &mut position, last);
+                c = last;
+            }
+            vertices.push(a, true);
+            vertices.push(b, feb == 0 || feb == 15);
+            vertices.push(c, fec == 0 || fec == 15);
+            edges.push([b, a], true);
+            edges.push([c, b], true);
+            edges.push([a, c], true);
+            [a, b, c]
+        };
+        for &index in &triangle {
+            write_index(&mut out, index, size);
+        }
+    }
+
+    // All the data has to have been used, up to the table.
+    if position != data_end {
+        return None;
+    }
+    Some(out)
+}
+
+/// The recent edges or vertices the index codec refers back to.
+struct Fifo<T> {
+    items: [T; 16],
+    offset: usize,
+}
+
+impl<T: Copy> Fifo<T> {
+    fn new(item: T) -> Fifo<T> {
+        Fifo {
+            items: [item; 16],
+            offset: 0,
+        }
+    }
+
+    /// Overwritten by the next push unless `advance`.
+    fn push(&mut self, item: T, advance: bool) {
+        self.items[self.offset] = item;
+        self.offset = (self.offset + advance as usize) & 15;
+    }
+
+    /// What was pushed `back` pushes ago, 1 being the last.
+    fn get(&self, back: usize) -> T {
+        self.items[self.offset.wrapping_sub(back) & 15]
+    }
+}
+
+fn decode_index_sequence(data: &[u8], count: usize, size: usize) -> Option<Vec<u8>> {
+    if size != 2 && size != 4 {
+        return None;
+    }
+    // At least a byte per index, and a 4 byte tail.
+    if data.len() < 1 + count + 4 || data[0] & 0xf0 != SEQUENCE_HEADER || data[0] & 0x0f > 1 {
+        return None;
+    }
+    let data_end = data.len() - 4;
+    let mut position = 1;
+    // The lowest bit of each value says which of the two it follows.
+    let mut last = [0u32; 2];
+    let mut out = Vec::with_capacity(count * size);
+    for _ in 0..count {
+        if position >= data_end {
+            return None;
+        }
+        let v = read_varint(data, &mut position);
+        let baseline = &mut last[(v & 1) as usize];
+        let v = v >> 1;
+        *baseline = baseline.wrapping_add((v >> 1) ^ (v & 1).wrapping_neg());
+        write_index(&mut out, *baseline, size);
+    }
+    if position != data_end {
+        return None;
+    }
+    Some(out)
+}
+
+/// The x and y of a normal on an octahedron, with z holding what 1 is stored as.
+fn octahedral(x: f32, y: f32, one: f32, max: f32) -> [f32; 3] {
+    let z = one - x.abs() - y.abs();
+    // Unfold the lower half.
+    let t = z.min(0.0);
+    let x = x + if x >= 0.0 { t } else { -t };
+    let y = y + if y >= 0.0 { t } else { -t };
+    let scale = max / (x * x + y * y + z * z).sqrt();
+    [x * scale, y * scale, z * scale]
+}
+
+/// Round half away from zero, as the reference decoder does.
+fn round(v: f32) -> f32 {
+    (v + if v >= 0.0 { 0.5 } else { -0.5 }).trunc()
+}
+
+fn octahedral_8(data: &mut [u8]) {
+    let [x, y, one] = [0, 1, 2].map(|i| data[i] as i8 as f32);
+    for (i, v) in octahedral(x, y, one, 127.0).iter().enumerate() {
+        data[i] = round(*v) as i8 as u8;
+    }
+}
+
+fn octahedral_16(data: &mut [u8]) {
+    let component = |i: usize| i16::from_le_bytes([data[i * 2], data[i * 2 + 1]]) as f32;
+    let [x, y, one] = [0, 1, 2].map(component);
+    for (i, v) in octahedral(x, y, one, 32767.0).iter().enumerate() {
+        data[i * 2..i * 2 + 2].copy_from_slice(&(round(*v) as i16).to_le_bytes());
+    }
+}
+
+/// Three components of a unit quaternion, scaled to ±1/√2, with the index of the fourth (the
+/// largest, rebuilt from the others) in the low bits of the last.
+fn quaternion(data: &mut [u8]) {
+    let component = |i: usize| i16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+    let last = component(3);
+    let scale = std::f32::consts::FRAC_1_SQRT_2 / (last | 3) as f32;
+    let [x, y, z] = [0, 1, 2].map(|i| component(i) as f32 * scale);
+    let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+    let largest = (last & 3) as usize;
+    for (i, v) in [w, x, y, z].iter().enumerate() {
+        let out = (largest + i) & 3;
+        data[out * 2..out * 2 + 2].copy_from_slice(&(round(v * 32767.0) as i16).to_le_bytes());
+    }
+}
+
+/// A 24-bit signed mantissa and an 8-bit signed exponent.
+fn exponential(data: &mut [u8]) {
+    let v = i32::from_le_bytes(data.try_into().unwrap());
+    let (exponent, mantissa) = (v >> 24, (v << 8) >> 8);
+    let value = mantissa as f32 * 2f32.powi(exponent);
+    data.copy_from_slice(&value.to_le_bytes());
+}
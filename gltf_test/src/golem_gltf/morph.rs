@@ -0,0 +1,75 @@
+use super::Vertices;
+
+struct MorphTarget {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    tangents: Vec<[f32; 3]>,
+}
+
+/// Morph target displacements of a primitive, applied on the CPU.
+pub struct MorphTargets {
+    targets: Vec<MorphTarget>,
+    applied_weights: Option<Vec<f32>>,
+}
+
+impl MorphTargets {
+    pub fn from_reader<'a, 's, F>(reader: &gltf::mesh::Reader<'a, 's, F>) -> Option<MorphTargets>
+    where
+        F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+    {
+        let targets: Vec<MorphTarget> = reader
+            .read_morph_targets()
+            .map(|(positions, normals, tangents)| MorphTarget {
+                positions: positions.map(Iterator::collect).unwrap_or_default(),
+                normals: normals.map(Iterator::collect).unwrap_or_default(),
+                tangents: tangents.map(Iterator::collect).unwrap_or_default(),
+            })
+            .collect();
+        if targets.is_empty() {
+            None
+        } else {
+            Some(MorphTargets {
+                targets,
+                applied_weights: None,
+            })
+        }
+    }
+
+    pub fn target_count(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// Whether `weights` differ from the ones last passed to [`MorphTargets::apply`].
+    pub fn needs_update(&self, weights: &[f32]) -> bool {
+        self.applied_weights.as_deref() != Some(weights)
+    }
+
+    /// Write `base` displaced by the weighted targets into `out`. Missing weights count as zero.
+    pub fn apply(&mut self, weights: &[f32], base: &Vertices, out: &mut Vertices) {
+        out.positions.clone_from(&base.positions);
+        out.normals.clone_from(&base.normals);
+        out.tangents.clone_from(&base.tangents);
+
+        for (target, &weight) in self.targets.iter().zip(weights) {
+            if weight == 0.0 {
+                continue;
+            }
+            for (p, d) in out.positions.iter_mut().zip(&target.positions) {
+                add_scaled(p, d, weight);
+            }
+            for (n, d) in out.normals.iter_mut().zip(&target.normals) {
+                add_scaled(n, d, weight);
+            }
+            for (t, d) in out.tangents.iter_mut().zip(&target.tangents) {
+                add_scaled(t, d, weight);
+            }
+        }
+        self.applied_weights = Some(weights.to_vec());
+    }
+}
+
+fn add_scaled(v: &mut [f32], delta: &[f32; 3], weight: f32) {
+    for (v, d) in v.iter_mut().zip(delta) {
+        *v += d * weight;
+    }
+}
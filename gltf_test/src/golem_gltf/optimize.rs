@@ -0,0 +1,1069 @@
+//! Optional optimization of the triangle meshes in a file, before anything is uploaded.
+//!
+//! It works on the file's JSON and buffers rather than on the loaded vertices, so every attribute
+//! and morph target follows along (including the ones the viewer doesn't read), and exporting the
+//! scene afterwards writes the optimized data:
+//!
+//! - vertices equal in every attribute and morph target are merged;
+//! - triangles are put in vertex cache order (Tipsify, Sander et al. 2007), then clusters of
+//!   them are sorted front to back to reduce overdraw, as meshoptimizer does;
+//! - vertices are put in the order the triangles first use them, dropping unused ones;
+//! - indices take 16 bits when the vertices fit;
+//! - with `KHR_mesh_quantization`, normals and tangents are stored as normalized 16-bit integers
+//!   and texture coordinates within 0..1 as normalized unsigned 16-bit integers.
+//!
+//! Positions stay floats, since dequantizing them takes a node transform, which the nodes
+//! sharing the mesh and their children would pick up too.
+//!
+//! golem only uploads floats and 32-bit indices, so the 16-bit indices and quantized attributes
+//! don't save any GPU memory: they only make exported files smaller.
+//!
+//! Only indexed or non-indexed triangle lists are optimized, and only where their accessors
+//! aren't shared with anything else. Blended primitives keep their triangle order, which changes
+//! how they look.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use gltf::accessor::{DataType, Dimensions};
+use gltf::json::Value;
+use nalgebra_glm as glm;
+
+use super::accessor;
+
+pub const EXTENSION: &str = "KHR_mesh_quantization";
+
+/// Entries in the simulated post-transform vertex cache.
+const CACHE_SIZE: usize = 16;
+/// How much worse than its whole cluster a piece of it may be in cache misses per triangle, when
+/// splitting clusters for overdraw sorting.
+const CLUSTER_THRESHOLD: f32 = 1.05;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Which steps to run, all of them by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Optimization {
+    pub deduplicate: bool,
+    /// Vertex cache, overdraw and vertex fetch order.
+    pub reorder: bool,
+    pub small_indices: bool,
+    pub quantize: bool,
+}
+
+impl Default for Optimization {
+    fn default() -> Optimization {
+        Optimization {
+            deduplicate: true,
+            reorder: true,
+            small_indices: true,
+            quantize: true,
+        }
+    }
+}
+
+/// Totals over the primitives optimized.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OptimizationStats {
+    pub primitives: usize,
+    /// Primitives left alone: not triangle lists, sharing accessors, or unreadable.
+    pub skipped: usize,
+    pub triangles: usize,
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    /// Attribute and morph target data.
+    pub vertex_bytes_before: usize,
+    pub vertex_bytes_after: usize,
+    pub index_bytes_before: usize,
+    pub index_bytes_after: usize,
+    /// Vertices transformed with a FIFO cache of 16 entries.
+    pub cache_misses_before: usize,
+    pub cache_misses_after: usize,
+}
+
+impl OptimizationStats {
+    /// Average cache miss ratio: vertices transformed per triangle, from 0.5 at best to 3.
+    pub fn acmr(&self) -> (f32, f32) {
+        let triangles = self.triangles.max(1) as f32;
+        (
+            self.cache_misses_before as f32 / triangles,
+            self.cache_misses_after as f32 / triangles,
+        )
+    }
+}
+
+impl fmt::Display for OptimizationStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (acmr_before, acmr_after) = self.acmr();
+        writeln!(
+            f,
+            "optimized {} primitives ({} skipped), {} triangles",
+            self.primitives, self.skipped, self.triangles
+        )?;
+        writeln!(
+            f,
+            "  vertices: {} -> {}",
+            self.vertices_before, self.vertices_after
+        )?;
+        writeln!(
+            f,
+            "  vertex bytes: {} -> {}",
+            self.vertex_bytes_before, self.vertex_bytes_after
+        )?;
+        writeln!(
+            f,
+            "  index bytes: {} -> {}",
+            self.index_bytes_before, self.index_bytes_after
+        )?;
+        write!(f, "  ACMR: {:.3} -> {:.3}", acmr_before, acmr_after)
+    }
+}
+
+/// Optimize the meshes of `document` by rewriting `json`, its JSON, and `buffers`, which are
+/// packed into one without the data the optimized primitives used before. The accessors keep
+/// their indices, so nothing referring to them changes. `document` has to be parsed again from
+/// `json` afterwards.
+pub fn optimize(
+    json: &mut Value,
+    document: &gltf::Document,
+    buffers: &mut Vec<gltf::buffer::Data>,
+    options: &Optimization,
+) -> OptimizationStats {
+    let mut stats = OptimizationStats::default();
+    let shared = shared_accessors(json, document);
+    let mut writer = Writer {
+        buffer: buffers.len(),
+        data: Vec::new(),
+        quantized: false,
+    };
+
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            let blended = primitive.material().alpha_mode() == gltf::material::AlphaMode::Blend;
+            let options = Optimization {
+                reorder: options.reorder && !blended,
+                ..*options
+            };
+            let optimized = read_primitive(&primitive, buffers, &shared)
+                .map(|p| p.optimize(&options, &mut stats))
+                .map(|p| p.write(json, &mesh, &primitive, &options, &mut writer, &mut stats));
+            match optimized {
+                Some(()) => stats.primitives += 1,
+                None => stats.skipped += 1,
+            }
+        }
+    }
+
+    if !writer.data.is_empty() {
+        let mut buffer = Value::Null;
+        buffer["byteLength"] = Value::from(writer.data.len());
+        push(json, "buffers", buffer);
+        buffers.push(gltf::buffer::Data(writer.data));
+        let packed = super::export::pack_buffers(json, buffers, None);
+        *buffers = packed.into_iter().map(gltf::buffer::Data).collect();
+    }
+    if writer.quantized {
+        for key in &["extensionsUsed", "extensionsRequired"] {
+            let listed = json[*key]
+                .as_array()
+                .is_some_and(|names| names.iter().any(|n| n == EXTENSION));
+            if !listed {
+                push(json, key, Value::from(EXTENSION));
+            }
+        }
+    }
+    stats
+}
+
+/// Accessors used more than once, anywhere the viewer knows of. Primitives using them can't
+/// have their vertices renumbered.
+fn shared_accessors(json: &Value, document: &gltf::Document) -> Vec<bool> {
+    let mut uses = vec![0usize; document.accessors().count()];
+    let mut count = |index: usize| {
+        if let Some(uses) = uses.get_mut(index) {
+            *uses += 1;
+        }
+    };
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            primitive.attributes().for_each(|(_, a)| count(a.index()));
+            primitive
+                .indices()
+                .into_iter()
+                .for_each(|a| count(a.index()));
+            for target in primitive.morph_targets() {
+                let accessors = target.positions().into_iter().chain(target.normals());
+                accessors
+                    .chain(target.tangents())
+                    .for_each(|a| count(a.index()));
+            }
+        }
+    }
+    for skin in document.skins() {
+        skin.inverse_bind_matrices()
+            .into_iter()
+            .for_each(|a| count(a.index()));
+    }
+    for animation in document.animations() {
+        for sampler in animation.samplers() {
+            count(sampler.input().index());
+            count(sampler.output().index());
+        }
+    }
+    if let Some(nodes) = json["nodes"].as_array() {
+        for node in nodes {
+            let instancing = &node["extensions"][super::gpu_instancing::EXTENSION]["attributes"];
+            if let Some(attributes) = instancing.as_object() {
+                attributes
+                    .values()
+                    .filter_map(Value::as_u64)
+                    .for_each(|a| count(a as usize));
+            }
+        }
+    }
+    uses.into_iter().map(|uses| uses > 1).collect()
+}
+
+/// An attribute or morph target attribute of a primitive, read into memory.
+struct Stream {
+    accessor: usize,
+    /// `None` for morph targets, which are copied as they are.
+    semantic: Option<gltf::Semantic>,
+    data_type: DataType,
+    dimensions: Dimensions,
+    normalized: bool,
+    /// Bytes per element.
+    size: usize,
+    data: Vec<u8>,
+    has_bounds: bool,
+}
+
+struct Geometry {
+    streams: Vec<Stream>,
+    positions: Vec<[f32; 3]>,
+    /// `None` for non-indexed primitives.
+    index_accessor: Option<(usize, DataType)>,
+    indices: Vec<u32>,
+    /// Vertex the primitive used before, for each of the vertices it uses after optimizing.
+    vertices: Vec<usize>,
+    cache_misses_before: usize,
+}
+
+fn read_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    shared: &[bool],
+) -> Option<Geometry> {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+        return None;
+    }
+    let position = primitive.get(&gltf::Semantic::Positions)?;
+    let vertex_count = position.count();
+
+    let stream = |accessor: gltf::Accessor, semantic: Option<gltf::Semantic>| {
+        if shared[accessor.index()] || accessor.count() != vertex_count {
+            return None;
+        }
+        Some(Stream {
+            accessor: accessor.index(),
+            semantic,
+            data_type: accessor.data_type(),
+            dimensions: accessor.dimensions(),
+            normalized: accessor.normalized(),
+            size: accessor.size(),
+            data: accessor::read_elements(&accessor, buffers)?,
+            has_bounds: accessor.min().is_some() && accessor.max().is_some(),
+        })
+    };
+    let mut streams = Vec::new();
+    for (semantic, accessor) in primitive.attributes() {
+        streams.push(stream(accessor, Some(semantic))?);
+    }
+    for target in primitive.morph_targets() {
+        let accessors = target.positions().into_iter().chain(target.normals());
+        for accessor in accessors.chain(target.tangents()) {
+            streams.push(stream(accessor, None)?);
+        }
+    }
+
+    let (index_accessor, mut indices) = match primitive.indices() {
+        Some(accessor) if shared[accessor.index()] => return None,
+        Some(accessor) => {
+            let data = accessor::read_elements(&accessor, buffers)?;
+            let indices: Vec<u32> = match accessor.data_type() {
+                DataType::U8 => data.iter().map(|&i| i as u32).collect(),
+                DataType::U16 => data
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]) as u32)
+                    .collect(),
+                DataType::U32 => data
+                    .chunks_exact(4)
+                    .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect(),
+                _ => return None,
+            };
+            (Some((accessor.index(), accessor.data_type())), indices)
+        }
+        None => (None, (0..vertex_count as u32).collect()),
+    };
+    indices.truncate(indices.len() / 3 * 3);
+    if indices.iter().any(|&i| i as usize >= vertex_count) {
+        return None;
+    }
+
+    Some(Geometry {
+        streams,
+        positions: accessor::read_vec3(&position, buffers)?,
+        cache_misses_before: cache_misses(&indices, vertex_count),
+        index_accessor,
+        indices,
+        vertices: (0..vertex_count).collect(),
+    })
+}
+
+impl Geometry {
+    fn optimize(mut self, options: &Optimization, stats: &mut OptimizationStats) -> Geometry {
+        let vertex_count = self.vertices.len();
+        stats.vertices_before += vertex_count;
+        stats.vertex_bytes_before +=
+            self.streams.iter().map(|s| s.size).sum::<usize>() * vertex_count;
+        stats.index_bytes_before += match self.index_accessor {
+            Some((_, data_type)) => self.indices.len() * data_type.size(),
+            None => 0,
+        };
+        stats.triangles += self.indices.len() / 3;
+        stats.cache_misses_before += self.cache_misses_before;
+
+        // Renumber the vertices so that equal ones share a number, counting from 0 in the order
+        // they first appear in the buffers.
+        if options.deduplicate {
+            let mut unique: HashMap<Vec<u8>, u32> = HashMap::new();
+            let mut first = Vec::new();
+            let remap: Vec<u32> = (0..vertex_count)
+                .map(|v| {
+                    let key: Vec<u8> = self
+                        .streams
+                        .iter()
+                        .flat_map(|s| s.data[v * s.size..(v + 1) * s.size].iter().copied())
+                        .collect();
+                    *unique.entry(key).or_insert_with(|| {
+                        first.push(v);
+                        first.len() as u32 - 1
+                    })
+                })
+                .collect();
+            for index in &mut self.indices {
+                *index = remap[*index as usize];
+            }
+            self.vertices = first;
+        }
+
+        if options.reorder {
+            let positions: Vec<glm::Vec3> = self
+                .vertices
+                .iter()
+                .map(|&v| glm::make_vec3(&self.positions[v]))
+                .collect();
+            let (indices, hard_boundaries) = tipsify(&self.indices, self.vertices.len());
+            let clusters = soft_boundaries(&indices, self.vertices.len(), &hard_boundaries);
+            self.indices = sort_clusters(&indices, &clusters, &positions);
+        }
+
+        // Number the vertices in the order the triangles first use them, which also drops the
+        // unused ones.
+        let mut remap = vec![u32::MAX; self.vertices.len()];
+        let mut vertices = Vec::new();
+        for index in &mut self.indices {
+            let old = *index as usize;
+            if remap[old] == u32::MAX {
+                remap[old] = vertices.len() as u32;
+                vertices.push(self.vertices[old]);
+            }
+            *index = remap[old];
+        }
+        self.vertices = vertices;
+
+        stats.vertices_after += self.vertices.len();
+        stats.cache_misses_after += cache_misses(&self.indices, self.vertices.len());
+        self
+    }
+
+    fn write(
+        self,
+        json: &mut Value,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+        options: &Optimization,
+        writer: &mut Writer,
+        stats: &mut OptimizationStats,
+    ) {
+        for stream in &self.streams {
+            let (data_type, normalized, size, data) = stream.convert(&self.vertices, options);
+            writer.quantized |= (data_type, normalized) != (stream.data_type, stream.normalized)
+                && stream.data_type == DataType::F32;
+            let stride = size.div_ceil(4) * 4;
+            let mut padded = Vec::with_capacity(stride * self.vertices.len());
+            for element in data.chunks_exact(size) {
+                padded.extend_from_slice(element);
+                padded.resize(padded.len() + stride - size, 0);
+            }
+            stats.vertex_bytes_after += padded.len();
+            let view = writer.push_view(
+                json,
+                &padded,
+                Some(stride).filter(|&s| s != size),
+                ARRAY_BUFFER,
+            );
+
+            let bounds = if stream.has_bounds {
+                bounds(&data, data_type, stream.dimensions.multiplicity())
+            } else {
+                None
+            };
+            let accessor = &mut json["accessors"][stream.accessor];
+            set_accessor(accessor, view, data_type, normalized, self.vertices.len());
+            match bounds {
+                Some((min, max)) => {
+                    accessor["min"] = min;
+                    accessor["max"] = max;
+                }
+                None => {
+                    remove(accessor, "min");
+                    remove(accessor, "max");
+                }
+            }
+        }
+
+        let vertex_count = self.vertices.len();
+        let original = self.index_accessor.map(|(_, data_type)| data_type);
+        let data_type = if original == Some(DataType::U8) && vertex_count <= 0xff {
+            DataType::U8
+        } else if (options.small_indices || original == Some(DataType::U16))
+            && vertex_count <= 0xffff
+        {
+            DataType::U16
+        } else {
+            DataType::U32
+        };
+        let data: Vec<u8> = match data_type {
+            DataType::U8 => self.indices.iter().map(|&i| i as u8).collect(),
+            DataType::U16 => self
+                .indices
+                .iter()
+                .flat_map(|&i| (i as u16).to_le_bytes().to_vec())
+                .collect(),
+            _ => self
+                .indices
+                .iter()
+                .flat_map(|&i| i.to_le_bytes().to_vec())
+                .collect(),
+        };
+        stats.index_bytes_after += data.len();
+        let view = writer.push_view(json, &data, None, ELEMENT_ARRAY_BUFFER);
+        let accessor = match self.index_accessor {
+            Some((accessor, _)) => accessor,
+            None => {
+                let mut accessor = Value::Null;
+                accessor["type"] = Value::from("SCALAR");
+                let index = push(json, "accessors", accessor);
+                json["meshes"][mesh.index()]["primitives"][primitive.index()]["indices"] =
+                    Value::from(index);
+                index
+            }
+        };
+        let accessor = &mut json["accessors"][accessor];
+        set_accessor(accessor, view, data_type, false, self.indices.len());
+        remove(accessor, "min");
+        remove(accessor, "max");
+    }
+}
+
+impl Stream {
+    /// The elements for `vertices`, quantized if allowed, with their component type, whether
+    /// they are normalized, and the size of each.
+    fn convert(
+        &self,
+        vertices: &[usize],
+        options: &Optimization,
+    ) -> (DataType, bool, usize, Vec<u8>) {
+        let floats = |v: usize| {
+            self.data[v * self.size..(v + 1) * self.size]
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        };
+        let quantizable = options.quantize && self.data_type == DataType::F32;
+        match &self.semantic {
+            Some(gltf::Semantic::Normals) | Some(gltf::Semantic::Tangents) if quantizable => {
+                let data = vertices
+                    .iter()
+                    .flat_map(|&v| floats(v))
+                    .flat_map(|c| {
+                        ((c.clamp(-1.0, 1.0) * 32767.0).round() as i16)
+                            .to_le_bytes()
+                            .to_vec()
+                    })
+                    .collect();
+                (DataType::I16, true, self.size / 2, data)
+            }
+            Some(gltf::Semantic::TexCoords(_))
+                if quantizable
+                    && vertices
+                        .iter()
+                        .flat_map(|&v| floats(v))
+                        .all(|c| (0.0..=1.0).contains(&c)) =>
+            {
+                let data = vertices
+                    .iter()
+                    .flat_map(|&v| floats(v))
+                    .flat_map(|c| ((c * 65535.0).round() as u16).to_le_bytes().to_vec())
+                    .collect();
+                (DataType::U16, true, self.size / 2, data)
+            }
+            _ => {
+                let data = vertices
+                    .iter()
+                    .flat_map(|&v| {
+                        self.data[v * self.size..(v + 1) * self.size]
+                            .iter()
+                            .copied()
+                    })
+                    .collect();
+                (self.data_type, self.normalized, self.size, data)
+            }
+        }
+    }
+}
+
+/// The new buffer the optimized data goes to.
+struct Writer {
+    buffer: usize,
+    data: Vec<u8>,
+    quantized: bool,
+}
+
+impl Writer {
+    fn push_view(
+        &mut self,
+        json: &mut Value,
+        data: &[u8],
+        stride: Option<usize>,
+        target: u32,
+    ) -> usize {
+        while self.data.len() % 4 != 0 {
+            self.data.push(0);
+        }
+        let mut view = Value::Null;
+        view["buffer"] = Value::from(self.buffer);
+        view["byteOffset"] = Value::from(self.data.len());
+        view["byteLength"] = Value::from(data.len());
+        if let Some(stride) = stride {
+            view["byteStride"] = Value::from(stride);
+        }
+        view["target"] = Value::from(target);
+        self.data.extend_from_slice(data);
+        push(json, "bufferViews", view)
+    }
+}
+
+fn set_accessor(
+    accessor: &mut Value,
+    view: usize,
+    data_type: DataType,
+    normalized: bool,
+    count: usize,
+) {
+    accessor["bufferView"] = Value::from(view);
+    accessor["componentType"] = Value::from(data_type.as_gl_enum());
+    accessor["count"] = Value::from(count);
+    remove(accessor, "byteOffset");
+    remove(accessor, "sparse");
+    if normalized {
+        accessor["normalized"] = Value::from(true);
+    } else {
+        remove(accessor, "normalized");
+    }
+}
+
+/// Per-component minimum and maximum of tightly packed `data`, in the accessor's own units.
+fn bounds(data: &[u8], data_type: DataType, components: usize) -> Option<(Value, Value)> {
+    let values: Vec<f64> = match data_type {
+        DataType::F32 => data
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+            .collect(),
+        DataType::I8 => data.iter().map(|&c| c as i8 as f64).collect(),
+        DataType::U8 => data.iter().map(|&c| c as f64).collect(),
+        DataType::I16 => data
+            .chunks_exact(2)
+            .map(|c| i16::from_le_bytes([c[0], c[1]]) as f64)
+            .collect(),
+        DataType::U16 => data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]) as f64)
+            .collect(),
+        DataType::U32 => data
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+            .collect(),
+    };
+    if values.is_empty() {
+        return None;
+    }
+    let mut min = vec![f64::INFINITY; components];
+    let mut max = vec![f64::NEG_INFINITY; components];
+    for element in values.chunks_exact(components) {
+        for (i, &c) in element.iter().enumerate() {
+            min[i] = min[i].min(c);
+            max[i] = max[i].max(c);
+        }
+    }
+    // Floats go through f32's shortest decimal, so they read back as the same f32.
+    let number = |c: f64| match data_type {
+        DataType::F32 => Value::from((c as f32).to_string().parse::<f64>().unwrap_or(c)),
+        _ => Value::from(c as i64),
+    };
+    Some((
+        Value::from(min.into_iter().map(number).collect::<Vec<_>>()),
+        Value::from(max.into_iter().map(number).collect::<Vec<_>>()),
+    ))
+}
+
+fn push(json: &mut Value, key: &str, value: Value) -> usize {
+    if !json[key].is_array() {
+        json[key] = Value::from(Vec::<Value>::new());
+    }
+    let array = json[key].as_array_mut().unwrap();
+    array.push(value);
+    array.len() - 1
+}
+
+fn remove(object: &mut Value, key: &str) {
+    if let Some(object) = object.as_object_mut() {
+        object.remove(key);
+    }
+}
+
+/// Vertices transformed drawing `indices` through a FIFO cache of [`CACHE_SIZE`] entries.
+fn cache_misses(indices: &[u32], vertex_count: usize) -> usize {
+    let mut cache = Cache::new(vertex_count);
+    indices
+        .iter()
+        .filter(|&&v| cache.insert(v as usize))
+        .count()
+}
+
+/// A FIFO vertex cache, kept as the time each vertex last entered it.
+struct Cache {
+    entered: Vec<usize>,
+    time: usize,
+}
+
+impl Cache {
+    fn new(vertex_count: usize) -> Cache {
+        Cache {
+            entered: vec![0; vertex_count],
+            time: CACHE_SIZE + 1,
+        }
+    }
+
+    fn contains(&self, vertex: usize) -> bool {
+        self.time - self.entered[vertex] <= CACHE_SIZE
+    }
+
+    /// Returns whether it was a miss.
+    fn insert(&mut self, vertex: usize) -> bool {
+        if self.contains(vertex) {
+            return false;
+        }
+        self.entered[vertex] = self.time;
+        self.time += 1;
+        true
+    }
+
+    fn clear(&mut self) {
+        self.time += CACHE_SIZE + 1;
+    }
+}
+
+/// Triangles reordered for the vertex cache with Tipsify, keeping each one's winding, and the
+/// first triangle of each run after the fan had to jump to a vertex far from the last ones.
+fn tipsify(indices: &[u32], vertex_count: usize) -> (Vec<u32>, Vec<usize>) {
+    let triangle_count = indices.len() / 3;
+    // Triangles around each vertex, as ranges of `adjacent`.
+    let mut live = vec![0usize; vertex_count];
+    for &v in indices {
+        live[v as usize] += 1;
+    }
+    let mut offsets = Vec::with_capacity(vertex_count + 1);
+    offsets.push(0);
+    for v in 0..vertex_count {
+        offsets.push(offsets[v] + live[v]);
+    }
+    let mut adjacent = vec![0usize; indices.len()];
+    let mut filled = offsets.clone();
+    for (i, &v) in indices.iter().enumerate() {
+        adjacent[filled[v as usize]] = i / 3;
+        filled[v as usize] += 1;
+    }
+
+    let mut cache = Cache::new(vertex_count);
+    let mut emitted = vec![false; triangle_count];
+    let mut dead_ends: Vec<usize> = Vec::new();
+    let mut cursor = 0;
+    let mut output = Vec::with_capacity(indices.len());
+    let mut boundaries = vec![0];
+    let mut fanning = Some(0).filter(|_| vertex_count > 0);
+
+    while let Some(f) = fanning {
+        let mut candidates = Vec::new();
+        for &t in &adjacent[offsets[f]..offsets[f + 1]] {
+            if emitted[t] {
+                continue;
+            }
+            for &v in &indices[t * 3..t * 3 + 3] {
+                let v = v as usize;
+                output.push(v as u32);
+                dead_ends.push(v);
+                candidates.push(v);
+                live[v] -= 1;
+                cache.insert(v);
+            }
+            emitted[t] = true;
+        }
+
+        // The candidate still in the cache after its remaining triangles are emitted that
+        // entered it longest ago, or else the most recent dead end, or else the next vertex.
+        let mut best = None;
+        let mut best_priority = 0;
+        for &v in &candidates {
+            if live[v] == 0 {
+                continue;
+            }
+            let age = cache.time - cache.entered[v];
+            let priority = if age + 2 * live[v] <= CACHE_SIZE {
+                age
+            } else {
+                0
+            };
+            if best.is_none() || priority > best_priority {
+                best = Some(v);
+                best_priority = priority;
+            }
+        }
+        fanning = best.or_else(|| {
+            while let Some(v) = dead_ends.pop() {
+                if live[v] > 0 {
+                    return Some(v);
+                }
+            }
+            while cursor < vertex_count {
+                cursor += 1;
+                if live[cursor - 1] > 0 {
+                    if output.len() / 3 != *boundaries.last().unwrap() {
+                        boundaries.push(output.len() / 3);
+                    }
+                    return Some(cursor - 1);
+                }
+            }
+            None
+        });
+    }
+    (output, boundaries)
+}
+
+/// `hard_boundaries` split further wherever a cluster's own cache miss ratio gets within
+/// [`CLUSTER_THRESHOLD`] of the whole one's, so there are more clusters to sort for overdraw
+/// without losing much vertex cache efficiency.
+fn soft_boundaries(indices: &[u32], vertex_count: usize, hard_boundaries: &[usize]) -> Vec<usize> {
+    let triangle_count = indices.len() / 3;
+    let mut cache = Cache::new(vertex_count);
+    let misses = |cache: &mut Cache, t: usize| {
+        indices[t * 3..t * 3 + 3]
+            .iter()
+            .filter(|&&v| cache.insert(v as usize))
+            .count()
+    };
+
+    let mut boundaries = Vec::new();
+    for (i, &start) in hard_boundaries.iter().enumerate() {
+        let end = hard_boundaries
+            .get(i + 1)
+            .copied()
+            .unwrap_or(triangle_count);
+        cache.clear();
+        let cluster_misses: usize = (start..end).map(|t| misses(&mut cache, t)).sum();
+        let threshold = cluster_misses as f32 / (end - start).max(1) as f32 * CLUSTER_THRESHOLD;
+
+        boundaries.push(start);
+        cache.clear();
+        let (mut running_misses, mut running_triangles) = (0, 0);
+        for t in start..end {
+            running_misses += misses(&mut cache, t);
+            running_triangles += 1;
+            if running_misses as f32 / running_triangles as f32 <= threshold && t + 1 < end {
+                boundaries.push(t + 1);
+                cache.clear();
+                running_misses = 0;
+                running_triangles = 0;
+            }
+        }
+    }
+    boundaries
+}
+
+/// The clusters starting at `boundaries`, those facing out from the middle of the mesh first, so
+/// they tend to hide the ones behind them.
+fn sort_clusters(indices: &[u32], boundaries: &[usize], positions: &[glm::Vec3]) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let corners = |t: usize| {
+        let p = |k: usize| positions[indices[t * 3 + k] as usize];
+        (p(0), p(1), p(2))
+    };
+
+    let mut clusters: Vec<(usize, usize, glm::Vec3, glm::Vec3, f32)> = Vec::new();
+    let mut mesh_centroid = glm::Vec3::zeros();
+    let mut mesh_area = 0.0;
+    for (i, &start) in boundaries.iter().enumerate() {
+        let end = boundaries.get(i + 1).copied().unwrap_or(triangle_count);
+        let mut centroid = glm::Vec3::zeros();
+        let mut normal = glm::Vec3::zeros();
+        let mut area = 0.0;
+        for t in start..end {
+            let (a, b, c) = corners(t);
+            let cross = (b - a).cross(&(c - a));
+            let triangle_area = cross.norm();
+            centroid += (a + b + c) / 3.0 * triangle_area;
+            normal += cross;
+            area += triangle_area;
+        }
+        if area > 0.0 {
+            centroid /= area;
+        }
+        mesh_centroid += centroid * area;
+        mesh_area += area;
+        let length = normal.norm();
+        if length > 0.0 {
+            normal /= length;
+        }
+        clusters.push((start, end, centroid, normal, 0.0));
+    }
+    if mesh_area > 0.0 {
+        mesh_centroid /= mesh_area;
+    }
+    for cluster in &mut clusters {
+        cluster.4 = (cluster.2 - mesh_centroid).dot(&cluster.3);
+    }
+    clusters.sort_by(|a, b| b.4.partial_cmp(&a.4).unwrap_or(std::cmp::Ordering::Equal));
+
+    clusters
+        .iter()
+        .flat_map(|&(start, end, ..)| indices[start * 3..end * 3].iter().copied())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIDE: u32 = 10;
+
+    /// The triangles of a grid of `SIDE` by `SIDE` squares, as indices into its vertices, which
+    /// are numbered row by row.
+    fn grid() -> Vec<[u32; 3]> {
+        let vertex = |x: u32, y: u32| y * (SIDE + 1) + x;
+        let mut triangles = Vec::new();
+        for y in 0..SIDE {
+            for x in 0..SIDE {
+                triangles.push([vertex(x, y), vertex(x + 1, y), vertex(x + 1, y + 1)]);
+                triangles.push([vertex(x, y), vertex(x + 1, y + 1), vertex(x, y + 1)]);
+            }
+        }
+        triangles
+    }
+
+    fn grid_position(vertex: u32) -> [f32; 3] {
+        [
+            (vertex % (SIDE + 1)) as f32,
+            (vertex / (SIDE + 1)) as f32,
+            0.0,
+        ]
+    }
+
+    /// A mesh with one primitive, with 32-bit `indices` if there are any.
+    fn document(
+        positions: &[[f32; 3]],
+        indices: Option<&[u32]>,
+    ) -> (Value, gltf::Document, Vec<gltf::buffer::Data>) {
+        let mut data: Vec<u8> = positions
+            .iter()
+            .flatten()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        let position_length = data.len();
+        let mut views = format!(r#"{{"buffer": 0, "byteLength": {}}}"#, position_length);
+        let mut accessors = format!(
+            r#"{{"bufferView": 0, "componentType": 5126, "count": {}, "type": "VEC3",
+                "min": [0, 0, 0], "max": [{side}, {side}, 0]}}"#,
+            positions.len(),
+            side = SIDE,
+        );
+        let mut primitive = r#""attributes": {"POSITION": 0}"#.to_string();
+        if let Some(indices) = indices {
+            data.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+            views += &format!(
+                r#", {{"buffer": 0, "byteOffset": {}, "byteLength": {}}}"#,
+                position_length,
+                indices.len() * 4
+            );
+            accessors += &format!(
+                r#", {{"bufferView": 1, "componentType": 5125, "count": {}, "type": "SCALAR"}}"#,
+                indices.len()
+            );
+            primitive += r#", "indices": 1"#;
+        }
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "buffers": [{{"byteLength": {}}}],
+                "bufferViews": [{}],
+                "accessors": [{}],
+                "meshes": [{{"primitives": [{{{}}}]}}]
+            }}"#,
+            data.len(),
+            views,
+            accessors,
+            primitive
+        );
+        let json: Value = gltf::json::deserialize::from_str(&json).unwrap();
+        let document = parse(&json);
+        (json, document, vec![gltf::buffer::Data(data)])
+    }
+
+    fn parse(json: &Value) -> gltf::Document {
+        let bytes = gltf::json::serialize::to_vec(json).unwrap();
+        gltf::Gltf::from_slice(&bytes).unwrap().document
+    }
+
+    /// The triangles of the first primitive by the positions of their corners, each starting
+    /// from its smallest corner so that rotating a triangle doesn't change it, sorted.
+    fn triangles(document: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<[[i32; 3]; 3]> {
+        let primitive = document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .next()
+            .unwrap();
+        let positions =
+            accessor::read_vec3(&primitive.get(&gltf::Semantic::Positions).unwrap(), buffers)
+                .unwrap();
+        let indices: Vec<usize> = match primitive.indices() {
+            Some(accessor) => {
+                let data = accessor::read_elements(&accessor, buffers).unwrap();
+                match accessor.data_type() {
+                    DataType::U16 => data
+                        .chunks_exact(2)
+                        .map(|c| u16::from_le_bytes([c[0], c[1]]) as usize)
+                        .collect(),
+                    _ => data
+                        .chunks_exact(4)
+                        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize)
+                        .collect(),
+                }
+            }
+            None => (0..positions.len()).collect(),
+        };
+        let mut triangles: Vec<[[i32; 3]; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let mut corners = [[0; 3]; 3];
+                for (corner, &index) in corners.iter_mut().zip(triangle) {
+                    *corner = [0, 1, 2].map(|c| positions[index][c] as i32);
+                }
+                let first = (0..3).min_by_key(|&c| corners[c]).unwrap();
+                corners.rotate_left(first);
+                corners
+            })
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    /// The grid's triangles in a scrambled order, as indices.
+    fn scrambled_grid() -> Vec<u32> {
+        let mut triangles = grid();
+        let mut state = 0x2545_f491_u32;
+        for i in (1..triangles.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            triangles.swap(i, state as usize % (i + 1));
+        }
+        triangles.into_iter().flatten().collect()
+    }
+
+    #[test]
+    fn deduplicates_vertices() {
+        let positions: Vec<[f32; 3]> = grid().into_iter().flatten().map(grid_position).collect();
+        let (mut json, document, mut buffers) = document(&positions, None);
+        let before = triangles(&document, &buffers);
+
+        let stats = optimize(&mut json, &document, &mut buffers, &Optimization::default());
+        assert_eq!(stats.primitives, 1);
+        assert_eq!(stats.vertices_before, positions.len());
+        assert_eq!(stats.vertices_after, ((SIDE + 1) * (SIDE + 1)) as usize);
+        assert_eq!(triangles(&parse(&json), &buffers), before);
+    }
+
+    #[test]
+    fn reordering_keeps_triangles_and_lowers_cache_misses() {
+        let positions: Vec<[f32; 3]> = (0..(SIDE + 1) * (SIDE + 1)).map(grid_position).collect();
+        let indices = scrambled_grid();
+        let (mut json, document, mut buffers) = document(&positions, Some(&indices));
+        let before = triangles(&document, &buffers);
+
+        let stats = optimize(&mut json, &document, &mut buffers, &Optimization::default());
+        let (acmr_before, acmr_after) = stats.acmr();
+        assert!(
+            acmr_after <= acmr_before,
+            "{} > {}",
+            acmr_after,
+            acmr_before
+        );
+        assert_eq!(triangles(&parse(&json), &buffers), before);
+    }
+
+    #[test]
+    fn narrows_indices() {
+        let positions: Vec<[f32; 3]> = (0..(SIDE + 1) * (SIDE + 1)).map(grid_position).collect();
+        let indices = scrambled_grid();
+        for &(small_indices, component_type, size) in &[(true, 5123, 2), (false, 5125, 4)] {
+            let (mut json, document, mut buffers) = document(&positions, Some(&indices));
+            let options = Optimization {
+                small_indices,
+                ..Optimization::default()
+            };
+            let stats = optimize(&mut json, &document, &mut buffers, &options);
+            assert_eq!(json["accessors"][1]["componentType"], component_type);
+            assert_eq!(stats.index_bytes_after, indices.len() * size);
+            assert_eq!(triangles(&parse(&json), &buffers).len(), indices.len() / 3);
+        }
+    }
+
+    #[test]
+    fn drops_replaced_data() {
+        let positions: Vec<[f32; 3]> = grid().into_iter().flatten().map(grid_position).collect();
+        let (mut json, document, mut buffers) = document(&positions, None);
+        let length_before = buffers[0].len();
+
+        let stats = optimize(&mut json, &document, &mut buffers, &Optimization::default());
+        assert_eq!(buffers.len(), 1);
+        assert!(buffers[0].len() < length_before);
+        assert_eq!(
+            buffers[0].len(),
+            stats.vertex_bytes_after + stats.index_bytes_after
+        );
+        assert_eq!(json["buffers"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,389 @@
+//! Ray casts against the loaded triangles, for picking with the mouse.
+
+use nalgebra_glm as glm;
+
+use super::Aabb;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: glm::Vec3,
+    /// Unit length.
+    pub direction: glm::Vec3,
+}
+
+impl Ray {
+    /// The ray through `ndc` (normalized device coordinates, -1 to 1 with +Y up) from the near
+    /// plane of `view_projection`.
+    pub fn unproject(ndc: glm::Vec2, view_projection: &glm::Mat4) -> Ray {
+        let inverse = glm::inverse(view_projection);
+        let point = |z: f32| {
+            let p = inverse * glm::vec4(ndc.x, ndc.y, z, 1.0);
+            p.xyz() / p.w
+        };
+        let (near, far) = (point(-1.0), point(1.0));
+        Ray {
+            origin: near,
+            direction: glm::normalize(&(far - near)),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> glm::Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// The ray in the space `matrix` maps from. The direction isn't normalized, so that distances
+    /// along the ray stay the same as along this one.
+    pub fn transform_inverse(&self, matrix: &glm::Mat4) -> Ray {
+        let inverse = glm::inverse(matrix);
+        let o = self.origin;
+        let d = self.direction;
+        Ray {
+            origin: (inverse * glm::vec4(o.x, o.y, o.z, 1.0)).xyz(),
+            direction: (inverse * glm::vec4(d.x, d.y, d.z, 0.0)).xyz(),
+        }
+    }
+
+    /// Distance to where the ray enters `aabb`, if it does before `max_distance`.
+    fn enters(&self, aabb: &Aabb, max_distance: f32) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, max_distance);
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let a = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let b = (aabb.max[axis] - self.origin[axis]) * inverse;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+
+    /// Distance and barycentric coordinates of `b` and `c` where the ray crosses the triangle,
+    /// from either side (Möller & Trumbore).
+    fn triangle(&self, [a, b, c]: [glm::Vec3; 3]) -> Option<(f32, f32, f32)> {
+        let (e1, e2) = (b - a, c - a);
+        let p = self.direction.cross(&e2);
+        let det = e1.dot(&p);
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = self.origin - a;
+        let u = t.dot(&p) / det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = t.cross(&e1);
+        let v = self.direction.dot(&q) / det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = e2.dot(&q) / det;
+        if distance >= 0.0 {
+            Some((distance, u, v))
+        } else {
+            None
+        }
+    }
+}
+
+/// Where a ray hit the model.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub node: usize,
+    /// Index into the node's `EXT_mesh_gpu_instancing` transforms, or 0 without them.
+    pub instance: usize,
+    pub mesh: usize,
+    /// Index of the primitive in the mesh.
+    pub primitive: usize,
+    /// Index of the triangle in the primitive, counting strips and fans one triangle at a time.
+    pub triangle: usize,
+    /// Along the ray, in world units.
+    pub distance: f32,
+    pub point: glm::Vec3,
+    /// Interpolated from the vertex normals, or the face normal where there are none.
+    pub normal: glm::Vec3,
+}
+
+/// Closest triangle crossed by `ray`, as (triangle, distance, u, v).
+pub type TriangleHit = (usize, f32, f32, f32);
+
+/// The triangle lists of strips and fans, with strips rewound so every triangle faces the same
+/// way. Points and lines have none.
+pub fn triangles(indices: &[u32], mode: gltf::mesh::Mode) -> Vec<[u32; 3]> {
+    use gltf::mesh::Mode;
+    match mode {
+        Mode::Triangles => indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect(),
+        Mode::TriangleStrip => indices
+            .windows(3)
+            .enumerate()
+            .map(|(i, t)| {
+                if i % 2 == 0 {
+                    [t[0], t[1], t[2]]
+                } else {
+                    [t[1], t[0], t[2]]
+                }
+            })
+            .collect(),
+        Mode::TriangleFan if indices.len() >= 3 => indices[1..]
+            .windows(2)
+            .map(|t| [indices[0], t[0], t[1]])
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn corners(positions: &[[f32; 3]], triangle: [u32; 3]) -> [glm::Vec3; 3] {
+    let p = |i: u32| glm::make_vec3(&positions[i as usize]);
+    [p(triangle[0]), p(triangle[1]), p(triangle[2])]
+}
+
+/// Test every triangle, for geometry that moves too often to keep a [`Bvh`] of.
+pub fn intersect_all(
+    ray: &Ray,
+    positions: &[[f32; 3]],
+    triangles: &[[u32; 3]],
+) -> Option<TriangleHit> {
+    triangles
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &t)| {
+            ray.triangle(corners(positions, t))
+                .map(|(d, u, v)| (i, d, u, v))
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Branch {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } | BvhNode::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over the triangles of a primitive, split at the median centroid
+/// along the longest axis.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// Triangle indices, ordered so each leaf covers a contiguous range.
+    order: Vec<usize>,
+}
+
+const LEAF_SIZE: usize = 4;
+
+impl Bvh {
+    pub fn new(positions: &[[f32; 3]], triangles: &[[u32; 3]]) -> Bvh {
+        let bounds: Vec<Aabb> = triangles
+            .iter()
+            .map(|&t| {
+                let corners: [[f32; 3]; 3] = [
+                    positions[t[0] as usize],
+                    positions[t[1] as usize],
+                    positions[t[2] as usize],
+                ];
+                Aabb::from_points(&corners).unwrap()
+            })
+            .collect();
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            order: (0..triangles.len()).collect(),
+        };
+        if !triangles.is_empty() {
+            bvh.build(&bounds, 0, triangles.len());
+        }
+        bvh
+    }
+
+    /// Add the node for `order[start..end]` and return its index.
+    fn build(&mut self, bounds: &[Aabb], start: usize, end: usize) -> usize {
+        let range_bounds = self.order[start..end]
+            .iter()
+            .map(|&i| bounds[i])
+            .reduce(|a, b| a.union(&b))
+            .unwrap();
+        let index = self.nodes.len();
+        if end - start <= LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf {
+                bounds: range_bounds,
+                start,
+                end,
+            });
+            return index;
+        }
+
+        let centroids = Aabb::from_points(
+            &self.order[start..end]
+                .iter()
+                .map(|&i| bounds[i].center().into())
+                .collect::<Vec<[f32; 3]>>(),
+        )
+        .unwrap();
+        let size = centroids.size();
+        let axis = if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        };
+        let middle = (start + end) / 2;
+        self.order[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            bounds[a].center()[axis]
+                .partial_cmp(&bounds[b].center()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        // Placeholder until the children's indices are known.
+        self.nodes.push(BvhNode::Leaf {
+            bounds: range_bounds,
+            start,
+            end,
+        });
+        let left = self.build(bounds, start, middle);
+        let right = self.build(bounds, middle, end);
+        self.nodes[index] = BvhNode::Branch {
+            bounds: range_bounds,
+            left,
+            right,
+        };
+        index
+    }
+
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        positions: &[[f32; 3]],
+        triangles: &[[u32; 3]],
+    ) -> Option<TriangleHit> {
+        let mut closest: Option<TriangleHit> = None;
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let max_distance = closest.map_or(f32::INFINITY, |c| c.1);
+            if ray.enters(node.bounds(), max_distance).is_none() {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { start, end, .. } => {
+                    for &i in &self.order[start..end] {
+                        match (ray.triangle(corners(positions, triangles[i])), closest) {
+                            (Some((d, _, _)), Some(c)) if d >= c.1 => {}
+                            (Some((d, u, v)), _) => closest = Some((i, d, u, v)),
+                            (None, _) => {}
+                        }
+                    }
+                }
+                BvhNode::Branch { left, right, .. } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small xorshift generator, so the tests don't need a dependency for random numbers.
+    struct Random(u32);
+
+    impl Random {
+        /// Uniform in -1..1.
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+        }
+
+        fn vec3(&mut self) -> glm::Vec3 {
+            glm::vec3(self.next(), self.next(), self.next())
+        }
+    }
+
+    #[test]
+    fn bvh_finds_what_testing_every_triangle_finds() {
+        let mut random = Random(0x2545_f491);
+        let mut positions = Vec::new();
+        for _ in 0..300 {
+            let center = random.vec3() * 2.0;
+            for _ in 0..3 {
+                positions.push((center + random.vec3() * 0.3).into());
+            }
+        }
+        let triangles: Vec<[u32; 3]> = (0..300).map(|i| [i * 3, i * 3 + 1, i * 3 + 2]).collect();
+        let bvh = Bvh::new(&positions, &triangles);
+
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let origin = random.vec3() * 4.0;
+            let ray = Ray {
+                origin,
+                direction: glm::normalize(&(random.vec3() - origin * 0.2)),
+            };
+            let expected = intersect_all(&ray, &positions, &triangles);
+            let actual = bvh.intersect(&ray, &positions, &triangles);
+            match (expected, actual) {
+                (Some(e), Some(a)) => {
+                    assert!((e.1 - a.1).abs() < 1e-5, "{:?} != {:?}", a, e);
+                    hits += 1;
+                }
+                (None, None) => {}
+                _ => panic!("{:?} != {:?} for {:?}", actual, expected, ray),
+            }
+        }
+        // Enough of the rays hit something for the comparison to mean anything.
+        assert!(hits > 100, "{} hits", hits);
+    }
+
+    #[test]
+    fn unprojecting_the_center_looks_down_the_view_direction() {
+        let eye = glm::vec3(1.0, 2.0, 3.0);
+        let target = glm::vec3(0.0, 0.5, -1.0);
+        let view = glm::look_at(&eye, &target, &glm::vec3(0.0, 1.0, 0.0));
+        let projection = glm::perspective(1.5, 0.8, 0.1, 100.0);
+        let ray = Ray::unproject(glm::vec2(0.0, 0.0), &(projection * view));
+
+        let forward = glm::normalize(&(target - eye));
+        assert!(glm::distance(&ray.direction, &forward) < 1e-4);
+        // It starts on the near plane.
+        assert!(glm::distance(&ray.origin, &(eye + forward * 0.1)) < 1e-4);
+    }
+
+    #[test]
+    fn strips_are_rewound() {
+        use gltf::mesh::Mode;
+        assert_eq!(
+            triangles(&[0, 1, 2, 3], Mode::TriangleStrip),
+            [[0, 1, 2], [2, 1, 3]]
+        );
+        assert_eq!(
+            triangles(&[0, 1, 2, 3], Mode::TriangleFan),
+            [[0, 1, 2], [0, 2, 3]]
+        );
+    }
+}
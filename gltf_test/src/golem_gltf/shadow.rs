@@ -0,0 +1,318 @@
+//! A shadow map for the first directional light. golem 0.1 has neither depth textures nor depth
+//! attachments for its surfaces, so like [`super::Instancing`] the framebuffer goes through a
+//! second `glow` context on the same GL context. Depth is packed into the bytes of an RGBA8
+//! target, and depth-tested against a renderbuffer.
+
+use std::convert::TryInto;
+use std::num::NonZeroU32;
+
+use glow::HasContext;
+use golem::*;
+use nalgebra_glm as glm;
+
+use super::light::{Light, LightKind};
+use super::material::Material;
+use super::Aabb;
+
+/// Width and height of the map.
+pub const SIZE: i32 = 1024;
+const BIND_POINT: u32 = 6;
+
+pub struct ShadowMap {
+    gl: glow::Context,
+    framebuffer: <glow::Context as HasContext>::Framebuffer,
+    texture: <glow::Context as HasContext>::Texture,
+    depth: <glow::Context as HasContext>::Renderbuffer,
+    /// World to map coordinates, all in 0..1, if a light was drawn into the map last.
+    matrix: Option<glm::Mat4>,
+    /// Index of the light the map was drawn for.
+    light: usize,
+}
+
+impl ShadowMap {
+    /// `None` if the framebuffer can't be completed.
+    pub fn new(gl: glow::Context) -> Option<ShadowMap> {
+        unsafe {
+            let framebuffer = gl.create_framebuffer().ok()?;
+            let texture = gl.create_texture().ok()?;
+            let depth = gl.create_renderbuffer().ok()?;
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA as i32,
+                SIZE,
+                SIZE,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+            // Packed depths can't be interpolated, so filtering is done in the shader.
+            for &(parameter, value) in &[
+                (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+                (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth));
+            gl.renderbuffer_storage(glow::RENDERBUFFER, glow::DEPTH_COMPONENT16, SIZE, SIZE);
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(texture),
+                0,
+            );
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_ATTACHMENT,
+                glow::RENDERBUFFER,
+                Some(depth),
+            );
+            let complete =
+                gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+            let map = ShadowMap {
+                gl,
+                framebuffer,
+                texture,
+                depth,
+                matrix: None,
+                light: 0,
+            };
+            if complete {
+                Some(map)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// The light that casts shadows: the first directional one.
+    pub fn caster(lights: &[Light]) -> Option<usize> {
+        lights
+            .iter()
+            .take(super::light::MAX_LIGHTS)
+            .position(|l| matches!(l.kind, LightKind::Directional))
+    }
+
+    /// View-projection of a light shining in `direction`, fitted around `bounds`.
+    pub fn light_matrix(direction: &glm::Vec3, bounds: &Aabb) -> glm::Mat4 {
+        let center = bounds.center();
+        let radius = bounds.radius().max(1e-4) * 1.01;
+        let up = if direction.x.abs() < 0.9 {
+            glm::vec3(1.0, 0.0, 0.0)
+        } else {
+            glm::vec3(0.0, 1.0, 0.0)
+        };
+        let view = glm::look_at(&(center - direction * radius), &center, &up);
+        glm::ortho(-radius, radius, -radius, radius, 0.0, radius * 2.0) * view
+    }
+
+    /// Bind the map's framebuffer, clear it to the far plane, and remember `light_matrix` for the
+    /// lighting pass. This leaves the viewport set to the map.
+    pub fn begin(&mut self, ctx: &Context, light: usize, light_matrix: &glm::Mat4) {
+        // From -1..1 to 0..1.
+        let bias = glm::translate(&glm::identity(), &glm::vec3(0.5, 0.5, 0.5))
+            * glm::scale(&glm::identity(), &glm::vec3(0.5, 0.5, 0.5));
+        self.matrix = Some(bias * light_matrix);
+        self.light = light;
+        unsafe {
+            self.gl
+                .bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+        }
+        ctx.set_viewport(0, 0, SIZE as u32, SIZE as u32);
+        ctx.set_clear_color(1.0, 1.0, 1.0, 1.0);
+        ctx.clear();
+    }
+
+    /// Draw to the screen again.
+    pub fn end(&self) {
+        unsafe { self.gl.bind_framebuffer(glow::FRAMEBUFFER, None) };
+    }
+
+    /// Forget the last light, when there is none to draw the map for.
+    pub fn clear(&mut self) {
+        self.matrix = None;
+    }
+
+    /// Set the lighting shader's shadow uniforms, using `white` as the map without a shadow map.
+    pub fn set_uniforms(
+        map: Option<&ShadowMap>,
+        shader: &ShaderProgram,
+        white: &Texture,
+    ) -> Result<(), GolemError> {
+        let (light, matrix) = match map.and_then(|m| m.matrix.map(|matrix| (m, matrix))) {
+            Some((map, matrix)) => {
+                unsafe {
+                    map.gl.active_texture(glow::TEXTURE0 + BIND_POINT);
+                    map.gl.bind_texture(glow::TEXTURE_2D, Some(map.texture));
+                    // golem uploads textures through unit 0.
+                    map.gl.active_texture(glow::TEXTURE0);
+                }
+                (map.light as f32, matrix)
+            }
+            None => {
+                white.set_active(NonZeroU32::new(BIND_POINT).unwrap());
+                (-1.0, glm::identity())
+            }
+        };
+        shader.set_uniform("shadow_map", UniformValue::Int(BIND_POINT as i32))?;
+        shader.set_uniform("shadow_light", UniformValue::Float(light))?;
+        shader.set_uniform(
+            "shadow_matrix",
+            UniformValue::Matrix4(glm::value_ptr(&matrix).try_into().unwrap()),
+        )
+    }
+}
+
+impl Drop for ShadowMap {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_framebuffer(self.framebuffer);
+            self.gl.delete_texture(self.texture);
+            self.gl.delete_renderbuffer(self.depth);
+        }
+    }
+}
+
+/// A floor under the model, to show its shadows on.
+pub struct Ground {
+    vb: VertexBuffer,
+    eb: ElementBuffer,
+    material: Material,
+}
+
+impl Ground {
+    pub fn new(ctx: &Context) -> Result<Ground, GolemError> {
+        // A square from -1 to 1 facing up, in the layout of the lighting shader's inputs.
+        let mut vertices = Vec::new();
+        for &(x, z) in &[(-1.0, -1.0), (-1.0, 1.0), (1.0, 1.0), (1.0, -1.0)] {
+            vertices.extend_from_slice(&[x, 0.0, z, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        }
+        let mut vb = VertexBuffer::new(ctx)?;
+        vb.set_data(&vertices);
+        let mut eb = ElementBuffer::new(ctx)?;
+        eb.set_data(&[0, 1, 2, 2, 3, 0]);
+        Ok(Ground {
+            vb,
+            eb,
+            material: Material {
+                name: Some("ground".to_owned()),
+                base_color_factor: glm::vec4(0.6, 0.6, 0.6, 1.0),
+                metallic_factor: 0.0,
+                ..Material::default()
+            },
+        })
+    }
+
+    /// Place the ground at the bottom of `bounds`, reaching well past it.
+    pub fn model_matrix(bounds: &Aabb) -> glm::Mat4 {
+        let center = bounds.center();
+        let extent = bounds.radius().max(1e-4) * 3.0;
+        glm::translate(
+            &glm::identity(),
+            &glm::vec3(center.x, bounds.min.y, center.z),
+        ) * glm::scale(&glm::identity(), &glm::vec3(extent, 1.0, extent))
+    }
+
+    /// Draw with the lighting shader, which must be bound with its light and view uniforms set.
+    pub fn draw(
+        &self,
+        shader: &ShaderProgram,
+        model_matrix: &glm::Mat4,
+        white: &Texture,
+    ) -> Result<(), GolemError> {
+        shader.set_uniform(
+            "model_matrix",
+            UniformValue::Matrix4(glm::value_ptr(model_matrix).try_into().unwrap()),
+        )?;
+        self.material.set_uniforms(shader, &[], white)?;
+        unsafe { shader.draw(&self.vb, &self.eb, 0..6, GeometryMode::Triangles) }
+    }
+}
+
+/// The uniforms [`ShadowMap::set_uniforms`] sets, and `shadow_pass`, which makes the lighting
+/// shader write packed depth instead.
+pub fn uniforms() -> Vec<Uniform> {
+    let float = || UniformType::Scalar(NumberType::Float);
+    vec![
+        Uniform::new("shadow_pass", float()),
+        Uniform::new("shadow_light", float()),
+        Uniform::new("shadow_matrix", UniformType::Matrix(Dimension::D4)),
+        Uniform::new("shadow_map", UniformType::Sampler2D),
+    ]
+}
+
+/// GLSL packing depth into the shadow map, and `shadow`, the fraction of the 3x3 texels around a
+/// world-space position that see the light.
+pub fn shadow_glsl() -> String {
+    format!(
+        r#"
+        vec4 pack_depth(float depth) {{
+            vec4 bytes = fract(depth * vec4(1.0, 255.0, 65025.0, 16581375.0));
+            return bytes - bytes.yzww * vec4(1.0 / 255.0, 1.0 / 255.0, 1.0 / 255.0, 0.0);
+        }}
+        float unpack_depth(vec4 bytes) {{
+            return dot(bytes, vec4(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0));
+        }}
+        float shadow(vec3 position, float n_dot_l) {{
+            vec4 p = shadow_matrix * vec4(position, 1.0);
+            if (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0) return 1.0;
+            // Beyond the far plane is still behind whatever is in the map.
+            float depth = min(p.z, 1.0) - (0.002 + 0.004 * (1.0 - n_dot_l));
+            float lit = 0.0;
+            for (int x = -1; x <= 1; x++) {{
+                for (int y = -1; y <= 1; y++) {{
+                    vec2 uv = p.xy + vec2(float(x), float(y)) / {size:.1};
+                    lit += depth <= unpack_depth(texture(shadow_map, uv)) ? 1.0 : 0.0;
+                }}
+            }}
+            return lit / 9.0;
+        }}
+"#,
+        size = SIZE as f32
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_matrix_fits_the_bounds() {
+        let bounds = Aabb {
+            min: glm::vec3(-1.0, 0.0, 2.0),
+            max: glm::vec3(3.0, 1.0, 4.0),
+        };
+        for direction in &[
+            glm::vec3(0.0, -1.0, 0.0),
+            glm::normalize(&glm::vec3(-1.0, -1.0, 0.0)),
+            glm::vec3(1.0, 0.0, 0.0),
+        ] {
+            let matrix = ShadowMap::light_matrix(direction, &bounds);
+            for corner in &bounds.corners() {
+                let p = matrix * glm::vec4(corner.x, corner.y, corner.z, 1.0);
+                assert!(
+                    p.x.abs() <= 1.0 && p.y.abs() <= 1.0 && p.z.abs() <= 1.0,
+                    "{:?}",
+                    p
+                );
+            }
+            // Nearer the light is nearer in the map.
+            let (near, far) = (bounds.center() - direction, bounds.center() + direction);
+            let depth = |p: glm::Vec3| (matrix * glm::vec4(p.x, p.y, p.z, 1.0)).z;
+            assert!(depth(near) < depth(far));
+        }
+    }
+}
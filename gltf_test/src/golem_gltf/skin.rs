@@ -0,0 +1,67 @@
+use std::convert::TryInto;
+
+use golem::*;
+use nalgebra_glm as glm;
+
+use super::Node;
+
+/// Size of the `joint_matrices` uniform array in the skinning shader.
+pub const MAX_JOINTS: usize = 64;
+
+pub struct Skin {
+    joints: Vec<usize>,
+    inverse_bind_matrices: Vec<glm::Mat4>,
+    joint_matrices: Vec<glm::Mat4>,
+}
+
+impl Skin {
+    /// Skins the validation report finds unusable are read anyway, to keep the indices of the
+    /// others, but nothing is drawn with them.
+    pub fn from_gltf_skin(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> Skin {
+        let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+        let inverse_bind_matrices = skin
+            .inverse_bind_matrices()
+            .and_then(|a| super::accessor::read_floats(&a, buffers))
+            .filter(|floats| floats.len() >= joints.len() * 16)
+            .map_or_else(
+                || vec![glm::identity(); joints.len()],
+                |floats| floats.chunks_exact(16).map(glm::make_mat4).collect(),
+            );
+
+        Skin {
+            joint_matrices: vec![glm::identity(); joints.len()],
+            joints,
+            inverse_bind_matrices,
+        }
+    }
+
+    pub fn joints(&self) -> &[usize] {
+        &self.joints
+    }
+
+    /// Joint global matrices times inverse bind matrices, as of the last update.
+    pub fn joint_matrices(&self) -> &[glm::Mat4] {
+        &self.joint_matrices
+    }
+
+    pub fn update_joint_matrices(&mut self, nodes: &[Node]) {
+        for ((joint_matrix, &joint), inverse_bind_matrix) in self
+            .joint_matrices
+            .iter_mut()
+            .zip(&self.joints)
+            .zip(&self.inverse_bind_matrices)
+        {
+            *joint_matrix = nodes[joint].global_matrix() * inverse_bind_matrix;
+        }
+    }
+
+    pub fn set_uniforms(&self, shader: &ShaderProgram) -> Result<(), GolemError> {
+        for (i, joint_matrix) in self.joint_matrices.iter().take(MAX_JOINTS).enumerate() {
+            shader.set_uniform(
+                &format!("joint_matrices[{}]", i),
+                UniformValue::Matrix4(glm::value_ptr(joint_matrix).try_into().unwrap()),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+//! Textures for materials, uploaded from the images gltf decodes while importing.
+
+use golem::*;
+use nalgebra_glm as glm;
+
+use gltf::image::Format;
+use gltf::json::Value;
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+/// golem refuses textures this large (it compares against the `GL_MAX_TEXTURE_SIZE` enum rather
+/// than the limit), so larger images are scaled down.
+const MAX_SIZE: u32 = 2048;
+
+/// A texture as a material uses it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureRef {
+    /// Index of the texture in the file.
+    pub texture: usize,
+    /// Which `TEXCOORD_n` to sample with, 0 or 1.
+    pub tex_coord: u32,
+    /// From `KHR_texture_transform`, applied to the texture coordinates.
+    pub offset: glm::Vec2,
+    /// Counterclockwise, in radians.
+    pub rotation: f32,
+    pub scale: glm::Vec2,
+}
+
+impl TextureRef {
+    /// `json` is the texture info in the material's JSON, where gltf drops the extensions.
+    pub fn from_info(info: &gltf::texture::Info, json: &Value) -> TextureRef {
+        let transform = &json["extensions"]["KHR_texture_transform"];
+        let vec2 = |value: &Value, default: f32| {
+            let component = |i: usize| value[i].as_f64().map_or(default, |c| c as f32);
+            glm::vec2(component(0), component(1))
+        };
+        TextureRef {
+            texture: info.texture().index(),
+            tex_coord: transform_tex_coord(json).unwrap_or_else(|| info.tex_coord()),
+            offset: vec2(&transform["offset"], 0.0),
+            rotation: transform["rotation"].as_f64().unwrap_or(0.0) as f32,
+            scale: vec2(&transform["scale"], 1.0),
+        }
+    }
+
+    /// Translation * rotation * scale, as `KHR_texture_transform` defines them.
+    #[rustfmt::skip]
+    pub fn transform(&self) -> glm::Mat3 {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (offset, scale) = (self.offset, self.scale);
+        glm::mat3(
+            cos * scale.x, sin * scale.y, offset.x,
+            -sin * scale.x, cos * scale.y, offset.y,
+            0.0, 0.0, 1.0,
+        )
+    }
+
+    pub fn has_transform(&self) -> bool {
+        self.offset != glm::vec2(0.0, 0.0)
+            || self.rotation != 0.0
+            || self.scale != glm::vec2(1.0, 1.0)
+    }
+}
+
+fn transform_tex_coord(json: &Value) -> Option<u32> {
+    let tex_coord = json["extensions"]["KHR_texture_transform"]["texCoord"].as_u64()?;
+    Some(tex_coord as u32)
+}
+
+/// Upload the image of `texture` with its sampler's filters and wrapping.
+///
+/// WebGL 1 only repeats and mipmaps textures whose sides are powers of two, and golem holds
+/// desktop GL to the same rule, so other images are scaled to the nearest power of two when
+/// their sampler needs either.
+pub fn upload(
+    ctx: &Context,
+    texture: &gltf::Texture,
+    images: &[gltf::image::Data],
+) -> Result<Texture, GolemError> {
+    let sampler = texture.sampler();
+    let image = &images[texture.source().index()];
+    let (mut width, mut height) = (image.width, image.height);
+    let mut pixels = rgba(image);
+
+    let mipmapped = match sampler.min_filter() {
+        None => true,
+        Some(filter) => !matches!(filter, MinFilter::Nearest | MinFilter::Linear),
+    };
+    let wraps = sampler.wrap_s() != WrappingMode::ClampToEdge
+        || sampler.wrap_t() != WrappingMode::ClampToEdge;
+    let power_of_two = width.is_power_of_two() && height.is_power_of_two();
+    let (target_width, target_height) = if (mipmapped || wraps) && !power_of_two {
+        (nearest_power_of_two(width), nearest_power_of_two(height))
+    } else {
+        (width, height)
+    };
+    let (target_width, target_height) = (target_width.min(MAX_SIZE), target_height.min(MAX_SIZE));
+    if (target_width, target_height) != (width, height) {
+        pixels = resize(&pixels, width, height, target_width, target_height);
+        width = target_width;
+        height = target_height;
+    }
+
+    let mut out = Texture::new(ctx)?;
+    out.set_image(Some(&pixels), width, height, ColorFormat::RGBA);
+    let power_of_two = width.is_power_of_two() && height.is_power_of_two();
+    let min_filter = match sampler.min_filter() {
+        Some(MinFilter::Nearest) => TextureFilter::Nearest,
+        Some(MinFilter::Linear) => TextureFilter::Linear,
+        _ if !power_of_two => TextureFilter::Linear,
+        Some(MinFilter::NearestMipmapNearest) => TextureFilter::NearestMipmapNearest,
+        Some(MinFilter::LinearMipmapNearest) => TextureFilter::LinearMipmapNearest,
+        Some(MinFilter::NearestMipmapLinear) => TextureFilter::NearestMipmapLinear,
+        Some(MinFilter::LinearMipmapLinear) | None => TextureFilter::LinearMipmapLinear,
+    };
+    out.set_minification(min_filter)?;
+    out.set_magnification(match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => TextureFilter::Nearest,
+        Some(MagFilter::Linear) | None => TextureFilter::Linear,
+    })?;
+    if power_of_two {
+        out.set_wrap_h(wrap(sampler.wrap_s()))?;
+        out.set_wrap_v(wrap(sampler.wrap_t()))?;
+    }
+    Ok(out)
+}
+
+/// A 1×1 white texture, bound where a material has no texture.
+pub fn white(ctx: &Context) -> Result<Texture, GolemError> {
+    let mut texture = Texture::new(ctx)?;
+    texture.set_image(Some(&[255; 4]), 1, 1, ColorFormat::RGBA);
+    Ok(texture)
+}
+
+fn wrap(mode: WrappingMode) -> TextureWrap {
+    match mode {
+        WrappingMode::ClampToEdge => TextureWrap::ClampToEdge,
+        WrappingMode::MirroredRepeat => TextureWrap::MirroredRepeat,
+        WrappingMode::Repeat => TextureWrap::Repeat,
+    }
+}
+
+fn nearest_power_of_two(size: u32) -> u32 {
+    let above = size.next_power_of_two();
+    if above - size <= size - above / 2 {
+        above
+    } else {
+        above / 2
+    }
+}
+
+/// The pixels of `image` as 8-bit RGBA, keeping the high byte of 16-bit channels.
+fn rgba(image: &gltf::image::Data) -> Vec<u8> {
+    let pixels = &image.pixels;
+    let high = |i: usize| (u16::from_ne_bytes([pixels[i], pixels[i + 1]]) >> 8) as u8;
+    let count = (image.width * image.height) as usize;
+    let mut out = Vec::with_capacity(count * 4);
+    for i in 0..count {
+        let pixel = match image.format {
+            Format::R8 => vec![pixels[i], pixels[i], pixels[i], 255],
+            Format::R8G8 => vec![
+                pixels[i * 2],
+                pixels[i * 2],
+                pixels[i * 2],
+                pixels[i * 2 + 1],
+            ],
+            Format::R8G8B8 => vec![pixels[i * 3], pixels[i * 3 + 1], pixels[i * 3 + 2], 255],
+            Format::R8G8B8A8 => pixels[i * 4..i * 4 + 4].to_vec(),
+            Format::B8G8R8 => vec![pixels[i * 3 + 2], pixels[i * 3 + 1], pixels[i * 3], 255],
+            Format::B8G8R8A8 => vec![
+                pixels[i * 4 + 2],
+                pixels[i * 4 + 1],
+                pixels[i * 4],
+                pixels[i * 4 + 3],
+            ],
+            Format::R16 => vec![high(i * 2), high(i * 2), high(i * 2), 255],
+            Format::R16G16 => vec![high(i * 4), high(i * 4), high(i * 4), high(i * 4 + 2)],
+            Format::R16G16B16 => vec![high(i * 6), high(i * 6 + 2), high(i * 6 + 4), 255],
+            Format::R16G16B16A16 => (0..4).map(|c| high(i * 8 + c * 2)).collect(),
+        };
+        out.extend(pixel);
+    }
+    out
+}
+
+/// Bilinear resampling of RGBA `pixels`, sampling at pixel centers.
+fn resize(pixels: &[u8], width: u32, height: u32, new_width: u32, new_height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let at = |x: usize, y: usize, c: usize| pixels[(y * width + x) * 4 + c] as f32;
+    let mut out = Vec::with_capacity((new_width * new_height * 4) as usize);
+    for y in 0..new_height {
+        let sy = ((y as f32 + 0.5) * height as f32 / new_height as f32 - 0.5).max(0.0);
+        let (y0, fy) = (sy as usize, sy.fract());
+        let y1 = (y0 + 1).min(height - 1);
+        for x in 0..new_width {
+            let sx = ((x as f32 + 0.5) * width as f32 / new_width as f32 - 0.5).max(0.0);
+            let (x0, fx) = (sx as usize, sx.fract());
+            let x1 = (x0 + 1).min(width - 1);
+            for c in 0..4 {
+                let top = at(x0, y0, c) * (1.0 - fx) + at(x1, y0, c) * fx;
+                let bottom = at(x0, y1, c) * (1.0 - fx) + at(x1, y1, c) * fx;
+                out.push((top * (1.0 - fy) + bottom * fy).round() as u8);
+            }
+        }
+    }
+    out
+}
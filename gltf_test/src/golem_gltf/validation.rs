@@ -0,0 +1,651 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use nalgebra_glm as glm;
+
+use super::skin::MAX_JOINTS;
+
+/// Extensions the loader understands. Anything else is reported.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "EXT_mesh_gpu_instancing",
+    "EXT_meshopt_compression",
+    "KHR_lights_punctual",
+    "KHR_materials_emissive_strength",
+    "KHR_draco_mesh_compression",
+    "KHR_materials_unlit",
+    "KHR_mesh_quantization",
+    "KHR_texture_transform",
+];
+
+/// Buffers above this size are reported, since browsers tend to refuse allocations this large.
+pub const MAX_BUFFER_LENGTH: usize = 256 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub enum Issue {
+    UnsupportedExtension {
+        name: String,
+        required: bool,
+    },
+    BufferTooShort {
+        buffer: usize,
+        byte_length: usize,
+        data_length: usize,
+    },
+    OversizedBuffer {
+        buffer: usize,
+        byte_length: usize,
+    },
+    AccessorOutOfBounds {
+        accessor: usize,
+    },
+    MissingAttribute {
+        mesh: usize,
+        primitive: usize,
+        semantic: &'static str,
+    },
+    IndexOutOfRange {
+        mesh: usize,
+        primitive: usize,
+        index: u32,
+        vertex_count: usize,
+    },
+    JointOutOfRange {
+        mesh: usize,
+        primitive: usize,
+        joint: u16,
+        joint_count: usize,
+    },
+    DegenerateTriangles {
+        mesh: usize,
+        primitive: usize,
+        count: usize,
+    },
+    NonNormalizedNormals {
+        mesh: usize,
+        primitive: usize,
+        count: usize,
+    },
+    /// The skin's meshes are drawn without it.
+    TooManyJoints {
+        skin: usize,
+        joint_count: usize,
+    },
+    TooFewInverseBindMatrices {
+        skin: usize,
+        count: usize,
+        joint_count: usize,
+    },
+    /// The node's mesh is drawn once, without `EXT_mesh_gpu_instancing`.
+    UnreadableInstances {
+        node: usize,
+    },
+    /// Materials sample white instead.
+    TextureNotUploaded {
+        texture: usize,
+        error: String,
+    },
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::UnsupportedExtension { required, .. } if *required => Severity::Error,
+            Issue::BufferTooShort { .. }
+            | Issue::AccessorOutOfBounds { .. }
+            | Issue::IndexOutOfRange { .. }
+            | Issue::JointOutOfRange { .. }
+            | Issue::TooFewInverseBindMatrices { .. } => Severity::Error,
+            Issue::MissingAttribute { semantic, .. } if *semantic == "POSITION" => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::UnsupportedExtension { name, required } => write!(
+                f,
+                "unsupported extension {}{}",
+                name,
+                if *required { " (required)" } else { "" }
+            ),
+            Issue::BufferTooShort {
+                buffer,
+                byte_length,
+                data_length,
+            } => write!(
+                f,
+                "buffer {}: declares {} bytes but only {} were loaded",
+                buffer, byte_length, data_length
+            ),
+            Issue::OversizedBuffer {
+                buffer,
+                byte_length,
+            } => write!(
+                f,
+                "buffer {}: {} bytes exceeds the {} byte limit",
+                buffer, byte_length, MAX_BUFFER_LENGTH
+            ),
+            Issue::AccessorOutOfBounds { accessor } => {
+                write!(
+                    f,
+                    "accessor {}: reads past the end of its buffer view",
+                    accessor
+                )
+            }
+            Issue::MissingAttribute {
+                mesh,
+                primitive,
+                semantic,
+            } => write!(
+                f,
+                "mesh {} primitive {}: missing {} attribute",
+                mesh, primitive, semantic
+            ),
+            Issue::IndexOutOfRange {
+                mesh,
+                primitive,
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "mesh {} primitive {}: index {} out of range for {} vertices",
+                mesh, primitive, index, vertex_count
+            ),
+            Issue::JointOutOfRange {
+                mesh,
+                primitive,
+                joint,
+                joint_count,
+            } => write!(
+                f,
+                "mesh {} primitive {}: joint {} out of range for a skin with {} joints",
+                mesh, primitive, joint, joint_count
+            ),
+            Issue::DegenerateTriangles {
+                mesh,
+                primitive,
+                count,
+            } => write!(
+                f,
+                "mesh {} primitive {}: {} degenerate triangles",
+                mesh, primitive, count
+            ),
+            Issue::NonNormalizedNormals {
+                mesh,
+                primitive,
+                count,
+            } => write!(
+                f,
+                "mesh {} primitive {}: {} normals aren't unit length",
+                mesh, primitive, count
+            ),
+            Issue::TooManyJoints { skin, joint_count } => write!(
+                f,
+                "skin {}: {} joints, more than the {} that can be drawn, drawn unskinned",
+                skin, joint_count, MAX_JOINTS
+            ),
+            Issue::TooFewInverseBindMatrices {
+                skin,
+                count,
+                joint_count,
+            } => write!(
+                f,
+                "skin {}: {} inverse bind matrices for {} joints",
+                skin, count, joint_count
+            ),
+            Issue::UnreadableInstances { node } => write!(
+                f,
+                "node {}: can't read the {} attributes",
+                node,
+                super::gpu_instancing::EXTENSION
+            ),
+            Issue::TextureNotUploaded { texture, error } => {
+                write!(f, "texture {}: can't be uploaded: {}", texture, error)
+            }
+        }
+    }
+}
+
+/// Problems found in a document before it is uploaded.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    issues: Vec<Issue>,
+    /// (mesh, primitive) pairs whose data can't be read or drawn safely.
+    broken: HashSet<(usize, usize)>,
+    /// Skins that can't be read or drawn safely.
+    broken_skins: HashSet<usize>,
+    /// Accessors that read outside their buffer view.
+    bad_accessors: HashSet<usize>,
+}
+
+impl ValidationReport {
+    pub fn validate(document: &gltf::Document, buffers: &[gltf::buffer::Data]) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        let required = unsupported_required_extensions(document);
+        for name in document.extensions_used() {
+            if !SUPPORTED_EXTENSIONS.contains(&name) && !required.iter().any(|r| r == name) {
+                report.issues.push(Issue::UnsupportedExtension {
+                    name: name.to_owned(),
+                    required: false,
+                });
+            }
+        }
+        for name in required {
+            report.issues.push(Issue::UnsupportedExtension {
+                name,
+                required: true,
+            });
+        }
+
+        for buffer in document.buffers() {
+            let data_length = buffers.get(buffer.index()).map_or(0, |d| d.0.len());
+            if data_length < buffer.length() {
+                report.issues.push(Issue::BufferTooShort {
+                    buffer: buffer.index(),
+                    byte_length: buffer.length(),
+                    data_length,
+                });
+            }
+            if buffer.length() > MAX_BUFFER_LENGTH {
+                report.issues.push(Issue::OversizedBuffer {
+                    buffer: buffer.index(),
+                    byte_length: buffer.length(),
+                });
+            }
+        }
+
+        for accessor in document.accessors() {
+            if !accessor_in_bounds(&accessor, buffers) {
+                report.bad_accessors.insert(accessor.index());
+                report.issues.push(Issue::AccessorOutOfBounds {
+                    accessor: accessor.index(),
+                });
+            }
+        }
+
+        for skin in document.skins() {
+            let joint_count = skin.joints().count();
+            if joint_count > MAX_JOINTS {
+                report.issues.push(Issue::TooManyJoints {
+                    skin: skin.index(),
+                    joint_count,
+                });
+                report.broken_skins.insert(skin.index());
+            }
+            if let Some(matrices) = skin.inverse_bind_matrices() {
+                if report.bad_accessors.contains(&matrices.index()) {
+                    report.broken_skins.insert(skin.index());
+                } else if matrices.count() < joint_count {
+                    report.issues.push(Issue::TooFewInverseBindMatrices {
+                        skin: skin.index(),
+                        count: matrices.count(),
+                        joint_count,
+                    });
+                    report.broken_skins.insert(skin.index());
+                }
+            }
+        }
+
+        // Skins used with each mesh, to check joint indices against.
+        let mut mesh_skins: Vec<Vec<usize>> = vec![Vec::new(); document.meshes().len()];
+        for node in document.nodes() {
+            if let (Some(mesh), Some(skin)) = (node.mesh(), node.skin()) {
+                if !report.broken_skins.contains(&skin.index()) {
+                    mesh_skins[mesh.index()].push(skin.joints().count());
+                }
+            }
+        }
+
+        for mesh in document.meshes() {
+            for primitive in mesh.primitives() {
+                report.validate_primitive(&mesh, &primitive, buffers, &mesh_skins[mesh.index()]);
+            }
+        }
+        report
+    }
+
+    fn validate_primitive(
+        &mut self,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+        joint_counts: &[usize],
+    ) {
+        use gltf::Semantic;
+
+        let key = (mesh.index(), primitive.index());
+        let (mesh, primitive_index) = key;
+
+        let accessors = primitive
+            .attributes()
+            .map(|(_, a)| a)
+            .chain(primitive.indices())
+            .chain(primitive.morph_targets().flat_map(|t| {
+                t.positions()
+                    .into_iter()
+                    .chain(t.normals())
+                    .chain(t.tangents())
+            }));
+        if accessors
+            .into_iter()
+            .any(|a| self.bad_accessors.contains(&a.index()))
+        {
+            self.broken.insert(key);
+            return;
+        }
+
+        let missing = |semantic: &'static str| Issue::MissingAttribute {
+            mesh,
+            primitive: primitive_index,
+            semantic,
+        };
+        if primitive.get(&Semantic::Positions).is_none() {
+            self.issues.push(missing("POSITION"));
+            self.broken.insert(key);
+            return;
+        }
+        if primitive.get(&Semantic::Normals).is_none() {
+            self.issues.push(missing("NORMAL"));
+        }
+        let skinned = !joint_counts.is_empty();
+        let has_joints = primitive.get(&Semantic::Joints(0)).is_some();
+        let has_weights = primitive.get(&Semantic::Weights(0)).is_some();
+        if skinned && !has_joints {
+            self.issues.push(missing("JOINTS_0"));
+        }
+        if skinned && !has_weights {
+            self.issues.push(missing("WEIGHTS_0"));
+        }
+
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|d| &d.0[..]));
+        let read_vec3 = |semantic| {
+            primitive
+                .get(&semantic)
+                .and_then(|a| super::accessor::read_vec3(&a, buffers))
+        };
+        let positions = match read_vec3(gltf::Semantic::Positions) {
+            Some(positions) => positions,
+            None => return,
+        };
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if let Some(&index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+            self.issues.push(Issue::IndexOutOfRange {
+                mesh,
+                primitive: primitive_index,
+                index,
+                vertex_count: positions.len(),
+            });
+            self.broken.insert(key);
+            return;
+        }
+
+        if let (Some(&joint_count), Some(joints)) =
+            (joint_counts.iter().min(), reader.read_joints(0))
+        {
+            let out_of_range = joints
+                .into_u16()
+                .flat_map(|j| j.to_vec())
+                .find(|&j| j as usize >= joint_count);
+            if let Some(joint) = out_of_range {
+                self.issues.push(Issue::JointOutOfRange {
+                    mesh,
+                    primitive: primitive_index,
+                    joint,
+                    joint_count,
+                });
+                self.broken.insert(key);
+            }
+        }
+
+        if primitive.mode() == gltf::mesh::Mode::Triangles {
+            let count = indices
+                .chunks_exact(3)
+                .filter(|t| is_degenerate(&positions, t[0], t[1], t[2]))
+                .count();
+            if count > 0 {
+                self.issues.push(Issue::DegenerateTriangles {
+                    mesh,
+                    primitive: primitive_index,
+                    count,
+                });
+            }
+        }
+
+        if let Some(normals) = read_vec3(gltf::Semantic::Normals) {
+            let count = normals
+                .iter()
+                .filter(|n| (glm::length(&glm::make_vec3(&n[..])) - 1.0).abs() > 1e-3)
+                .count();
+            if count > 0 {
+                self.issues.push(Issue::NonNormalizedNormals {
+                    mesh,
+                    primitive: primitive_index,
+                    count,
+                });
+            }
+        }
+    }
+
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+
+    /// Add a problem found while uploading the document.
+    pub fn push(&mut self, issue: Issue) {
+        self.issues.push(issue);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity() == severity)
+            .count()
+    }
+
+    /// Whether the primitive's data is safe to upload. Primitives that aren't are skipped by the
+    /// loader.
+    pub fn is_renderable(&self, mesh: usize, primitive: usize) -> bool {
+        !self.broken.contains(&(mesh, primitive))
+    }
+
+    /// Whether the accessor's data lies inside its buffer view.
+    pub fn is_accessor_readable(&self, accessor: usize) -> bool {
+        !self.bad_accessors.contains(&accessor)
+    }
+
+    /// Whether the skin can be drawn with. Meshes using one that can't are drawn unskinned.
+    pub fn is_skin_usable(&self, skin: usize) -> bool {
+        !self.broken_skins.contains(&skin)
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} errors, {} warnings",
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        )?;
+        for issue in &self.issues {
+            let severity = match issue.severity() {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            write!(f, "\n  {}: {}", severity, issue)?;
+        }
+        Ok(())
+    }
+}
+
+fn accessor_in_bounds(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> bool {
+    let view = match accessor.view() {
+        Some(view) => view,
+        // Sparse-only accessors start out zeroed.
+        None => return true,
+    };
+    let buffer_length = buffers.get(view.buffer().index()).map_or(0, |d| d.0.len());
+    if view.offset() + view.length() > buffer_length {
+        return false;
+    }
+    if accessor.count() == 0 {
+        return true;
+    }
+    let stride = view.stride().unwrap_or_else(|| accessor.size());
+    accessor.offset() + stride * (accessor.count() - 1) + accessor.size() <= view.length()
+}
+
+fn is_degenerate(positions: &[[f32; 3]], a: u32, b: u32, c: u32) -> bool {
+    if a == b || b == c || a == c {
+        return true;
+    }
+    let p = |i: u32| glm::make_vec3(&positions[i as usize]);
+    let (e1, e2) = (p(b) - p(a), p(c) - p(a));
+    glm::length(&e1.cross(&e2)) <= 1e-6 * glm::length(&e1) * glm::length(&e2)
+}
+
+/// Extensions the file can't be drawn correctly without that the loader doesn't understand,
+/// including ones missing from `extensionsUsed`.
+pub fn unsupported_required_extensions(document: &gltf::Document) -> Vec<String> {
+    let mut names: Vec<String> = document
+        .extensions_required()
+        .filter(|name| !SUPPORTED_EXTENSIONS.contains(name))
+        .map(str::to_owned)
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A triangle with `positions` and `indices` packed in one buffer, plus whatever `extra`
+    /// JSON is merged into the primitive.
+    fn document(
+        positions: &[[f32; 3]],
+        indices: &[u16],
+        extra: &str,
+    ) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        let mut data: Vec<u8> = positions
+            .iter()
+            .flatten()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        let index_offset = data.len();
+        data.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "buffers": [{{"byteLength": {length}}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteLength": {index_offset}}},
+                    {{"buffer": 0, "byteOffset": {index_offset}, "byteLength": {index_length}}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": {count}, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 1]}},
+                    {{"bufferView": 1, "componentType": 5123, "count": {indices}, "type": "SCALAR"}}
+                ],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1{extra}}}]}}]
+            }}"#,
+            length = data.len(),
+            index_offset = index_offset,
+            index_length = indices.len() * 2,
+            count = positions.len(),
+            indices = indices.len(),
+            extra = extra,
+        );
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        (document, vec![gltf::buffer::Data(data)])
+    }
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    #[test]
+    fn a_valid_triangle_only_lacks_normals() {
+        let (document, buffers) = document(&TRIANGLE, &[0, 1, 2], "");
+        let report = ValidationReport::validate(&document, &buffers);
+        assert_eq!(report.count(Severity::Error), 0, "{}", report);
+        assert!(matches!(
+            report.issues(),
+            [Issue::MissingAttribute {
+                semantic: "NORMAL",
+                ..
+            }]
+        ));
+        assert!(report.is_renderable(0, 0));
+    }
+
+    #[test]
+    fn indices_past_the_vertices_break_the_primitive() {
+        let (document, buffers) = document(&TRIANGLE, &[0, 1, 3], "");
+        let report = ValidationReport::validate(&document, &buffers);
+        assert!(report
+            .issues()
+            .iter()
+            .any(|i| matches!(i, Issue::IndexOutOfRange { index: 3, .. })));
+        assert!(!report.is_renderable(0, 0));
+    }
+
+    #[test]
+    fn short_buffers_break_the_accessors_reading_them() {
+        let (document, mut buffers) = document(&TRIANGLE, &[0, 1, 2], "");
+        buffers[0].0.truncate(30);
+        let report = ValidationReport::validate(&document, &buffers);
+        assert!(report.issues().iter().any(|i| matches!(
+            i,
+            Issue::BufferTooShort {
+                data_length: 30,
+                ..
+            }
+        )));
+        assert!(!report.is_accessor_readable(0));
+        assert!(!report.is_renderable(0, 0));
+    }
+
+    #[test]
+    fn degenerate_triangles_are_warnings() {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]];
+        let (document, buffers) = document(&positions, &[0, 1, 2, 0, 0, 1], "");
+        let report = ValidationReport::validate(&document, &buffers);
+        assert!(report
+            .issues()
+            .iter()
+            .any(|i| matches!(i, Issue::DegenerateTriangles { count: 2, .. })));
+        assert_eq!(report.count(Severity::Error), 0);
+        assert!(report.is_renderable(0, 0));
+    }
+
+    #[test]
+    fn unknown_extensions_are_reported() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "extensionsUsed": ["KHR_texture_transform", "EXT_made_up", "EXT_also_made_up"],
+            "extensionsRequired": ["EXT_made_up"]
+        }"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        assert_eq!(unsupported_required_extensions(&document), ["EXT_made_up"]);
+        let report = ValidationReport::validate(&document, &[]);
+        assert_eq!(report.count(Severity::Error), 1);
+        assert_eq!(report.count(Severity::Warning), 1);
+    }
+}
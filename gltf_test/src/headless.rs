@@ -0,0 +1,667 @@
+//! Offscreen rendering without a window, for thumbnails and regression images.
+//!
+//! An OpenGL 3.2 core context is created through EGL, loaded at runtime so nothing has to be
+//! linked at build time. On a machine without a GPU, Mesa's llvmpipe works; the surfaceless
+//! platform is used when available so no display server is needed either.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use camera_controller::CameraController;
+use glow::HasContext;
+use golem::*;
+use khronos_egl as egl;
+use nalgebra_glm as glm;
+use post_process::PostProcess;
+
+use crate::framing::Framing;
+use crate::golem_gltf::{
+    export_file, DebugView, Environment, Gltf, HdrImage, Instancing, Optimization, ShadowMap,
+    ToneMapping, ToneOperator,
+};
+use crate::time::Instant;
+
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    Egl(String),
+    Framebuffer(String),
+    Golem(GolemError),
+    Gltf(gltf::Error),
+    Png(String),
+    Io(std::io::Error),
+    Usage(String),
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeadlessError::Egl(e) => write!(f, "EGL: {}", e),
+            HeadlessError::Framebuffer(e) => write!(f, "framebuffer: {}", e),
+            HeadlessError::Golem(e) => write!(f, "golem: {}", e),
+            HeadlessError::Gltf(e) => write!(f, "glTF: {}", e),
+            HeadlessError::Png(e) => write!(f, "PNG: {}", e),
+            HeadlessError::Io(e) => write!(f, "{}", e),
+            HeadlessError::Usage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for HeadlessError {}
+
+impl From<egl::Error> for HeadlessError {
+    fn from(e: egl::Error) -> HeadlessError {
+        HeadlessError::Egl(e.to_string())
+    }
+}
+
+impl From<GolemError> for HeadlessError {
+    fn from(e: GolemError) -> HeadlessError {
+        HeadlessError::Golem(e)
+    }
+}
+
+impl From<gltf::Error> for HeadlessError {
+    fn from(e: gltf::Error) -> HeadlessError {
+        HeadlessError::Gltf(e)
+    }
+}
+
+impl From<std::io::Error> for HeadlessError {
+    fn from(e: std::io::Error) -> HeadlessError {
+        HeadlessError::Io(e)
+    }
+}
+
+/// Where the image is rendered from.
+#[derive(Clone, Copy, Debug)]
+pub enum View {
+    /// The viewer's default orbit view, turned by `yaw` and `pitch` (radians). `zoom` works like
+    /// the scroll wheel: positive values move away from the model.
+    Framed { yaw: f32, pitch: f32, zoom: f32 },
+    /// A camera defined in the file, by index.
+    Camera(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    pub background: [f32; 4],
+    pub view: View,
+    /// The scene to draw, by index, instead of the file's default.
+    pub scene: Option<usize>,
+    /// Time in seconds into the first animation, if the model should be posed.
+    pub time: Option<f32>,
+    /// An equirectangular `.hdr` image lighting the model and drawn behind it in place of
+    /// `background`.
+    pub environment: Option<PathBuf>,
+    pub tone_mapping: ToneMapping,
+    /// Draw through a post-process chain with these bloom and FXAA settings, which applies
+    /// `tone_mapping` at its end.
+    pub post: Option<post_process::Settings>,
+    /// Cast shadows from the first directional light.
+    pub shadows: bool,
+    /// Draw a floor under the model.
+    pub ground: bool,
+    pub debug: DebugView,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            width: 512,
+            height: 512,
+            background: [0.1, 0.2, 0.3, 1.0],
+            view: View::Framed {
+                yaw: 0.0,
+                pitch: 0.0,
+                zoom: 0.0,
+            },
+            scene: None,
+            time: None,
+            environment: None,
+            tone_mapping: ToneMapping::default(),
+            post: None,
+            shadows: false,
+            ground: false,
+            debug: DebugView::default(),
+        }
+    }
+}
+
+/// RGBA8 pixels, top row first.
+#[derive(Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), HeadlessError> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|e| HeadlessError::Png(e.to_string()))
+    }
+
+    pub fn load_png(path: impl AsRef<Path>) -> Result<Image, HeadlessError> {
+        let decoder = png::Decoder::new(std::fs::File::open(path)?);
+        let (info, mut reader) = decoder
+            .read_info()
+            .map_err(|e| HeadlessError::Png(e.to_string()))?;
+        if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+            return Err(HeadlessError::Png(format!(
+                "expected 8-bit RGBA, found {:?} {:?}",
+                info.bit_depth, info.color_type
+            )));
+        }
+        let mut pixels = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut pixels)
+            .map_err(|e| HeadlessError::Png(e.to_string()))?;
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+/// An offscreen GL context. It is current on the thread that created it.
+pub struct Headless {
+    egl: egl::DynamicInstance<egl::EGL1_4>,
+    display: egl::Display,
+    surface: egl::Surface,
+    context: egl::Context,
+    // golem keeps its glow context to itself, so framebuffers and readback go through a second
+    // one loaded from the same GL context.
+    gl: glow::Context,
+    ctx: Context,
+}
+
+impl Headless {
+    pub fn new() -> Result<Headless, HeadlessError> {
+        let egl = unsafe { egl::DynamicInstance::<egl::EGL1_4>::load_required() }
+            .map_err(|e| HeadlessError::Egl(e.to_string()))?;
+        let display = Self::display(&egl)?;
+        egl.initialize(display)?;
+        egl.bind_api(egl::OPENGL_API)?;
+
+        let config_attributes = [
+            egl::SURFACE_TYPE,
+            egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE,
+            egl::OPENGL_BIT,
+            egl::NONE,
+        ];
+        let config = egl
+            .choose_first_config(display, &config_attributes)?
+            .ok_or_else(|| HeadlessError::Egl("no config supports desktop OpenGL".to_owned()))?;
+        // Same version as blinds asks for on desktop, which golem's shaders are written against.
+        let context_attributes = [
+            egl::CONTEXT_MAJOR_VERSION,
+            3,
+            egl::CONTEXT_MINOR_VERSION,
+            2,
+            egl::CONTEXT_OPENGL_PROFILE_MASK,
+            egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            egl::NONE,
+        ];
+        let context = egl.create_context(display, config, None, &context_attributes)?;
+        // Rendering goes to a framebuffer object; the surface only exists to make the context
+        // current where surfaceless contexts aren't supported.
+        let surface = egl.create_pbuffer_surface(
+            display,
+            config,
+            &[egl::WIDTH, 1, egl::HEIGHT, 1, egl::NONE],
+        )?;
+        egl.make_current(display, Some(surface), Some(surface), Some(context))?;
+
+        let load = |s: &str| {
+            egl.get_proc_address(s)
+                .map_or(std::ptr::null(), |f| f as *const _)
+        };
+        let gl = glow::Context::from_loader_function(load);
+        let ctx = Context::from_glow(glow::Context::from_loader_function(load))?;
+
+        Ok(Headless {
+            egl,
+            display,
+            surface,
+            context,
+            gl,
+            ctx,
+        })
+    }
+
+    fn display(egl: &egl::DynamicInstance<egl::EGL1_4>) -> Result<egl::Display, HeadlessError> {
+        let surfaceless = egl
+            .query_string(None, egl::EXTENSIONS)
+            .map(|e| {
+                e.to_string_lossy()
+                    .split(' ')
+                    .any(|e| e == "EGL_MESA_platform_surfaceless")
+            })
+            .unwrap_or(false);
+        if let (true, Some(egl15)) = (surfaceless, egl.upcast::<egl::EGL1_5>()) {
+            if let Ok(display) = egl15.get_platform_display(
+                PLATFORM_SURFACELESS_MESA,
+                std::ptr::null_mut(),
+                &[egl::ATTRIB_NONE],
+            ) {
+                return Ok(display);
+            }
+        }
+        egl.get_display(egl::DEFAULT_DISPLAY)
+            .ok_or_else(|| HeadlessError::Egl("no display".to_owned()))
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.ctx
+    }
+
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<Gltf, HeadlessError> {
+        Ok(self.with_instancing(Gltf::load(path, &self.ctx)?))
+    }
+
+    pub fn load_optimized(
+        &self,
+        path: impl AsRef<Path>,
+        optimization: &Optimization,
+    ) -> Result<Gltf, HeadlessError> {
+        Ok(self.with_instancing(Gltf::load_optimized(path, optimization, &self.ctx)?))
+    }
+
+    fn with_instancing(&self, mut model: Gltf) -> Gltf {
+        model.set_instancing(Instancing::new(self.glow_context()));
+        model
+    }
+
+    /// A second context for what golem doesn't expose.
+    fn glow_context(&self) -> glow::Context {
+        glow::Context::from_loader_function(|s| {
+            self.egl
+                .get_proc_address(s)
+                .map_or(std::ptr::null(), |f| f as *const _)
+        })
+    }
+
+    pub fn render(
+        &self,
+        model: &mut Gltf,
+        options: &RenderOptions,
+    ) -> Result<Image, HeadlessError> {
+        let (width, height) = (options.width, options.height);
+        if width == 0 || height == 0 {
+            return Err(HeadlessError::Usage(
+                "image size must be non-zero".to_owned(),
+            ));
+        }
+
+        if let Some(scene) = options.scene {
+            if scene >= model.scenes().len() {
+                return Err(HeadlessError::Usage(format!(
+                    "scene {} out of range, the model has {}",
+                    scene,
+                    model.scenes().len()
+                )));
+            }
+        }
+        model.set_scene(options.scene.or_else(|| model.default_scene()));
+        if let Some(time) = options.time {
+            if !model.animations().is_empty() {
+                let player = model.player_mut();
+                player.play(0);
+                player.pause();
+                player.seek(time);
+            }
+        }
+        model.update_animation(Instant::now());
+        model.update_transforms();
+        let environment = match &options.environment {
+            Some(path) => Some(std::rc::Rc::new(Environment::new(
+                &self.ctx,
+                &HdrImage::load(path)?,
+            )?)),
+            None => None,
+        };
+        model.set_environment(environment.clone());
+        model.set_tone_mapping(match options.post {
+            Some(_) => options.tone_mapping.hdr_target(),
+            None => options.tone_mapping,
+        });
+        model.set_ground(options.ground);
+        model.set_debug_view(options.debug);
+        if !options.shadows {
+            model.set_shadow_map(None);
+        } else if model.shadow_map().is_none() {
+            let map = ShadowMap::new(self.glow_context()).ok_or_else(|| {
+                HeadlessError::Framebuffer("can't create the shadow map".to_owned())
+            })?;
+            model.set_shadow_map(Some(map));
+        }
+        // Before binding the image's framebuffer, since this leaves the screen bound.
+        model.draw_shadows(&self.ctx)?;
+
+        let aspect = width as f32 / height as f32;
+        let mvp_matrix = match options.view {
+            View::Framed { yaw, pitch, zoom } => {
+                // As the viewer's camera would be placed: turning the model by `yaw` is orbiting
+                // the camera the other way.
+                let framing = Framing::new(model.bounding_box());
+                let mut controller = CameraController::new(framing.center, 1.0);
+                framing.reset(&mut controller, zoom);
+                controller.set_orientation(-yaw, pitch);
+                framing.p_matrix(aspect, controller.distance()) * controller.view_matrix()
+            }
+            View::Camera(i) => {
+                let camera = model.cameras().get(i).ok_or_else(|| {
+                    HeadlessError::Usage(format!(
+                        "camera {} out of range, the model has {}",
+                        i,
+                        model.cameras().len()
+                    ))
+                })?;
+                camera.projection_matrix(aspect) * camera.view_matrix(model.nodes())
+            }
+        };
+
+        let gl = &self.gl;
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        unsafe {
+            let framebuffer = gl
+                .create_framebuffer()
+                .map_err(HeadlessError::Framebuffer)?;
+            let color = gl
+                .create_renderbuffer()
+                .map_err(HeadlessError::Framebuffer)?;
+            let depth = gl
+                .create_renderbuffer()
+                .map_err(HeadlessError::Framebuffer)?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            for &(renderbuffer, format, attachment) in &[
+                (color, glow::RGBA8, glow::COLOR_ATTACHMENT0),
+                (depth, glow::DEPTH_COMPONENT24, glow::DEPTH_ATTACHMENT),
+            ] {
+                gl.bind_renderbuffer(glow::RENDERBUFFER, Some(renderbuffer));
+                gl.renderbuffer_storage(glow::RENDERBUFFER, format, width as i32, height as i32);
+                gl.framebuffer_renderbuffer(
+                    glow::FRAMEBUFFER,
+                    attachment,
+                    glow::RENDERBUFFER,
+                    Some(renderbuffer),
+                );
+            }
+
+            let result =
+                if gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE {
+                    self.draw(
+                        model,
+                        environment.as_deref(),
+                        options,
+                        &mvp_matrix,
+                        framebuffer,
+                    )
+                    .map(|()| {
+                        gl.read_pixels(
+                            0,
+                            0,
+                            width as i32,
+                            height as i32,
+                            glow::RGBA,
+                            glow::UNSIGNED_BYTE,
+                            &mut pixels,
+                        );
+                    })
+                } else {
+                    Err(HeadlessError::Framebuffer("incomplete".to_owned()))
+                };
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            gl.delete_renderbuffer(color);
+            gl.delete_renderbuffer(depth);
+            gl.delete_framebuffer(framebuffer);
+            result?;
+        }
+
+        // GL reads bottom row first.
+        let row = (width * 4) as usize;
+        let pixels = pixels.chunks(row).rev().flatten().copied().collect();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn draw(
+        &self,
+        model: &mut Gltf,
+        environment: Option<&Environment>,
+        options: &RenderOptions,
+        mvp_matrix: &glm::Mat4,
+        framebuffer: <glow::Context as HasContext>::Framebuffer,
+    ) -> Result<(), HeadlessError> {
+        let ctx = &self.ctx;
+        let [r, g, b, a] = options.background;
+        let mut post = match options.post {
+            Some(settings) => Some(PostProcess::new(
+                ctx,
+                self.glow_context(),
+                options.tone_mapping.post_settings(settings),
+            )?),
+            None => None,
+        };
+        let tone_mapping = match &mut post {
+            Some(post) => {
+                post.begin(ctx, options.width, options.height)?;
+                // The background goes through the tone mapping like the rest of the scene.
+                let gamma = options.tone_mapping.gamma;
+                let [r, g, b] = post_process::encode([r.powf(gamma), g.powf(gamma), b.powf(gamma)]);
+                ctx.set_clear_color(r, g, b, 1.0);
+                options.tone_mapping.hdr_target()
+            }
+            None => {
+                ctx.set_viewport(0, 0, options.width, options.height);
+                ctx.set_clear_color(r, g, b, a);
+                options.tone_mapping
+            }
+        };
+        ctx.clear();
+        if let Some(environment) = environment {
+            environment.draw_sky(mvp_matrix, &tone_mapping)?;
+        }
+        ctx.set_depth_test_mode(Some(depth::DepthTestMode::default()));
+        let result = model.draw(ctx, mvp_matrix);
+        ctx.set_depth_test_mode(None);
+        result?;
+        if let Some(post) = &mut post {
+            post.resolve(ctx)?;
+            // Resolving leaves golem's idea of the screen bound, rather than the image's.
+            unsafe {
+                self.gl
+                    .bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer))
+            };
+            post.draw_output(ctx)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Headless {
+    fn drop(&mut self) {
+        // The display is shared with any other instance in the process, so it isn't terminated.
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_surface(self.display, self.surface);
+        let _ = self.egl.destroy_context(self.display, self.context);
+    }
+}
+
+/// Load a model and write a single image of it.
+pub fn render_to_png(
+    model_path: impl AsRef<Path>,
+    output_path: impl AsRef<Path>,
+    options: &RenderOptions,
+) -> Result<(), HeadlessError> {
+    let headless = Headless::new()?;
+    let mut model = headless.load(model_path)?;
+    headless.render(&mut model, options)?.save_png(output_path)
+}
+
+/// Read a model, optimizing it if asked to, and write it back out, as `.gltf` and `.bin` if
+/// `output_path` ends in `.gltf` and as `.glb` otherwise. This needs no GL context.
+pub fn export(
+    model_path: impl AsRef<Path>,
+    output_path: impl AsRef<Path>,
+    optimization: Option<&Optimization>,
+) -> Result<(), HeadlessError> {
+    export_file(model_path, output_path, optimization)?;
+    Ok(())
+}
+
+pub const USAGE: &str = "usage: gltf_test render <model> <output.png> [options]
+    --size <width>x<height>     image size (default 512x512)
+    --background <r,g,b[,a]>    clear color, components in 0..1
+    --yaw <degrees>             turn the framed view around the vertical axis
+    --pitch <degrees>           tilt the framed view
+    --zoom <steps>              scroll steps away from the framed view
+    --camera <index>            render through a camera from the file instead
+    --scene <index>             draw this scene instead of the file's default
+    --time <seconds>            pose the model at this time into the first animation
+    --environment <sky.hdr>     light the model with an equirectangular HDR image, drawn behind it
+    --exposure <stops>          brighten or darken before tone mapping
+    --tone-mapping <operator>   clamp (default), reinhard or aces; the latter two encode with
+                                gamma 2.2
+    --bloom <strength>          draw through the post-process chain, blooming highlights
+    --fxaa                      draw through the post-process chain, anti-aliasing with FXAA
+    --shadows                   cast shadows from the first directional light
+    --ground                    draw a floor under the model
+    --debug <views>             comma-separated debug views: wireframe, normals, uv, bounds,
+                                skeleton, axes";
+
+/// Parse the arguments following `render` on the command line.
+pub fn parse_args(args: &[String]) -> Result<(String, String, RenderOptions), HeadlessError> {
+    /// The post-process chain before any of its options turn effects on.
+    fn no_post_effects() -> post_process::Settings {
+        post_process::Settings {
+            fxaa: false,
+            bloom: 0.0,
+            ..post_process::Settings::default()
+        }
+    }
+
+    fn number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, HeadlessError> {
+        value
+            .parse()
+            .map_err(|_| HeadlessError::Usage(format!("invalid value for {}: {}", option, value)))
+    }
+
+    let mut paths = Vec::new();
+    let mut options = RenderOptions::default();
+    let (mut yaw, mut pitch, mut zoom) = (0.0f32, 0.0f32, 0.0f32);
+    let mut camera = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            paths.push(arg.clone());
+            continue;
+        }
+        match arg.as_str() {
+            "--shadows" => {
+                options.shadows = true;
+                continue;
+            }
+            "--ground" => {
+                options.ground = true;
+                continue;
+            }
+            "--fxaa" => {
+                options.post.get_or_insert_with(no_post_effects).fxaa = true;
+                continue;
+            }
+            _ => {}
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| HeadlessError::Usage(format!("missing value for {}", arg)))?;
+        match arg.as_str() {
+            "--size" => {
+                let mut size = value.splitn(2, 'x');
+                options.width = number(arg, size.next().unwrap_or(""))?;
+                options.height = number(arg, size.next().unwrap_or(""))?;
+            }
+            "--background" => {
+                let components = value
+                    .split(',')
+                    .map(|c| number(arg, c))
+                    .collect::<Result<Vec<f32>, _>>()?;
+                options.background = match components[..] {
+                    [r, g, b] => [r, g, b, 1.0],
+                    [r, g, b, a] => [r, g, b, a],
+                    _ => return Err(HeadlessError::Usage(format!("invalid color: {}", value))),
+                };
+            }
+            "--yaw" => yaw = number::<f32>(arg, value)?.to_radians(),
+            "--pitch" => pitch = number::<f32>(arg, value)?.to_radians(),
+            "--zoom" => zoom = number(arg, value)?,
+            "--camera" => camera = Some(number(arg, value)?),
+            "--scene" => options.scene = Some(number(arg, value)?),
+            "--time" => options.time = Some(number(arg, value)?),
+            "--debug" => {
+                options.debug = DebugView::parse(value).ok_or_else(|| {
+                    HeadlessError::Usage(format!("unknown debug view in {}", value))
+                })?;
+            }
+            "--bloom" => {
+                options.post.get_or_insert_with(no_post_effects).bloom = number(arg, value)?;
+            }
+            "--environment" => options.environment = Some(PathBuf::from(value)),
+            "--exposure" => {
+                options.tone_mapping.exposure = 2f32.powf(number::<f32>(arg, value)?);
+            }
+            "--tone-mapping" => {
+                let (operator, gamma) = match value.as_str() {
+                    "clamp" => (ToneOperator::Clamp, 1.0),
+                    "reinhard" => (ToneOperator::Reinhard, 2.2),
+                    "aces" => (ToneOperator::Aces, 2.2),
+                    _ => {
+                        return Err(HeadlessError::Usage(format!(
+                            "unknown tone mapping {}",
+                            value
+                        )))
+                    }
+                };
+                options.tone_mapping.operator = operator;
+                options.tone_mapping.gamma = gamma;
+            }
+            _ => return Err(HeadlessError::Usage(format!("unknown option {}", arg))),
+        }
+    }
+
+    options.view = match camera {
+        Some(i) => View::Camera(i),
+        None => View::Framed { yaw, pitch, zoom },
+    };
+    match &paths[..] {
+        [model, output] => Ok((model.clone(), output.clone(), options)),
+        _ => Err(HeadlessError::Usage(USAGE.to_owned())),
+    }
+}
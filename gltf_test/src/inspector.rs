@@ -0,0 +1,329 @@
+use nalgebra_glm as glm;
+
+use crate::golem_gltf::{Gltf, LightKind, Material};
+use crate::time::Duration;
+use crate::ui::{self, Rect, Ui};
+
+const TABS: [&str; 5] = ["NODES", "MESHES", "MATERIALS", "TEXTURES", "ANIMS"];
+const WIDTH: f32 = 330.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tab {
+    Nodes,
+    Meshes,
+    Materials,
+    Textures,
+    Animations,
+}
+
+impl Tab {
+    fn from_index(index: usize) -> Tab {
+        match index {
+            0 => Tab::Nodes,
+            1 => Tab::Meshes,
+            2 => Tab::Materials,
+            3 => Tab::Textures,
+            _ => Tab::Animations,
+        }
+    }
+}
+
+/// The structure of the model on screen, listed in a panel on the left with the details of the
+/// selected item in another below it. A selected node is outlined in the viewport, and a
+/// selected animation plays.
+pub struct Inspector {
+    pub visible: bool,
+    tab: usize,
+    /// First row of the list shown.
+    scroll: usize,
+    selected: Option<(Tab, usize)>,
+}
+
+impl Inspector {
+    pub fn new() -> Inspector {
+        Inspector {
+            visible: false,
+            tab: 0,
+            scroll: 0,
+            selected: None,
+        }
+    }
+
+    /// Forget the selection, for a new model.
+    pub fn reset(&mut self) {
+        self.scroll = 0;
+        self.selected = None;
+    }
+
+    pub fn selected_node(&self) -> Option<usize> {
+        match self.selected {
+            Some((Tab::Nodes, node)) if self.visible => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Select `node` in the node tree, as when picked in the viewport.
+    pub fn select_node(&mut self, model: &Gltf, node: usize) {
+        self.tab = 0;
+        self.selected = Some((Tab::Nodes, node));
+        if let Some(row) = node_rows(model).iter().position(|&(n, _)| n == node) {
+            self.scroll = row.saturating_sub(3);
+        }
+    }
+
+    pub fn draw(&mut self, ui: &mut Ui, model: &mut Gltf) {
+        model.set_highlight(self.selected_node());
+        if !self.visible {
+            return;
+        }
+        let height = ui.size().y;
+        let list_height = (height * 0.55).floor();
+        ui.panel(Rect::new(0.0, 0.0, WIDTH, list_height));
+        let tab = self.tab;
+        ui.tabs(&TABS, &mut self.tab);
+        if self.tab != tab {
+            self.scroll = 0;
+        }
+        if let (Tab::Nodes, Some(scene)) = (Tab::from_index(self.tab), model.scene()) {
+            let name = model.scenes().get(scene).and_then(|s| s.name.as_deref());
+            ui.label(&title("SCENE", scene, name), ui::DIM);
+        }
+        ui.space();
+        let rows = self.rows(model);
+        let shown = ui.rows_left();
+        let scrolled = self.scroll as f32 - ui.scroll().round();
+        self.scroll = (scrolled.max(0.0) as usize).min(rows.len().saturating_sub(shown));
+        let tab = Tab::from_index(self.tab);
+        for (index, label) in rows.iter().skip(self.scroll).take(shown) {
+            let selected = self.selected == Some((tab, *index));
+            if ui.button(label, ui::TEXT, selected) {
+                self.selected = if selected { None } else { Some((tab, *index)) };
+                if let (Tab::Animations, false) = (tab, selected) {
+                    model
+                        .player_mut()
+                        .cross_fade(*index, Duration::from_millis(300));
+                }
+            }
+        }
+        if rows.is_empty() {
+            ui.label("NONE", ui::DIM);
+        }
+
+        ui.panel(Rect::new(0.0, list_height, WIDTH, height - list_height));
+        match self.selected {
+            Some((Tab::Nodes, i)) if i < model.nodes().len() => node_details(ui, model, i),
+            Some((Tab::Meshes, i)) if i < model.meshes().len() => mesh_details(ui, model, i),
+            Some((Tab::Materials, i)) if i < model.materials().len() => {
+                material_details(ui, model, i)
+            }
+            Some((Tab::Textures, i)) if i < model.textures().len() => texture_details(ui, model, i),
+            Some((Tab::Animations, i)) if i < model.animations().len() => {
+                let animation = &model.animations()[i];
+                ui.label(&title("ANIMATION", i, animation.name()), ui::HEADING);
+                ui.label(&format!("DURATION {:.2}S", animation.duration()), ui::TEXT);
+                if model.player().current_clip() == Some(i) {
+                    if let Some(time) = model.player().current_time() {
+                        ui.label(&format!("PLAYING AT {:.2}S", time), ui::TEXT);
+                    }
+                }
+            }
+            _ => ui.label("SELECT AN ITEM, OR CLICK THE MODEL", ui::DIM),
+        }
+    }
+
+    /// The items of the current tab, with their labels.
+    fn rows(&self, model: &Gltf) -> Vec<(usize, String)> {
+        match Tab::from_index(self.tab) {
+            Tab::Nodes => node_rows(model)
+                .into_iter()
+                .map(|(i, depth)| {
+                    let node = &model.nodes()[i];
+                    let mut label = format!("{}{}", "  ".repeat(depth), name(i, &node.name));
+                    if node.mesh().is_some() {
+                        label.push_str(" *");
+                    }
+                    (i, label)
+                })
+                .collect(),
+            Tab::Meshes => enumerate(model.meshes().iter().map(|m| &m.name)),
+            Tab::Materials => enumerate(model.materials().iter().map(|m| &m.name)),
+            Tab::Textures => enumerate(model.textures().iter().map(|t| &t.name)),
+            Tab::Animations => model
+                .animations()
+                .iter()
+                .enumerate()
+                .map(|(i, a)| (i, name(i, &a.name().map(str::to_owned))))
+                .collect(),
+        }
+    }
+}
+
+/// Every node of the scene drawn with its depth, parents first and children in file order.
+fn node_rows(model: &Gltf) -> Vec<(usize, usize)> {
+    fn visit(model: &Gltf, node: usize, depth: usize, rows: &mut Vec<(usize, usize)>) {
+        rows.push((node, depth));
+        for &child in model.nodes()[node].children() {
+            visit(model, child, depth + 1, rows);
+        }
+    }
+    let mut rows = Vec::new();
+    for (i, node) in model.nodes().iter().enumerate() {
+        if node.parent().is_none() && model.is_in_scene(i) {
+            visit(model, i, 0, &mut rows);
+        }
+    }
+    rows
+}
+
+fn name(index: usize, name: &Option<String>) -> String {
+    match name {
+        Some(name) => name.clone(),
+        None => format!("#{}", index),
+    }
+}
+
+fn title(kind: &str, index: usize, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("{} {} {}", kind, index, name),
+        None => format!("{} {}", kind, index),
+    }
+}
+
+fn enumerate<'a>(names: impl Iterator<Item = &'a Option<String>>) -> Vec<(usize, String)> {
+    names.enumerate().map(|(i, n)| (i, name(i, n))).collect()
+}
+
+fn vector(values: &[f32]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{:.2}", v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn node_details(ui: &mut Ui, model: &Gltf, index: usize) {
+    let node = &model.nodes()[index];
+    ui.label(&title("NODE", index, node.name.as_deref()), ui::HEADING);
+    if let Some(parent) = node.parent() {
+        ui.label(&format!("PARENT {}", parent), ui::TEXT);
+    }
+    if !node.children().is_empty() {
+        ui.label(&format!("CHILDREN {}", node.children().len()), ui::TEXT);
+    }
+    ui.label(
+        &format!("T {}", vector(node.translation.as_slice())),
+        ui::TEXT,
+    );
+    ui.label(
+        &format!("R {}", vector(node.rotation.coords.as_slice())),
+        ui::TEXT,
+    );
+    ui.label(&format!("S {}", vector(node.scale.as_slice())), ui::TEXT);
+    let world = (node.global_matrix() * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz();
+    ui.label(&format!("WORLD {}", vector(world.as_slice())), ui::DIM);
+    if !node.weights.is_empty() {
+        ui.label(&format!("WEIGHTS {}", vector(&node.weights)), ui::TEXT);
+    }
+    if let Some((i, camera)) = model
+        .cameras()
+        .iter()
+        .enumerate()
+        .find(|(_, c)| c.node() == index)
+    {
+        ui.label(&title("CAMERA", i, camera.name()), ui::TEXT);
+    }
+    if let Some(light) = model.lights().iter().find(|l| l.node() == Some(index)) {
+        let kind = match light.kind {
+            LightKind::Directional => "DIRECTIONAL",
+            LightKind::Point => "POINT",
+            LightKind::Spot { .. } => "SPOT",
+        };
+        ui.label(&format!("{} LIGHT", kind), ui::TEXT);
+    }
+    if let Some(mesh) = node.mesh() {
+        ui.space();
+        mesh_details(ui, model, mesh);
+    }
+}
+
+fn mesh_details(ui: &mut Ui, model: &Gltf, index: usize) {
+    let mesh = &model.meshes()[index];
+    let counts = model.mesh_counts(index);
+    ui.label(&title("MESH", index, mesh.name.as_deref()), ui::HEADING);
+    ui.label(&format!("PRIMITIVES {}", mesh.materials.len()), ui::TEXT);
+    ui.label(&format!("VERTICES {}", counts.vertices), ui::TEXT);
+    ui.label(&format!("TRIANGLES {}", counts.triangles), ui::TEXT);
+    let mut materials: Vec<usize> = mesh.materials.iter().flatten().copied().collect();
+    materials.dedup();
+    for material in materials {
+        ui.space();
+        material_details(ui, model, material);
+    }
+}
+
+fn material_details(ui: &mut Ui, model: &Gltf, index: usize) {
+    let material: &Material = &model.materials()[index];
+    ui.label(
+        &title("MATERIAL", index, material.name.as_deref()),
+        ui::HEADING,
+    );
+    ui.label(
+        &format!("BASE {}", vector(material.base_color_factor.as_slice())),
+        ui::TEXT,
+    );
+    ui.label(
+        &format!(
+            "METALLIC {:.2} ROUGHNESS {:.2}",
+            material.metallic_factor, material.roughness_factor
+        ),
+        ui::TEXT,
+    );
+    let emissive = material.emissive_factor * material.emissive_strength;
+    if emissive != glm::Vec3::zeros() {
+        ui.label(
+            &format!("EMISSIVE {}", vector(emissive.as_slice())),
+            ui::TEXT,
+        );
+    }
+    let mut flags = format!("{:?}", material.alpha_mode);
+    if material.alpha_mode == gltf::material::AlphaMode::Mask {
+        flags.push_str(&format!(" {:.2}", material.alpha_cutoff));
+    }
+    if material.double_sided {
+        flags.push_str(" DOUBLE SIDED");
+    }
+    if material.unlit {
+        flags.push_str(" UNLIT");
+    }
+    ui.label(&flags, ui::TEXT);
+    for (slot, texture) in &[
+        ("BASE", &material.base_color_texture),
+        ("METAL/ROUGH", &material.metallic_roughness_texture),
+        ("EMISSIVE", &material.emissive_texture),
+    ] {
+        if let Some(texture) = texture {
+            ui.label(
+                &format!(
+                    "{} TEXTURE {} UV{}",
+                    slot, texture.texture, texture.tex_coord
+                ),
+                ui::DIM,
+            );
+        }
+    }
+}
+
+fn texture_details(ui: &mut Ui, model: &Gltf, index: usize) {
+    let texture = &model.textures()[index];
+    ui.label(
+        &title("TEXTURE", index, texture.name.as_deref()),
+        ui::HEADING,
+    );
+    if let Some(image) = &texture.image {
+        ui.label(image, ui::TEXT);
+    }
+    ui.label(&format!("{} X {}", texture.width, texture.height), ui::TEXT);
+    if !model.is_texture_uploaded(index) {
+        ui.label("NOT UPLOADED", ui::DIM);
+    }
+}
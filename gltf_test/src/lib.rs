@@ -1,13 +1,31 @@
 #![allow(dead_code)]
 
 mod fps_counter;
-mod golem_gltf;
+mod framing;
+pub mod golem_gltf;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod headless;
+mod inspector;
+mod model_source;
+mod progress_overlay;
+mod report_overlay;
 mod time;
+mod ui;
 
 use blinds::*;
+use camera_controller::{CameraController, Mode};
 use fps_counter::FpsCounter;
+use framing::Framing;
 use golem::*;
+use golem_gltf::{DebugView, Environment, HdrImage, Loader, ToneMapping, ToneOperator};
+use inspector::Inspector;
+use model_source::{ModelData, ModelSource};
 use nalgebra_glm as glm;
+use post_process::PostProcess;
+use progress_overlay::ProgressOverlay;
+use report_overlay::ReportOverlay;
+use time::*;
+use ui::Ui;
 
 use wasm_bindgen::prelude::*;
 #[wasm_bindgen(start)]
@@ -30,112 +48,413 @@ fn context_from_blinds(window: &Window) -> Result<Context, GolemError> {
     Context::from_glow(glow_ctx)
 }
 
-#[cfg(target_arch = "wasm32")]
-async fn js_fetch(url: impl AsRef<str>) -> Vec<u8> {
-    use js_sys::Uint8Array;
-    use wasm_bindgen::JsCast;
-    use wasm_bindgen_futures::JsFuture;
-    use web_sys::{Request, RequestInit, Response};
-
-    let opts = RequestInit::new();
-    let request = Request::new_with_str_and_init(url.as_ref(), &opts).unwrap();
-    let window = web_sys::window().unwrap();
-    let resp_value = JsFuture::from(window.fetch_with_request(&request))
-        .await
-        .unwrap();
-    let resp: Response = resp_value.dyn_into().unwrap();
-    let arrbuff_value = JsFuture::from(resp.array_buffer().unwrap()).await.unwrap();
-    Uint8Array::new(&arrbuff_value).to_vec()
+/// Instanced draws go through a separate glow context, since golem doesn't expose its own.
+fn instancing_from_blinds(window: &Window) -> Option<golem_gltf::Instancing> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let instancing = golem_gltf::Instancing::new(glow::Context::from_loader_function(|s| {
+        window.get_proc_address(s) as *const _
+    }));
+    #[cfg(target_arch = "wasm32")]
+    let instancing = golem_gltf::Instancing::from_webgl1_context(window.webgl_context());
+
+    if instancing.is_none() {
+        println!("instanced drawing isn't supported, instances are drawn one by one");
+    }
+    instancing
 }
 
-async fn app(window: Window, mut events: EventStream) -> Result<(), GolemError> {
-    let ctx = &context_from_blinds(&window)?;
+/// The depth pass for shadows goes through a separate glow context too.
+fn shadow_map_from_blinds(window: &Window) -> Option<golem_gltf::ShadowMap> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let gl = glow::Context::from_loader_function(|s| window.get_proc_address(s) as *const _);
+    #[cfg(target_arch = "wasm32")]
+    let gl = glow::Context::from_webgl1_context(window.webgl_context());
 
-    let mut gltf_model = {
-        #[cfg(target_arch = "wasm32")]
-        {
-            let data = js_fetch("test.glb").await;
-            golem_gltf::Gltf::load_slice(&data, ctx).unwrap()
-        }
+    let map = golem_gltf::ShadowMap::new(gl);
+    if map.is_none() {
+        println!("can't create a shadow map, models are drawn without shadows");
+    }
+    map
+}
+
+/// The post-process chain's target needs a depth attachment, which goes through glow as well.
+fn post_process_from_blinds(window: &Window, ctx: &Context) -> Option<PostProcess> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let gl = glow::Context::from_loader_function(|s| window.get_proc_address(s) as *const _);
+    #[cfg(target_arch = "wasm32")]
+    let gl = glow::Context::from_webgl1_context(window.webgl_context());
 
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            golem_gltf::Gltf::load("test.glb", ctx).unwrap()
+    match PostProcess::new(ctx, gl, post_process::Settings::default()) {
+        Ok(post) => Some(post),
+        Err(e) => {
+            println!(
+                "can't create the post-process chain, drawing straight to the screen: {}",
+                e
+            );
+            None
         }
+    }
+}
+
+/// Time spent uploading meshes each frame while a model loads.
+const LOAD_BUDGET: Duration = Duration::from_millis(8);
+
+async fn app(window: Window, mut events: EventStream) -> Result<(), GolemError> {
+    let ctx = &context_from_blinds(&window)?;
+
+    // Nothing is drawn but the progress until the first model is parsed.
+    let mut gltf_model = golem_gltf::Gltf::empty(ctx);
+    #[cfg(target_arch = "wasm32")]
+    let first = ("test.glb".to_string(), Loader::fetch("test.glb", None));
+    #[cfg(not(target_arch = "wasm32"))]
+    let first = {
+        let path = std::env::args()
+            .nth(1)
+            .unwrap_or_else(|| "test.glb".to_string());
+        (path.clone(), Loader::open(path, None))
     };
+    let mut loading: Option<(String, Loader)> = Some(first);
+    let environment = std::rc::Rc::new(Environment::new(ctx, &environment_image())?);
+    let mut tone_mapping = ToneMapping::filmic();
+    let mut post = post_process_from_blinds(&window, ctx);
+    let mut post_enabled = true;
+    let mut ground = false;
+    let mut debug_view = DebugView::default();
+    let model_source = ModelSource::new();
 
     window.present();
 
-    let mut scroll_absolute = 10.0f32;
     let mut window_size = glm::make_vec2(window.size().as_ref()) * window.scale_factor();
-    let mut mouse_dragging = false;
-    let mut mouse_location = glm::zero::<glm::Vec2>();
 
-    let mut m_matrix: glm::Mat4 = glm::identity();
+    let mut framing = Framing::new(gltf_model.bounding_box());
+    let mut controller = CameraController::new(framing.center, framing.distance(0.0));
+    framing.reset(&mut controller, 0.0);
+    controller.set_viewport(glm::make_vec2(window.size().as_ref()));
+    let mut camera: Option<usize> = None;
+    let mut last_frame = Instant::now();
 
-    let mut v_matrix = make_v_matrix(scroll_absolute);
+    // A left click that doesn't turn into a drag picks what is under the pointer.
+    let mut pointer = glm::vec2(0.0, 0.0);
+    let mut left_pressed_at: Option<glm::Vec2> = None;
+    let mut pick_at: Option<glm::Vec2> = None;
 
-    let mut p_matrix = make_p_matrix(window_size);
+    let mut hud_p_matrix = make_hud_p_matrix(window_size);
 
     let mut fps_counter = FpsCounter::new(ctx);
+    let mut report_overlay = ReportOverlay::new(ctx, gltf_model.validation());
+    let mut progress_overlay = ProgressOverlay::new(ctx);
+    let mut ui = Ui::new(ctx)?;
+    let mut inspector = Inspector::new();
 
     loop {
         while let Some(event) = events.next_event().await {
             use blinds::event::*;
 
+            // Pointer events on the inspector stop there.
+            if ui.handle_event(&event) {
+                continue;
+            }
+            controller.handle_event(&event);
             match event {
                 Event::Resized(size) => {
                     window_size =
                         glm::make_vec2(size.logical_size().as_ref()) * window.scale_factor();
                     ctx.set_viewport(0, 0, window_size.x as u32, window_size.y as u32);
-                    p_matrix = make_p_matrix(window_size);
+                    hud_p_matrix = make_hud_p_matrix(window_size);
+                    controller.set_viewport(glm::make_vec2(size.logical_size().as_ref()));
+                }
+                Event::PointerMoved(e) => pointer = glm::make_vec2(e.location().as_ref()),
+                Event::PointerInput(e) if e.button() == MouseButton::Left => {
+                    if e.is_down() {
+                        left_pressed_at = Some(pointer);
+                    } else if let Some(pressed_at) = left_pressed_at.take() {
+                        if glm::distance(&pressed_at, &pointer) < 4.0 {
+                            pick_at = Some(pointer);
+                        }
+                    }
                 }
                 Event::ScaleFactorChanged(scale) => {
                     window_size = glm::make_vec2(window.size().as_ref()) * scale.scale_factor();
                     ctx.set_viewport(0, 0, window_size.x as u32, window_size.y as u32);
-                    p_matrix = make_p_matrix(window_size);
-                }
-                Event::ScrollInput(ScrollDelta::Lines(delta)) => {
-                    scroll_absolute -= delta.y;
-                    v_matrix = make_v_matrix(scroll_absolute);
+                    hud_p_matrix = make_hud_p_matrix(window_size);
                 }
-                Event::PointerInput(e) => {
-                    if e.button() == MouseButton::Left {
-                        mouse_dragging = e.is_down();
+                Event::KeyboardInput(e) if e.is_down() => match e.key() {
+                    Key::Space => {
+                        let player = gltf_model.player_mut();
+                        if player.is_playing() {
+                            player.pause();
+                        } else {
+                            player.resume();
+                        }
+                    }
+                    Key::N if !gltf_model.animations().is_empty() => {
+                        let clips = gltf_model.animations().len();
+                        let next = gltf_model.player().current_clip().map_or(0, |c| c + 1) % clips;
+                        gltf_model
+                            .player_mut()
+                            .cross_fade(next, Duration::from_millis(300));
+                    }
+                    Key::C => {
+                        // Cycle through the cameras in the model on screen, then back to the
+                        // orbit view.
+                        let shown = loading
+                            .as_ref()
+                            .and_then(|(_, loader)| loader.model())
+                            .unwrap_or(&gltf_model);
+                        camera = match camera {
+                            None if !shown.cameras().is_empty() => Some(0),
+                            Some(i) if i + 1 < shown.cameras().len() => Some(i + 1),
+                            _ => None,
+                        };
+                        if let Some(i) = camera {
+                            println!("camera: {:?}", shown.cameras()[i].name());
+                        }
+                    }
+                    Key::V => {
+                        report_overlay.visible = !report_overlay.visible;
+                        if report_overlay.visible {
+                            println!("{}", gltf_model.validation());
+                        }
+                    }
+                    Key::M => {
+                        let mode = match controller.mode() {
+                            Mode::Orbit => Mode::Fly,
+                            Mode::Fly => Mode::Orbit,
+                        };
+                        controller.set_mode(mode);
+                        println!("camera mode: {:?}", mode);
+                    }
+                    Key::K => {
+                        let culling = !gltf_model.culling();
+                        gltf_model.set_culling(culling);
+                        println!(
+                            "culling: {}, last frame: {:?}",
+                            culling,
+                            gltf_model.draw_stats()
+                        );
+                    }
+                    Key::Minus | Key::Equals => {
+                        let stops = if e.key() == Key::Minus { -0.5 } else { 0.5 };
+                        tone_mapping.exposure *= 2f32.powf(stops);
+                        println!("exposure: {:+.1} stops", tone_mapping.exposure.log2());
+                    }
+                    Key::T => {
+                        tone_mapping.operator = match tone_mapping.operator {
+                            ToneOperator::Clamp => ToneOperator::Reinhard,
+                            ToneOperator::Reinhard => ToneOperator::Aces,
+                            ToneOperator::Aces | ToneOperator::Hdr => ToneOperator::Clamp,
+                        };
+                        println!("tone mapping: {:?}", tone_mapping.operator);
+                    }
+                    Key::P => {
+                        post_enabled = !post_enabled;
+                        println!("post-processing: {}", post_enabled);
+                    }
+                    Key::B if post.is_some() => {
+                        let settings = &mut post.as_mut().unwrap().settings;
+                        settings.bloom = if settings.bloom > 0.0 {
+                            0.0
+                        } else {
+                            post_process::Settings::default().bloom
+                        };
+                        println!("bloom: {}", settings.bloom);
                     }
+                    Key::X if post.is_some() => {
+                        let settings = &mut post.as_mut().unwrap().settings;
+                        settings.fxaa = !settings.fxaa;
+                        println!("FXAA: {}", settings.fxaa);
+                    }
+                    Key::G => {
+                        ground = !ground;
+                        println!("ground: {}", ground);
+                    }
+                    Key::Key1 | Key::Key2 | Key::Key3 | Key::Key4 | Key::Key5 | Key::Key6 => {
+                        let index = match e.key() {
+                            Key::Key1 => 0,
+                            Key::Key2 => 1,
+                            Key::Key3 => 2,
+                            Key::Key4 => 3,
+                            Key::Key5 => 4,
+                            _ => 5,
+                        };
+                        let shown = debug_view.get_mut(index);
+                        *shown = !*shown;
+                        println!("{}: {}", DebugView::NAMES[index], shown);
+                    }
+                    Key::I => inspector.visible = !inspector.visible,
+                    Key::Tab if gltf_model.scenes().len() > 1 => {
+                        let scenes = gltf_model.scenes().len();
+                        let next = gltf_model.scene().map_or(0, |s| (s + 1) % scenes);
+                        gltf_model.set_scene(Some(next));
+                        println!("scene {}: {:?}", next, gltf_model.scenes()[next].name);
+                        framing = Framing::new(gltf_model.bounding_box());
+                        framing.reset(&mut controller, 0.0);
+                        camera = None;
+                        inspector.reset();
+                    }
+                    Key::F => {
+                        framing = Framing::new(gltf_model.bounding_box());
+                        framing.reset(&mut controller, 0.0);
+                        camera = None;
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        if let Some(data) = model_source.poll() {
+            let name = data.name();
+            let loader = match data {
+                #[cfg(not(target_arch = "wasm32"))]
+                ModelData::Path(path) => Loader::open(path, None),
+                ModelData::Bytes { data, .. } => Loader::from_bytes(data, None),
+            };
+            // Dropping the previous loader cancels it.
+            if let Some((previous, _)) = loading.replace((name, loader)) {
+                println!("stopped loading {}", previous);
+            }
+            camera = None;
+        }
+
+        if let Some((name, loader)) = &mut loading {
+            let meshes_loaded = loader.progress().meshes_loaded;
+            match loader.poll(ctx, LOAD_BUDGET) {
+                Ok(true) => {
+                    let (name, loader) = loading.take().unwrap();
+                    println!("opened {}", name);
+                    gltf_model = loader.into_model().unwrap();
+                    if !gltf_model.validation().is_empty() {
+                        println!("{}", gltf_model.validation());
+                    }
+                    gltf_model.set_instancing(instancing_from_blinds(&window));
+                    gltf_model.set_shadow_map(shadow_map_from_blinds(&window));
+                    if !gltf_model.animations().is_empty() {
+                        gltf_model.player_mut().play(0);
+                    }
+                    framing = Framing::new(gltf_model.bounding_box());
+                    framing.reset(&mut controller, 0.0);
+                    camera = None;
+                    report_overlay.set_report(gltf_model.validation());
+                    inspector.reset();
                 }
-                Event::PointerMoved(e) => {
-                    let mouse_location_n = glm::make_vec2(e.location().as_ref());
-                    let mouse_moved = if mouse_dragging {
-                        mouse_location_n - mouse_location
-                    } else {
-                        glm::zero()
-                    };
-                    m_matrix = glm::rotate_x(&m_matrix, mouse_moved.y / 100.0);
-                    m_matrix = glm::rotate_y(&m_matrix, mouse_moved.x / 100.0);
-                    mouse_location = mouse_location_n;
-                    v_matrix = make_v_matrix(scroll_absolute);
+                // The view follows the model as its meshes come in.
+                Ok(false) if loader.progress().meshes_loaded > meshes_loaded => {
+                    let partial = loader.model().unwrap();
+                    framing = Framing::new(partial.bounding_box());
+                    framing.reset(&mut controller, 0.0);
+                    camera = None;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    println!("can't open {}: {}", name, e);
+                    loading = None;
                 }
-                _ => {}
             }
         }
 
         fps_counter.count();
+        let now = Instant::now();
+        controller.update(now - last_frame);
+        last_frame = now;
+        gltf_model.update_animation(now);
+        // Until the new model is parsed, the current one stays on screen.
+        let model = match loading.as_mut().and_then(|(_, loader)| loader.model_mut()) {
+            Some(partial) => partial,
+            None => &mut gltf_model,
+        };
 
-        ctx.set_clear_color(0.1, 0.2, 0.3, 1.0);
-        ctx.clear();
+        let aspect = window_size.x / window_size.y;
+        // The orbit view, if the camera picked isn't in the model on screen.
+        let mvp_matrix = match camera.and_then(|i| model.cameras().get(i)) {
+            Some(camera) => camera.projection_matrix(aspect) * camera.view_matrix(model.nodes()),
+            None => {
+                let eye_distance = glm::distance(&controller.eye(), &framing.center);
+                framing.p_matrix(aspect, eye_distance) * controller.view_matrix()
+            }
+        };
 
+        if let Some(location) = pick_at.take() {
+            let size = glm::make_vec2(window.size().as_ref());
+            let ndc = glm::vec2(
+                location.x / size.x * 2.0 - 1.0,
+                1.0 - location.y / size.y * 2.0,
+            );
+            let hit = model.pick(&golem_gltf::Ray::unproject(ndc, &mvp_matrix));
+            if let Some(hit) = &hit {
+                inspector.select_node(model, hit.node);
+            }
+            match hit {
+                Some(hit) => println!(
+                    "hit node {} {:?}, mesh {} primitive {} triangle {}, at {:?}, normal {:?}",
+                    hit.node,
+                    model.nodes()[hit.node].name,
+                    hit.mesh,
+                    hit.primitive,
+                    hit.triangle,
+                    hit.point.as_slice(),
+                    hit.normal.as_slice()
+                ),
+                None => println!("nothing under the pointer"),
+            }
+        }
+
+        // Through the post-process chain, exposure and tone mapping are left to its last pass.
+        let mut active_post = post.as_mut().filter(|_| post_enabled);
+        let scene_tone_mapping = match &active_post {
+            Some(_) => tone_mapping.hdr_target(),
+            None => tone_mapping,
+        };
+        model.set_environment(Some(environment.clone()));
+        model.set_tone_mapping(scene_tone_mapping);
+        model.set_ground(ground);
+        model.set_debug_view(debug_view);
+        // Laid out before drawing the model, which outlines the node selected.
+        ui.begin(glm::make_vec2(window.size().as_ref()));
+        inspector.draw(&mut ui, model);
+        model.draw_shadows(ctx)?;
+        let (width, height) = (window_size.x as u32, window_size.y as u32);
+        match &mut active_post {
+            Some(post) => {
+                post.settings = tone_mapping.post_settings(post.settings);
+                post.begin(ctx, width, height)?;
+            }
+            None => ctx.set_viewport(0, 0, width, height),
+        }
+        // The sky fills the background, so only depth needs clearing.
+        ctx.clear();
+        environment.draw_sky(&mvp_matrix, &scene_tone_mapping)?;
         ctx.set_depth_test_mode(Some(depth::DepthTestMode::default()));
-        let mvp_matrix = p_matrix * v_matrix * m_matrix;
-        gltf_model.draw(&mvp_matrix)?;
+        model.draw(ctx, &mvp_matrix)?;
         ctx.set_depth_test_mode(None);
-        fps_counter.draw(&p_matrix)?;
+        if let Some(post) = active_post {
+            post.resolve(ctx)?;
+            post.draw_output(ctx)?;
+        }
+        fps_counter.draw(&hud_p_matrix)?;
+        report_overlay.draw(&hud_p_matrix)?;
+        if let Some((_, loader)) = &loading {
+            progress_overlay.draw(&loader.progress(), &hud_p_matrix)?;
+        }
+        ui.end(ctx)?;
 
         window.present();
     }
 }
 
-fn make_p_matrix(window_size: glm::Vec2) -> glm::Mat4 {
+/// The `.hdr` image given after the model on the command line, or a clear sky.
+fn environment_image() -> HdrImage {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = std::env::args().nth(2) {
+        match HdrImage::load(&path) {
+            Ok(image) => return image,
+            Err(e) => println!("can't open {}: {}", path, e),
+        }
+    }
+    HdrImage::sky()
+}
+
+fn make_hud_p_matrix(window_size: glm::Vec2) -> glm::Mat4 {
     glm::perspective(
         window_size.x / window_size.y,
         std::f32::consts::PI / 2.0,
@@ -143,11 +462,3 @@ fn make_p_matrix(window_size: glm::Vec2) -> glm::Mat4 {
         100.0,
     )
 }
-
-fn make_v_matrix(scroll_absolute: f32) -> glm::Mat4 {
-    glm::look_at(
-        &glm::vec3(0.0, 0.0, 1.1_f32.powf(scroll_absolute)),
-        &glm::vec3(0.0, 0.0, 0.0),
-        &glm::vec3(0.0, 1.0, 0.0),
-    )
-}
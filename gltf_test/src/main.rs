@@ -1,3 +1,38 @@
 fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if args.first().map(String::as_str) == Some("render") {
+            use gltf_test_lib::headless;
+
+            let result = headless::parse_args(&args[1..]).and_then(|(model, output, options)| {
+                headless::render_to_png(model, output, &options)
+            });
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        if args.first().map(String::as_str) == Some("export") {
+            let optimization = gltf_test_lib::golem_gltf::Optimization::default();
+            let result = match &args[1..] {
+                [model, output] => gltf_test_lib::headless::export(model, output, None),
+                [flag, model, output] if flag == "--optimize" => {
+                    gltf_test_lib::headless::export(model, output, Some(&optimization))
+                }
+                _ => Err(gltf_test_lib::headless::HeadlessError::Usage(
+                    "usage: gltf_test export [--optimize] <model> <output.glb|output.gltf>"
+                        .to_owned(),
+                )),
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
     gltf_test_lib::start();
 }
@@ -0,0 +1,178 @@
+//! Models opened while the viewer is running.
+//!
+//! On the web, files dropped anywhere on the page or chosen with the file input are read into
+//! memory. Only self-contained files (`.glb`, or `.gltf` with embedded buffers) can be opened this
+//! way, since the browser gives no access to the files next to them.
+//!
+//! Natively, dropping files on the window isn't implemented: `blinds` owns the winit event loop
+//! and discards winit's `DroppedFile` events, with no way to reach them. Models are opened from
+//! the command line instead, and paths are read from standard input while the viewer runs, so
+//! dropping a file onto the terminal the viewer was started from (which types its path) opens it.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+pub enum ModelData {
+    #[cfg(not(target_arch = "wasm32"))]
+    Path(PathBuf),
+    Bytes {
+        name: String,
+        data: Vec<u8>,
+    },
+}
+
+impl ModelData {
+    pub fn name(&self) -> String {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            ModelData::Path(path) => path.display().to_string(),
+            ModelData::Bytes { name, .. } => name.clone(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ModelSource {
+    receiver: std::sync::mpsc::Receiver<ModelData>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ModelSource {
+    pub fn new() -> ModelSource {
+        use std::io::BufRead;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let path = match line {
+                    Ok(line) => parse_dropped_path(&line),
+                    Err(_) => break,
+                };
+                if let Some(path) = path {
+                    if sender.send(ModelData::Path(path)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        ModelSource { receiver }
+    }
+
+    /// The most recently opened model, if any arrived since the last call.
+    pub fn poll(&self) -> Option<ModelData> {
+        self.receiver.try_iter().last()
+    }
+}
+
+/// Terminals quote or escape dropped paths, and some insert a `file://` URI instead.
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_dropped_path(line: &str) -> Option<PathBuf> {
+    let line = line.trim();
+    let line = line
+        .strip_prefix('\'')
+        .and_then(|l| l.strip_suffix('\''))
+        .or_else(|| line.strip_prefix('"').and_then(|l| l.strip_suffix('"')))
+        .unwrap_or(line);
+    let line = line.strip_prefix("file://").unwrap_or(line);
+    if line.is_empty() {
+        return None;
+    }
+
+    let mut path = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if !cfg!(windows) => path.extend(chars.next()),
+            _ => path.push(c),
+        }
+    }
+    Some(PathBuf::from(path))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct ModelSource {
+    queue: std::rc::Rc<std::cell::RefCell<Vec<ModelData>>>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl ModelSource {
+    pub fn new() -> ModelSource {
+        use wasm_bindgen::prelude::*;
+        use wasm_bindgen::JsCast;
+        use web_sys::{DragEvent, Event, HtmlInputElement};
+
+        let source = ModelSource {
+            queue: Default::default(),
+        };
+        let document = web_sys::window().unwrap().document().unwrap();
+
+        let input: HtmlInputElement = document
+            .create_element("input")
+            .unwrap()
+            .dyn_into()
+            .unwrap();
+        input.set_type("file");
+        input.set_accept(".glb,.gltf");
+        document.body().unwrap().append_child(&input).unwrap();
+
+        let queue = source.queue.clone();
+        let picked = input.clone();
+        let on_change = Closure::wrap(Box::new(move |_: Event| {
+            if let Some(file) = picked.files().and_then(|files| files.get(0)) {
+                read_file(file, queue.clone());
+            }
+        }) as Box<dyn FnMut(Event)>);
+        input
+            .add_event_listener_with_callback("change", on_change.as_ref().unchecked_ref())
+            .unwrap();
+        on_change.forget();
+
+        // The page only becomes a drop target if dragover is cancelled.
+        let on_dragover =
+            Closure::wrap(Box::new(|e: Event| e.prevent_default()) as Box<dyn FnMut(Event)>);
+        document
+            .add_event_listener_with_callback("dragover", on_dragover.as_ref().unchecked_ref())
+            .unwrap();
+        on_dragover.forget();
+
+        let queue = source.queue.clone();
+        let on_drop = Closure::wrap(Box::new(move |e: Event| {
+            e.prevent_default();
+            let file = e
+                .dyn_ref::<DragEvent>()
+                .and_then(|e| e.data_transfer())
+                .and_then(|data| data.files())
+                .and_then(|files| files.get(0));
+            if let Some(file) = file {
+                read_file(file, queue.clone());
+            }
+        }) as Box<dyn FnMut(Event)>);
+        document
+            .add_event_listener_with_callback("drop", on_drop.as_ref().unchecked_ref())
+            .unwrap();
+        on_drop.forget();
+
+        source
+    }
+
+    /// The most recently opened model, if any arrived since the last call.
+    pub fn poll(&self) -> Option<ModelData> {
+        self.queue.borrow_mut().drain(..).last()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_file(file: web_sys::File, queue: std::rc::Rc<std::cell::RefCell<Vec<ModelData>>>) {
+    use js_sys::Uint8Array;
+    use wasm_bindgen_futures::JsFuture;
+
+    wasm_bindgen_futures::spawn_local(async move {
+        match JsFuture::from(file.array_buffer()).await {
+            Ok(buffer) => queue.borrow_mut().push(ModelData::Bytes {
+                name: file.name(),
+                data: Uint8Array::new(&buffer).to_vec(),
+            }),
+            Err(e) => web_sys::console::log_1(&e),
+        }
+    });
+}
@@ -0,0 +1,56 @@
+use golem::*;
+use nalgebra_glm as glm;
+
+use crate::fps_counter::DigitRender;
+use crate::golem_gltf::LoadProgress;
+
+/// Percentage of a model loaded so far, in the bottom left corner, with the number of textures
+/// uploaded above it once the meshes are in.
+pub struct ProgressOverlay {
+    digit_render: DigitRender,
+}
+
+impl ProgressOverlay {
+    pub fn new(ctx: &Context) -> ProgressOverlay {
+        ProgressOverlay {
+            digit_render: DigitRender::new(ctx),
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        progress: &LoadProgress,
+        p_matrix: &glm::Mat4,
+    ) -> Result<(), GolemError> {
+        let percent = (progress.fraction() * 100.0) as usize;
+        self.draw_number(percent, -0.98, [0.6, 0.9, 1.0, 1.0], p_matrix)?;
+        let meshes_done =
+            progress.meshes_total > 0 && progress.meshes_loaded == progress.meshes_total;
+        if meshes_done && progress.textures_total > 0 {
+            self.draw_number(
+                progress.textures_loaded,
+                -0.85,
+                [1.0, 0.8, 0.5, 1.0],
+                p_matrix,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn draw_number(
+        &mut self,
+        number: usize,
+        y: f32,
+        color: [f32; 4],
+        p_matrix: &glm::Mat4,
+    ) -> Result<(), GolemError> {
+        for (i, digit) in number.to_string().bytes().enumerate() {
+            let matrix = p_matrix
+                * glm::translation(&glm::vec3(-0.98 + 0.08 * i as f32, y, 0.0))
+                * glm::scaling(&glm::vec3(0.1 * 3.0 / 5.0, 0.1, 1.0));
+            self.digit_render
+                .draw_colored((digit - b'0') as usize, &matrix, color)?;
+        }
+        Ok(())
+    }
+}
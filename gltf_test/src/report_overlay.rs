@@ -0,0 +1,58 @@
+use golem::*;
+use nalgebra_glm as glm;
+
+use crate::fps_counter::DigitRender;
+use crate::golem_gltf::{Severity, ValidationReport};
+
+/// Error and warning counts from the validation report, in the top left corner.
+pub struct ReportOverlay {
+    digit_render: DigitRender,
+    errors: usize,
+    warnings: usize,
+    pub visible: bool,
+}
+
+impl ReportOverlay {
+    pub fn new(ctx: &Context, report: &ValidationReport) -> ReportOverlay {
+        let mut overlay = ReportOverlay {
+            digit_render: DigitRender::new(ctx),
+            errors: 0,
+            warnings: 0,
+            visible: false,
+        };
+        overlay.set_report(report);
+        overlay
+    }
+
+    /// Show the counts from `report`, making the overlay visible if there is anything to show.
+    pub fn set_report(&mut self, report: &ValidationReport) {
+        self.errors = report.count(Severity::Error);
+        self.warnings = report.count(Severity::Warning);
+        self.visible = !report.is_empty();
+    }
+
+    pub fn draw(&mut self, p_matrix: &glm::Mat4) -> Result<(), GolemError> {
+        if !self.visible {
+            return Ok(());
+        }
+        self.draw_number(self.errors, p_matrix, 0.9, [1.0, 0.3, 0.3, 1.0])?;
+        self.draw_number(self.warnings, p_matrix, 0.78, [1.0, 0.9, 0.3, 1.0])
+    }
+
+    fn draw_number(
+        &mut self,
+        num: usize,
+        p_matrix: &glm::Mat4,
+        y: f32,
+        color: [f32; 4],
+    ) -> Result<(), GolemError> {
+        for (i, digit) in num.to_string().bytes().enumerate() {
+            let matrix = p_matrix
+                * glm::translation(&glm::vec3(-0.98 + 0.08 * i as f32, y, 0.0))
+                * glm::scaling(&glm::vec3(0.1 * 3.0 / 5.0, 0.1, 1.0));
+            self.digit_render
+                .draw_colored((digit - b'0') as usize, &matrix, color)?;
+        }
+        Ok(())
+    }
+}
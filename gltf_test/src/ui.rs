@@ -0,0 +1,435 @@
+//! A small immediate-mode UI drawn over the scene: panels of text rows, some of which can be
+//! clicked, laid out again every frame between [`Ui::begin`] and [`Ui::end`] and drawn in one
+//! batch.
+
+use std::{convert::TryInto, num::NonZeroU32};
+
+use blinds::event::{Event, ScrollDelta};
+use blinds::MouseButton;
+use golem::*;
+use nalgebra_glm as glm;
+
+/// Size of a font pixel, in logical pixels.
+const SCALE: f32 = 2.0;
+/// Glyphs are 3 by 5 font pixels.
+const ADVANCE: f32 = 4.0 * SCALE;
+pub const ROW_HEIGHT: f32 = 7.0 * SCALE;
+const PADDING: f32 = 3.0 * SCALE;
+
+pub const TEXT: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+pub const DIM: [f32; 4] = [0.6, 0.65, 0.7, 1.0];
+pub const HEADING: [f32; 4] = [0.6, 0.9, 1.0, 1.0];
+const BACKGROUND: [f32; 4] = [0.05, 0.07, 0.1, 0.8];
+const HOVERED: [f32; 4] = [1.0, 1.0, 1.0, 0.1];
+const SELECTED: [f32; 4] = [0.3, 0.5, 0.9, 0.6];
+
+/// ASCII from space to underscore; lowercase letters are drawn as capitals. Each octal digit is
+/// a row from the top, its bits the pixels from the left.
+#[rustfmt::skip]
+const FONT: [u16; 64] = [
+    0o00000, 0o22202, 0o55000, 0o57575, 0o36362, 0o51245, 0o25257, 0o22000, // space ! " # $ % & '
+    0o12221, 0o42224, 0o05250, 0o02720, 0o00024, 0o00700, 0o00002, 0o11244, // ( ) * + , - . /
+    0o75557, 0o26227, 0o71747, 0o71717, 0o55711, 0o74717, 0o74757, 0o71111, // 0 - 7
+    0o75757, 0o75717, 0o02020, 0o02024, 0o12421, 0o07070, 0o42124, 0o71202, // 8 9 : ; < = > ?
+    0o75743, 0o25755, 0o65656, 0o34443, 0o65556, 0o74747, 0o74744, 0o34553, // @ A - G
+    0o55755, 0o72227, 0o11152, 0o55655, 0o44447, 0o57755, 0o65555, 0o25552, // H - O
+    0o65644, 0o25563, 0o65655, 0o34216, 0o72222, 0o55557, 0o55552, 0o55775, // P - W
+    0o55255, 0o55222, 0o71247, 0o64446, 0o44211, 0o32223, 0o25000, 0o00007, // X Y Z [ \ ] ^ _
+];
+/// The glyph texture holds the glyphs side by side, 4 pixels apart, and a white pixel in the
+/// last column of its last row to draw solid rectangles with.
+const GLYPHS_WIDTH: u32 = 256;
+const GLYPHS_HEIGHT: u32 = 8;
+
+/// The index in [`FONT`] of the glyph drawn for `c`.
+fn glyph(c: char) -> usize {
+    let c = c.to_ascii_uppercase();
+    match c {
+        ' '..='_' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    }
+}
+
+/// How many characters of a text fit in `width`.
+fn fitting_chars(width: f32) -> usize {
+    ((width + ADVANCE - 3.0 * SCALE) / ADVANCE).max(0.0) as usize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub min: glm::Vec2,
+    pub size: glm::Vec2,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {
+            min: glm::vec2(x, y),
+            size: glm::vec2(width, height),
+        }
+    }
+
+    pub fn contains(&self, point: &glm::Vec2) -> bool {
+        let max = self.min + self.size;
+        point.x >= self.min.x && point.y >= self.min.y && point.x < max.x && point.y < max.y
+    }
+}
+
+pub struct Ui {
+    shader: ShaderProgram,
+    vb: VertexBuffer,
+    eb: ElementBuffer,
+    glyphs: Texture,
+    /// Interleaved positions, texture coordinates and colors of this frame's quads.
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+    size: glm::Vec2,
+    pointer: Option<glm::Vec2>,
+    pressed_at: Option<glm::Vec2>,
+    /// Where the left button was released since the last frame, if it didn't move while down.
+    click: Option<glm::Vec2>,
+    /// Lines scrolled since the last frame.
+    scroll: f32,
+    /// The panels of the last frame, which take the pointer's events.
+    panels: Vec<Rect>,
+    last_panels: Vec<Rect>,
+    panel: Rect,
+    cursor: glm::Vec2,
+}
+
+impl Ui {
+    pub fn new(ctx: &Context) -> Result<Ui, GolemError> {
+        let shader = ShaderProgram::new(
+            ctx,
+            ShaderDescription {
+                vertex_input: &[
+                    Attribute::new("vert_position", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("vert_uv", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("vert_color", AttributeType::Vector(Dimension::D4)),
+                ],
+                fragment_input: &[
+                    Attribute::new("frag_uv", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("frag_color", AttributeType::Vector(Dimension::D4)),
+                ],
+                uniforms: &[
+                    Uniform::new("matrix", UniformType::Matrix(Dimension::D4)),
+                    Uniform::new("glyphs", UniformType::Sampler2D),
+                ],
+                vertex_shader: r#" void main() {
+                    gl_Position = matrix * vec4(vert_position, 0.0, 1.0);
+                    frag_uv = vert_uv;
+                    frag_color = vert_color;
+                }"#,
+                fragment_shader: r#" void main() {
+                    float coverage = texture(glyphs, frag_uv).a;
+                    gl_FragColor = vec4(frag_color.rgb, frag_color.a * coverage);
+                }"#,
+            },
+        )?;
+        Ok(Ui {
+            shader,
+            vb: VertexBuffer::new(ctx)?,
+            eb: ElementBuffer::new(ctx)?,
+            glyphs: glyphs(ctx)?,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            size: glm::vec2(1.0, 1.0),
+            pointer: None,
+            pressed_at: None,
+            click: None,
+            scroll: 0.0,
+            panels: Vec::new(),
+            last_panels: Vec::new(),
+            panel: Rect::new(0.0, 0.0, 0.0, 0.0),
+            cursor: glm::vec2(0.0, 0.0),
+        })
+    }
+
+    fn over_panel(&self) -> bool {
+        match &self.pointer {
+            Some(p) => self.last_panels.iter().any(|r| r.contains(p)),
+            None => false,
+        }
+    }
+
+    /// Track the pointer, returning `true` if the event was on a panel and shouldn't reach the
+    /// scene. Drags that started off the panels are left alone.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::PointerMoved(e) => {
+                self.pointer = Some(glm::make_vec2(e.location().as_ref()));
+                false
+            }
+            Event::PointerLeft(_) => {
+                self.pointer = None;
+                false
+            }
+            Event::PointerInput(e) if e.is_down() => {
+                if !self.over_panel() {
+                    return false;
+                }
+                if e.button() == MouseButton::Left {
+                    self.pressed_at = self.pointer;
+                }
+                true
+            }
+            Event::PointerInput(e) if e.button() == MouseButton::Left => {
+                match (self.pressed_at.take(), self.pointer) {
+                    (Some(pressed_at), Some(pointer)) => {
+                        if glm::distance(&pressed_at, &pointer) < 4.0 {
+                            self.click = Some(pointer);
+                        }
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Event::ScrollInput(delta) if self.over_panel() => {
+                self.scroll += match delta {
+                    ScrollDelta::Lines(delta) => delta.y,
+                    ScrollDelta::Pixels(delta) => delta.y / ROW_HEIGHT,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Start a frame on a window `size` logical pixels large.
+    pub fn begin(&mut self, size: glm::Vec2) {
+        self.size = size;
+        self.vertices.clear();
+        self.indices.clear();
+        self.last_panels = std::mem::take(&mut self.panels);
+    }
+
+    /// Draw the frame, with blending on the way in and off on the way out.
+    pub fn end(&mut self, ctx: &Context) -> Result<(), GolemError> {
+        self.click = None;
+        self.scroll = 0.0;
+        if self.indices.is_empty() {
+            return Ok(());
+        }
+        self.vb.set_data(&self.vertices);
+        self.eb.set_data(&self.indices);
+        let matrix = glm::ortho(0.0, self.size.x, self.size.y, 0.0, -1.0, 1.0);
+        self.shader.bind();
+        self.glyphs
+            .set_active(unsafe { NonZeroU32::new_unchecked(1) });
+        self.shader.set_uniform(
+            "matrix",
+            UniformValue::Matrix4(glm::value_ptr(&matrix).try_into().unwrap()),
+        )?;
+        self.shader.set_uniform("glyphs", UniformValue::Int(1))?;
+        ctx.set_blend_mode(Some(blend::BlendMode::default()));
+        let result = unsafe {
+            self.shader.draw(
+                &self.vb,
+                &self.eb,
+                0..self.indices.len(),
+                GeometryMode::Triangles,
+            )
+        };
+        ctx.set_blend_mode(None);
+        result
+    }
+
+    pub fn size(&self) -> glm::Vec2 {
+        self.size
+    }
+
+    /// Start laying out rows from the top of `rect`.
+    pub fn panel(&mut self, rect: Rect) {
+        self.panels.push(rect);
+        self.rect(&rect, BACKGROUND);
+        self.panel = rect;
+        self.cursor = rect.min + glm::vec2(PADDING, PADDING);
+    }
+
+    /// Lines scrolled over the current panel since the last frame, positive upwards.
+    pub fn scroll(&self) -> f32 {
+        match &self.pointer {
+            Some(p) if self.panel.contains(p) => self.scroll,
+            _ => 0.0,
+        }
+    }
+
+    /// How many more rows fit in the current panel.
+    pub fn rows_left(&self) -> usize {
+        let bottom = self.panel.min.y + self.panel.size.y - PADDING;
+        ((bottom - self.cursor.y) / ROW_HEIGHT).max(0.0) as usize
+    }
+
+    pub fn label(&mut self, text: &str, color: [f32; 4]) {
+        if self.rows_left() > 0 {
+            let row = self.row();
+            self.text(&row, text, color);
+        }
+    }
+
+    /// A row that can be clicked, filled in if `selected`. Returns whether it was clicked.
+    pub fn button(&mut self, text: &str, color: [f32; 4], selected: bool) -> bool {
+        if self.rows_left() == 0 {
+            return false;
+        }
+        let row = self.row();
+        let clicked = self.interact(&row, selected);
+        self.text(&row, text, color);
+        clicked
+    }
+
+    /// A row of buttons, one of which is selected.
+    pub fn tabs(&mut self, names: &[&str], selected: &mut usize) {
+        if self.rows_left() == 0 {
+            return;
+        }
+        let row = self.row();
+        let mut x = row.min.x;
+        for (i, name) in names.iter().enumerate() {
+            let width = (name.len() as f32 + 1.0) * ADVANCE;
+            let tab = Rect::new(x, row.min.y, width, row.size.y);
+            if self.interact(&tab, i == *selected) {
+                *selected = i;
+            }
+            self.text(&tab, name, TEXT);
+            x += width + SCALE;
+        }
+    }
+
+    /// Half a row of space.
+    pub fn space(&mut self) {
+        self.cursor.y += ROW_HEIGHT / 2.0;
+    }
+
+    fn row(&mut self) -> Rect {
+        let row = Rect::new(
+            self.cursor.x,
+            self.cursor.y,
+            self.panel.size.x - 2.0 * PADDING,
+            ROW_HEIGHT,
+        );
+        self.cursor.y += ROW_HEIGHT;
+        row
+    }
+
+    /// Fill `rect` for hovering and selection, and take the click if it was in there.
+    fn interact(&mut self, rect: &Rect, selected: bool) -> bool {
+        let hovered = matches!(&self.pointer, Some(p) if rect.contains(p));
+        if selected {
+            self.rect(rect, SELECTED);
+        } else if hovered {
+            self.rect(rect, HOVERED);
+        }
+        match self.click {
+            Some(click) if rect.contains(&click) => {
+                self.click = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// `text` on a row, cut short where it would run out of `rect`.
+    fn text(&mut self, rect: &Rect, text: &str, color: [f32; 4]) {
+        let glyph_size = glm::vec2(3.0, 5.0);
+        let top = rect.min.y + (rect.size.y - glyph_size.y * SCALE) / 2.0;
+        let left = rect.min.x + SCALE;
+        for (i, c) in text
+            .chars()
+            .take(fitting_chars(rect.size.x - SCALE))
+            .enumerate()
+        {
+            let g = glyph(c);
+            if g == 0 {
+                continue;
+            }
+            let min = glm::vec2(left + i as f32 * ADVANCE, top);
+            let uv_min = glm::vec2((g * 4) as f32 / GLYPHS_WIDTH as f32, 0.0);
+            let uv_size = glm::vec2(
+                glyph_size.x / GLYPHS_WIDTH as f32,
+                glyph_size.y / GLYPHS_HEIGHT as f32,
+            );
+            self.quad(min, glyph_size * SCALE, uv_min, uv_size, color);
+        }
+    }
+
+    fn rect(&mut self, rect: &Rect, color: [f32; 4]) {
+        // The middle of the white pixel.
+        let white = glm::vec2(
+            (GLYPHS_WIDTH as f32 - 0.5) / GLYPHS_WIDTH as f32,
+            (GLYPHS_HEIGHT as f32 - 0.5) / GLYPHS_HEIGHT as f32,
+        );
+        self.quad(rect.min, rect.size, white, glm::vec2(0.0, 0.0), color);
+    }
+
+    fn quad(
+        &mut self,
+        min: glm::Vec2,
+        size: glm::Vec2,
+        uv_min: glm::Vec2,
+        uv_size: glm::Vec2,
+        color: [f32; 4],
+    ) {
+        let first = (self.vertices.len() / 8) as u32;
+        for &(x, y) in &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+            self.vertices.extend_from_slice(&[
+                min.x + size.x * x,
+                min.y + size.y * y,
+                uv_min.x + uv_size.x * x,
+                uv_min.y + uv_size.y * y,
+            ]);
+            self.vertices.extend_from_slice(&color);
+        }
+        self.indices
+            .extend([0, 1, 2, 2, 1, 3].iter().map(|i| first + i));
+    }
+}
+
+fn glyphs(ctx: &Context) -> Result<Texture, GolemError> {
+    let (width, height) = (GLYPHS_WIDTH as usize, GLYPHS_HEIGHT as usize);
+    let mut pixels = vec![0u8; width * height * 4];
+    let mut set = |x: usize, y: usize| pixels[(y * width + x) * 4..][..4].fill(255);
+    for (g, bits) in FONT.iter().enumerate() {
+        for y in 0..5 {
+            let row = bits >> (3 * (4 - y)) & 0o7;
+            for x in 0..3 {
+                if row & (4 >> x) != 0 {
+                    set(g * 4 + x, y);
+                }
+            }
+        }
+    }
+    set(width - 1, height - 1);
+    let mut texture = Texture::new(ctx)?;
+    texture.set_image(
+        Some(&pixels),
+        GLYPHS_WIDTH,
+        GLYPHS_HEIGHT,
+        ColorFormat::RGBA,
+    );
+    texture.set_minification(TextureFilter::Nearest)?;
+    texture.set_magnification(TextureFilter::Nearest)?;
+    Ok(texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_char_has_a_glyph() {
+        assert_eq!(glyph(' '), 0);
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('_'), FONT.len() - 1);
+        assert_eq!(glyph('~'), glyph('?'));
+        assert_eq!(glyph('é'), glyph('?'));
+    }
+
+    #[test]
+    fn text_is_cut_at_the_last_whole_glyph() {
+        assert_eq!(fitting_chars(0.0), 0);
+        assert_eq!(fitting_chars(3.0 * SCALE), 1);
+        assert_eq!(fitting_chars(ADVANCE + 3.0 * SCALE - 0.1), 1);
+        assert_eq!(fitting_chars(ADVANCE + 3.0 * SCALE), 2);
+    }
+}
@@ -0,0 +1,528 @@
+//! Renders the models in `tests/models` through the headless path and compares them against the
+//! reference images in `tests/golden`.
+//!
+//! - `UPDATE_GOLDEN=1` rewrites the references from the current output instead of comparing.
+//! - On a mismatch the rendered image and a diff are written to `target/tmp/golden`.
+//! - Without a usable EGL the tests are skipped, unless `GOLDEN_REQUIRE_GL=1` is set (for CI).
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::path::{Path, PathBuf};
+
+use gltf_test_lib::golem_gltf::{self, DebugView, Gltf, Optimization, ToneMapping};
+use gltf_test_lib::headless::{Headless, Image, RenderOptions, View};
+use nalgebra_glm as glm;
+
+/// Two pixels count as the same when their perceived difference is below this fraction of the
+/// largest possible one.
+const PIXEL_THRESHOLD: f32 = 0.1;
+/// Fraction of pixels allowed to differ, to absorb rasterization differences between drivers.
+const MAX_DIFFERENT_PIXELS: f32 = 0.005;
+
+const SIZE: u32 = 256;
+
+fn manifest_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn options(view: View) -> RenderOptions {
+    RenderOptions {
+        width: SIZE,
+        height: SIZE,
+        view,
+        ..RenderOptions::default()
+    }
+}
+
+fn framed(yaw: f32, pitch: f32, zoom: f32) -> View {
+    View::Framed {
+        yaw: yaw.to_radians(),
+        pitch: pitch.to_radians(),
+        zoom,
+    }
+}
+
+/// `None` if the test named `name` should be skipped.
+fn headless(name: &str) -> Option<Headless> {
+    match Headless::new() {
+        Ok(headless) => Some(headless),
+        Err(e) if std::env::var_os("GOLDEN_REQUIRE_GL").is_none() => {
+            eprintln!("skipping {}: no offscreen GL context ({})", name, e);
+            None
+        }
+        Err(e) => panic!("no offscreen GL context: {}", e),
+    }
+}
+
+fn check(name: &str, model: impl AsRef<Path>, options: RenderOptions) {
+    let headless = match headless(name) {
+        Some(headless) => headless,
+        None => return,
+    };
+    let mut model = headless.load(manifest_dir().join(model)).unwrap();
+    let actual = headless.render(&mut model, &options).unwrap();
+
+    let reference_path = manifest_dir()
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save_png(&reference_path).unwrap();
+        return;
+    }
+    let reference = Image::load_png(&reference_path).unwrap_or_else(|e| {
+        panic!(
+            "can't read {} ({}), run with UPDATE_GOLDEN=1 to create it",
+            reference_path.display(),
+            e
+        )
+    });
+
+    if let Some((different, diff)) = compare(&actual, &reference) {
+        let output = output_dir();
+        let actual_path = output.join(format!("{}.actual.png", name));
+        let diff_path = output.join(format!("{}.diff.png", name));
+        actual.save_png(&actual_path).unwrap();
+        diff.save_png(&diff_path).unwrap();
+        panic!(
+            "{}: {} of {} pixels differ from {}, see {} and {}",
+            name,
+            different,
+            SIZE * SIZE,
+            reference_path.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn output_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The number of differing pixels and an image marking them, if there are too many.
+fn compare(actual: &Image, reference: &Image) -> Option<(usize, Image)> {
+    if (actual.width, actual.height) != (reference.width, reference.height) {
+        panic!(
+            "size {}x{} doesn't match the reference's {}x{}",
+            actual.width, actual.height, reference.width, reference.height
+        );
+    }
+
+    let max_delta = 35215.0 * PIXEL_THRESHOLD * PIXEL_THRESHOLD;
+    let mut different = 0;
+    let mut diff = Vec::with_capacity(actual.pixels.len());
+    for (a, r) in actual.pixels.chunks(4).zip(reference.pixels.chunks(4)) {
+        if color_delta(a, r) > max_delta {
+            different += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // Faded reference, so the differences stand out but can still be located.
+            let y = 255.0 - (255.0 - luma(r)) * 0.1;
+            diff.extend_from_slice(&[y as u8, y as u8, y as u8, 255]);
+        }
+    }
+
+    let allowed = (actual.pixels.len() / 4) as f32 * MAX_DIFFERENT_PIXELS;
+    if different as f32 > allowed {
+        Some((
+            different,
+            Image {
+                width: actual.width,
+                height: actual.height,
+                pixels: diff,
+            },
+        ))
+    } else {
+        None
+    }
+}
+
+/// Pixel over white, in 0..255.
+fn blend(pixel: &[u8]) -> [f32; 3] {
+    let a = pixel[3] as f32 / 255.0;
+    let c = |c: u8| 255.0 + (c as f32 - 255.0) * a;
+    [c(pixel[0]), c(pixel[1]), c(pixel[2])]
+}
+
+fn luma(pixel: &[u8]) -> f32 {
+    let [r, g, b] = blend(pixel);
+    r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_2
+}
+
+/// Squared distance in YIQ space, weighted for perceived difference (Kotsarenko & Ramos, 2009),
+/// as used by pixelmatch. The largest possible value is about 35215.
+fn color_delta(a: &[u8], b: &[u8]) -> f32 {
+    let yiq = |pixel: &[u8]| {
+        let [r, g, b] = blend(pixel);
+        [
+            r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_2,
+            r * 0.595_978 - g * 0.274_176_5 - b * 0.321_801_5,
+            r * 0.211_470_2 - g * 0.522_617_4 + b * 0.311_147_2,
+        ]
+    };
+    let (a, b) = (yiq(a), yiq(b));
+    let (y, i, q) = (a[0] - b[0], a[1] - b[1], a[2] - b[2]);
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
+
+#[test]
+fn default_view() {
+    check("default_view", "test.glb", options(framed(0.0, 0.0, 0.0)));
+}
+
+#[test]
+fn orbit_view() {
+    check("orbit_view", "test.glb", options(framed(45.0, 30.0, 2.0)));
+}
+
+#[test]
+fn background() {
+    check(
+        "background",
+        "test.glb",
+        RenderOptions {
+            width: SIZE,
+            height: SIZE,
+            background: [1.0, 1.0, 1.0, 0.0],
+            ..RenderOptions::default()
+        },
+    );
+}
+
+#[test]
+fn skinned_animation() {
+    // Framed on the posed mesh, since the bounds of skinned primitives follow their joints.
+    check(
+        "skinned_animation",
+        "tests/models/skinned.gltf",
+        RenderOptions {
+            time: Some(0.5),
+            ..options(framed(30.0, 20.0, 0.0))
+        },
+    );
+}
+
+#[test]
+fn unusable_skins() {
+    // Inverse bind matrices outside the buffer, and more joints than the shader has room for:
+    // both load and render, with the mesh left unskinned.
+    let headless = match headless("unusable_skins") {
+        Some(headless) => headless,
+        None => return,
+    };
+    let skinned = std::fs::read(manifest_dir().join("tests/models/skinned.gltf")).unwrap();
+    let mut too_many_joints: gltf::json::Value =
+        gltf::json::deserialize::from_slice(&skinned).unwrap();
+    too_many_joints["skins"][0]["joints"] = vec![1; 65].into();
+    too_many_joints["skins"][0]
+        .as_object_mut()
+        .unwrap()
+        .remove("inverseBindMatrices");
+    let too_many_joints_path = output_dir().join("too_many_joints.gltf");
+    std::fs::write(
+        &too_many_joints_path,
+        gltf::json::serialize::to_vec(&too_many_joints).unwrap(),
+    )
+    .unwrap();
+
+    for (path, expected) in &[
+        (
+            manifest_dir().join("tests/models/broken_skin.gltf"),
+            "AccessorOutOfBounds",
+        ),
+        (too_many_joints_path, "TooManyJoints"),
+    ] {
+        let mut model = headless.load(path).unwrap();
+        let issues = format!("{:?}", model.validation().issues());
+        assert!(issues.contains(expected), "{}", issues);
+        headless
+            .render(&mut model, &options(framed(30.0, 20.0, 0.0)))
+            .unwrap();
+    }
+}
+
+#[test]
+fn morph_targets() {
+    check(
+        "morph_targets",
+        "tests/models/morph.gltf",
+        options(framed(30.0, 20.0, 2.0)),
+    );
+}
+
+#[test]
+fn alpha_modes() {
+    check(
+        "alpha_modes",
+        "tests/models/alpha.gltf",
+        options(framed(0.0, 0.0, 0.0)),
+    );
+}
+
+#[test]
+fn camera_and_lights() {
+    check(
+        "camera_and_lights",
+        "tests/models/lit.gltf",
+        options(View::Camera(0)),
+    );
+}
+
+#[test]
+fn instancing() {
+    check(
+        "instancing",
+        "tests/models/instanced.gltf",
+        options(framed(20.0, 30.0, 0.0)),
+    );
+}
+
+#[test]
+fn export_round_trip() {
+    // Written as .glb and loaded again, the model renders as before. gltf doesn't parse
+    // EXT_mesh_gpu_instancing, so this also checks that extensions it doesn't know survive.
+    let headless = match headless("export_round_trip") {
+        Some(headless) => headless,
+        None => return,
+    };
+    let exported = output_dir().join("instanced.glb");
+    headless
+        .load(manifest_dir().join("tests/models/instanced.gltf"))
+        .unwrap()
+        .save(&exported)
+        .unwrap();
+    check("instancing", &exported, options(framed(20.0, 30.0, 0.0)));
+}
+
+#[test]
+fn material_export_round_trip() {
+    // Materials are written from the loaded scene, with their extensions, so changes to them
+    // are saved, and a file converted without a GL context renders as before.
+    let headless = match headless("material_export_round_trip") {
+        Some(headless) => headless,
+        None => return,
+    };
+    {
+        let mut model = headless
+            .load(manifest_dir().join("tests/models/materials.gltf"))
+            .unwrap();
+        let materials = model.materials_mut();
+        materials[0].base_color_texture.as_mut().unwrap().rotation = 0.5;
+        materials[1].unlit = false;
+        materials[2].emissive_strength = 1.0;
+        materials[3].emissive_factor = glm::vec3(0.5, 0.0, 0.0);
+        materials[3].emissive_texture = materials[3].base_color_texture;
+        let exported = output_dir().join("materials.glb");
+        model.save(&exported).unwrap();
+        let reloaded = headless.load(&exported).unwrap();
+        for (saved, loaded) in model.materials().iter().zip(reloaded.materials()) {
+            assert_eq!(saved.name, loaded.name);
+            assert_eq!(saved.base_color_factor, loaded.base_color_factor);
+            assert_eq!(saved.base_color_texture, loaded.base_color_texture);
+            assert_eq!(saved.emissive_factor, loaded.emissive_factor);
+            assert_eq!(saved.emissive_strength, loaded.emissive_strength);
+            assert_eq!(saved.emissive_texture, loaded.emissive_texture);
+            assert_eq!(saved.unlit, loaded.unlit);
+        }
+    }
+
+    let converted = output_dir().join("materials.converted.gltf");
+    golem_gltf::export_file(
+        manifest_dir().join("tests/models/materials.gltf"),
+        &converted,
+        None,
+    )
+    .unwrap();
+    check(
+        "material_extensions",
+        &converted,
+        options(framed(0.0, 0.0, 0.0)),
+    );
+}
+
+#[test]
+fn optimized_export() {
+    // Optimized and quantized, then written and loaded again, the model renders as before.
+    let headless = match headless("optimized_export") {
+        Some(headless) => headless,
+        None => return,
+    };
+    let exported = output_dir().join("lit.optimized.glb");
+    let model = headless
+        .load_optimized(
+            manifest_dir().join("tests/models/lit.gltf"),
+            &Optimization::default(),
+        )
+        .unwrap();
+    let stats = model.optimization_stats().unwrap();
+    assert_eq!(stats.primitives, 2);
+    assert!(stats.vertex_bytes_after < stats.vertex_bytes_before);
+    model.save(&exported).unwrap();
+    check("camera_and_lights", &exported, options(View::Camera(0)));
+}
+
+#[test]
+fn meshopt_compression() {
+    // lit.gltf with its vertices and indices compressed by meshoptimizer, and a fallback buffer
+    // without data.
+    check(
+        "camera_and_lights",
+        "tests/models/meshopt.gltf",
+        options(View::Camera(0)),
+    );
+}
+
+#[test]
+fn draco_compression() {
+    // lit.gltf with its meshes in Draco's sequential encoding: quantized, delta coded positions
+    // and octahedral normals.
+    check(
+        "camera_and_lights",
+        "tests/models/draco.gltf",
+        options(View::Camera(0)),
+    );
+}
+
+#[test]
+fn material_extensions() {
+    // A transformed texture, an unlit material, a strengthened emissive one, and a texture
+    // repeated through TEXCOORD_1 that has to be scaled to a power of two.
+    check(
+        "material_extensions",
+        "tests/models/materials.gltf",
+        options(framed(0.0, 0.0, 0.0)),
+    );
+}
+
+#[test]
+fn environment_lighting() {
+    // Dielectric, polished and rough metal spheres reflecting an HDR environment with a sun,
+    // which is drawn behind them.
+    check(
+        "environment_lighting",
+        "tests/models/spheres.gltf",
+        RenderOptions {
+            environment: Some(manifest_dir().join("tests/models/environment.hdr")),
+            tone_mapping: ToneMapping::filmic(),
+            ..options(framed(20.0, 10.0, 0.0))
+        },
+    );
+}
+
+#[test]
+fn post_process() {
+    // The same spheres through the HDR target: the bright sky and highlights bloom, and the
+    // spheres' edges are smoothed by FXAA.
+    check(
+        "post_process",
+        "tests/models/spheres.gltf",
+        RenderOptions {
+            environment: Some(manifest_dir().join("tests/models/environment.hdr")),
+            tone_mapping: ToneMapping::filmic(),
+            post: Some(post_process::Settings {
+                bloom: 1.0,
+                ..post_process::Settings::default()
+            }),
+            ..options(framed(20.0, 10.0, 0.0))
+        },
+    );
+}
+
+#[test]
+fn shadows() {
+    // The key light of the default rig casts the frame's shadow on itself and on the ground.
+    check(
+        "shadows",
+        "test.glb",
+        RenderOptions {
+            shadows: true,
+            ground: true,
+            ..options(framed(30.0, 25.0, 0.0))
+        },
+    );
+}
+
+#[test]
+fn debug_views() {
+    check(
+        "debug_views",
+        "tests/models/skinned.gltf",
+        RenderOptions {
+            time: Some(0.5),
+            debug: DebugView::parse("wireframe,bounds,skeleton,axes").unwrap(),
+            ..options(framed(30.0, 20.0, 0.0))
+        },
+    );
+}
+
+#[test]
+fn uv_checker() {
+    check(
+        "uv_checker",
+        "tests/models/materials.gltf",
+        RenderOptions {
+            debug: DebugView::parse("uv").unwrap(),
+            ..options(framed(0.0, 0.0, 0.0))
+        },
+    );
+}
+
+#[test]
+fn scenes() {
+    // The default scene is the second, without the file's light, so the default rig lights it.
+    check(
+        "default_scene",
+        "tests/models/scenes.gltf",
+        options(framed(30.0, 20.0, 0.0)),
+    );
+    check(
+        "other_scene",
+        "tests/models/scenes.gltf",
+        RenderOptions {
+            scene: Some(0),
+            ..options(framed(30.0, 20.0, 0.0))
+        },
+    );
+
+    let headless = match headless("scenes") {
+        Some(headless) => headless,
+        None => return,
+    };
+    let mut model = headless
+        .load(manifest_dir().join("tests/models/scenes.gltf"))
+        .unwrap();
+    assert_eq!(model.scenes().len(), 2);
+    assert_eq!(model.default_scene(), Some(1));
+    assert_eq!(model.scene(), Some(1));
+    // The pyramid and the box on top of it.
+    let in_scene = |model: &Gltf| {
+        (0..model.nodes().len())
+            .filter(|&n| model.is_in_scene(n))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(in_scene(&model), [2, 3]);
+    let pyramid = model.bounding_box().unwrap();
+    model.set_scene(None);
+    assert_eq!(in_scene(&model).len(), model.nodes().len());
+    assert!(model.bounding_box().unwrap().radius() > pyramid.radius());
+}
+
+#[test]
+fn unsupported_required_extension() {
+    let headless = match headless("unsupported_required_extension") {
+        Some(headless) => headless,
+        None => return,
+    };
+    let json = r#"{
+        "asset": {"version": "2.0"},
+        "extensionsUsed": ["KHR_texture_transform", "EXT_made_up"],
+        "extensionsRequired": ["KHR_texture_transform", "EXT_made_up"]
+    }"#;
+    let error = match Gltf::load_slice(json, headless.context()) {
+        Ok(_) => panic!("loaded a file requiring an unsupported extension"),
+        Err(error) => error.to_string(),
+    };
+    assert!(error.contains("EXT_made_up"), "{}", error);
+    assert!(!error.contains("KHR_texture_transform"), "{}", error);
+}
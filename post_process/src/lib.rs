@@ -0,0 +1,466 @@
+//! An HDR render target and the full-screen passes that turn it into the picture on screen:
+//! bloom, exposure and tone mapping, gamma, and FXAA.
+//!
+//! golem only has RGBA8 textures, so the scene is drawn with its colors encoded by
+//! [`ENCODE_GLSL`]'s `encode_hdr`, which keeps linear values up to [`HDR_RANGE`] with more
+//! precision in the darks. Anything blended into the target mixes in that curve rather than
+//! linearly, which is close enough for transparent surfaces.
+//!
+//! ```ignore
+//! post.begin(ctx, width, height)?;
+//! // Draw the scene, writing encode_hdr(color).
+//! post.resolve(ctx)?;
+//! // Bind the framebuffer to show it in, if it isn't the screen.
+//! post.draw_output(ctx)?;
+//! ```
+//!
+//! Like the depth attachment of the target, which golem's surfaces lack, anything golem doesn't
+//! wrap goes through a second `glow` context on the same GL context.
+
+use std::num::NonZeroU32;
+
+use golem::glow::{self, HasContext};
+use golem::*;
+
+/// Brightest linear value the HDR target holds.
+pub const HDR_RANGE: f32 = 8.0;
+
+/// GLSL for `encode_hdr` and `decode_hdr`, between linear colors and what the HDR target stores.
+pub const ENCODE_GLSL: &str = r#"
+        vec3 encode_hdr(vec3 color) {
+            return sqrt(clamp(color / 8.0, 0.0, 1.0));
+        }
+        vec3 decode_hdr(vec3 encoded) {
+            return encoded * encoded * 8.0;
+        }
+"#;
+
+/// `encode_hdr` on the CPU, for clear colors.
+pub fn encode(color: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = color;
+    let encode = |c: f32| (c / HDR_RANGE).clamp(0.0, 1.0).sqrt();
+    [encode(r), encode(g), encode(b)]
+}
+
+/// `decode_hdr` on the CPU.
+pub fn decode(encoded: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = encoded;
+    [r * r * HDR_RANGE, g * g * HDR_RANGE, b * b * HDR_RANGE]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    /// Anything brighter than 1 is clipped.
+    Clamp,
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    /// Multiplies the color before the operator.
+    pub exposure: f32,
+    pub operator: Operator,
+    /// The output is encoded with this gamma.
+    pub gamma: f32,
+    pub fxaa: bool,
+    /// How much of the blurred highlights is added to the picture. 0 skips the bloom passes.
+    pub bloom: f32,
+    /// Linear brightness above which colors bloom.
+    pub bloom_threshold: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            exposure: 1.0,
+            operator: Operator::Aces,
+            gamma: 2.2,
+            fxaa: true,
+            bloom: 0.3,
+            bloom_threshold: 1.0,
+        }
+    }
+}
+
+const SOURCE_BIND_POINT: u32 = 1;
+const BLOOM_BIND_POINT: u32 = 2;
+
+/// The surfaces for one size of output.
+struct Targets {
+    width: u32,
+    height: u32,
+    /// Encoded HDR colors, with the depth renderbuffer attached.
+    scene: Surface,
+    /// At half the size: the bright parts, and the blur between its two directions.
+    bloom: [Surface; 2],
+    /// The tone mapped picture, before FXAA.
+    output: Surface,
+}
+
+pub struct PostProcess {
+    pub settings: Settings,
+    gl: glow::Context,
+    depth: <glow::Context as HasContext>::Renderbuffer,
+    targets: Option<Targets>,
+    bright_pass: ShaderProgram,
+    blur_pass: ShaderProgram,
+    composite_pass: ShaderProgram,
+    output_pass: ShaderProgram,
+    quad: (VertexBuffer, ElementBuffer),
+}
+
+impl PostProcess {
+    /// `gl` must be a context on the same GL context as `ctx`.
+    pub fn new(
+        ctx: &Context,
+        gl: glow::Context,
+        settings: Settings,
+    ) -> Result<PostProcess, GolemError> {
+        let depth = unsafe { gl.create_renderbuffer() }?;
+        let sampler = |name| Uniform::new(name, UniformType::Sampler2D);
+        let float = |name| Uniform::new(name, UniformType::Scalar(NumberType::Float));
+        let vec2 = |name| Uniform::new(name, UniformType::Vector(NumberType::Float, Dimension::D2));
+
+        let bright_pass = pass(
+            ctx,
+            &[sampler("source"), vec2("texel"), float("threshold")],
+            r#"void main() {
+                // The four texels under this one, averaged in linear color.
+                vec3 color = vec3(0.0);
+                color += decode_hdr(texture(source, frag_uv + texel * vec2(-0.5, -0.5)).rgb);
+                color += decode_hdr(texture(source, frag_uv + texel * vec2(0.5, -0.5)).rgb);
+                color += decode_hdr(texture(source, frag_uv + texel * vec2(-0.5, 0.5)).rgb);
+                color += decode_hdr(texture(source, frag_uv + texel * vec2(0.5, 0.5)).rgb);
+                gl_FragColor = vec4(encode_hdr(max(color * 0.25 - threshold, 0.0)), 1.0);
+            }"#,
+        )?;
+        let blur_pass = pass(
+            ctx,
+            &[sampler("source"), vec2("direction")],
+            r#"void main() {
+                // A 9-tap gaussian along `direction`.
+                vec3 color = decode_hdr(texture(source, frag_uv).rgb) * 0.227027;
+                color += decode_hdr(texture(source, frag_uv + direction).rgb) * 0.1945946;
+                color += decode_hdr(texture(source, frag_uv - direction).rgb) * 0.1945946;
+                color += decode_hdr(texture(source, frag_uv + direction * 2.0).rgb) * 0.1216216;
+                color += decode_hdr(texture(source, frag_uv - direction * 2.0).rgb) * 0.1216216;
+                color += decode_hdr(texture(source, frag_uv + direction * 3.0).rgb) * 0.054054;
+                color += decode_hdr(texture(source, frag_uv - direction * 3.0).rgb) * 0.054054;
+                color += decode_hdr(texture(source, frag_uv + direction * 4.0).rgb) * 0.016216;
+                color += decode_hdr(texture(source, frag_uv - direction * 4.0).rgb) * 0.016216;
+                gl_FragColor = vec4(encode_hdr(color), 1.0);
+            }"#,
+        )?;
+        let composite_pass = pass(
+            ctx,
+            &[
+                sampler("source"),
+                sampler("bloom_map"),
+                float("bloom"),
+                float("exposure"),
+                float("tone_operator"),
+                float("gamma"),
+            ],
+            r#"void main() {
+                vec3 color = decode_hdr(texture(source, frag_uv).rgb)
+                    + decode_hdr(texture(bloom_map, frag_uv).rgb) * bloom;
+                color *= exposure;
+                if (tone_operator == 1.0) {
+                    color = color / (1.0 + color);
+                } else if (tone_operator == 2.0) {
+                    color = (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
+                }
+                gl_FragColor = vec4(pow(clamp(color, 0.0, 1.0), vec3(1.0 / gamma)), 1.0);
+            }"#,
+        )?;
+        let output_pass = pass(
+            ctx,
+            &[sampler("source"), vec2("texel"), float("fxaa")],
+            FXAA_GLSL,
+        )?;
+
+        let mut vb = VertexBuffer::new(ctx)?;
+        vb.set_data(&[-1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, 1.0]);
+        let mut eb = ElementBuffer::new(ctx)?;
+        eb.set_data(&[0, 1, 2, 2, 3, 0]);
+
+        Ok(PostProcess {
+            settings,
+            gl,
+            depth,
+            targets: None,
+            bright_pass,
+            blur_pass,
+            composite_pass,
+            output_pass,
+            quad: (vb, eb),
+        })
+    }
+
+    /// Bind the HDR target, at `width` by `height`, and set the viewport to it. The scene drawn
+    /// next should clear it, with a color from [`encode`].
+    pub fn begin(&mut self, ctx: &Context, width: u32, height: u32) -> Result<(), GolemError> {
+        let (width, height) = (width.max(1), height.max(1));
+        let resized = match &self.targets {
+            Some(targets) => targets.width != width || targets.height != height,
+            None => true,
+        };
+        if resized {
+            // The old surfaces go first, so the sizes never add up.
+            self.targets = None;
+            self.targets = Some(self.create_targets(ctx, width, height)?);
+        }
+        let targets = self.targets.as_ref().unwrap();
+        targets.scene.bind();
+        ctx.set_viewport(0, 0, width, height);
+        Ok(())
+    }
+
+    fn create_targets(
+        &self,
+        ctx: &Context,
+        width: u32,
+        height: u32,
+    ) -> Result<Targets, GolemError> {
+        let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+        let targets = Targets {
+            width,
+            height,
+            scene: surface(ctx, width, height)?,
+            bloom: [
+                surface(ctx, half_width, half_height)?,
+                surface(ctx, half_width, half_height)?,
+            ],
+            output: surface(ctx, width, height)?,
+        };
+        targets.scene.bind();
+        let gl = &self.gl;
+        let complete = unsafe {
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(self.depth));
+            gl.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                glow::DEPTH_COMPONENT16,
+                width as i32,
+                height as i32,
+            );
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_ATTACHMENT,
+                glow::RENDERBUFFER,
+                Some(self.depth),
+            );
+            gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE
+        };
+        Surface::unbind(ctx);
+        if complete {
+            Ok(targets)
+        } else {
+            Err(GolemError::ContextError(
+                "the HDR target can't be completed".to_owned(),
+            ))
+        }
+    }
+
+    /// Run the bloom and tone mapping passes on what was drawn since [`PostProcess::begin`]. This
+    /// leaves the screen bound, with depth testing and blending off.
+    pub fn resolve(&mut self, ctx: &Context) -> Result<(), GolemError> {
+        let targets = self.targets.as_ref().expect("resolve called before begin");
+        let settings = &self.settings;
+        ctx.set_depth_test_mode(None);
+        ctx.set_blend_mode(None);
+        // The target's surfaces aren't bound while their textures are read below.
+        let scene = unsafe { targets.scene.borrow_texture() }.unwrap();
+        let bloom = [
+            unsafe { targets.bloom[0].borrow_texture() }.unwrap(),
+            unsafe { targets.bloom[1].borrow_texture() }.unwrap(),
+        ];
+
+        if settings.bloom > 0.0 {
+            let half_size = [bloom[0].width(), bloom[0].height()];
+            ctx.set_viewport(0, 0, half_size[0], half_size[1]);
+
+            targets.bloom[0].bind();
+            let shader = &mut self.bright_pass;
+            shader.bind();
+            set_source(shader, scene)?;
+            shader.set_uniform(
+                "texel",
+                UniformValue::Vector2([1.0 / targets.width as f32, 1.0 / targets.height as f32]),
+            )?;
+            shader.set_uniform("threshold", UniformValue::Float(settings.bloom_threshold))?;
+            draw(shader, &self.quad)?;
+
+            // Across, into the second surface, then down, back into the first.
+            let shader = &mut self.blur_pass;
+            shader.bind();
+            for (from, direction) in [
+                (0, [1.0 / half_size[0] as f32, 0.0]),
+                (1, [0.0, 1.0 / half_size[1] as f32]),
+            ] {
+                targets.bloom[1 - from].bind();
+                set_source(shader, bloom[from])?;
+                shader.set_uniform("direction", UniformValue::Vector2(direction))?;
+                draw(shader, &self.quad)?;
+            }
+        }
+
+        ctx.set_viewport(0, 0, targets.width, targets.height);
+        targets.output.bind();
+        let shader = &mut self.composite_pass;
+        shader.bind();
+        set_source(shader, scene)?;
+        bloom[0].set_active(NonZeroU32::new(BLOOM_BIND_POINT).unwrap());
+        shader.set_uniform("bloom_map", UniformValue::Int(BLOOM_BIND_POINT as i32))?;
+        shader.set_uniform("bloom", UniformValue::Float(settings.bloom.max(0.0)))?;
+        shader.set_uniform("exposure", UniformValue::Float(settings.exposure))?;
+        let operator = match settings.operator {
+            Operator::Clamp => 0.0,
+            Operator::Reinhard => 1.0,
+            Operator::Aces => 2.0,
+        };
+        shader.set_uniform("tone_operator", UniformValue::Float(operator))?;
+        shader.set_uniform("gamma", UniformValue::Float(settings.gamma))?;
+        draw(shader, &self.quad)?;
+
+        Surface::unbind(ctx);
+        Ok(())
+    }
+
+    /// Draw the resolved picture into the framebuffer bound, anti-aliased if FXAA is on, and
+    /// set the viewport to it.
+    pub fn draw_output(&mut self, ctx: &Context) -> Result<(), GolemError> {
+        let targets = self
+            .targets
+            .as_ref()
+            .expect("draw_output called before begin");
+        ctx.set_viewport(0, 0, targets.width, targets.height);
+        let shader = &mut self.output_pass;
+        shader.bind();
+        set_source(shader, unsafe { targets.output.borrow_texture() }.unwrap())?;
+        shader.set_uniform(
+            "texel",
+            UniformValue::Vector2([1.0 / targets.width as f32, 1.0 / targets.height as f32]),
+        )?;
+        let fxaa = if self.settings.fxaa { 1.0 } else { 0.0 };
+        shader.set_uniform("fxaa", UniformValue::Float(fxaa))?;
+        draw(shader, &self.quad)
+    }
+}
+
+impl Drop for PostProcess {
+    fn drop(&mut self) {
+        unsafe { self.gl.delete_renderbuffer(self.depth) };
+    }
+}
+
+/// A full-screen pass, with `frag_uv` from 0 to 1 across the target.
+fn pass(ctx: &Context, uniforms: &[Uniform], body: &str) -> Result<ShaderProgram, GolemError> {
+    ShaderProgram::new(
+        ctx,
+        ShaderDescription {
+            vertex_input: &[Attribute::new(
+                "vert_position",
+                AttributeType::Vector(Dimension::D2),
+            )],
+            fragment_input: &[Attribute::new(
+                "frag_uv",
+                AttributeType::Vector(Dimension::D2),
+            )],
+            uniforms,
+            vertex_shader: r#" void main() {
+                gl_Position = vec4(vert_position, 0.0, 1.0);
+                frag_uv = vert_position * 0.5 + 0.5;
+            }"#,
+            fragment_shader: &format!("{}{}", ENCODE_GLSL, body),
+        },
+    )
+}
+
+fn surface(ctx: &Context, width: u32, height: u32) -> Result<Surface, GolemError> {
+    let mut texture = Texture::new(ctx)?;
+    texture.set_image(None, width, height, ColorFormat::RGBA);
+    texture.set_magnification(TextureFilter::Linear)?;
+    texture.set_wrap_h(TextureWrap::ClampToEdge)?;
+    texture.set_wrap_v(TextureWrap::ClampToEdge)?;
+    Surface::new(ctx, texture)
+}
+
+fn set_source(shader: &ShaderProgram, texture: &Texture) -> Result<(), GolemError> {
+    texture.set_active(NonZeroU32::new(SOURCE_BIND_POINT).unwrap());
+    shader.set_uniform("source", UniformValue::Int(SOURCE_BIND_POINT as i32))
+}
+
+fn draw(shader: &ShaderProgram, quad: &(VertexBuffer, ElementBuffer)) -> Result<(), GolemError> {
+    let (vb, eb) = quad;
+    unsafe { shader.draw(vb, eb, 0..6, GeometryMode::Triangles) }
+}
+
+/// Copies `source`, or with `fxaa` on, smooths its edges along the direction of the luma
+/// gradient, after Timothy Lottes' FXAA.
+const FXAA_GLSL: &str = r#"
+        const float REDUCE_MIN = 1.0 / 128.0;
+        const float REDUCE_MUL = 1.0 / 8.0;
+        const float SPAN_MAX = 8.0;
+        void main() {
+            vec4 center = texture(source, frag_uv);
+            if (fxaa == 0.0) {
+                gl_FragColor = center;
+                return;
+            }
+            vec3 weights = vec3(0.299, 0.587, 0.114);
+            float luma_nw = dot(texture(source, frag_uv + texel * vec2(-1.0, -1.0)).rgb, weights);
+            float luma_ne = dot(texture(source, frag_uv + texel * vec2(1.0, -1.0)).rgb, weights);
+            float luma_sw = dot(texture(source, frag_uv + texel * vec2(-1.0, 1.0)).rgb, weights);
+            float luma_se = dot(texture(source, frag_uv + texel * vec2(1.0, 1.0)).rgb, weights);
+            float luma_m = dot(center.rgb, weights);
+            float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+            float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+            vec2 dir = vec2(
+                -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+                (luma_nw + luma_sw) - (luma_ne + luma_se)
+            );
+            float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+            float scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+            dir = clamp(dir * scale, -SPAN_MAX, SPAN_MAX) * texel;
+
+            vec3 a = 0.5 * (
+                texture(source, frag_uv + dir * (1.0 / 3.0 - 0.5)).rgb
+                + texture(source, frag_uv + dir * (2.0 / 3.0 - 0.5)).rgb
+            );
+            vec3 b = a * 0.5 + 0.25 * (
+                texture(source, frag_uv - dir * 0.5).rgb + texture(source, frag_uv + dir * 0.5).rgb
+            );
+            float luma_b = dot(b, weights);
+            gl_FragColor = vec4(luma_b < luma_min || luma_b > luma_max ? a : b, center.a);
+        }
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_round_trips_up_to_the_range() {
+        for &value in &[0.0, 0.01, 0.5, 1.0, 4.0, HDR_RANGE] {
+            let [decoded, _, _] = decode(encode([value, 0.0, 0.0]));
+            assert!(
+                (decoded - value).abs() < 1e-5,
+                "{} became {}",
+                value,
+                decoded
+            );
+        }
+        assert_eq!(encode([-1.0, 100.0, HDR_RANGE]), [0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn darks_get_more_of_the_encoding() {
+        // A quarter of the bytes cover the first sixteenth of the range.
+        let [quarter, _, _] = decode([0.25, 0.0, 0.0]);
+        assert!((quarter - HDR_RANGE / 16.0).abs() < 1e-6);
+    }
+}
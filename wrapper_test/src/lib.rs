@@ -1,6 +1,9 @@
 pub mod event;
 
 pub use golem;
+pub use post_process;
+
+use post_process::PostProcess;
 
 #[cfg(target_arch = "wasm32")]
 use winit::{
@@ -28,6 +31,7 @@ pub struct Builder<Model> {
     event_fn: EventFn<Model>,
     update_fn: UpdateFn<Model>,
     view_fn: ViewFn<Model>,
+    post_process: Option<post_process::Settings>,
 }
 impl<Model: 'static> Builder<Model> {
     pub fn init(init_fn: InitFn<Model>) -> Builder<Model> {
@@ -36,6 +40,7 @@ impl<Model: 'static> Builder<Model> {
             event_fn: |_, _, _| {},
             update_fn: |_, _| {},
             view_fn: |_, _| {},
+            post_process: None,
         }
     }
     pub fn event(self, event_fn: EventFn<Model>) -> Builder<Model> {
@@ -47,6 +52,15 @@ impl<Model: 'static> Builder<Model> {
     pub fn view(self, view_fn: ViewFn<Model>) -> Builder<Model> {
         Builder { view_fn, ..self }
     }
+    /// Draw the view into an HDR target and through a post-process chain, rather than straight
+    /// to the window. The view should then write `encode_hdr` colors (see
+    /// [`post_process::ENCODE_GLSL`]).
+    pub fn post_process(self, settings: post_process::Settings) -> Builder<Model> {
+        Builder {
+            post_process: Some(settings),
+            ..self
+        }
+    }
     pub fn run(self) {
         App::new().run(self);
     }
@@ -134,6 +148,30 @@ impl App {
         }
     }
 
+    /// Another glow context on the window's GL context, for what golem doesn't wrap.
+    fn glow_context(&self) -> golem::glow::Context {
+        #[cfg(target_arch = "wasm32")]
+        {
+            use wasm_bindgen::JsCast;
+            use winit::platform::web::WindowExtWebSys;
+
+            let webgl2_context = self
+                .window
+                .canvas()
+                .get_context("webgl2")
+                .unwrap()
+                .unwrap()
+                .dyn_into::<web_sys::WebGl2RenderingContext>()
+                .unwrap();
+            golem::glow::Context::from_webgl2_context(webgl2_context)
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        golem::glow::Context::from_loader_function(|s| {
+            self.windowed_context.get_proc_address(s) as *const _
+        })
+    }
+
     pub fn window_size(&self) -> event::PhysicalSize<u32> {
         #[cfg(target_arch = "wasm32")]
         return self.window.inner_size();
@@ -148,7 +186,10 @@ impl App {
             event_fn,
             update_fn,
             view_fn,
+            post_process,
         } = builder;
+        let mut post = post_process
+            .map(|settings| PostProcess::new(&self.draw, self.glow_context(), settings).unwrap());
         let mut model = init_fn(&self);
         let event_loop = self.el.take().unwrap();
 
@@ -175,7 +216,15 @@ impl App {
                     self.window.request_redraw();
                 }
                 Event::RedrawRequested(_) => {
+                    let size = self.window_size();
+                    if let Some(post) = &mut post {
+                        post.begin(&self.draw, size.width, size.height).unwrap();
+                    }
                     view_fn(&self, &model);
+                    if let Some(post) = &mut post {
+                        post.resolve(&self.draw).unwrap();
+                        post.draw_output(&self.draw).unwrap();
+                    }
 
                     #[cfg(not(target_arch = "wasm32"))]
                     self.windowed_context.swap_buffers().unwrap();
@@ -1,11 +1,18 @@
-use wrapper_test::{golem::*, log, App, Builder};
+use std::cell::RefCell;
+
+use wrapper_test::{golem::*, log, post_process, App, Builder};
 
 pub fn main() {
-    Builder::init(init).update(update).view(view).run();
+    Builder::init(init)
+        .update(update)
+        .view(view)
+        .post_process(post_process::Settings::default())
+        .run();
 }
 
 struct Model {
-    shader: ShaderProgram,
+    // Bound again every frame, after the post-process passes.
+    shader: RefCell<ShaderProgram>,
     vb: VertexBuffer,
     eb: ElementBuffer,
     indices: Vec<u32>,
@@ -21,7 +28,7 @@ fn init(app: &App) -> Model {
         0.5, -0.5,          1.0, 0.0, 0.5, 1.0,
     ];
     let indices = [0, 1, 2];
-    let mut shader = ShaderProgram::new(
+    let shader = ShaderProgram::new(
         ctx,
         ShaderDescription {
             vertex_input: &[
@@ -32,14 +39,22 @@ fn init(app: &App) -> Model {
                 "frag_color",
                 AttributeType::Vector(Dimension::D4),
             )],
-            uniforms: &[],
+            uniforms: &[Uniform::new(
+                "brightness",
+                UniformType::Scalar(NumberType::Float),
+            )],
             vertex_shader: r#" void main() {
                 gl_Position = vec4(vert_position, 0, 1);
                 frag_color = vert_color;
             }"#,
-            fragment_shader: r#" void main() {
-                gl_FragColor = frag_color;
-            }"#,
+            fragment_shader: &format!(
+                r#"{}
+                void main() {{
+                    // Bright enough for the corners to bloom.
+                    gl_FragColor = vec4(encode_hdr(frag_color.rgb * brightness), frag_color.a);
+                }}"#,
+                post_process::ENCODE_GLSL
+            ),
         },
     )
     .unwrap();
@@ -48,10 +63,9 @@ fn init(app: &App) -> Model {
     let mut eb = ElementBuffer::new(ctx).unwrap();
     vb.set_data(&vertices);
     eb.set_data(&indices);
-    shader.bind();
 
     Model {
-        shader,
+        shader: RefCell::new(shader),
         vb,
         eb,
         indices: indices.to_vec(),
@@ -67,11 +81,16 @@ fn view(app: &App, model: &Model) {
 
     let ctx = &app.draw;
 
-    ctx.set_clear_color(0.1, 0.2, 0.3, 1.0);
+    let [r, g, b] = post_process::encode([0.01, 0.03, 0.07]);
+    ctx.set_clear_color(r, g, b, 1.0);
     ctx.clear();
+    let shader = &mut *model.shader.borrow_mut();
+    shader.bind();
+    shader
+        .set_uniform("brightness", UniformValue::Float(4.0))
+        .unwrap();
     unsafe {
-        model
-            .shader
+        shader
             .draw(
                 &model.vb,
                 &model.eb,